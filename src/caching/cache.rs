@@ -1,7 +1,8 @@
 use crate::caching::config::CacheConfig;
 use crate::caching::error::CacheError;
+use crate::fetch::SourceCacheHeaders;
 use crate::monitoring::{increment_cache_hit, increment_cache_miss};
-use crate::utils::format_to_content_type;
+use crate::utils::{format_to_content_type, SourceMetadata};
 use bytes::Bytes;
 use foyer::{
     BlockEngineConfig, Cache, CacheBuilder, Code, Error as FoyerError, ErrorKind, FsDeviceBuilder, HybridCache,
@@ -11,7 +12,8 @@ use foyer::{DeviceBuilder, RecoverMode};
 use std::io::{Read, Write};
 use std::path::Path;
 use std::sync::Arc;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
 
 const DEFAULT_BLOCK_SIZE: usize = 16 * 1024 * 1024;
 const MIN_BLOCK_SIZE: usize = 4 * 1024;
@@ -20,6 +22,13 @@ const MIN_BLOCK_SIZE: usize = 4 * 1024;
 // the in-memory footprint effectively negligible.
 const DISK_MODE_MEMORY_CAPACITY: usize = 1;
 const DISK_MODE_MEMORY_SHARDS: usize = 1;
+// Foyer splits capacity evenly across shards. Source entries are weighted by size, so a
+// single shard lets one large source use the whole budget instead of a fraction of it.
+const SOURCE_CACHE_SHARDS: usize = 1;
+// Leading byte of every encoded entry. Bump it whenever a layout changes: disk entries written
+// by another version then fail to decode, which reads as a cache miss instead of misparsed data.
+const CACHED_IMAGE_FORMAT_VERSION: u8 = 1;
+const CACHED_METADATA_FORMAT_VERSION: u8 = 1;
 
 fn block_size_for_capacity(capacity: usize) -> usize {
     let target = capacity.min(DEFAULT_BLOCK_SIZE);
@@ -35,10 +44,16 @@ fn block_size_for_capacity(capacity: usize) -> usize {
 pub struct CachedImage {
     pub bytes: Bytes,
     pub content_type: &'static str,
+    /// Placeholder data URI generated for `lqip` requests.
+    pub lqip: Option<String>,
+    /// Caching headers of the source, kept to revalidate it when `IMGFORGE_UPSTREAM_CACHE_HEADERS`
+    /// is enabled.
+    pub source_cache_headers: Option<SourceCacheHeaders>,
 }
 
 impl Code for CachedImage {
     fn encode(&self, writer: &mut impl Write) -> Result<(), FoyerError> {
+        CACHED_IMAGE_FORMAT_VERSION.encode(writer)?;
         let data = self.bytes.as_ref();
         data.len().encode(writer)?;
         writer.write_all(data).map_err(FoyerError::io_error)?;
@@ -46,10 +61,25 @@ impl Code for CachedImage {
         let content_type_bytes = self.content_type.as_bytes();
         content_type_bytes.len().encode(writer)?;
         writer.write_all(content_type_bytes).map_err(FoyerError::io_error)?;
+
+        // An empty placeholder encodes `None`; generated data URIs are never empty.
+        let lqip_bytes = self.lqip.as_deref().unwrap_or_default().as_bytes();
+        lqip_bytes.len().encode(writer)?;
+        writer.write_all(lqip_bytes).map_err(FoyerError::io_error)?;
+
+        self.source_cache_headers.is_some().encode(writer)?;
+        if let Some(cache_headers) = &self.source_cache_headers {
+            // Absent headers are stored as empty strings, which origins never send as values.
+            encode_string(cache_headers.etag.as_deref().unwrap_or_default(), writer)?;
+            encode_string(cache_headers.last_modified.as_deref().unwrap_or_default(), writer)?;
+            encode_string(cache_headers.cache_control.as_deref().unwrap_or_default(), writer)?;
+            cache_headers.fetched_at.encode(writer)?;
+        }
         Ok(())
     }
 
     fn decode(reader: &mut impl Read) -> Result<Self, FoyerError> {
+        check_format_version(reader, CACHED_IMAGE_FORMAT_VERSION, "image")?;
         let len = usize::decode(reader)?;
         let mut data = vec![0u8; len];
         reader.read_exact(&mut data).map_err(FoyerError::io_error)?;
@@ -61,14 +91,50 @@ impl Code for CachedImage {
             .map_err(|_| FoyerError::new(ErrorKind::Parse, "invalid utf8 in content type"))?;
         let content_type = format_to_content_type(content_str);
 
+        let lqip_len = usize::decode(reader)?;
+        let mut lqip_buf = vec![0u8; lqip_len];
+        reader.read_exact(&mut lqip_buf).map_err(FoyerError::io_error)?;
+        let lqip =
+            String::from_utf8(lqip_buf).map_err(|_| FoyerError::new(ErrorKind::Parse, "invalid utf8 in lqip"))?;
+
+        let source_cache_headers = if bool::decode(reader)? {
+            let non_empty = |value: String| (!value.is_empty()).then_some(value);
+            Some(SourceCacheHeaders {
+                etag: non_empty(decode_string(reader, "etag")?),
+                last_modified: non_empty(decode_string(reader, "last modified")?),
+                cache_control: non_empty(decode_string(reader, "cache control")?),
+                fetched_at: u64::decode(reader)?,
+            })
+        } else {
+            None
+        };
+
         Ok(CachedImage {
             bytes: Bytes::from(data),
             content_type,
+            lqip: (!lqip.is_empty()).then_some(lqip),
+            source_cache_headers,
         })
     }
 
     fn estimated_size(&self) -> usize {
-        self.bytes.len() + self.content_type.len() + std::mem::size_of::<usize>() * 2
+        std::mem::size_of::<u8>()
+            + self.bytes.len()
+            + self.content_type.len()
+            + self.lqip.as_ref().map_or(0, String::len)
+            + std::mem::size_of::<usize>() * 3
+            + self.source_cache_headers.as_ref().map_or(0, |cache_headers| {
+                std::mem::size_of::<usize>() * 3
+                    + std::mem::size_of::<u64>()
+                    + [
+                        &cache_headers.etag,
+                        &cache_headers.last_modified,
+                        &cache_headers.cache_control,
+                    ]
+                    .iter()
+                    .map(|value| value.as_ref().map_or(0, String::len))
+                    .sum::<usize>()
+            })
     }
 }
 
@@ -82,10 +148,38 @@ pub struct CachedMetadata {
     pub channels: u32,
     pub has_alpha: bool,
     pub orientation: u32,
+    pub animated: bool,
+    /// ICC and EXIF details, present when the request asked for them.
+    pub source_metadata: Option<SourceMetadata>,
+}
+
+fn encode_string(value: &str, writer: &mut impl Write) -> Result<(), FoyerError> {
+    value.len().encode(writer)?;
+    writer.write_all(value.as_bytes()).map_err(FoyerError::io_error)
+}
+
+fn decode_string(reader: &mut impl Read, field: &str) -> Result<String, FoyerError> {
+    let len = usize::decode(reader)?;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).map_err(FoyerError::io_error)?;
+    String::from_utf8(buf).map_err(|_| FoyerError::new(ErrorKind::Parse, format!("invalid utf8 in cached {}", field)))
+}
+
+/// Reads the leading format version of a cached entry, failing when it is not `expected`.
+fn check_format_version(reader: &mut impl Read, expected: u8, kind: &str) -> Result<(), FoyerError> {
+    let version = u8::decode(reader)?;
+    if version != expected {
+        return Err(FoyerError::new(
+            ErrorKind::Parse,
+            format!("cached {} format version {} (expected {})", kind, version, expected),
+        ));
+    }
+    Ok(())
 }
 
 impl Code for CachedMetadata {
     fn encode(&self, writer: &mut impl Write) -> Result<(), FoyerError> {
+        CACHED_METADATA_FORMAT_VERSION.encode(writer)?;
         self.width.encode(writer)?;
         self.height.encode(writer)?;
 
@@ -101,10 +195,22 @@ impl Code for CachedMetadata {
         self.channels.encode(writer)?;
         self.has_alpha.encode(writer)?;
         self.orientation.encode(writer)?;
+        self.animated.encode(writer)?;
+
+        self.source_metadata.is_some().encode(writer)?;
+        if let Some(source_metadata) = &self.source_metadata {
+            source_metadata.has_icc_profile.encode(writer)?;
+            // Absent tags are stored as empty strings, which the EXIF reader never reports.
+            encode_string(source_metadata.date_time.as_deref().unwrap_or_default(), writer)?;
+            encode_string(source_metadata.make.as_deref().unwrap_or_default(), writer)?;
+            encode_string(source_metadata.model.as_deref().unwrap_or_default(), writer)?;
+            source_metadata.has_gps.encode(writer)?;
+        }
         Ok(())
     }
 
     fn decode(reader: &mut impl Read) -> Result<Self, FoyerError> {
+        check_format_version(reader, CACHED_METADATA_FORMAT_VERSION, "metadata")?;
         let width = u32::decode(reader)?;
         let height = u32::decode(reader)?;
 
@@ -126,6 +232,21 @@ impl Code for CachedMetadata {
         let channels = u32::decode(reader)?;
         let has_alpha = bool::decode(reader)?;
         let orientation = u32::decode(reader)?;
+        let animated = bool::decode(reader)?;
+
+        let source_metadata = if bool::decode(reader)? {
+            let has_icc_profile = bool::decode(reader)?;
+            let non_empty = |value: String| (!value.is_empty()).then_some(value);
+            Some(SourceMetadata {
+                has_icc_profile,
+                date_time: non_empty(decode_string(reader, "date time")?),
+                make: non_empty(decode_string(reader, "make")?),
+                model: non_empty(decode_string(reader, "model")?),
+                has_gps: bool::decode(reader)?,
+            })
+        } else {
+            None
+        };
 
         Ok(CachedMetadata {
             width,
@@ -136,15 +257,30 @@ impl Code for CachedMetadata {
             channels,
             has_alpha,
             orientation,
+            animated,
+            source_metadata,
         })
     }
 
     fn estimated_size(&self) -> usize {
-        std::mem::size_of::<u32>() * 4
+        std::mem::size_of::<u8>()
+            + std::mem::size_of::<u32>() * 4
             + std::mem::size_of::<usize>() * 2
-            + std::mem::size_of::<bool>()
+            + std::mem::size_of::<bool>() * 2
             + self.format.len()
             + self.content_type.len()
+            + self.source_metadata.as_ref().map_or(0, |source_metadata| {
+                std::mem::size_of::<usize>() * 3
+                    + std::mem::size_of::<bool>() * 2
+                    + [
+                        &source_metadata.date_time,
+                        &source_metadata.make,
+                        &source_metadata.model,
+                    ]
+                    .iter()
+                    .map(|value| value.as_ref().map_or(0, String::len))
+                    .sum::<usize>()
+            })
     }
 }
 
@@ -255,6 +391,92 @@ impl MetadataCache {
     }
 }
 
+/// Raw source bytes shared by every transform of the same source URL.
+#[derive(Clone)]
+pub struct CachedSource {
+    pub bytes: Bytes,
+    pub content_type: Option<String>,
+    pub cache_headers: SourceCacheHeaders,
+    fetched_at: Instant,
+}
+
+/// In-memory cache of downloaded source images, keyed by the decoded source URL.
+///
+/// Entries are weighted by their byte length, so the capacity bounds memory rather than the
+/// number of sources, and expire once they are older than the configured TTL.
+pub enum SourceCache {
+    None,
+    Memory {
+        cache: Arc<Cache<String, CachedSource>>,
+        capacity: usize,
+        ttl: Duration,
+    },
+}
+
+impl SourceCache {
+    /// Create a source cache holding up to `capacity` bytes; `None` or `0` disables it.
+    pub fn new(capacity: Option<usize>, ttl: Duration) -> Self {
+        match capacity {
+            None | Some(0) => Self::None,
+            Some(capacity) => {
+                let cache = CacheBuilder::new(capacity)
+                    .with_shards(SOURCE_CACHE_SHARDS)
+                    .with_weighter(|_key: &String, value: &CachedSource| value.bytes.len())
+                    .build();
+                Self::Memory {
+                    cache: Arc::new(cache),
+                    capacity,
+                    ttl,
+                }
+            }
+        }
+    }
+
+    /// Retrieve the source bytes for `url` if they were fetched within the TTL.
+    pub fn get(&self, url: &str) -> Option<CachedSource> {
+        let Self::Memory { cache, ttl, .. } = self else {
+            return None;
+        };
+
+        let res = cache.get(url).map(|e| e.value().clone());
+        let res = match res {
+            Some(source) if source.fetched_at.elapsed() > *ttl => {
+                cache.remove(url);
+                None
+            }
+            other => other,
+        };
+        record_cache_metric(res.is_some(), "source-memory");
+        res
+    }
+
+    /// Insert freshly fetched source bytes; sources larger than the whole cache are skipped.
+    pub fn insert(&self, url: String, bytes: Bytes, content_type: Option<String>, cache_headers: SourceCacheHeaders) {
+        let Self::Memory { cache, capacity, .. } = self else {
+            return;
+        };
+
+        if bytes.len() > *capacity {
+            debug!(
+                "Not caching source of {} bytes, above source cache capacity {}",
+                bytes.len(),
+                capacity
+            );
+            return;
+        }
+
+        cache.insert(
+            url,
+            CachedSource {
+                bytes,
+                content_type,
+                cache_headers,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+}
+
 async fn build_typed_cache<T>(config: Option<CacheConfig>, cache_name: &str) -> Result<TypedCache<T>, CacheError>
 where
     T: Clone + Code + Send + Sync + 'static,
@@ -384,6 +606,8 @@ mod tests {
         let value = CachedImage {
             bytes: Bytes::from(vec![1, 2, 3]),
             content_type: "image/jpeg",
+            lqip: None,
+            source_cache_headers: None,
         };
 
         cache.insert(key.clone(), value.clone()).await.unwrap();
@@ -402,10 +626,119 @@ mod tests {
         let value = CachedImage {
             bytes: Bytes::from(vec![1, 2, 3]),
             content_type: "image/jpeg",
+            lqip: None,
+            source_cache_headers: None,
         };
         cache.insert(key.clone(), value.clone()).await.unwrap();
         let retrieved = cache.get(&key).await.unwrap();
         assert_eq!(retrieved.bytes, value.bytes);
         assert_eq!(retrieved.content_type, value.content_type);
     }
+
+    #[test]
+    fn test_cached_image_round_trips_source_cache_headers() {
+        let value = CachedImage {
+            bytes: Bytes::from(vec![1, 2, 3]),
+            content_type: "image/png",
+            lqip: None,
+            source_cache_headers: Some(SourceCacheHeaders {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+                cache_control: Some("max-age=60".to_string()),
+                fetched_at: 1_700_000_000,
+            }),
+        };
+
+        let mut encoded = Vec::new();
+        value.encode(&mut encoded).unwrap();
+        let decoded = CachedImage::decode(&mut encoded.as_slice()).unwrap();
+
+        assert_eq!(decoded.bytes, value.bytes);
+        assert_eq!(decoded.source_cache_headers, value.source_cache_headers);
+    }
+
+    #[test]
+    fn test_cached_entries_with_another_format_version_fail_to_decode() {
+        let image = CachedImage {
+            bytes: Bytes::from(vec![1, 2, 3]),
+            content_type: "image/png",
+            lqip: None,
+            source_cache_headers: None,
+        };
+        let mut encoded = Vec::new();
+        image.encode(&mut encoded).unwrap();
+        assert_eq!(encoded[0], CACHED_IMAGE_FORMAT_VERSION);
+        encoded[0] = CACHED_IMAGE_FORMAT_VERSION + 1;
+        assert!(CachedImage::decode(&mut encoded.as_slice()).is_err());
+
+        let metadata = CachedMetadata {
+            width: 10,
+            height: 20,
+            format: "png".to_string(),
+            content_type: "image/png".to_string(),
+            size_bytes: 100,
+            channels: 4,
+            has_alpha: true,
+            orientation: 1,
+            animated: false,
+            source_metadata: None,
+        };
+        let mut encoded = Vec::new();
+        metadata.encode(&mut encoded).unwrap();
+        assert!(CachedMetadata::decode(&mut encoded.as_slice()).is_ok());
+        encoded[0] = CACHED_METADATA_FORMAT_VERSION + 1;
+        assert!(CachedMetadata::decode(&mut encoded.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_source_cache_disabled_without_capacity() {
+        let ttl = Duration::from_secs(60);
+        assert!(matches!(SourceCache::new(None, ttl), SourceCache::None));
+        assert!(matches!(SourceCache::new(Some(0), ttl), SourceCache::None));
+    }
+
+    #[test]
+    fn test_source_cache_operations() {
+        let cache = SourceCache::new(Some(1024), Duration::from_secs(60));
+        let url = "https://example.com/image.jpg".to_string();
+
+        cache.insert(
+            url.clone(),
+            Bytes::from(vec![1, 2, 3]),
+            Some("image/jpeg".to_string()),
+            SourceCacheHeaders::default(),
+        );
+        let retrieved = cache.get(&url).unwrap();
+        assert_eq!(retrieved.bytes, Bytes::from(vec![1, 2, 3]));
+        assert_eq!(retrieved.content_type.as_deref(), Some("image/jpeg"));
+    }
+
+    #[test]
+    fn test_source_cache_expires_entries_after_ttl() {
+        let cache = SourceCache::new(Some(1024), Duration::ZERO);
+        let url = "https://example.com/image.jpg".to_string();
+
+        cache.insert(
+            url.clone(),
+            Bytes::from(vec![1, 2, 3]),
+            None,
+            SourceCacheHeaders::default(),
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(cache.get(&url).is_none());
+    }
+
+    #[test]
+    fn test_source_cache_skips_sources_larger_than_capacity() {
+        let cache = SourceCache::new(Some(4), Duration::from_secs(60));
+        let url = "https://example.com/image.jpg".to_string();
+
+        cache.insert(
+            url.clone(),
+            Bytes::from(vec![0; 8]),
+            None,
+            SourceCacheHeaders::default(),
+        );
+        assert!(cache.get(&url).is_none());
+    }
 }
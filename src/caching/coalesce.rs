@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::OnceCell;
+
+type InFlight<T> = HashMap<String, Arc<OnceCell<T>>>;
+
+/// Shares one computation between identical requests that are in flight at the same time.
+///
+/// Unlike the persistent caches, an entry only lives while its computation runs: the first
+/// request for a key does the work, requests arriving meanwhile wait for its result, and the key
+/// is dropped once the result is ready. This covers the burst before the processed cache is
+/// populated, including when that cache is disabled.
+pub struct RequestCoalescer<T> {
+    in_flight: Mutex<InFlight<T>>,
+}
+
+impl<T> Default for RequestCoalescer<T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> RequestCoalescer<T> {
+    /// Runs `compute` for `key`, or waits for the result of a computation already running for it.
+    ///
+    /// If the request doing the work is cancelled, one of the waiting requests takes over with
+    /// its own `compute`.
+    pub async fn run<F>(&self, key: &str, compute: F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let cell = self
+            .lock()
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+        let _guard = InFlightGuard {
+            coalescer: self,
+            key,
+            cell: &cell,
+        };
+
+        cell.get_or_init(|| compute).await.clone()
+    }
+}
+
+impl<T> RequestCoalescer<T> {
+    /// Number of keys with a computation in flight.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no computation is in flight.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, InFlight<T>> {
+        self.in_flight.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Drops the key once its result is ready, or once nobody is left to compute it.
+struct InFlightGuard<'a, T> {
+    coalescer: &'a RequestCoalescer<T>,
+    key: &'a str,
+    cell: &'a Arc<OnceCell<T>>,
+}
+
+impl<T> Drop for InFlightGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut in_flight = self.coalescer.lock();
+        let is_current = in_flight
+            .get(self.key)
+            .is_some_and(|entry| Arc::ptr_eq(entry, self.cell));
+        // Waiters clone the cell under the lock, so two references mean only the map and this
+        // request still hold it.
+        if is_current && (self.cell.initialized() || Arc::strong_count(self.cell) <= 2) {
+            in_flight.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_runs_share_one_computation() {
+        let coalescer = RequestCoalescer::default();
+        let calls = AtomicUsize::new(0);
+        let compute = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            42
+        };
+
+        let results = futures::future::join_all((0..5).map(|_| coalescer.run("key", compute()))).await;
+
+        assert_eq!(results, vec![42; 5]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(coalescer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sequential_runs_compute_again() {
+        let coalescer = RequestCoalescer::default();
+        assert_eq!(coalescer.run("key", async { 1 }).await, 1);
+        assert_eq!(coalescer.run("key", async { 2 }).await, 2);
+        assert_eq!(coalescer.run("other", async { 3 }).await, 3);
+        assert!(coalescer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_run_leaves_no_entry() {
+        let coalescer: RequestCoalescer<u32> = RequestCoalescer::default();
+        let pending = coalescer.run("key", std::future::pending());
+        assert!(tokio::time::timeout(Duration::from_millis(10), pending).await.is_err());
+        assert!(coalescer.is_empty());
+    }
+}
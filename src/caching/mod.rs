@@ -1,3 +1,4 @@
 pub mod cache;
+pub mod coalesce;
 pub mod config;
 pub mod error;
@@ -1,15 +1,137 @@
 use crate::constants::*;
 use crate::processing::options::ProcessingOption;
 use crate::processing::presets::parse_options_string;
+use crate::processing::quality::DEFAULT_TARGET_SSIM;
 use std::collections::HashMap;
 use std::env;
+use std::net::IpAddr;
+
+/// How fetch and processing failures are answered when error images are enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorImageMode {
+    /// Serve the error image with the status the failure maps to.
+    KeepStatus,
+    /// Serve the error image with `200 OK`.
+    Ok,
+}
+
+/// Where `s3://` sources are fetched from, and how requests are authenticated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3Config {
+    /// Base URL of the S3-compatible service. Objects are addressed path-style below it, as
+    /// `{endpoint}/{bucket}/{key}`.
+    pub endpoint: String,
+    pub region: String,
+    /// Bucket read by `s3:///key` URLs, which do not name one.
+    pub bucket: Option<String>,
+    /// Keys that sign requests with AWS Signature Version 4. `None` sends anonymous requests.
+    pub credentials: Option<S3Credentials>,
+}
+
+/// An entry of the allowed or blocked source host lists.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceHostRule {
+    /// A hostname, matched exactly.
+    Host(String),
+    /// A `*.example.com` pattern, stored without the `*.`, matching any subdomain.
+    Subdomains(String),
+    /// An IP address or CIDR range, matched against IP hosts and the addresses names resolve to.
+    Network { addr: IpAddr, prefix: u8 },
+}
+
+impl SourceHostRule {
+    /// Parses a hostname, `*.example.com`, IP address, or CIDR range such as `10.0.0.0/8`.
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let entry = entry.trim().to_lowercase();
+        let invalid = || format!("invalid source host rule: {}", entry);
+        if let Some((addr, prefix)) = entry.split_once('/') {
+            let addr = addr.parse::<IpAddr>().map_err(|_| invalid())?;
+            let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(invalid)?;
+            return Ok(Self::Network { addr, prefix });
+        }
+        if let Ok(addr) = entry.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            let prefix = if addr.is_ipv4() { 32 } else { 128 };
+            return Ok(Self::Network { addr, prefix });
+        }
+        let (host, subdomains) = match entry.strip_prefix("*.") {
+            Some(domain) => (domain, true),
+            None => (entry.as_str(), false),
+        };
+        if host.is_empty() || host.contains(['*', ':', '/']) {
+            return Err(invalid());
+        }
+        Ok(if subdomains {
+            Self::Subdomains(host.to_string())
+        } else {
+            Self::Host(host.to_string())
+        })
+    }
+
+    /// Whether a hostname rule names `host`. Network rules never match by name.
+    pub fn matches_name(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        match self {
+            Self::Host(name) => *name == host,
+            Self::Subdomains(domain) => host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.')),
+            Self::Network { .. } => false,
+        }
+    }
+
+    /// Whether a network rule contains `ip`. IPv4-mapped IPv6 addresses match IPv4 ranges.
+    pub fn matches_ip(&self, ip: IpAddr) -> bool {
+        let Self::Network { addr, prefix } = self else {
+            return false;
+        };
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(_) => ip,
+        };
+        match (addr, ip) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                u32::from(*addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                u128::from(*addr) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Access keys for an S3-compatible service.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Token sent with temporary credentials.
+    pub session_token: Option<String>,
+}
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub workers: usize,
+    pub raw_concurrency: Option<usize>,
+    /// Whether `raw` requests obey the source size and MIME type limits. When off, raw requests
+    /// pass the origin's bytes through unchecked.
+    pub raw_enforce_limits: bool,
     pub bind_address: String,
     pub prometheus_bind_address: Option<String>,
+    pub enable_metrics: bool,
+    pub debug_headers: bool,
     pub timeout: u64,
+    /// Longest request path and query, in bytes, accepted before answering `414 URI Too Long`.
+    pub max_path_length: usize,
+    /// Largest request body, in bytes, accepted before answering `413 Payload Too Large`.
+    pub max_request_body_size: usize,
     pub key: Vec<u8>,
     pub salt: Vec<u8>,
     pub allow_unsigned: bool,
@@ -17,12 +139,79 @@ pub struct Config {
     pub max_src_file_size: Option<usize>,
     pub max_src_resolution: Option<f32>,
     pub allowed_mime_types: Option<Vec<String>>,
+    pub decode_fallback: bool,
+    pub max_tiff_pages: u32,
+    /// Most frames decoded from an animated source whose animation is kept. Every decoded frame
+    /// counts towards `max_src_resolution`.
+    pub max_animation_frames: u32,
+    /// Largest blur sigma applied; larger requests are clamped to it, as blur cost grows with
+    /// the sigma.
+    pub max_blur_sigma: f32,
+    /// Side, in pixels, of the square that SVGs without a width, height or viewBox are rasterized
+    /// to cover when the request has no resize target.
+    pub svg_default_size: u32,
     pub download_timeout: u64,
     pub secret: Option<String>,
     pub presets: HashMap<String, Vec<ProcessingOption>>,
     pub only_presets: bool,
     pub watermark_path: Option<String>,
+    pub watermark_min_size: Option<u32>,
     pub rate_limit_per_minute: Option<u32>,
+    pub circuit_breaker_threshold: Option<u32>,
+    pub circuit_breaker_cooldown: u64,
+    /// Times a source fetch is repeated after a connection error or a `502`/`503`/`504` response.
+    pub fetch_retries: u32,
+    /// Most source fetches running against one origin host at once. `None` or `0` is unlimited.
+    pub per_host_concurrency: Option<usize>,
+    /// Whether origin Cache-Control, ETag and Last-Modified headers drive response headers and
+    /// revalidation of cached images.
+    pub upstream_cache_headers: bool,
+    /// Seconds after its source goes stale that a cached image is still served while a background
+    /// task fetches and processes it again. `0` revalidates before answering.
+    pub stale_while_revalidate: u64,
+    pub source_cache_capacity: Option<usize>,
+    pub source_cache_ttl: u64,
+    pub self_hosts: Vec<String>,
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Ports source URLs may connect to, with the scheme's default port filled in. Empty allows any.
+    pub allowed_source_ports: Vec<u16>,
+    pub max_source_url_depth: usize,
+    /// Source hosts that may be fetched. Empty allows any host not otherwise rejected.
+    pub allowed_source_hosts: Vec<SourceHostRule>,
+    /// Source hosts that are never fetched, even when also allowed.
+    pub blocked_source_hosts: Vec<SourceHostRule>,
+    /// Whether sources on loopback, private, and link-local addresses may be fetched without
+    /// being listed in `allowed_source_hosts`.
+    pub allow_private_sources: bool,
+    /// Oldest TLS version source fetches negotiate. `None` keeps the TLS backend's default.
+    pub min_tls_version: Option<reqwest::tls::Version>,
+    /// Whether source fetches accept invalid TLS certificates, such as self-signed or expired ones.
+    pub allow_invalid_certs: bool,
+    /// Directory that `local://` and `file://` source URLs are resolved against. `None` rejects
+    /// local sources.
+    pub local_root: Option<String>,
+    /// Settings for `s3://` sources. `None` rejects S3 sources.
+    pub s3: Option<S3Config>,
+    pub even_dimensions: bool,
+    /// Whether requests that do not set `strip_metadata` drop source metadata from the output.
+    pub strip_metadata_default: bool,
+    pub force_srgb: bool,
+    pub use_embedded_thumbnail: bool,
+    pub gif_to_webp: bool,
+    pub default_quality: Option<u8>,
+    pub format_quality: HashMap<String, u8>,
+    pub auto_quality: bool,
+    pub auto_quality_target: f64,
+    pub max_bytes_format_ladder: Vec<String>,
+    pub error_image_mode: Option<ErrorImageMode>,
+    /// Local path or `http(s)://` URL of an image served, processed like the source would have
+    /// been, when a source cannot be fetched or decoded. `None` reports the failure.
+    pub fallback_image: Option<String>,
+    /// Seconds clients and CDNs may cache a response built from the fallback image.
+    pub fallback_ttl: u64,
+    /// Widths whose signed request paths are listed as `Link` preloads on processed images, minus
+    /// the width being served. Empty sends no `Link` header.
+    pub preload_breakpoints: Vec<u32>,
 }
 
 fn normalize_bind_address(raw: &str) -> String {
@@ -65,14 +254,168 @@ fn parse_presets(presets_str: &str) -> Result<HashMap<String, Vec<ProcessingOpti
     Ok(presets)
 }
 
+/// Reads the S3 source settings, which are enabled by setting an endpoint or a region.
+///
+/// Without an endpoint, requests go to AWS in the configured region. Credentials are optional,
+/// but the access key ID and secret must be set together.
+fn parse_s3_config() -> Result<Option<S3Config>, String> {
+    let non_empty = |key: &str| env::var(key).ok().filter(|s| !s.trim().is_empty());
+    let endpoint = non_empty(ENV_S3_ENDPOINT);
+    let region = non_empty(ENV_S3_REGION);
+    if endpoint.is_none() && region.is_none() {
+        return Ok(None);
+    }
+
+    let region = region.unwrap_or_else(|| "us-east-1".to_string());
+    let endpoint = endpoint.unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+    let parsed = reqwest::Url::parse(&endpoint).map_err(|e| format!("Invalid S3 endpoint '{}': {}", endpoint, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("Invalid S3 endpoint '{}': expected an http(s) URL", endpoint));
+    }
+
+    let credentials = match (non_empty(ENV_S3_ACCESS_KEY_ID), non_empty(ENV_S3_SECRET_ACCESS_KEY)) {
+        (Some(access_key_id), Some(secret_access_key)) => Some(S3Credentials {
+            access_key_id,
+            secret_access_key,
+            session_token: non_empty(ENV_S3_SESSION_TOKEN),
+        }),
+        (None, None) => None,
+        _ => {
+            return Err(format!(
+                "{} and {} must be set together",
+                ENV_S3_ACCESS_KEY_ID, ENV_S3_SECRET_ACCESS_KEY
+            ))
+        }
+    };
+
+    Ok(Some(S3Config {
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        region,
+        bucket: non_empty(ENV_S3_BUCKET),
+        credentials,
+    }))
+}
+
+/// Output formats that may appear in the `max_bytes` format ladder.
+const LADDER_FORMATS: [&str; 6] = ["jpeg", "png", "webp", "avif", "tiff", "gif"];
+
+fn parse_quality(value: &str) -> Result<u8, String> {
+    value
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|quality| (1..=100).contains(quality))
+        .ok_or_else(|| format!("invalid quality: {}", value))
+}
+
+/// Parses per-format qualities in the form `jpeg=80,webp=75`. `jpg` is stored as `jpeg`.
+fn parse_format_quality(format_quality_str: &str) -> Result<HashMap<String, u8>, String> {
+    let mut qualities = HashMap::new();
+    for entry in format_quality_str.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let Some((format, quality)) = entry.split_once('=') else {
+            return Err(format!("invalid format quality definition: {}", entry));
+        };
+        let format = match format.trim().to_lowercase().as_str() {
+            "" => return Err(format!("invalid format quality definition: {}", entry)),
+            "jpg" => "jpeg".to_string(),
+            other => other.to_string(),
+        };
+        qualities.insert(format, parse_quality(quality)?);
+    }
+
+    Ok(qualities)
+}
+
+/// Parses the `max_bytes` format ladder, e.g. `jpeg,webp,avif`. `jpg` is stored as `jpeg`.
+fn parse_format_ladder(ladder_str: &str) -> Result<Vec<String>, String> {
+    let mut ladder: Vec<String> = Vec::new();
+    for entry in ladder_str.split(',') {
+        let format = match entry.trim().to_lowercase().as_str() {
+            "" => continue,
+            "jpg" => "jpeg".to_string(),
+            other => other.to_string(),
+        };
+        if !LADDER_FORMATS.contains(&format.as_str()) {
+            return Err(format!("invalid format in max_bytes ladder: {}", entry.trim()));
+        }
+        if ladder.contains(&format) {
+            return Err(format!("duplicate format in max_bytes ladder: {}", format));
+        }
+        ladder.push(format);
+    }
+
+    Ok(ladder)
+}
+
+/// Parses a comma-separated list of source host rules, e.g. `cdn.example.com,*.example.org,10.0.0.0/8`.
+fn parse_source_host_rules(rules_str: &str) -> Result<Vec<SourceHostRule>, String> {
+    rules_str
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(SourceHostRule::parse)
+        .collect()
+}
+
+/// Parses `IMGFORGE_ERROR_IMAGE_MODE`: `status` or `ok` enable error images, empty or `off`
+/// keeps plain status responses.
+fn parse_error_image_mode(value: &str) -> Result<Option<ErrorImageMode>, String> {
+    match value.trim().to_lowercase().as_str() {
+        "" | "off" => Ok(None),
+        "status" => Ok(Some(ErrorImageMode::KeepStatus)),
+        "ok" => Ok(Some(ErrorImageMode::Ok)),
+        other => Err(format!("invalid error image mode: {}", other)),
+    }
+}
+
+/// Parses a boolean setting: `true` or `false` in any case, or the default when empty. Anything
+/// else fails startup rather than silently picking a side.
+fn parse_bool_setting(name: &str, value: &str, default: bool) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "" => Ok(default),
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!(
+            "invalid {} value: {} (expected true or false)",
+            name,
+            value.trim()
+        )),
+    }
+}
+
+/// Parses `IMGFORGE_MIN_TLS_VERSION`: `1.0` to `1.3`, optionally prefixed with `TLS`/`TLSv`.
+/// Empty keeps the TLS backend's default.
+fn parse_min_tls_version(value: &str) -> Result<Option<reqwest::tls::Version>, String> {
+    let trimmed = value.trim().to_lowercase();
+    let version = trimmed.trim_start_matches("tls").trim_start_matches('v');
+    match version {
+        "" => Ok(None),
+        "1.0" => Ok(Some(reqwest::tls::Version::TLS_1_0)),
+        "1.1" => Ok(Some(reqwest::tls::Version::TLS_1_1)),
+        "1.2" => Ok(Some(reqwest::tls::Version::TLS_1_2)),
+        "1.3" => Ok(Some(reqwest::tls::Version::TLS_1_3)),
+        _ => Err(format!("invalid minimum TLS version: {}", value.trim())),
+    }
+}
+
 impl Config {
     /// Create a configuration with default values using raw key and salt bytes.
     pub fn new(key: Vec<u8>, salt: Vec<u8>) -> Self {
         Self {
             workers: num_cpus::get() * 2,
+            raw_concurrency: None,
+            raw_enforce_limits: true,
             bind_address: "0.0.0.0:3000".to_string(),
             prometheus_bind_address: None,
+            enable_metrics: true,
+            debug_headers: false,
             timeout: 30,
+            max_path_length: 8192,
+            max_request_body_size: 1024,
             key,
             salt,
             allow_unsigned: false,
@@ -80,12 +423,51 @@ impl Config {
             max_src_file_size: None,
             max_src_resolution: None,
             allowed_mime_types: None,
+            decode_fallback: false,
+            max_tiff_pages: 100,
+            max_animation_frames: 256,
+            max_blur_sigma: 100.0,
+            svg_default_size: 1024,
             download_timeout: 10,
             secret: None,
             presets: HashMap::new(),
             only_presets: false,
             watermark_path: None,
+            watermark_min_size: None,
             rate_limit_per_minute: None,
+            circuit_breaker_threshold: None,
+            circuit_breaker_cooldown: 30,
+            fetch_retries: 0,
+            per_host_concurrency: None,
+            upstream_cache_headers: false,
+            stale_while_revalidate: 0,
+            source_cache_capacity: None,
+            source_cache_ttl: 300,
+            self_hosts: Vec::new(),
+            trusted_proxies: Vec::new(),
+            allowed_source_ports: Vec::new(),
+            max_source_url_depth: 1,
+            allowed_source_hosts: Vec::new(),
+            blocked_source_hosts: Vec::new(),
+            allow_private_sources: false,
+            min_tls_version: None,
+            allow_invalid_certs: false,
+            local_root: None,
+            s3: None,
+            even_dimensions: false,
+            strip_metadata_default: true,
+            force_srgb: false,
+            use_embedded_thumbnail: false,
+            gif_to_webp: false,
+            default_quality: None,
+            format_quality: HashMap::new(),
+            auto_quality: false,
+            auto_quality_target: DEFAULT_TARGET_SSIM,
+            max_bytes_format_ladder: Vec::new(),
+            error_image_mode: None,
+            fallback_image: None,
+            fallback_ttl: 60,
+            preload_breakpoints: Vec::new(),
         }
     }
 
@@ -106,16 +488,34 @@ impl Config {
             .parse()
             .unwrap_or(0);
         config.workers = if workers == 0 { num_cpus::get() * 2 } else { workers };
+        config.raw_concurrency = env::var(ENV_RAW_CONCURRENCY).ok().and_then(|s| s.parse().ok());
+        config.raw_enforce_limits = parse_bool_setting(
+            ENV_RAW_ENFORCE_LIMITS,
+            &env::var(ENV_RAW_ENFORCE_LIMITS).unwrap_or_default(),
+            true,
+        )?;
 
         let bind_address_raw = env::var(ENV_BIND).unwrap_or_else(|_| "0.0.0.0:3000".to_string());
         config.bind_address = normalize_bind_address(&bind_address_raw);
         config.prometheus_bind_address = env::var(ENV_PROMETHEUS_BIND)
             .ok()
             .map(|value| normalize_bind_address(&value));
+        config.enable_metrics = env::var(ENV_ENABLE_METRICS)
+            .map(|value| value.to_lowercase() != "false")
+            .unwrap_or(true);
+        config.debug_headers = env::var(ENV_DEBUG_HEADERS).unwrap_or_default().to_lowercase() == "true";
         config.timeout = env::var(ENV_TIMEOUT)
             .ok()
             .and_then(|s| s.parse::<u64>().ok())
             .unwrap_or(30);
+        config.max_path_length = env::var(ENV_MAX_PATH_LENGTH)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(8192);
+        config.max_request_body_size = env::var(ENV_MAX_REQUEST_BODY_SIZE)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(1024);
 
         config.allow_unsigned = env::var(ENV_ALLOW_UNSIGNED).unwrap_or_default().to_lowercase() == "true";
         config.allow_security_options =
@@ -126,6 +526,26 @@ impl Config {
         config.allowed_mime_types = env::var(ENV_ALLOWED_MIME_TYPES)
             .ok()
             .map(|s| s.split(',').map(|s| s.to_string()).collect());
+        config.decode_fallback = env::var(ENV_DECODE_FALLBACK).unwrap_or_default().to_lowercase() == "true";
+        config.max_tiff_pages = env::var(ENV_MAX_TIFF_PAGES)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(100);
+        config.max_animation_frames = env::var(ENV_MAX_ANIMATION_FRAMES)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|frames| *frames > 0)
+            .unwrap_or(256);
+        config.max_blur_sigma = env::var(ENV_MAX_BLUR_SIGMA)
+            .ok()
+            .and_then(|s| s.parse::<f32>().ok())
+            .filter(|sigma| sigma.is_finite() && *sigma > 0.0)
+            .unwrap_or(100.0);
+        config.svg_default_size = env::var(ENV_SVG_DEFAULT_SIZE)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|size| *size > 0)
+            .unwrap_or(1024);
         config.download_timeout = env::var(ENV_DOWNLOAD_TIMEOUT)
             .ok()
             .and_then(|s| s.parse::<u64>().ok())
@@ -136,12 +556,151 @@ impl Config {
         config.only_presets = env::var(ENV_ONLY_PRESETS).unwrap_or_default().to_lowercase() == "true";
 
         config.watermark_path = env::var(ENV_WATERMARK_PATH).ok();
+        config.watermark_min_size = env::var(ENV_WATERMARK_MIN_SIZE)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|size| *size > 0);
         config.rate_limit_per_minute = env::var(ENV_RATE_LIMIT_PER_MINUTE)
             .ok()
             .and_then(|s| s.parse::<u32>().ok());
+        config.circuit_breaker_threshold = env::var(ENV_CIRCUIT_BREAKER_THRESHOLD)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok());
+        config.circuit_breaker_cooldown = env::var(ENV_CIRCUIT_BREAKER_COOLDOWN)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(30);
+        config.fetch_retries = env::var(ENV_FETCH_RETRIES)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(0);
+        config.per_host_concurrency = env::var(ENV_PER_HOST_CONCURRENCY)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok());
+        config.upstream_cache_headers =
+            env::var(ENV_UPSTREAM_CACHE_HEADERS).unwrap_or_default().to_lowercase() == "true";
+        config.stale_while_revalidate = env::var(ENV_STALE_WHILE_REVALIDATE)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(0);
+        config.source_cache_capacity = env::var(ENV_SOURCE_CACHE_CAPACITY)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok());
+        config.source_cache_ttl = env::var(ENV_SOURCE_CACHE_TTL)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(300);
+        config.local_root = env::var(ENV_LOCAL_ROOT).ok().filter(|s| !s.is_empty());
+        config.s3 = parse_s3_config()?;
+        config.self_hosts = env::var(ENV_SELF_HOSTS)
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        config.trusted_proxies = env::var(ENV_TRUSTED_PROXIES)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<IpAddr>()
+                    .map_err(|e| format!("Invalid trusted proxy address '{}': {}", s, e))
+            })
+            .collect::<Result<_, _>>()?;
+        config.allowed_source_ports = env::var(ENV_ALLOWED_SOURCE_PORTS)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<u16>()
+                    .map_err(|e| format!("Invalid allowed source port '{}': {}", s, e))
+            })
+            .collect::<Result<_, _>>()?;
+        config.max_source_url_depth = env::var(ENV_MAX_SOURCE_URL_DEPTH)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(1);
+        config.allowed_source_hosts = parse_source_host_rules(&env::var(ENV_ALLOWED_SOURCE_HOSTS).unwrap_or_default())?;
+        config.blocked_source_hosts = parse_source_host_rules(&env::var(ENV_BLOCKED_SOURCE_HOSTS).unwrap_or_default())?;
+        config.allow_private_sources = env::var(ENV_ALLOW_PRIVATE_SOURCES).unwrap_or_default().to_lowercase() == "true";
+        config.min_tls_version = parse_min_tls_version(&env::var(ENV_MIN_TLS_VERSION).unwrap_or_default())?;
+        config.allow_invalid_certs = env::var(ENV_ALLOW_INVALID_CERTS).unwrap_or_default().to_lowercase() == "true";
+        config.even_dimensions = env::var(ENV_EVEN_DIMENSIONS).unwrap_or_default().to_lowercase() == "true";
+        config.strip_metadata_default = parse_bool_setting(
+            ENV_STRIP_METADATA,
+            &env::var(ENV_STRIP_METADATA).unwrap_or_default(),
+            true,
+        )?;
+        config.force_srgb = env::var(ENV_FORCE_SRGB).unwrap_or_default().to_lowercase() == "true";
+        config.use_embedded_thumbnail =
+            env::var(ENV_USE_EMBEDDED_THUMBNAIL).unwrap_or_default().to_lowercase() == "true";
+        config.gif_to_webp = env::var(ENV_GIF_TO_WEBP).unwrap_or_default().to_lowercase() == "true";
+        config.default_quality = env::var(ENV_QUALITY)
+            .ok()
+            .map(|value| parse_quality(&value))
+            .transpose()?;
+        config.format_quality = parse_format_quality(&env::var(ENV_FORMAT_QUALITY).unwrap_or_default())?;
+        config.auto_quality = env::var(ENV_AUTO_QUALITY).unwrap_or_default().to_lowercase() == "true";
+        config.auto_quality_target = env::var(ENV_AUTO_QUALITY_TARGET)
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|target| *target > 0.0 && *target <= 1.0)
+            .unwrap_or(DEFAULT_TARGET_SSIM);
+        config.max_bytes_format_ladder =
+            parse_format_ladder(&env::var(ENV_MAX_BYTES_FORMAT_LADDER).unwrap_or_default())?;
+        config.error_image_mode = parse_error_image_mode(&env::var(ENV_ERROR_IMAGE_MODE).unwrap_or_default())?;
+        config.fallback_image = env::var(ENV_FALLBACK_IMAGE).ok().filter(|value| !value.is_empty());
+        config.fallback_ttl = env::var(ENV_FALLBACK_TTL)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(60);
+        config.preload_breakpoints = env::var(ENV_PRELOAD_BREAKPOINTS)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<u32>()
+                    .ok()
+                    .filter(|width| *width > 0)
+                    .ok_or_else(|| format!("Invalid preload breakpoint '{}'", s))
+            })
+            .collect::<Result<_, _>>()?;
 
         Ok(config)
     }
+
+    /// Returns true when `host`/`port` address this imgforge instance.
+    ///
+    /// Matches the configured `self_hosts` (with or without a port) and the bind address; a
+    /// wildcard bind address also matches loopback names on the bound port.
+    pub fn is_self_host(&self, host: &str, port: Option<u16>) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+        let host_port = port.map(|port| format!("{}:{}", host, port));
+
+        let listed = self.self_hosts.iter().any(|entry| {
+            let entry = entry.to_lowercase();
+            entry == host || host_port.as_deref() == Some(entry.as_str())
+        });
+        if listed {
+            return true;
+        }
+
+        let Some((bind_host, bind_port)) = self.bind_address.rsplit_once(':') else {
+            return false;
+        };
+        if port.map(|port| port.to_string()).as_deref() != Some(bind_port) {
+            return false;
+        }
+        let bind_host = bind_host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+        if bind_host == "0.0.0.0" || bind_host == "::" {
+            matches!(host.as_str(), "localhost" | "127.0.0.1" | "::1" | "0.0.0.0")
+        } else {
+            bind_host == host
+        }
+    }
 }
 
 #[cfg(test)]
@@ -252,6 +811,89 @@ mod tests {
         assert!(parse_presets(presets_str).is_err());
     }
 
+    #[test]
+    fn test_parse_format_quality() {
+        let qualities = parse_format_quality("jpg=80, WebP = 70").expect("parses");
+        assert_eq!(qualities.get("jpeg"), Some(&80));
+        assert_eq!(qualities.get("webp"), Some(&70));
+        assert!(parse_format_quality("").expect("parses").is_empty());
+    }
+
+    #[test]
+    fn test_parse_format_ladder() {
+        assert_eq!(
+            parse_format_ladder("JPG, webp,avif").expect("parses"),
+            vec!["jpeg".to_string(), "webp".to_string(), "avif".to_string()]
+        );
+        assert!(parse_format_ladder("").expect("parses").is_empty());
+        assert!(parse_format_ladder("jpeg,bmp").is_err());
+        assert!(parse_format_ladder("jpeg,jpg").is_err());
+    }
+
+    #[test]
+    fn test_parse_min_tls_version() {
+        assert_eq!(parse_min_tls_version("").expect("parses"), None);
+        assert_eq!(
+            parse_min_tls_version("1.2").expect("parses"),
+            Some(reqwest::tls::Version::TLS_1_2)
+        );
+        assert_eq!(
+            parse_min_tls_version(" TLSv1.3 ").expect("parses"),
+            Some(reqwest::tls::Version::TLS_1_3)
+        );
+        assert_eq!(
+            parse_min_tls_version("tls1.0").expect("parses"),
+            Some(reqwest::tls::Version::TLS_1_0)
+        );
+        assert!(parse_min_tls_version("1.4").is_err());
+        assert!(parse_min_tls_version("ssl3").is_err());
+    }
+
+    #[test]
+    fn test_config_tls_settings_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original_version = env::var(ENV_MIN_TLS_VERSION).ok();
+        let original_invalid_certs = env::var(ENV_ALLOW_INVALID_CERTS).ok();
+
+        env::remove_var(ENV_MIN_TLS_VERSION);
+        env::remove_var(ENV_ALLOW_INVALID_CERTS);
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.min_tls_version, None);
+        assert!(!config.allow_invalid_certs);
+
+        env::set_var(ENV_MIN_TLS_VERSION, "1.2");
+        env::set_var(ENV_ALLOW_INVALID_CERTS, "true");
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.min_tls_version, Some(reqwest::tls::Version::TLS_1_2));
+        assert!(config.allow_invalid_certs);
+
+        env::set_var(ENV_MIN_TLS_VERSION, "1.5");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_MIN_TLS_VERSION, original_version);
+        restore_env_var(ENV_ALLOW_INVALID_CERTS, original_invalid_certs);
+    }
+
+    #[test]
+    fn test_parse_error_image_mode() {
+        assert_eq!(parse_error_image_mode("").expect("parses"), None);
+        assert_eq!(parse_error_image_mode("off").expect("parses"), None);
+        assert_eq!(
+            parse_error_image_mode("Status").expect("parses"),
+            Some(ErrorImageMode::KeepStatus)
+        );
+        assert_eq!(parse_error_image_mode("ok").expect("parses"), Some(ErrorImageMode::Ok));
+        assert!(parse_error_image_mode("always").is_err());
+    }
+
+    #[test]
+    fn test_parse_format_quality_invalid() {
+        assert!(parse_format_quality("jpeg").is_err());
+        assert!(parse_format_quality("=80").is_err());
+        assert!(parse_format_quality("jpeg=0").is_err());
+        assert!(parse_format_quality("jpeg=101").is_err());
+    }
+
     #[test]
     fn test_config_presets_from_env() {
         let _guard = ENV_LOCK.lock().unwrap();
@@ -285,4 +927,445 @@ mod tests {
 
         restore_env_var(ENV_ONLY_PRESETS, original_only_presets);
     }
+
+    #[test]
+    fn test_config_trusted_proxies_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original_trusted_proxies = env::var(ENV_TRUSTED_PROXIES).ok();
+
+        env::set_var(ENV_TRUSTED_PROXIES, "10.0.0.1, ::1");
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(
+            config.trusted_proxies,
+            vec!["10.0.0.1".parse::<IpAddr>().unwrap(), "::1".parse::<IpAddr>().unwrap()]
+        );
+
+        env::set_var(ENV_TRUSTED_PROXIES, "10.0.0.0/8");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_TRUSTED_PROXIES, original_trusted_proxies);
+    }
+
+    #[test]
+    fn test_source_host_rule_parse() {
+        assert_eq!(
+            SourceHostRule::parse(" CDN.example.com "),
+            Ok(SourceHostRule::Host("cdn.example.com".to_string()))
+        );
+        assert_eq!(
+            SourceHostRule::parse("*.example.com"),
+            Ok(SourceHostRule::Subdomains("example.com".to_string()))
+        );
+        assert_eq!(
+            SourceHostRule::parse("10.0.0.0/8"),
+            Ok(SourceHostRule::Network {
+                addr: "10.0.0.0".parse().unwrap(),
+                prefix: 8
+            })
+        );
+        assert_eq!(
+            SourceHostRule::parse("[::1]"),
+            Ok(SourceHostRule::Network {
+                addr: "::1".parse().unwrap(),
+                prefix: 128
+            })
+        );
+        for invalid in [
+            "10.0.0.0/33",
+            "fd00::/129",
+            "example.com/8",
+            "*.",
+            "a*.example.com",
+            "host:80",
+        ] {
+            assert!(SourceHostRule::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_source_host_rule_matches() {
+        let host = SourceHostRule::parse("cdn.example.com").unwrap();
+        assert!(host.matches_name("CDN.example.com"));
+        assert!(!host.matches_name("img.cdn.example.com"));
+
+        let subdomains = SourceHostRule::parse("*.example.com").unwrap();
+        assert!(subdomains.matches_name("img.example.com"));
+        assert!(subdomains.matches_name("a.b.example.com"));
+        assert!(!subdomains.matches_name("example.com"));
+        assert!(!subdomains.matches_name("badexample.com"));
+
+        let network = SourceHostRule::parse("169.254.0.0/16").unwrap();
+        assert!(network.matches_ip("169.254.169.254".parse().unwrap()));
+        assert!(network.matches_ip("::ffff:169.254.169.254".parse().unwrap()));
+        assert!(!network.matches_ip("169.255.0.1".parse().unwrap()));
+        assert!(!network.matches_name("169.254.169.254"));
+
+        let v6 = SourceHostRule::parse("fd00::/8").unwrap();
+        assert!(v6.matches_ip("fd12::1".parse().unwrap()));
+        assert!(!v6.matches_ip("fe80::1".parse().unwrap()));
+
+        let everything = SourceHostRule::parse("0.0.0.0/0").unwrap();
+        assert!(everything.matches_ip("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_config_source_host_rules_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original_allowed = env::var(ENV_ALLOWED_SOURCE_HOSTS).ok();
+        let original_blocked = env::var(ENV_BLOCKED_SOURCE_HOSTS).ok();
+        let original_private = env::var(ENV_ALLOW_PRIVATE_SOURCES).ok();
+
+        env::remove_var(ENV_ALLOWED_SOURCE_HOSTS);
+        env::remove_var(ENV_BLOCKED_SOURCE_HOSTS);
+        env::remove_var(ENV_ALLOW_PRIVATE_SOURCES);
+        let config = Config::from_env().expect("config loads");
+        assert!(config.allowed_source_hosts.is_empty());
+        assert!(config.blocked_source_hosts.is_empty());
+        assert!(!config.allow_private_sources);
+
+        env::set_var(ENV_ALLOWED_SOURCE_HOSTS, "*.example.com, 10.1.0.0/16");
+        env::set_var(ENV_BLOCKED_SOURCE_HOSTS, "private.example.com");
+        env::set_var(ENV_ALLOW_PRIVATE_SOURCES, "TRUE");
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.allowed_source_hosts.len(), 2);
+        assert_eq!(
+            config.blocked_source_hosts,
+            vec![SourceHostRule::Host("private.example.com".to_string())]
+        );
+        assert!(config.allow_private_sources);
+
+        env::set_var(ENV_BLOCKED_SOURCE_HOSTS, "10.0.0.0/99");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_ALLOWED_SOURCE_HOSTS, original_allowed);
+        restore_env_var(ENV_BLOCKED_SOURCE_HOSTS, original_blocked);
+        restore_env_var(ENV_ALLOW_PRIVATE_SOURCES, original_private);
+    }
+
+    #[test]
+    fn test_config_allowed_source_ports_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_ALLOWED_SOURCE_PORTS).ok();
+
+        env::remove_var(ENV_ALLOWED_SOURCE_PORTS);
+        assert!(Config::from_env()
+            .expect("config loads")
+            .allowed_source_ports
+            .is_empty());
+
+        env::set_var(ENV_ALLOWED_SOURCE_PORTS, "80, 443");
+        assert_eq!(
+            Config::from_env().expect("config loads").allowed_source_ports,
+            vec![80, 443]
+        );
+
+        env::set_var(ENV_ALLOWED_SOURCE_PORTS, "443,https");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_ALLOWED_SOURCE_PORTS, original);
+    }
+
+    #[test]
+    fn test_config_preload_breakpoints_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_PRELOAD_BREAKPOINTS).ok();
+
+        env::remove_var(ENV_PRELOAD_BREAKPOINTS);
+        assert!(Config::from_env().expect("config loads").preload_breakpoints.is_empty());
+
+        env::set_var(ENV_PRELOAD_BREAKPOINTS, "320, 640,1280");
+        assert_eq!(
+            Config::from_env().expect("config loads").preload_breakpoints,
+            vec![320, 640, 1280]
+        );
+
+        env::set_var(ENV_PRELOAD_BREAKPOINTS, "320,0");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_PRELOAD_BREAKPOINTS, original);
+    }
+
+    #[test]
+    fn test_config_max_blur_sigma_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_MAX_BLUR_SIGMA).ok();
+
+        env::remove_var(ENV_MAX_BLUR_SIGMA);
+        assert_eq!(Config::from_env().expect("config loads").max_blur_sigma, 100.0);
+
+        env::set_var(ENV_MAX_BLUR_SIGMA, "25.5");
+        assert_eq!(Config::from_env().expect("config loads").max_blur_sigma, 25.5);
+
+        env::set_var(ENV_MAX_BLUR_SIGMA, "0");
+        assert_eq!(Config::from_env().expect("config loads").max_blur_sigma, 100.0);
+
+        restore_env_var(ENV_MAX_BLUR_SIGMA, original);
+    }
+
+    #[test]
+    fn test_config_max_animation_frames_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_MAX_ANIMATION_FRAMES).ok();
+
+        env::remove_var(ENV_MAX_ANIMATION_FRAMES);
+        assert_eq!(Config::from_env().expect("config loads").max_animation_frames, 256);
+
+        env::set_var(ENV_MAX_ANIMATION_FRAMES, "20");
+        assert_eq!(Config::from_env().expect("config loads").max_animation_frames, 20);
+
+        env::set_var(ENV_MAX_ANIMATION_FRAMES, "0");
+        assert_eq!(Config::from_env().expect("config loads").max_animation_frames, 256);
+
+        restore_env_var(ENV_MAX_ANIMATION_FRAMES, original);
+    }
+
+    #[test]
+    fn test_config_fetch_retries_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_FETCH_RETRIES).ok();
+
+        env::remove_var(ENV_FETCH_RETRIES);
+        assert_eq!(Config::from_env().expect("config loads").fetch_retries, 0);
+
+        env::set_var(ENV_FETCH_RETRIES, "2");
+        assert_eq!(Config::from_env().expect("config loads").fetch_retries, 2);
+
+        env::set_var(ENV_FETCH_RETRIES, "many");
+        assert_eq!(Config::from_env().expect("config loads").fetch_retries, 0);
+
+        restore_env_var(ENV_FETCH_RETRIES, original);
+    }
+
+    #[test]
+    fn test_config_per_host_concurrency_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_PER_HOST_CONCURRENCY).ok();
+
+        env::remove_var(ENV_PER_HOST_CONCURRENCY);
+        assert_eq!(Config::from_env().expect("config loads").per_host_concurrency, None);
+
+        env::set_var(ENV_PER_HOST_CONCURRENCY, "8");
+        assert_eq!(Config::from_env().expect("config loads").per_host_concurrency, Some(8));
+
+        restore_env_var(ENV_PER_HOST_CONCURRENCY, original);
+    }
+
+    #[test]
+    fn test_config_upstream_cache_headers_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_UPSTREAM_CACHE_HEADERS).ok();
+
+        env::remove_var(ENV_UPSTREAM_CACHE_HEADERS);
+        assert!(!Config::from_env().expect("config loads").upstream_cache_headers);
+
+        env::set_var(ENV_UPSTREAM_CACHE_HEADERS, "true");
+        assert!(Config::from_env().expect("config loads").upstream_cache_headers);
+
+        restore_env_var(ENV_UPSTREAM_CACHE_HEADERS, original);
+    }
+
+    #[test]
+    fn test_config_stale_while_revalidate_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_STALE_WHILE_REVALIDATE).ok();
+
+        env::remove_var(ENV_STALE_WHILE_REVALIDATE);
+        assert_eq!(Config::from_env().expect("config loads").stale_while_revalidate, 0);
+
+        env::set_var(ENV_STALE_WHILE_REVALIDATE, "300");
+        assert_eq!(Config::from_env().expect("config loads").stale_while_revalidate, 300);
+
+        restore_env_var(ENV_STALE_WHILE_REVALIDATE, original);
+    }
+
+    #[test]
+    fn test_config_fallback_image_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original_image = env::var(ENV_FALLBACK_IMAGE).ok();
+        let original_ttl = env::var(ENV_FALLBACK_TTL).ok();
+
+        env::remove_var(ENV_FALLBACK_IMAGE);
+        env::remove_var(ENV_FALLBACK_TTL);
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.fallback_image, None);
+        assert_eq!(config.fallback_ttl, 60);
+
+        env::set_var(ENV_FALLBACK_IMAGE, "/srv/images/missing.png");
+        env::set_var(ENV_FALLBACK_TTL, "10");
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.fallback_image.as_deref(), Some("/srv/images/missing.png"));
+        assert_eq!(config.fallback_ttl, 10);
+
+        restore_env_var(ENV_FALLBACK_IMAGE, original_image);
+        restore_env_var(ENV_FALLBACK_TTL, original_ttl);
+    }
+
+    #[test]
+    fn test_config_local_root_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_LOCAL_ROOT).ok();
+
+        env::remove_var(ENV_LOCAL_ROOT);
+        assert_eq!(Config::from_env().expect("config loads").local_root, None);
+
+        env::set_var(ENV_LOCAL_ROOT, "/srv/images");
+        assert_eq!(
+            Config::from_env().expect("config loads").local_root.as_deref(),
+            Some("/srv/images")
+        );
+
+        restore_env_var(ENV_LOCAL_ROOT, original);
+    }
+
+    #[test]
+    fn test_config_s3_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let keys = [
+            ENV_S3_ENDPOINT,
+            ENV_S3_REGION,
+            ENV_S3_BUCKET,
+            ENV_S3_ACCESS_KEY_ID,
+            ENV_S3_SECRET_ACCESS_KEY,
+            ENV_S3_SESSION_TOKEN,
+        ];
+        let originals: Vec<_> = keys.iter().map(|key| env::var(key).ok()).collect();
+        for key in keys {
+            env::remove_var(key);
+        }
+
+        assert_eq!(Config::from_env().expect("config loads").s3, None);
+
+        env::set_var(ENV_S3_REGION, "eu-central-1");
+        let s3 = Config::from_env().expect("config loads").s3.expect("region enables S3");
+        assert_eq!(s3.endpoint, "https://s3.eu-central-1.amazonaws.com");
+        assert_eq!(s3.credentials, None);
+
+        env::set_var(ENV_S3_ENDPOINT, "http://minio:9000/");
+        env::set_var(ENV_S3_BUCKET, "images");
+        env::set_var(ENV_S3_ACCESS_KEY_ID, "AKID");
+        env::set_var(ENV_S3_SECRET_ACCESS_KEY, "secret");
+        let s3 = Config::from_env()
+            .expect("config loads")
+            .s3
+            .expect("endpoint enables S3");
+        assert_eq!(s3.endpoint, "http://minio:9000");
+        assert_eq!(s3.region, "eu-central-1");
+        assert_eq!(s3.bucket.as_deref(), Some("images"));
+        assert_eq!(
+            s3.credentials,
+            Some(S3Credentials {
+                access_key_id: "AKID".to_string(),
+                secret_access_key: "secret".to_string(),
+                session_token: None,
+            })
+        );
+
+        env::remove_var(ENV_S3_SECRET_ACCESS_KEY);
+        assert!(Config::from_env().is_err());
+        env::set_var(ENV_S3_SECRET_ACCESS_KEY, "secret");
+        env::set_var(ENV_S3_ENDPOINT, "ftp://minio");
+        assert!(Config::from_env().is_err());
+
+        for (key, original) in keys.into_iter().zip(originals) {
+            restore_env_var(key, original);
+        }
+    }
+
+    #[test]
+    fn test_config_raw_enforce_limits_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_RAW_ENFORCE_LIMITS).ok();
+
+        env::remove_var(ENV_RAW_ENFORCE_LIMITS);
+        assert!(Config::from_env().expect("config loads").raw_enforce_limits);
+
+        env::set_var(ENV_RAW_ENFORCE_LIMITS, "false");
+        assert!(!Config::from_env().expect("config loads").raw_enforce_limits);
+
+        env::set_var(ENV_RAW_ENFORCE_LIMITS, "no");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_RAW_ENFORCE_LIMITS, original);
+    }
+
+    #[test]
+    fn test_config_strip_metadata_default_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_STRIP_METADATA).ok();
+
+        env::remove_var(ENV_STRIP_METADATA);
+        assert!(Config::from_env().expect("config loads").strip_metadata_default);
+
+        env::set_var(ENV_STRIP_METADATA, "false");
+        assert!(!Config::from_env().expect("config loads").strip_metadata_default);
+
+        env::set_var(ENV_STRIP_METADATA, "TRUE");
+        assert!(Config::from_env().expect("config loads").strip_metadata_default);
+
+        // A typo must not quietly turn stripping off
+        env::set_var(ENV_STRIP_METADATA, "flase");
+        assert!(Config::from_env().is_err());
+
+        restore_env_var(ENV_STRIP_METADATA, original);
+    }
+
+    #[test]
+    fn test_config_svg_default_size_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original = env::var(ENV_SVG_DEFAULT_SIZE).ok();
+
+        env::remove_var(ENV_SVG_DEFAULT_SIZE);
+        assert_eq!(Config::from_env().expect("config loads").svg_default_size, 1024);
+
+        env::set_var(ENV_SVG_DEFAULT_SIZE, "300");
+        assert_eq!(Config::from_env().expect("config loads").svg_default_size, 300);
+
+        env::set_var(ENV_SVG_DEFAULT_SIZE, "0");
+        assert_eq!(Config::from_env().expect("config loads").svg_default_size, 1024);
+
+        restore_env_var(ENV_SVG_DEFAULT_SIZE, original);
+    }
+
+    #[test]
+    fn test_config_request_limits_from_env() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        let original_path_length = env::var(ENV_MAX_PATH_LENGTH).ok();
+        let original_body_size = env::var(ENV_MAX_REQUEST_BODY_SIZE).ok();
+
+        env::remove_var(ENV_MAX_PATH_LENGTH);
+        env::remove_var(ENV_MAX_REQUEST_BODY_SIZE);
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.max_path_length, 8192);
+        assert_eq!(config.max_request_body_size, 1024);
+
+        env::set_var(ENV_MAX_PATH_LENGTH, "2048");
+        env::set_var(ENV_MAX_REQUEST_BODY_SIZE, "0");
+        let config = Config::from_env().expect("config loads");
+        assert_eq!(config.max_path_length, 2048);
+        assert_eq!(config.max_request_body_size, 0);
+
+        restore_env_var(ENV_MAX_PATH_LENGTH, original_path_length);
+        restore_env_var(ENV_MAX_REQUEST_BODY_SIZE, original_body_size);
+    }
+
+    #[test]
+    fn test_is_self_host_matches_loopback_on_wildcard_bind() {
+        let config = Config::new(vec![], vec![]);
+
+        assert!(config.is_self_host("127.0.0.1", Some(3000)));
+        assert!(config.is_self_host("localhost", Some(3000)));
+        assert!(config.is_self_host("[::1]", Some(3000)));
+        assert!(!config.is_self_host("127.0.0.1", Some(8080)));
+        assert!(!config.is_self_host("example.com", Some(3000)));
+    }
+
+    #[test]
+    fn test_is_self_host_matches_configured_hosts() {
+        let mut config = Config::new(vec![], vec![]);
+        config.self_hosts = vec!["img.example.com".to_string(), "cdn.example.com:8443".to_string()];
+
+        assert!(config.is_self_host("IMG.example.com", Some(443)));
+        assert!(config.is_self_host("cdn.example.com", Some(8443)));
+        assert!(!config.is_self_host("cdn.example.com", Some(443)));
+    }
 }
@@ -8,16 +8,66 @@ pub const ENV_ALLOWED_MIME_TYPES: &str = "IMGFORGE_ALLOWED_MIME_TYPES";
 pub const ENV_MAX_SRC_RESOLUTION: &str = "IMGFORGE_MAX_SRC_RESOLUTION";
 pub const ENV_ALLOW_SECURITY_OPTIONS: &str = "IMGFORGE_ALLOW_SECURITY_OPTIONS";
 pub const ENV_WORKERS: &str = "IMGFORGE_WORKERS";
+pub const ENV_RAW_CONCURRENCY: &str = "IMGFORGE_RAW_CONCURRENCY";
+pub const ENV_RAW_ENFORCE_LIMITS: &str = "IMGFORGE_RAW_ENFORCE_LIMITS";
+pub const ENV_DECODE_FALLBACK: &str = "IMGFORGE_DECODE_FALLBACK";
+pub const ENV_MAX_TIFF_PAGES: &str = "IMGFORGE_MAX_TIFF_PAGES";
+pub const ENV_MAX_ANIMATION_FRAMES: &str = "IMGFORGE_MAX_ANIMATION_FRAMES";
+pub const ENV_SVG_DEFAULT_SIZE: &str = "IMGFORGE_SVG_DEFAULT_SIZE";
+pub const ENV_EVEN_DIMENSIONS: &str = "IMGFORGE_EVEN_DIMENSIONS";
+pub const ENV_STRIP_METADATA: &str = "IMGFORGE_STRIP_METADATA";
+pub const ENV_FORCE_SRGB: &str = "IMGFORGE_FORCE_SRGB";
+pub const ENV_USE_EMBEDDED_THUMBNAIL: &str = "IMGFORGE_USE_EMBEDDED_THUMBNAIL";
+pub const ENV_GIF_TO_WEBP: &str = "IMGFORGE_GIF_TO_WEBP";
+pub const ENV_QUALITY: &str = "IMGFORGE_QUALITY";
+pub const ENV_FORMAT_QUALITY: &str = "IMGFORGE_FORMAT_QUALITY";
+pub const ENV_AUTO_QUALITY: &str = "IMGFORGE_AUTO_QUALITY";
+pub const ENV_AUTO_QUALITY_TARGET: &str = "IMGFORGE_AUTO_QUALITY_TARGET";
+pub const ENV_MAX_BYTES_FORMAT_LADDER: &str = "IMGFORGE_MAX_BYTES_FORMAT_LADDER";
+pub const ENV_ERROR_IMAGE_MODE: &str = "IMGFORGE_ERROR_IMAGE_MODE";
+pub const ENV_FALLBACK_IMAGE: &str = "IMGFORGE_FALLBACK_IMAGE";
+pub const ENV_FALLBACK_TTL: &str = "IMGFORGE_FALLBACK_TTL";
+pub const ENV_PRELOAD_BREAKPOINTS: &str = "IMGFORGE_PRELOAD_BREAKPOINTS";
 
 pub const ENV_CACHE_TYPE: &str = "IMGFORGE_CACHE_TYPE";
 pub const ENV_CACHE_MEMORY_CAPACITY: &str = "IMGFORGE_CACHE_MEMORY_CAPACITY";
 pub const ENV_CACHE_DISK_PATH: &str = "IMGFORGE_CACHE_DISK_PATH";
 pub const ENV_CACHE_DISK_CAPACITY: &str = "IMGFORGE_CACHE_DISK_CAPACITY";
+pub const ENV_SOURCE_CACHE_CAPACITY: &str = "IMGFORGE_SOURCE_CACHE_CAPACITY";
+pub const ENV_SOURCE_CACHE_TTL: &str = "IMGFORGE_SOURCE_CACHE_TTL";
 pub const ENV_WATERMARK_PATH: &str = "IMGFORGE_WATERMARK_PATH";
+pub const ENV_WATERMARK_MIN_SIZE: &str = "IMGFORGE_WATERMARK_MIN_SIZE";
+pub const ENV_MAX_BLUR_SIGMA: &str = "IMGFORGE_MAX_BLUR_SIGMA";
 pub const ENV_PROMETHEUS_BIND: &str = "IMGFORGE_PROMETHEUS_BIND";
+pub const ENV_DEBUG_HEADERS: &str = "IMGFORGE_DEBUG_HEADERS";
+pub const ENV_ENABLE_METRICS: &str = "IMGFORGE_ENABLE_METRICS";
 pub const ENV_BIND: &str = "IMGFORGE_BIND";
 pub const ENV_TIMEOUT: &str = "IMGFORGE_TIMEOUT";
 pub const ENV_DOWNLOAD_TIMEOUT: &str = "IMGFORGE_DOWNLOAD_TIMEOUT";
+pub const ENV_MAX_PATH_LENGTH: &str = "IMGFORGE_MAX_PATH_LENGTH";
+pub const ENV_MAX_REQUEST_BODY_SIZE: &str = "IMGFORGE_MAX_REQUEST_BODY_SIZE";
 pub const ENV_RATE_LIMIT_PER_MINUTE: &str = "IMGFORGE_RATE_LIMIT_PER_MINUTE";
+pub const ENV_CIRCUIT_BREAKER_THRESHOLD: &str = "IMGFORGE_CIRCUIT_BREAKER_THRESHOLD";
+pub const ENV_CIRCUIT_BREAKER_COOLDOWN: &str = "IMGFORGE_CIRCUIT_BREAKER_COOLDOWN";
+pub const ENV_FETCH_RETRIES: &str = "IMGFORGE_FETCH_RETRIES";
+pub const ENV_PER_HOST_CONCURRENCY: &str = "IMGFORGE_PER_HOST_CONCURRENCY";
+pub const ENV_UPSTREAM_CACHE_HEADERS: &str = "IMGFORGE_UPSTREAM_CACHE_HEADERS";
+pub const ENV_STALE_WHILE_REVALIDATE: &str = "IMGFORGE_STALE_WHILE_REVALIDATE";
+pub const ENV_LOCAL_ROOT: &str = "IMGFORGE_LOCAL_ROOT";
+pub const ENV_S3_ENDPOINT: &str = "IMGFORGE_S3_ENDPOINT";
+pub const ENV_S3_REGION: &str = "IMGFORGE_S3_REGION";
+pub const ENV_S3_BUCKET: &str = "IMGFORGE_S3_BUCKET";
+pub const ENV_S3_ACCESS_KEY_ID: &str = "IMGFORGE_S3_ACCESS_KEY_ID";
+pub const ENV_S3_SECRET_ACCESS_KEY: &str = "IMGFORGE_S3_SECRET_ACCESS_KEY";
+pub const ENV_S3_SESSION_TOKEN: &str = "IMGFORGE_S3_SESSION_TOKEN";
+pub const ENV_SELF_HOSTS: &str = "IMGFORGE_SELF_HOSTS";
+pub const ENV_TRUSTED_PROXIES: &str = "IMGFORGE_TRUSTED_PROXIES";
+pub const ENV_ALLOWED_SOURCE_PORTS: &str = "IMGFORGE_ALLOWED_SOURCE_PORTS";
+pub const ENV_MAX_SOURCE_URL_DEPTH: &str = "IMGFORGE_MAX_SOURCE_URL_DEPTH";
+pub const ENV_ALLOWED_SOURCE_HOSTS: &str = "IMGFORGE_ALLOWED_SOURCE_HOSTS";
+pub const ENV_BLOCKED_SOURCE_HOSTS: &str = "IMGFORGE_BLOCKED_SOURCE_HOSTS";
+pub const ENV_ALLOW_PRIVATE_SOURCES: &str = "IMGFORGE_ALLOW_PRIVATE_SOURCES";
+pub const ENV_MIN_TLS_VERSION: &str = "IMGFORGE_MIN_TLS_VERSION";
+pub const ENV_ALLOW_INVALID_CERTS: &str = "IMGFORGE_ALLOW_INVALID_CERTS";
 pub const ENV_PRESETS: &str = "IMGFORGE_PRESETS";
 pub const ENV_ONLY_PRESETS: &str = "IMGFORGE_ONLY_PRESETS";
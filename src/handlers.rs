@@ -9,6 +9,13 @@ use serde_json::json;
 use std::sync::Arc;
 use tracing::error;
 
+/// Response header carrying the low-quality placeholder requested with the `lqip` option.
+const LQIP_HEADER: &str = "X-Imgforge-LQIP";
+/// Response header listing the presets applied to the request, `default` first.
+const PRESET_HEADER: &str = "X-Imgforge-Preset";
+/// Response header carrying the processing time in milliseconds when debug headers are enabled.
+const DURATION_HEADER: &str = "X-Imgforge-Duration-ms";
+
 /// Handles the /status endpoint, returning a simple JSON status.
 pub async fn status_handler() -> impl IntoResponse {
     (StatusCode::OK, Json(json!({"status": "ok"})))
@@ -27,12 +34,13 @@ pub async fn info_handler(
         ProcessRequest {
             path: &path,
             bearer_token: bearer.as_deref(),
+            accept: None,
         },
     )
     .await
     {
         Ok(info) => {
-            let response = json!({
+            let mut response = json!({
                 "width": info.width,
                 "height": info.height,
                 "format": info.format,
@@ -41,7 +49,17 @@ pub async fn info_handler(
                 "channels": info.channels,
                 "has_alpha": info.has_alpha,
                 "orientation": info.orientation,
+                "animated": info.animated,
             });
+            if let Some(source_metadata) = info.source_metadata {
+                response["metadata"] = json!({
+                    "has_icc_profile": source_metadata.has_icc_profile,
+                    "date_time": source_metadata.date_time,
+                    "make": source_metadata.make,
+                    "model": source_metadata.model,
+                    "has_gps": source_metadata.has_gps,
+                });
+            }
             (StatusCode::OK, Json(response)).into_response()
         }
         Err(err) => {
@@ -51,19 +69,65 @@ pub async fn info_handler(
     }
 }
 
+/// Handles the /srcset/{*path} endpoint, returning request paths for several widths of an image.
+pub async fn srcset_handler(
+    State(state): State<Arc<AppState>>,
+    Path(path): Path<String>,
+    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+) -> impl IntoResponse {
+    let bearer = auth_header.map(|TypedHeader(auth)| auth.token().to_string());
+
+    match service::srcset_manifest(
+        &state.config,
+        ProcessRequest {
+            path: &path,
+            bearer_token: bearer.as_deref(),
+            accept: None,
+        },
+    ) {
+        Ok(manifest) => {
+            let images: Vec<_> = manifest
+                .images
+                .iter()
+                .map(|image| json!({"width": image.width, "path": image.path}))
+                .collect();
+            let response = json!({
+                "srcset": manifest.srcset(),
+                "images": images,
+            });
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            error!("Srcset handler error path={} error={}", path, err);
+            (err.status(), err.message().to_string()).into_response()
+        }
+    }
+}
+
 /// Handles the main image processing endpoint.
 pub async fn image_forge_handler(
     State(state): State<Arc<AppState>>,
     Path(path): Path<String>,
     auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+    request_headers: header::HeaderMap,
 ) -> impl IntoResponse {
     let bearer = auth_header.map(|TypedHeader(auth)| auth.token().to_string());
+    let accept = request_headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let debug_headers = state.config.debug_headers;
+    let upstream_cache_headers = state.config.upstream_cache_headers;
+    let if_none_match = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
 
     match service::process_path(
         state,
         ProcessRequest {
             path: &path,
             bearer_token: bearer.as_deref(),
+            accept,
         },
     )
     .await
@@ -77,8 +141,79 @@ pub async fn image_forge_handler(
                     HeaderValue::from_static(CacheStatus::Hit.as_header_value()),
                 );
             }
+            if result.vary_accept {
+                headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+            }
+            if !result.presets.is_empty() {
+                if let Ok(presets) = HeaderValue::from_str(&result.presets.join(", ")) {
+                    headers.insert(PRESET_HEADER, presets);
+                }
+            }
+            if !result.preload_links.is_empty() {
+                let links = result
+                    .preload_links
+                    .iter()
+                    .map(|path| format!("<{}>; rel=preload; as=image", path))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if let Ok(links) = HeaderValue::from_str(&links) {
+                    headers.insert(header::LINK, links);
+                }
+            }
+            if let Some(lqip) = result.lqip.as_deref().and_then(|uri| HeaderValue::from_str(uri).ok()) {
+                headers.insert(LQIP_HEADER, lqip);
+            }
+            if let Some(duration) = result.processing_duration.filter(|_| debug_headers) {
+                headers.insert(DURATION_HEADER, HeaderValue::from(duration.as_millis() as u64));
+            }
+
+            // Error images stand in for a failure, so they are never validated or cached downstream;
+            // fallback images only briefly, in case the source is fixed
+            if result.fallback {
+                if let Some(cache_control) = result
+                    .cache_control
+                    .as_deref()
+                    .and_then(|value| HeaderValue::from_str(value).ok())
+                {
+                    headers.insert(header::CACHE_CONTROL, cache_control);
+                }
+            } else if upstream_cache_headers && result.status == StatusCode::OK && !result.is_error_image {
+                if let Some(cache_control) = result
+                    .cache_control
+                    .as_deref()
+                    .and_then(|value| HeaderValue::from_str(value).ok())
+                {
+                    headers.insert(header::CACHE_CONTROL, cache_control);
+                }
+                let etag = service::output_etag(&result.bytes);
+                if let Ok(value) = HeaderValue::from_str(&etag) {
+                    headers.insert(header::ETAG, value);
+                }
+                if if_none_match.is_some_and(|if_none_match| service::etag_matches(&if_none_match, &etag)) {
+                    headers.remove(header::CONTENT_TYPE);
+                    return (StatusCode::NOT_MODIFIED, headers).into_response();
+                }
+            }
+
+            if result.data_uri {
+                return match service::encode_data_uri(&result) {
+                    Ok(inlined) => {
+                        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                        let body = json!({
+                            "data_uri": inlined.data_uri,
+                            "width": inlined.width,
+                            "height": inlined.height,
+                        });
+                        (result.status, headers, Json(body)).into_response()
+                    }
+                    Err(err) => {
+                        error!("Image handler error path={} error={}", path, err);
+                        (err.status(), err.message().to_string()).into_response()
+                    }
+                };
+            }
 
-            (StatusCode::OK, headers, result.bytes).into_response()
+            (result.status, headers, result.bytes).into_response()
         }
         Err(err) => {
             error!("Image handler error path={} error={}", path, err);
@@ -2,10 +2,12 @@ use crate::app::AppState;
 use crate::monitoring::increment_status_code;
 use crate::utils::format_to_content_type;
 use axum::body::Body;
-use axum::extract::State;
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
 use axum::{http::Request, http::StatusCode, middleware::Next, response::Response};
 use rand::distr::Alphanumeric;
 use rand::RngExt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -14,6 +16,10 @@ pub struct RequestId(pub String);
 #[derive(Clone)]
 pub struct OutputFormat(pub String);
 
+/// Address of the client that sent a request, as resolved by `client_ip_middleware`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
 fn generate_request_id() -> String {
     rand::rng()
         .sample_iter(&Alphanumeric)
@@ -59,9 +65,41 @@ pub async fn status_code_metric_middleware(req: Request<Body>, next: Next) -> Re
     response
 }
 
+/// Rejects requests whose path and query exceed `max_path_length` with `414 URI Too Long`.
+///
+/// Runs before routing, so oversized URLs never reach signature checks or option parsing.
+pub async fn path_length_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let length = request.uri().path_and_query().map_or(0, |path| path.as_str().len());
+    if length > state.config.max_path_length {
+        return Response::builder()
+            .status(StatusCode::URI_TOO_LONG)
+            .body(Body::from("URI Too Long"))
+            .unwrap();
+    }
+    next.run(request).await
+}
+
+/// Client buckets kept before idle ones are dropped from the rate limiter.
+const MAX_RATE_LIMITED_CLIENTS: usize = 10_000;
+
+/// Limits each client, keyed by its `ClientIp`, to `IMGFORGE_RATE_LIMIT_PER_MINUTE` requests.
+///
+/// Requests without a resolved client address share a single bucket.
 pub async fn rate_limit_middleware(State(state): State<Arc<AppState>>, request: Request<Body>, next: Next) -> Response {
     if let Some(rate_limiter) = &state.rate_limiter {
-        match rate_limiter.check() {
+        let client_ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ip| ip.0);
+        // Buckets that have refilled behave like new ones, so dropping them loses no state
+        if rate_limiter.len() > MAX_RATE_LIMITED_CLIENTS {
+            rate_limiter.retain_recent();
+        }
+        match rate_limiter.check_key(&client_ip) {
             Ok(_) => next.run(request).await,
             Err(_) => Response::builder()
                 .status(StatusCode::TOO_MANY_REQUESTS)
@@ -73,3 +111,81 @@ pub async fn rate_limit_middleware(State(state): State<Arc<AppState>>, request:
         next.run(request).await
     }
 }
+
+/// Resolves the client address and stores it as a `ClientIp` request extension, which the rate
+/// limiter and request logs use.
+///
+/// Requests served without connection info (for example in tests) get no extension.
+pub async fn client_ip_middleware(State(state): State<Arc<AppState>>, mut req: Request<Body>, next: Next) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(ip) = resolve_client_ip(req.headers(), peer, &state.config.trusted_proxies) {
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(req).await
+}
+
+/// Returns the client address for a request received from `peer`.
+///
+/// Forwarding headers can be forged by anyone, so they are read only when the peer is one of
+/// `trusted_proxies`. The hop chain (`Forwarded`, else `X-Forwarded-For`) is then walked from the
+/// nearest hop outward, skipping trusted proxies; the first other hop is the client. An
+/// unparseable hop, such as `for=unknown`, stops the walk and the peer address is used instead.
+pub fn resolve_client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    let hops = forwarded_hops(headers);
+    for hop in hops.iter().rev() {
+        match hop {
+            Some(ip) if trusted_proxies.contains(ip) => continue,
+            Some(ip) => return Some(*ip),
+            None => return Some(peer),
+        }
+    }
+
+    // Every hop is a trusted proxy, so the outermost one is the best answer available.
+    Some(hops.first().copied().flatten().unwrap_or(peer))
+}
+
+/// Collects the hop addresses from the `Forwarded` header, or from `X-Forwarded-For` when there is
+/// no `Forwarded` header, in the order the proxies appended them.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded: Vec<_> = headers.get_all("forwarded").iter().collect();
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_hop(value))
+            })
+            .collect();
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+        .map(parse_hop)
+        .collect()
+}
+
+/// Parses one hop address, accepting optional quotes, ports and IPv6 brackets.
+fn parse_hop(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split_once(']').and_then(|(ip, _)| ip.parse().ok());
+    }
+    value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
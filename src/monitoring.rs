@@ -15,11 +15,27 @@ pub fn register_metrics() {
             Unit::Seconds,
             "Source image fetch duration in seconds"
         );
+        describe_histogram!(
+            "imgforge_resize_scale",
+            "Scale factor applied by resize operations; values below 1 are downscales"
+        );
         describe_counter!("processed_images_total", "Total number of processed images");
+        describe_counter!(
+            "imgforge_requests_total",
+            "Total number of authorized image requests by URL authentication and option source"
+        );
         describe_counter!("source_images_fetched_total", "Total number of source images fetched");
         describe_counter!("cache_hits_total", "Total number of cache hits");
         describe_counter!("cache_misses_total", "Total number of cache misses");
         describe_counter!("status_codes_total", "Total number of response status codes");
+        describe_counter!(
+            "circuit_breaker_rejections_total",
+            "Total number of source fetches rejected by an open origin circuit breaker"
+        );
+        describe_gauge!(
+            "circuit_breaker_open_hosts",
+            "Number of origin hosts whose circuit breaker is currently open"
+        );
         describe_gauge!(
             "vips_tracked_mem_bytes",
             Unit::Bytes,
@@ -39,11 +55,22 @@ pub fn observe_image_processing_duration(format: &str, duration_seconds: f64) {
     metrics::histogram!("image_processing_duration_seconds", "format" => format_label).record(duration_seconds);
 }
 
+/// Records the scale factor of a resize, using the smaller axis for aspect-changing resizes.
+pub fn observe_resize_scale(scale: f64) {
+    metrics::histogram!("imgforge_resize_scale").record(scale);
+}
+
 pub fn increment_processed_images(format: &str) {
     let format_label = format.to_owned();
     metrics::counter!("processed_images_total", "format" => format_label).increment(1);
 }
 
+/// Counts an authorized image request. `auth` is `signed` or `unsigned`, and `kind` is `preset`
+/// when the URL references a preset and `inline` otherwise.
+pub fn increment_requests(auth: &'static str, kind: &'static str) {
+    metrics::counter!("imgforge_requests_total", "auth" => auth, "kind" => kind).increment(1);
+}
+
 pub fn observe_source_image_fetch_duration(duration_seconds: f64) {
     metrics::histogram!("source_image_fetch_duration_seconds").record(duration_seconds);
 }
@@ -68,6 +95,14 @@ pub fn increment_status_code(status: &str) {
     metrics::counter!("status_codes_total", "status" => status_label).increment(1);
 }
 
+pub fn increment_circuit_breaker_rejections() {
+    metrics::counter!("circuit_breaker_rejections_total").increment(1);
+}
+
+pub fn set_circuit_breaker_open_hosts(open_hosts: usize) {
+    metrics::gauge!("circuit_breaker_open_hosts").set(open_hosts as f64);
+}
+
 pub fn update_vips_metrics(vips_app: &std::sync::Arc<libvips::VipsApp>) {
     metrics::gauge!("vips_tracked_mem_bytes").set(vips_app.tracked_get_mem() as f64);
     metrics::gauge!("vips_tracked_mem_highwater_bytes").set(vips_app.tracked_get_mem_highwater() as f64);
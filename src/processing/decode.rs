@@ -0,0 +1,357 @@
+use crate::processing::error::ProcessingError;
+use exif::{In, Tag};
+use libvips::{ops, VipsImage};
+use std::io::Cursor;
+use tracing::{debug, warn};
+
+/// Relative aspect-ratio difference tolerated between an embedded thumbnail and its source.
+const THUMBNAIL_ASPECT_TOLERANCE: f64 = 0.02;
+/// Most frames laid out in a contact sheet; later frames are left out.
+pub const MAX_CONTACT_SHEET_FRAMES: u32 = 64;
+/// DCT scale factors the JPEG loader can shrink by while decoding, largest first.
+const JPEG_SHRINK_FACTORS: [u32; 3] = [8, 4, 2];
+/// Source formats that can be decoded with a forced loader, see [`load_with_format`].
+pub const SOURCE_FORMATS: [&str; 8] = ["jpeg", "png", "webp", "gif", "tiff", "heif", "avif", "svg"];
+
+/// Decodes source bytes with libvips, optionally retrying with the pure-Rust `image` crate.
+///
+/// The fallback covers formats the local libvips build cannot load (for example QOI or TGA).
+/// Decoded pixels are copied into a libvips-owned buffer, so the returned image does not borrow
+/// from any temporary allocation.
+pub fn load_image(image_bytes: &[u8], fallback: bool) -> Result<VipsImage, ProcessingError> {
+    let vips_error = match VipsImage::new_from_buffer(image_bytes, "") {
+        Ok(img) => return Ok(img),
+        Err(e) => e,
+    };
+
+    if !fallback {
+        return Err(ProcessingError::Decode(format!(
+            "Error loading image from memory: {}",
+            vips_error
+        )));
+    }
+
+    warn!(
+        "libvips failed to decode source ({}), trying image crate fallback",
+        vips_error
+    );
+    decode_with_image_crate(image_bytes).map_err(|e| {
+        ProcessingError::Decode(format!(
+            "Error loading image from memory: {} (fallback decode failed: {})",
+            vips_error, e
+        ))
+    })
+}
+
+/// Reads the source dimensions from the image header without decoding any pixels.
+///
+/// libvips parses only the header when an image is opened, and sequential access stops formats
+/// such as progressive JPEG from being staged for random access. This lets callers reject
+/// oversized sources before paying for a full decode. With `fallback` enabled, headers libvips
+/// cannot read are parsed by the `image` crate.
+pub fn read_dimensions(image_bytes: &[u8], fallback: bool) -> Result<(u32, u32), ProcessingError> {
+    let vips_error = match VipsImage::new_from_buffer(image_bytes, "access=sequential") {
+        Ok(img) => return Ok((img.get_width() as u32, img.get_height() as u32)),
+        Err(e) => e,
+    };
+
+    if !fallback {
+        return Err(ProcessingError::Decode(format!(
+            "Error reading image header: {}",
+            vips_error
+        )));
+    }
+
+    image::ImageReader::new(Cursor::new(image_bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())
+        .and_then(|reader| reader.into_dimensions().map_err(|e| e.to_string()))
+        .map_err(|e| {
+            ProcessingError::Decode(format!(
+                "Error reading image header: {} (fallback header read failed: {})",
+                vips_error, e
+            ))
+        })
+}
+
+/// Returns whether the source bytes start with a little- or big-endian TIFF signature.
+pub fn is_tiff(image_bytes: &[u8]) -> bool {
+    image_bytes.starts_with(b"II*\0") || image_bytes.starts_with(b"MM\0*")
+}
+
+/// Counts the pages of a multi-page source from its header, without decoding pixels.
+pub fn page_count(image_bytes: &[u8]) -> Result<u32, ProcessingError> {
+    let img = VipsImage::new_from_buffer(image_bytes, "access=sequential")
+        .map_err(|e| ProcessingError::Decode(format!("Error reading image header: {}", e)))?;
+    Ok(img.get_n_pages().max(1) as u32)
+}
+
+/// How far into a document to look for the root `<svg>` element.
+const SVG_SNIFF_LENGTH: usize = 4096;
+
+/// Returns the attributes of the root `<svg>` element, when the source is an SVG document.
+fn svg_root_attributes(image_bytes: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&image_bytes[..image_bytes.len().min(SVG_SNIFF_LENGTH)]);
+    let trimmed = head.trim_start_matches('\u{feff}').trim_start();
+    if !trimmed.starts_with('<') {
+        return None;
+    }
+    let start = trimmed.find("<svg")? + "<svg".len();
+    let end = start + trimmed[start..].find('>')?;
+    Some(trimmed[start..end].to_string())
+}
+
+/// Returns whether the source is an SVG document.
+pub fn is_svg(image_bytes: &[u8]) -> bool {
+    svg_root_attributes(image_bytes).is_some()
+}
+
+/// Returns whether the source is an SVG whose root element sets neither both `width` and `height`
+/// nor a `viewBox`, leaving its rasterized size up to the renderer.
+pub fn is_dimensionless_svg(image_bytes: &[u8]) -> bool {
+    let Some(attributes) = svg_root_attributes(image_bytes) else {
+        return false;
+    };
+    let has = |name: &str| {
+        attributes
+            .split(|c: char| c.is_ascii_whitespace())
+            .any(|token| token.strip_prefix(name).is_some_and(|rest| rest.starts_with('=')))
+    };
+    !(has("viewBox") || (has("width") && has("height")))
+}
+
+/// Rasterizes an SVG large enough to cover `width` x `height`, re-rendering the vector data at that
+/// scale rather than enlarging the renderer's default raster. A zero dimension is unconstrained.
+///
+/// The raster is scaled down to at most `max_pixels` pixels when a limit is given, since the
+/// resolution check only saw the SVG's intrinsic size.
+pub fn load_svg_at_size(
+    image_bytes: &[u8],
+    width: u32,
+    height: u32,
+    max_pixels: Option<f64>,
+) -> Result<VipsImage, ProcessingError> {
+    let header = VipsImage::new_from_buffer(image_bytes, "")
+        .map_err(|e| ProcessingError::Decode(format!("Error loading SVG: {}", e)))?;
+    let (header_width, header_height) = (header.get_width().max(1) as f64, header.get_height().max(1) as f64);
+    let scale_for = |target: u32, size: f64| (target > 0).then(|| target as f64 / size);
+    let Some(scale) = [scale_for(width, header_width), scale_for(height, header_height)]
+        .into_iter()
+        .flatten()
+        .reduce(f64::max)
+    else {
+        return Ok(header);
+    };
+    let scale = match max_pixels {
+        Some(max_pixels) => scale.min((max_pixels / (header_width * header_height)).sqrt()),
+        None => scale,
+    };
+    VipsImage::new_from_buffer(image_bytes, &format!("scale={}", scale))
+        .map_err(|e| ProcessingError::Decode(format!("Error rasterizing SVG: {}", e)))
+}
+
+/// Returns whether the source is a GIF with more than one frame.
+pub fn is_animated_gif(image_bytes: &[u8]) -> bool {
+    image_bytes.starts_with(b"GIF8") && page_count(image_bytes).is_ok_and(|pages| pages > 1)
+}
+
+/// Returns whether the source is a GIF or WebP with more than one frame.
+pub fn is_animated(image_bytes: &[u8]) -> bool {
+    let is_webp = image_bytes.len() >= 12 && image_bytes.starts_with(b"RIFF") && &image_bytes[8..12] == b"WEBP";
+    (image_bytes.starts_with(b"GIF8") || is_webp) && page_count(image_bytes).is_ok_and(|pages| pages > 1)
+}
+
+/// Decodes the first `frames` frames of an animated source into one image, with frames stacked
+/// vertically.
+///
+/// The loader records the frame height, delays and loop count as metadata on the result.
+pub fn load_animation(image_bytes: &[u8], frames: u32) -> Result<VipsImage, ProcessingError> {
+    VipsImage::new_from_buffer(image_bytes, &format!("n={}", frames.max(1)))
+        .map_err(|e| ProcessingError::Decode(format!("Error loading animation frames: {}", e)))
+}
+
+/// Lays the frames of an animated source out in a static grid `columns` frames wide.
+///
+/// At most `MAX_CONTACT_SHEET_FRAMES` frames are decoded. Cells of a partial last row stay
+/// transparent black. The grid is rebuilt from bare pixels, so it carries no page metadata and
+/// is processed as a single still image.
+pub fn load_contact_sheet(image_bytes: &[u8], columns: u32) -> Result<VipsImage, ProcessingError> {
+    let frame_count = page_count(image_bytes)?.min(MAX_CONTACT_SHEET_FRAMES);
+    let strip = VipsImage::new_from_buffer(image_bytes, &format!("n={}", frame_count))
+        .map_err(|e| ProcessingError::Decode(format!("Error loading animation frames: {}", e)))?;
+    let (width, frame_height) = (strip.get_width(), strip.get_height() / frame_count as i32);
+
+    let mut frames = (0..frame_count as i32)
+        .map(|i| {
+            ops::extract_area(&strip, 0, i * frame_height, width, frame_height)
+                .map_err(|e| ProcessingError::Vips(format!("Error extracting animation frame {}: {}", i, e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let sheet = ops::arrayjoin_with_opts(
+        &mut frames,
+        &ops::ArrayjoinOptions {
+            across: columns.min(frame_count) as i32,
+            background: vec![0.0; strip.get_bands() as usize],
+            hspacing: width,
+            vspacing: frame_height,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| ProcessingError::Vips(format!("Error joining contact sheet: {}", e)))?;
+    debug!(
+        "Laid out {} frames of {}x{} in {} columns",
+        frame_count, width, frame_height, columns
+    );
+
+    let format = sheet
+        .get_format()
+        .map_err(|e| ProcessingError::Vips(format!("Error reading contact sheet format: {}", e)))?;
+    let pixels = sheet.image_write_to_memory();
+    let bare = VipsImage::new_from_memory(
+        &pixels,
+        sheet.get_width(),
+        sheet.get_height(),
+        sheet.get_bands(),
+        format,
+    )
+    .map_err(|e| ProcessingError::Vips(format!("Error wrapping contact sheet: {}", e)))?;
+    VipsImage::image_copy_memory(bare).map_err(|e| ProcessingError::Vips(format!("Error copying contact sheet: {}", e)))
+}
+
+/// Decodes a single zero-based page of a multi-page TIFF through the loader's `page` option.
+pub fn load_tiff_page(image_bytes: &[u8], page: u32) -> Result<VipsImage, ProcessingError> {
+    VipsImage::new_from_buffer(image_bytes, &format!("page={}", page))
+        .map_err(|e| ProcessingError::Decode(format!("Error loading TIFF page {}: {}", page, e)))
+}
+
+/// Decodes the source with the libvips loader for `format` instead of sniffing the bytes.
+///
+/// `format` must be one of [`SOURCE_FORMATS`]; `jpg` is accepted for `jpeg`. Bytes the forced
+/// loader cannot parse are a decode error rather than a retry with another loader.
+pub fn load_with_format(image_bytes: &[u8], format: &str) -> Result<VipsImage, ProcessingError> {
+    let loaded = match format {
+        "jpeg" | "jpg" => ops::jpegload_buffer(image_bytes),
+        "png" => ops::pngload_buffer(image_bytes),
+        "webp" => ops::webpload_buffer(image_bytes),
+        "gif" => ops::gifload_buffer(image_bytes),
+        "tiff" => ops::tiffload_buffer(image_bytes),
+        "heif" | "avif" => ops::heifload_buffer(image_bytes),
+        "svg" => ops::svgload_buffer(image_bytes),
+        other => {
+            return Err(ProcessingError::Unsupported(format!(
+                "Unsupported source format: {}",
+                other
+            )))
+        }
+    };
+    loaded.map_err(|e| ProcessingError::Decode(format!("Source is not a valid {} image: {}", format, e)))
+}
+
+/// Loads the thumbnail embedded in a JPEG (EXIF) or HEIF/AVIF source, if it can stand in for
+/// the full image at `target_width` x `target_height`.
+///
+/// The thumbnail is used only when it is at least as large as the target (a `0` target
+/// dimension is unconstrained) and keeps the source aspect ratio, so outputs never look
+/// upscaled or differently framed. Returns `None` to fall back to the full decode.
+pub fn load_embedded_thumbnail(image_bytes: &[u8], target_width: u32, target_height: u32) -> Option<VipsImage> {
+    let thumbnail = if is_heif(image_bytes) {
+        VipsImage::new_from_buffer(image_bytes, "thumbnail=true").ok()?
+    } else {
+        // Copy the decoded pixels so the image does not borrow the temporary thumbnail buffer.
+        let bytes = exif_thumbnail_bytes(image_bytes)?;
+        VipsImage::image_copy_memory(VipsImage::new_from_buffer(&bytes, "").ok()?).ok()?
+    };
+
+    let (source_width, source_height) = read_dimensions(image_bytes, false).ok()?;
+    let (thumb_width, thumb_height) = (thumbnail.get_width() as u32, thumbnail.get_height() as u32);
+    let source_ratio = source_width as f64 / source_height.max(1) as f64;
+    let thumb_ratio = thumb_width as f64 / thumb_height.max(1) as f64;
+    if (source_ratio - thumb_ratio).abs() > source_ratio * THUMBNAIL_ASPECT_TOLERANCE {
+        debug!(
+            "Embedded thumbnail {}x{} does not match source aspect {}x{}",
+            thumb_width, thumb_height, source_width, source_height
+        );
+        return None;
+    }
+    if thumb_width < target_width || thumb_height < target_height {
+        debug!(
+            "Embedded thumbnail {}x{} is smaller than target {}x{}",
+            thumb_width, thumb_height, target_width, target_height
+        );
+        return None;
+    }
+
+    debug!(
+        "Using embedded {}x{} thumbnail instead of {}x{} source",
+        thumb_width, thumb_height, source_width, source_height
+    );
+    Some(thumbnail)
+}
+
+/// Decodes a JPEG source at a reduced DCT scale when it stays at least `target_width` x
+/// `target_height` (a `0` target dimension is unconstrained).
+///
+/// libvips then only inverse-transforms the coefficients it needs, so downscaling a large photo
+/// to a thumbnail never materializes the full-resolution pixels. Returns `None` for other
+/// formats, or when no shrink factor keeps enough pixels, to fall back to the full decode.
+pub fn load_shrunk_jpeg(image_bytes: &[u8], target_width: u32, target_height: u32) -> Option<VipsImage> {
+    if !is_jpeg(image_bytes) {
+        return None;
+    }
+
+    let (source_width, source_height) = read_dimensions(image_bytes, false).ok()?;
+    let shrink = JPEG_SHRINK_FACTORS
+        .into_iter()
+        .find(|shrink| source_width / shrink >= target_width && source_height / shrink >= target_height)?;
+
+    debug!(
+        "Shrinking {}x{} JPEG by {} on load for target {}x{}",
+        source_width, source_height, shrink, target_width, target_height
+    );
+    VipsImage::new_from_buffer(image_bytes, &format!("shrink={}", shrink))
+        .inspect_err(|e| warn!("Shrink-on-load failed, decoding at full size: {}", e))
+        .ok()
+}
+
+/// Returns the JPEG thumbnail stored in the EXIF IFD1 of a source, if any.
+fn exif_thumbnail_bytes(image_bytes: &[u8]) -> Option<Vec<u8>> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(image_bytes))
+        .ok()?;
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let length = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+
+    // Offsets are relative to the TIFF header, which is where `buf` starts.
+    exif.buf().get(offset..offset.checked_add(length)?).map(<[u8]>::to_vec)
+}
+
+/// Returns whether the source starts with the JPEG start-of-image marker.
+fn is_jpeg(image_bytes: &[u8]) -> bool {
+    image_bytes.starts_with(&[0xFF, 0xD8, 0xFF])
+}
+
+/// Returns whether the source is an ISO-BMFF container (HEIF, HEIC or AVIF).
+fn is_heif(image_bytes: &[u8]) -> bool {
+    image_bytes.get(4..8) == Some(b"ftyp".as_slice())
+}
+
+fn decode_with_image_crate(image_bytes: &[u8]) -> Result<VipsImage, String> {
+    let decoded = image::load_from_memory(image_bytes).map_err(|e| e.to_string())?;
+    let (width, height) = (decoded.width() as i32, decoded.height() as i32);
+    let (pixels, bands) = if decoded.color().has_alpha() {
+        (decoded.into_rgba8().into_raw(), 4)
+    } else {
+        (decoded.into_rgb8().into_raw(), 3)
+    };
+    debug!("Fallback decoded {}x{} image with {} bands", width, height, bands);
+
+    let wrapped = VipsImage::new_from_memory(&pixels, width, height, bands, ops::BandFormat::Uchar)
+        .map_err(|e| format!("Error wrapping decoded pixels: {}", e))?;
+    VipsImage::image_copy_memory(wrapped).map_err(|e| format!("Error copying decoded pixels: {}", e))
+}
@@ -0,0 +1,24 @@
+use thiserror::Error;
+
+/// Failure raised while decoding, transforming or encoding an image.
+///
+/// The variant tells callers what went wrong so the service can answer with a matching HTTP
+/// status; the message is meant for logs and error responses.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ProcessingError {
+    /// The source bytes could not be decoded as an image.
+    #[error("{0}")]
+    Decode(String),
+    /// The request asks for a format or operation this build cannot handle.
+    #[error("{0}")]
+    Unsupported(String),
+    /// The requested geometry cannot be produced from the image.
+    #[error("{0}")]
+    Geometry(String),
+    /// The encoder failed or produced an unusable output.
+    #[error("{0}")]
+    Encode(String),
+    /// A libvips operation failed while transforming the image.
+    #[error("{0}")]
+    Vips(String),
+}
@@ -0,0 +1,66 @@
+use crate::processing::error::ProcessingError;
+use crate::processing::save;
+use libvips::{ops, VipsImage};
+use tracing::debug;
+
+/// Width and height used for error images when the request does not set them.
+pub const DEFAULT_ERROR_IMAGE_SIZE: u32 = 300;
+/// Largest width or height of an error image, so a failing request cannot ask for a huge canvas.
+pub const MAX_ERROR_IMAGE_SIZE: u32 = 2048;
+/// Gray level of the error image background.
+const ERROR_IMAGE_BACKGROUND: u8 = 64;
+/// Share of the canvas the message may cover in each direction.
+const ERROR_IMAGE_TEXT_RATIO: f64 = 0.9;
+/// Quality used to encode error images in lossy formats.
+const ERROR_IMAGE_QUALITY: u8 = 80;
+
+/// Renders `message` as white text centred on a gray `width` x `height` canvas and encodes it.
+///
+/// The text is scaled to fit within the canvas. Formats the build cannot encode fall back to PNG;
+/// the second element of the result is the format actually used.
+pub fn render_error_image(
+    message: &str,
+    width: u32,
+    height: u32,
+    format: &str,
+) -> Result<(Vec<u8>, String), ProcessingError> {
+    let width = width.clamp(1, MAX_ERROR_IMAGE_SIZE) as i32;
+    let height = height.clamp(1, MAX_ERROR_IMAGE_SIZE) as i32;
+
+    let pixel = [ERROR_IMAGE_BACKGROUND; 3];
+    let canvas = VipsImage::new_from_memory(&pixel, 1, 1, 3, ops::BandFormat::Uchar)
+        .and_then(VipsImage::image_copy_memory)
+        .and_then(|pixel| ops::zoom(&pixel, width, height))
+        .map_err(|e| ProcessingError::Vips(format!("Error creating error image canvas: {}", e)))?;
+    let mask = text_mask(message, width, height)?;
+    let white = VipsImage::new_from_image(&canvas, &[255.0, 255.0, 255.0])
+        .map_err(|e| ProcessingError::Vips(format!("Error creating error image text colour: {}", e)))?;
+    let img = ops::ifthenelse_with_opts(&mask, &white, &canvas, &ops::IfthenelseOptions { blend: true })
+        .map_err(|e| ProcessingError::Vips(format!("Error drawing error image text: {}", e)))?;
+    let img = ops::copy(&img).map_err(|e| ProcessingError::Vips(format!("Error copying error image: {}", e)))?;
+
+    let retry = ops::copy(&img).map_err(|e| ProcessingError::Vips(format!("Error copying error image: {}", e)))?;
+    match save::save_image(img, format, ERROR_IMAGE_QUALITY) {
+        Ok(encoded) => Ok((encoded, format.to_string())),
+        Err(e) => {
+            debug!("Cannot encode error image as {} ({}), using PNG", format, e);
+            save::save_image(retry, "png", ERROR_IMAGE_QUALITY).map(|encoded| (encoded, "png".to_string()))
+        }
+    }
+}
+
+/// Renders `message` as a one-band coverage mask the size of the canvas, with the text centred.
+fn text_mask(message: &str, width: i32, height: i32) -> Result<VipsImage, ProcessingError> {
+    // libvips renders Pango markup, so escape the characters markup would interpret.
+    let escaped = message.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let text = ops::text(&escaped).map_err(|e| ProcessingError::Vips(format!("Error rendering error text: {}", e)))?;
+    let scale = (width as f64 * ERROR_IMAGE_TEXT_RATIO / text.get_width() as f64)
+        .min(height as f64 * ERROR_IMAGE_TEXT_RATIO / text.get_height() as f64);
+    let text =
+        ops::resize(&text, scale).map_err(|e| ProcessingError::Vips(format!("Error scaling error text: {}", e)))?;
+
+    let left = (width - text.get_width()).max(0) / 2;
+    let top = (height - text.get_height()).max(0) / 2;
+    ops::embed(&text, left, top, width, height)
+        .map_err(|e| ProcessingError::Vips(format!("Error placing error text: {}", e)))
+}
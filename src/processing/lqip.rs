@@ -0,0 +1,48 @@
+use crate::processing::error::ProcessingError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use libvips::ops;
+use tracing::debug;
+
+/// Width in pixels of generated placeholders; height follows the output aspect ratio.
+pub const LQIP_WIDTH: i32 = 16;
+/// JPEG quality used for placeholders, which are meant to be blurred by the client anyway.
+const LQIP_QUALITY: i32 = 40;
+/// Largest data URI returned, keeping the response header small.
+pub const MAX_LQIP_LENGTH: usize = 2048;
+
+/// Builds a `data:image/jpeg;base64,...` preview of an encoded output image.
+///
+/// Uses shrink-on-load so the cost stays bounded by the placeholder size rather than the output
+/// size. Returns `Ok(None)` when the data URI would exceed `MAX_LQIP_LENGTH`.
+pub fn generate_lqip(image_bytes: &[u8]) -> Result<Option<String>, ProcessingError> {
+    let thumbnail = ops::thumbnail_buffer(image_bytes, LQIP_WIDTH)
+        .map_err(|e| ProcessingError::Encode(format!("Error creating LQIP thumbnail: {}", e)))?;
+    // JPEG has no alpha channel, so flatten transparent outputs before encoding.
+    let thumbnail = if thumbnail.get_bands() == 2 || thumbnail.get_bands() == 4 {
+        ops::flatten(&thumbnail)
+            .map_err(|e| ProcessingError::Encode(format!("Error flattening LQIP thumbnail: {}", e)))?
+    } else {
+        thumbnail
+    };
+
+    let opts = ops::JpegsaveBufferOptions {
+        q: LQIP_QUALITY,
+        keep: ops::ForeignKeep::None,
+        ..Default::default()
+    };
+    let jpeg = ops::jpegsave_buffer_with_opts(&thumbnail, &opts)
+        .map_err(|e| ProcessingError::Encode(format!("Error encoding LQIP: {}", e)))?;
+
+    let data_uri = format!("data:image/jpeg;base64,{}", STANDARD.encode(jpeg));
+    if data_uri.len() > MAX_LQIP_LENGTH {
+        debug!(
+            "Skipping LQIP of {} bytes, above limit {}",
+            data_uri.len(),
+            MAX_LQIP_LENGTH
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(data_uri))
+}
@@ -1,17 +1,24 @@
+pub mod decode;
+pub mod error;
+pub mod error_image;
+pub mod lqip;
 pub mod options;
 pub mod presets;
+pub mod quality;
 pub mod save;
 pub mod transform;
 pub mod utils;
 pub mod watermark;
 
 use crate::monitoring::{increment_processed_images, observe_image_processing_duration};
+use crate::processing::error::ProcessingError;
 use crate::processing::options::ParsedOptions;
 use crate::processing::watermark::CachedWatermark;
+use crate::utils::read_exif_orientation;
 use bytes::Bytes;
-use libvips::VipsImage;
-use std::time::Instant;
-use tracing::debug;
+use libvips::{ops, VipsImage};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
 
 /// Processes an image by applying the given `ParsedOptions`.
 ///
@@ -30,61 +37,248 @@ use tracing::debug;
 ///
 /// A `Result` containing the processed image bytes on success, or an error message as a `String`.
 pub fn process_image(
+    img: VipsImage,
+    parsed_options: ParsedOptions,
+    source_bytes: &Bytes,
+    watermark: Option<&CachedWatermark>,
+) -> Result<Bytes, ProcessingError> {
+    process_image_timed(img, parsed_options, source_bytes, watermark).map(|encoded| encoded.bytes)
+}
+
+/// An image encoded by `process_image_timed`.
+pub struct EncodedImage {
+    pub bytes: Bytes,
+    /// Format the bytes are encoded in, which `max_bytes` may have switched from the requested one.
+    pub format: String,
+    /// Time spent processing and encoding.
+    pub duration: Duration,
+}
+
+/// Processes an image like `process_image`, also returning the output format and how long
+/// processing took.
+pub fn process_image_timed(
     mut img: VipsImage,
     mut parsed_options: ParsedOptions,
     source_bytes: &Bytes,
     watermark: Option<&CachedWatermark>,
-) -> Result<Bytes, String> {
+) -> Result<EncodedImage, ProcessingError> {
     let start = Instant::now();
     debug!("Starting image processing with options: {:?}", parsed_options);
 
     // Apply DPR scaling
-    if let Some(dpr) = parsed_options.dpr {
-        if dpr > 1.0 {
-            debug!("Applying DPR scaling: {}", dpr);
-            if let Some(ref mut resize) = parsed_options.resize {
-                debug!(
-                    "Scaling resize dimensions from {}x{} to {}x{}",
-                    resize.width,
-                    resize.height,
-                    (resize.width as f32 * dpr).round() as u32,
-                    (resize.height as f32 * dpr).round() as u32
-                );
-                resize.width = (resize.width as f32 * dpr).round() as u32;
-                resize.height = (resize.height as f32 * dpr).round() as u32;
-            }
-            if let Some(ref mut padding) = parsed_options.padding {
-                debug!(
-                    "Scaling padding from {:?} to {:?}",
-                    padding,
-                    (
-                        (padding.0 as f32 * dpr).round() as u32,
-                        (padding.1 as f32 * dpr).round() as u32,
-                        (padding.2 as f32 * dpr).round() as u32,
-                        (padding.3 as f32 * dpr).round() as u32
-                    )
-                );
-                padding.0 = (padding.0 as f32 * dpr).round() as u32;
-                padding.1 = (padding.1 as f32 * dpr).round() as u32;
-                padding.2 = (padding.2 as f32 * dpr).round() as u32;
-                padding.3 = (padding.3 as f32 * dpr).round() as u32;
-            }
+    if let Some(dpr) = parsed_options.dpr.filter(|dpr| *dpr > 1.0) {
+        debug!("Applying DPR scaling: {}", dpr);
+        if let Some(ref mut resize) = parsed_options.resize {
+            debug!(
+                "Scaling resize dimensions from {}x{} to {}x{}",
+                resize.width,
+                resize.height,
+                (resize.width as f32 * dpr).round() as u32,
+                (resize.height as f32 * dpr).round() as u32
+            );
+            resize.width = (resize.width as f32 * dpr).round() as u32;
+            resize.height = (resize.height as f32 * dpr).round() as u32;
+        }
+        if let Some(ref mut padding) = parsed_options.padding {
+            debug!(
+                "Scaling padding from {:?} to {:?}",
+                padding,
+                (
+                    (padding.0 as f32 * dpr).round() as u32,
+                    (padding.1 as f32 * dpr).round() as u32,
+                    (padding.2 as f32 * dpr).round() as u32,
+                    (padding.3 as f32 * dpr).round() as u32
+                )
+            );
+            padding.0 = (padding.0 as f32 * dpr).round() as u32;
+            padding.1 = (padding.1 as f32 * dpr).round() as u32;
+            padding.2 = (padding.2 as f32 * dpr).round() as u32;
+            padding.3 = (padding.3 as f32 * dpr).round() as u32;
+        }
+        if let Some(ref mut border) = parsed_options.border {
+            border.width = (border.width as f32 * dpr).round() as u32;
+        }
+        if let Some(ref mut radius) = parsed_options.border_radius {
+            *radius = (*radius as f32 * dpr).round() as u32;
+        }
+        if let Some(ref mut label) = parsed_options.label {
+            label.size = (label.size as f32 * dpr).round() as u32;
         }
     }
 
     debug!("Loaded image: {}x{}", img.get_width(), img.get_height());
 
-    // Apply EXIF autorotation if enabled
+    // Apply EXIF autorotation if enabled. Stripped outputs lose the orientation tag, so without
+    // autorotation clients have nothing left to rotate the image with.
+    // Copyrighted outputs are meant for distribution, so their source metadata is always dropped.
+    let strip_metadata = parsed_options.strip_metadata.unwrap_or(true) || parsed_options.copyright.is_some();
     if parsed_options.auto_rotate {
         debug!("Applying EXIF auto-rotation");
         img = transform::apply_exif_rotation(source_bytes.as_ref(), img)?;
+    } else if strip_metadata && parsed_options.exif_orientation.is_none() {
+        if let Some(orientation) = read_exif_orientation(source_bytes.as_ref()).filter(|o| *o != 1) {
+            warn!(
+                "Stripping metadata without auto_rotate drops EXIF orientation {}; clients may render the image unrotated",
+                orientation
+            );
+        }
     }
 
+    // Later steps read a fourth band as alpha, so CMYK sources are always converted first. Other
+    // sources are only converted on request, as the conversion costs a pass over every pixel.
+    if transform::is_cmyk(&img) || parsed_options.normalize_colorspace {
+        debug!("Converting source to sRGB before processing");
+        img = transform::convert_to_srgb(img)?;
+    }
+
+    let output_format = parsed_options.format.as_deref().unwrap_or("jpeg");
+    // Animated sources are decoded as a vertical strip of equally sized frames. Each frame is
+    // transformed on its own so geometry and watermarks apply per frame, then the strip is rebuilt.
+    let frame_height = img.get_page_height();
+    let animated = frame_height > 0 && frame_height < img.get_height();
+    let (img, output_frame_height) = if animated {
+        let frame_count = img.get_height() / frame_height;
+        if parsed_options.trim.is_some() {
+            debug!("Ignoring trim for animated source, as frames must keep a common size");
+        }
+        debug!("Processing {} animation frames of height {}", frame_count, frame_height);
+        let mut frames = (0..frame_count)
+            .map(|i| {
+                let frame = ops::extract_area(&img, 0, i * frame_height, img.get_width(), frame_height)
+                    .map_err(|e| ProcessingError::Vips(format!("Error extracting animation frame {}: {}", i, e)))?;
+                transform_frame(frame, &parsed_options, watermark)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_frame_height = frames[0].get_height();
+        let joined = ops::arrayjoin_with_opts(
+            &mut frames,
+            &ops::ArrayjoinOptions {
+                across: 1,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| ProcessingError::Vips(format!("Error joining animation frames: {}", e)))?;
+        (joined, Some(output_frame_height))
+    } else {
+        // Trim runs before any geometry, so crop coordinates and resize targets apply to the content
+        let img = match parsed_options.trim {
+            Some(ref trim) => {
+                debug!("Applying trim: {:?}", trim);
+                transform::trim_image(img, trim)?
+            }
+            None => img,
+        };
+        (transform_frame(img, &parsed_options, watermark)?, None)
+    };
+
+    // Convert wide-gamut and other non-sRGB outputs so every client renders the same colours
+    let img = if parsed_options.srgb.unwrap_or(false) {
+        debug!("Converting output to sRGB");
+        transform::convert_to_srgb(img)?
+    } else {
+        img
+    };
+
+    // Save image to bytes, searching for the quality when `quality:auto` is in effect
+    let save_options = save::SaveOptions {
+        bitdepth: parsed_options.bitdepth,
+        strip_metadata,
+        strip_profile: !parsed_options.keep_profile,
+        dpi: parsed_options.copyright.as_ref().map(|_| watermark::COPYRIGHT_DPI),
+    };
+    // `max_bytes` may settle on a different format than the one requested
+    let mut switched_format: Option<String> = None;
+    let output_vec = if let Some(frame_height) = output_frame_height {
+        save::save_animation(img, output_format, frame_height, save_options)?
+    } else if let Some(max_bytes) = parsed_options.max_bytes {
+        let quality = quality::resolve_quality(parsed_options.quality, output_format, &parsed_options.quality_defaults);
+        let encoded = quality::encode_within_budget(
+            &img,
+            output_format,
+            quality,
+            max_bytes,
+            &parsed_options.max_bytes_format_ladder,
+            save_options,
+        )?;
+        debug!(
+            "Max bytes {} selected {} at q={} ({} bytes)",
+            max_bytes,
+            encoded.format,
+            encoded.quality,
+            encoded.bytes.len()
+        );
+        if encoded.format != output_format {
+            switched_format = Some(encoded.format);
+        }
+        encoded.bytes
+    } else if parsed_options.auto_quality && quality::supports_quality_search(output_format) {
+        let target = parsed_options
+            .auto_quality_target
+            .unwrap_or(quality::DEFAULT_TARGET_SSIM);
+        let (encoded, chosen_quality) = quality::encode_with_target_ssim(&img, output_format, target, save_options)?;
+        debug!("Auto quality selected q={} for SSIM target {}", chosen_quality, target);
+        encoded
+    } else {
+        let quality = quality::resolve_quality(parsed_options.quality, output_format, &parsed_options.quality_defaults);
+        save::save_image_with_options(img, output_format, quality, save_options)?
+    };
+    let output_format = switched_format.as_deref().unwrap_or(output_format);
+    let output_vec = match parsed_options.exif_orientation {
+        Some(orientation) if matches!(output_format, "jpeg" | "jpg") => {
+            debug!("Setting EXIF orientation {}", orientation);
+            save::set_jpeg_orientation(&output_vec, orientation)?
+        }
+        Some(orientation) => {
+            warn!(
+                "Ignoring exif_orientation {} for {} output, as it is only written to JPEG",
+                orientation, output_format
+            );
+            output_vec
+        }
+        None => output_vec,
+    };
+    let output_bytes = Bytes::from(output_vec);
+
+    debug!("Image processing complete");
+
+    let duration = start.elapsed();
+    observe_image_processing_duration(output_format, duration.as_secs_f64());
+    increment_processed_images(output_format);
+
+    Ok(EncodedImage {
+        bytes: output_bytes,
+        format: output_format.to_string(),
+        duration,
+    })
+}
+
+/// Applies the geometry, effect, watermark and background steps of the pipeline to one image or
+/// animation frame.
+fn transform_frame(
+    mut img: VipsImage,
+    parsed_options: &ParsedOptions,
+    watermark: Option<&CachedWatermark>,
+) -> Result<VipsImage, ProcessingError> {
+    let output_format = parsed_options.format.as_deref().unwrap_or("jpeg");
+    transform::validate_geometry(parsed_options, img.get_width() as u32, img.get_height() as u32)?;
+
     // Apply crop if specified
-    if let Some(crop) = parsed_options.crop {
+    if let Some(ref crop) = parsed_options.crop {
         debug!("Applying crop: {:?}", crop);
-        img = transform::crop_image(img, crop)?;
+        img = transform::crop_image(img, crop.clone())?;
     }
+    if let Some(ref region) = parsed_options.region {
+        debug!("Extracting region: {:?}", region);
+        img = transform::crop_image(img, region.clone())?;
+    }
+
+    // Pixel art only changes how the image itself is scaled; pixelate and watermarks keep the
+    // requested algorithm.
+    let resize_algorithm = if parsed_options.pixel_art {
+        Some(transform::PIXEL_ART_ALGORITHM.to_string())
+    } else {
+        parsed_options.resizing_algorithm.clone()
+    };
 
     // Apply resize if specified
     let mut resolved_resize_dims: Option<(u32, u32)> = None;
@@ -104,7 +298,13 @@ pub fn process_image(
                 target_w, target_h, src_width, src_height
             );
         } else {
-            img = transform::apply_resize(img, resize, &parsed_options.gravity, &parsed_options.resizing_algorithm)?;
+            img = transform::apply_resize_with_focus(
+                img,
+                resize,
+                &parsed_options.gravity,
+                parsed_options.focus_point,
+                &resize_algorithm,
+            )?;
         }
     }
 
@@ -118,14 +318,14 @@ pub fn process_image(
             img,
             parsed_options.min_width,
             parsed_options.min_height,
-            &parsed_options.resizing_algorithm,
+            &resize_algorithm,
         )?;
     }
 
     // Apply zoom if specified
     if let Some(zoom) = parsed_options.zoom {
         debug!("Applying zoom: {}", zoom);
-        img = transform::apply_zoom(img, zoom, &parsed_options.resizing_algorithm)?;
+        img = transform::apply_zoom(img, zoom, &resize_algorithm)?;
     }
 
     // Apply extend if specified
@@ -150,16 +350,32 @@ pub fn process_image(
         img = transform::apply_padding(img, top, right, bottom, left, &parsed_options.background)?;
     }
 
+    // Apply border if specified
+    if let Some(ref border) = parsed_options.border {
+        debug!("Applying border: {:?}", border);
+        img = transform::apply_border(img, border)?;
+    }
+
     // Apply rotation if specified
     if let Some(rotation) = parsed_options.rotation {
         debug!("Applying rotation: {}", rotation);
         img = transform::apply_rotation(img, rotation)?;
     }
 
+    // Apply mirroring if specified
+    if parsed_options.flip {
+        debug!("Applying vertical flip");
+        img = transform::apply_flip(img)?;
+    }
+    if parsed_options.flop {
+        debug!("Applying horizontal flop");
+        img = transform::apply_flop(img)?;
+    }
+
     // Apply blur if specified
     if let Some(sigma) = parsed_options.blur {
         debug!("Applying blur with sigma: {}", sigma);
-        img = transform::apply_blur(img, sigma)?;
+        img = transform::apply_blur(img, sigma, parsed_options.max_blur_sigma)?;
     }
 
     // Apply sharpen if specified
@@ -168,41 +384,93 @@ pub fn process_image(
         img = transform::apply_sharpen(img, sigma)?;
     }
 
+    // Apply saturation, contrast, and gamma if specified
+    if let Some(factor) = parsed_options.saturation {
+        debug!("Applying saturation: {}", factor);
+        img = transform::apply_saturation(img, factor)?;
+    }
+    if let Some(factor) = parsed_options.contrast {
+        debug!("Applying contrast: {}", factor);
+        img = transform::apply_contrast(img, factor)?;
+    }
+    if let Some(gamma) = parsed_options.gamma {
+        debug!("Applying gamma: {}", gamma);
+        img = transform::apply_gamma(img, gamma)?;
+    }
+
     // Apply pixelate if specified
     if let Some(amount) = parsed_options.pixelate {
         debug!("Applying pixelate with amount: {}", amount);
         img = transform::apply_pixelate(img, amount, &parsed_options.resizing_algorithm)?;
     }
 
-    // Apply watermark if specified
+    // Apply grayscale if specified
+    if parsed_options.grayscale {
+        debug!("Applying grayscale");
+        img = transform::apply_grayscale(img)?;
+    }
+
+    // Apply watermark if specified, skipping outputs too small for the mark to be legible
     if let Some(ref watermark_opts) = parsed_options.watermark {
         if let Some(watermark) = watermark {
-            debug!("Applying watermark with options: {:?}", watermark_opts);
-            img = watermark::apply_watermark(img, watermark, watermark_opts, &parsed_options.resizing_algorithm)?;
+            let min_size = parsed_options.watermark_min_size.unwrap_or(0);
+            if (img.get_width() as u32) < min_size || (img.get_height() as u32) < min_size {
+                debug!(
+                    "Skipping watermark on {}x{} output below minimum size {}",
+                    img.get_width(),
+                    img.get_height(),
+                    min_size
+                );
+            } else {
+                debug!("Applying watermark with options: {:?}", watermark_opts);
+                img = watermark::apply_watermark(img, watermark, watermark_opts, &parsed_options.resizing_algorithm)?;
+            }
         }
     }
 
-    // Apply background color for JPEG if needed
-    let output_format = parsed_options.format.as_deref().unwrap_or("jpeg");
-    if let Some(bg_color) = parsed_options.background {
-        if output_format == "jpeg" {
-            debug!("Applying background color for JPEG output: {:?}", bg_color);
-            img = transform::apply_background_color(img, bg_color)?;
-        }
+    // Apply copyright line if specified
+    if let Some(ref text) = parsed_options.copyright {
+        debug!("Applying copyright line: {}", text);
+        img = watermark::apply_copyright(img, text, &parsed_options.resizing_algorithm)?;
     }
 
-    // Save image to bytes
-    let quality = parsed_options.quality.unwrap_or(85);
-    let output_vec = save::save_image(img, output_format, quality)?;
-    let output_bytes = Bytes::from(output_vec);
+    // Apply text label if specified
+    if let Some(ref label) = parsed_options.label {
+        debug!("Applying label: {:?}", label);
+        img = watermark::apply_text_label(img, label)?;
+    }
 
-    debug!("Image processing complete");
+    // Snap to even dimensions for chroma-subsampled output if requested
+    if parsed_options.even_dimensions.unwrap_or(false) && save::uses_chroma_subsampling(output_format) {
+        debug!(
+            "Snapping {}x{} to even dimensions for {} output",
+            img.get_width(),
+            img.get_height(),
+            output_format
+        );
+        img = transform::snap_to_even_dimensions(img)?;
+    }
 
-    let duration = start.elapsed().as_secs_f64();
-    observe_image_processing_duration(output_format, duration);
-    increment_processed_images(output_format);
+    // Round the corners of the final canvas, leaving them transparent until the flatten below
+    if let Some(radius) = parsed_options.border_radius {
+        debug!("Applying border radius: {}", radius);
+        img = transform::apply_border_radius(img, radius)?;
+    }
+
+    // Flatten onto the background last, so every earlier step (watermark blending, transparent
+    // padding) still sees the alpha channel. Formats that keep alpha are left untouched unless a
+    // checkerboard preview was requested, which always flattens.
+    if parsed_options.checkerboard {
+        debug!("Applying checkerboard background for {} output", output_format);
+        img = transform::apply_checkerboard_background(img)?;
+    } else if let Some(bg_color) = parsed_options.background {
+        if !save::supports_alpha(output_format) {
+            debug!("Applying background color for {} output: {:?}", output_format, bg_color);
+            img = transform::apply_background_color(img, bg_color)?;
+        }
+    }
 
-    Ok(output_bytes)
+    Ok(img)
 }
 
 #[cfg(test)]
@@ -10,6 +10,7 @@ pub struct ProcessingOption {
     /// Arguments for the processing option.
     pub args: Vec<String>,
 }
+use crate::processing::quality::QualityDefaults;
 use base64::engine::general_purpose;
 use base64::Engine as _;
 use tracing::{debug, error};
@@ -28,6 +29,10 @@ const SIZE: &str = "size";
 const SIZE_SHORT: &str = "sz";
 /// Alternate shorthand for size.
 const SIZE_SHORT_ALT: &str = "s";
+/// Option name for css_size.
+const CSS_SIZE: &str = "css_size";
+/// Shorthand for css_size.
+const CSS_SIZE_SHORT: &str = "cs";
 /// Option name for width.
 const WIDTH: &str = "width";
 /// Shorthand for width.
@@ -38,6 +43,8 @@ const HEIGHT: &str = "height";
 const HEIGHT_SHORT: &str = "h";
 /// Option name for gravity.
 const GRAVITY: &str = "gravity";
+/// Gravity that anchors cropping on a focus point given as two fractions, e.g. `gravity:fp:0.5:0.3`.
+const FOCUS_POINT_GRAVITY: &str = "fp";
 /// Shorthand for gravity.
 const GRAVITY_SHORT: &str = "g";
 /// Option name for quality.
@@ -48,6 +55,10 @@ const QUALITY_SHORT: &str = "q";
 const AUTO_ROTATE: &str = "auto_rotate";
 /// Shorthand for auto_rotate.
 const AUTO_ROTATE_SHORT: &str = "ar";
+/// Option name for exif_orientation.
+const EXIF_ORIENTATION: &str = "exif_orientation";
+/// Shorthand for exif_orientation.
+const EXIF_ORIENTATION_SHORT: &str = "eo";
 /// Option name for background.
 const BACKGROUND: &str = "background";
 /// Shorthand for background.
@@ -64,10 +75,18 @@ const EXTEND_SHORT: &str = "ex";
 const PADDING: &str = "padding";
 /// Shorthand for padding.
 const PADDING_SHORT: &str = "pd";
+/// Option name for border.
+const BORDER: &str = "border";
+/// Widest border a request may draw on each side, in pixels.
+const MAX_BORDER_WIDTH: u32 = 1000;
 /// Option name for rotation.
 const ROTATE: &str = "rotate";
 /// Shorthand for rotation.
 const ROTATE_SHORT: &str = "rot";
+/// Option name for flip (vertical mirror).
+const FLIP: &str = "flip";
+/// Option name for flop (horizontal mirror).
+const FLOP: &str = "flop";
 /// Option name for raw.
 const RAW: &str = "raw";
 /// Option name for blur.
@@ -76,8 +95,22 @@ const BLUR: &str = "blur";
 const BLUR_SHORT: &str = "bl";
 /// Option name for crop.
 const CROP: &str = "crop";
+/// Option name for region.
+const REGION: &str = "region";
+/// Option name for trim.
+const TRIM: &str = "trim";
+/// Shorthand for trim.
+const TRIM_SHORT: &str = "t";
+/// Colour difference below which `trim` treats a pixel as border, matching libvips' default.
+pub const DEFAULT_TRIM_THRESHOLD: f64 = 10.0;
+/// `trim` background argument that samples the border colour from the top-left pixel.
+const TRIM_AUTO_BACKGROUND: &str = "auto";
 /// Option name for format.
 const FORMAT: &str = "format";
+/// Option name `parse_path` uses for the format implied by a source URL extension (`@webp`, `.webp`).
+///
+/// An explicit `format` option always takes precedence over it, regardless of order.
+pub const EXTENSION_FORMAT: &str = "extension_format";
 /// Option name for max_src_resolution.
 const MAX_SRC_RESOLUTION: &str = "max_src_resolution";
 /// Option name for max_src_file_size.
@@ -102,10 +135,26 @@ const ZOOM_SHORT: &str = "z";
 const SHARPEN: &str = "sharpen";
 /// Shorthand for sharpen.
 const SHARPEN_SHORT: &str = "sh";
+/// Option name for saturation.
+const SATURATION: &str = "saturation";
+/// Shorthand for saturation.
+const SATURATION_SHORT: &str = "sat";
+/// Option name for contrast.
+const CONTRAST: &str = "contrast";
+/// Shorthand for contrast.
+const CONTRAST_SHORT: &str = "co";
+/// Largest saturation and contrast factors; larger values are clamped to it.
+pub const MAX_COLOR_ADJUSTMENT: f32 = 5.0;
+/// Option name for gamma.
+const GAMMA: &str = "gamma";
 /// Option name for pixelate.
 const PIXELATE: &str = "pixelate";
 /// Shorthand for pixelate.
 const PIXELATE_SHORT: &str = "px";
+/// Option name for grayscale.
+const GRAYSCALE: &str = "grayscale";
+/// Shorthand for grayscale.
+const GRAYSCALE_SHORT: &str = "gs";
 /// Option name for watermark.
 const WATERMARK: &str = "watermark";
 /// Shorthand for watermark.
@@ -114,13 +163,83 @@ const WATERMARK_SHORT: &str = "wm";
 const WATERMARK_URL: &str = "watermark_url";
 /// Shorthand for watermark_url.
 const WATERMARK_URL_SHORT: &str = "wmu";
+/// Option name for copyright.
+const COPYRIGHT: &str = "copyright";
+/// Option name for label.
+const LABEL: &str = "label";
+/// Font size of a label, in pixels, when none is given.
+const DEFAULT_LABEL_SIZE: u32 = 24;
+/// Largest font size a label may request, in pixels.
+const MAX_LABEL_SIZE: u32 = 500;
+/// Position of a label when none is given.
+const DEFAULT_LABEL_POSITION: &str = "south_east";
+/// Named positions a label may be placed at.
+const LABEL_POSITIONS: [&str; 9] = [
+    "north",
+    "south",
+    "east",
+    "west",
+    "north_west",
+    "north_east",
+    "south_west",
+    "south_east",
+    "center",
+];
 /// Option name for resizing_algorithm.
 const RESIZING_ALGORITHM: &str = "resizing_algorithm";
 /// Shorthand for resizing_algorithm.
 const RESIZING_ALGORITHM_SHORT: &str = "ra";
+/// Option name for even_dimensions.
+const EVEN_DIMENSIONS: &str = "even_dimensions";
+/// Shorthand for even_dimensions.
+const EVEN_DIMENSIONS_SHORT: &str = "ed";
+/// Option name for lqip.
+const LQIP: &str = "lqip";
+/// Option name for encode.
+const ENCODE: &str = "encode";
+/// Option name for bitdepth.
+const BITDEPTH: &str = "bitdepth";
+/// Option name for strip_metadata.
+const STRIP_METADATA: &str = "strip_metadata";
+/// Shorthand for strip_metadata.
+const STRIP_METADATA_SHORT: &str = "sm";
+/// Alternative name for strip_metadata.
+const STRIP: &str = "strip";
+/// Option name for keep_profile.
+const KEEP_PROFILE: &str = "keep_profile";
+/// Option name for use_embedded_thumbnail.
+const USE_EMBEDDED_THUMBNAIL: &str = "use_embedded_thumbnail";
+/// Option name for pixel_art.
+const PIXEL_ART: &str = "pixel_art";
+/// Option name for page.
+const PAGE: &str = "page";
+/// Shorthand for page.
+const PAGE_SHORT: &str = "pg";
+/// Option name for source_format.
+const SOURCE_FORMAT: &str = "source_format";
+/// Shorthand for source_format.
+const SOURCE_FORMAT_SHORT: &str = "sf";
+/// Option name for contact_sheet.
+const CONTACT_SHEET: &str = "contact_sheet";
+/// Columns in a contact sheet when the option does not name them.
+pub const DEFAULT_CONTACT_SHEET_COLUMNS: u32 = 4;
+/// Option name for border_radius.
+const BORDER_RADIUS: &str = "border_radius";
+/// Shorthand for border_radius.
+const BORDER_RADIUS_SHORT: &str = "br_radius";
+/// Option name for max_bytes.
+const MAX_BYTES: &str = "max_bytes";
+/// Shorthand for max_bytes.
+const MAX_BYTES_SHORT: &str = "mb";
+/// Option name for srgb.
+const SRGB: &str = "srgb";
+/// Option name for colorspace.
+const COLORSPACE: &str = "colorspace";
+/// Background value that selects the checkerboard transparency preview.
+const CHECKERBOARD: &str = "checkerboard";
 
 /// Represents the parameters for a resize operation.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Resize {
     /// The type of resizing to perform (e.g., "fill", "fit", "force").
     pub resizing_type: String,
@@ -130,8 +249,39 @@ pub struct Resize {
     pub height: u32,
 }
 
+/// Represents the parameters for a trim operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trim {
+    /// Largest colour difference from the background still trimmed as border.
+    pub threshold: f64,
+    /// Border colour; `None` samples it from the top-left pixel.
+    pub background: Option<[u8; 4]>,
+}
+
+/// Represents the parameters for a solid border drawn around the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Border {
+    /// Thickness of the border on every side, in pixels.
+    pub width: u32,
+    /// Border colour.
+    pub color: [u8; 4],
+}
+
+/// Represents the parameters for a text label drawn over the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelOptions {
+    /// Text of the label.
+    pub text: String,
+    /// Font size, in pixels.
+    pub size: u32,
+    /// Text colour.
+    pub color: [u8; 4],
+    /// Named position of the label, such as `south_east`.
+    pub position: String,
+}
+
 /// Represents the parameters for a crop operation.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Crop {
     /// The x-coordinate of the top-left corner of the crop area.
     pub x: u32,
@@ -148,12 +298,19 @@ pub struct Crop {
 pub struct Watermark {
     /// The opacity of the watermark.
     pub opacity: f32,
-    /// The position of the watermark.
+    /// The position of the watermark, or `tile` to repeat it across the whole image.
     pub position: String,
+    /// Watermark width as a fraction of the image width. Defaults to one quarter.
+    pub scale: Option<f32>,
+    /// Clockwise rotation of the watermark in degrees, applied before positioning or tiling.
+    pub angle: f32,
+    /// Top-left corner of the watermark as percentages (`0`-`100`) of the image width and height,
+    /// set when `position` is given as `<x>,<y>` instead of a name.
+    pub offset: Option<(f32, f32)>,
 }
 
 /// Holds all parsed image processing options.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParsedOptions {
     /// Optional resize operation parameters.
     pub resize: Option<Resize>,
@@ -161,28 +318,58 @@ pub struct ParsedOptions {
     pub blur: Option<f32>,
     /// Optional crop operation parameters.
     pub crop: Option<Crop>,
+    /// Optional source region to extract before resizing, e.g. one tile of a larger map. Unlike
+    /// `crop` it must have an area and cannot be combined with `crop` or `trim`.
+    pub region: Option<Crop>,
+    /// Optional trim of uniform borders, applied before crop and resize.
+    pub trim: Option<Trim>,
     /// Optional output image format.
     pub format: Option<String>,
+    /// Whether `format:auto` asked for the format to be negotiated from the Accept header.
+    pub prefer_accept: bool,
     /// Optional output image quality (1-100).
     pub quality: Option<u8>,
+    /// Whether `quality:auto` asked for the quality to be searched against an SSIM target.
+    pub auto_quality: bool,
+    /// SSIM target for `quality:auto`. `None` defers to the server default.
+    pub auto_quality_target: Option<f64>,
+    /// Largest output size in bytes; quality is lowered until the encode fits.
+    pub max_bytes: Option<usize>,
+    /// Formats `max_bytes` may switch to, in order, once lowering quality is not enough. Set
+    /// from the server config rather than the URL.
+    pub max_bytes_format_ladder: Vec<String>,
     /// Optional background color for transparent areas or extending.
     pub background: Option<[u8; 4]>, // RGBA array
+    /// Whether `background:checkerboard` asked for transparency to be flattened onto a checkerboard.
+    pub checkerboard: bool,
     /// Optional target width (used with `resize` if no explicit resize type).
     pub width: Option<u32>,
     /// Optional target height (used with `resize` if no explicit resize type).
     pub height: Option<u32>,
     /// Optional gravity for cropping or extending (e.g., "center", "north").
     pub gravity: Option<String>,
+    /// Crop centre for `fill` as fractions (`0.0`-`1.0`) of the width and height, set by
+    /// `gravity:fp:x:y`.
+    pub focus_point: Option<(f64, f64)>,
     /// Whether to allow enlarging the image beyond its original dimensions.
     pub enlarge: bool,
     /// Whether to extend the image with a background if target dimensions are larger.
     pub extend: bool,
     /// Optional padding values (top, right, bottom, left).
     pub padding: Option<(u32, u32, u32, u32)>,
+    /// Optional solid border drawn around the padded image.
+    pub border: Option<Border>,
     /// Optional image rotation (rotation angle).
     pub rotation: Option<u16>,
     /// Whether to automatically rotate the image based on EXIF data.
     pub auto_rotate: bool,
+    /// EXIF orientation tag to write on JPEG output, leaving the pixels as they are. Parsing it
+    /// turns `auto_rotate` off.
+    pub exif_orientation: Option<u32>,
+    /// Whether to mirror the image vertically (top to bottom).
+    pub flip: bool,
+    /// Whether to mirror the image horizontally (left to right).
+    pub flop: bool,
     /// Whether to bypass processing limits (e.g., worker limits).
     pub raw: bool,
     /// Maximum allowed source image resolution in megapixels.
@@ -191,7 +378,7 @@ pub struct ParsedOptions {
     pub max_src_file_size: Option<usize>,
     /// Value to bypass cache (e.g., timestamp).
     pub cache_buster: Option<String>,
-    /// Device pixel ratio factor to scale up dimensions.
+    /// Device pixel ratio factor to scale up dimensions. `None` means 1.0.
     pub dpr: Option<f32>,
     /// Minimum width for the image.
     pub min_width: Option<u32>,
@@ -201,13 +388,72 @@ pub struct ParsedOptions {
     pub zoom: Option<f32>,
     /// Sharpen factor for the image.
     pub sharpen: Option<f32>,
+    /// Saturation factor (`1.0` unchanged, `0.0` grayscale).
+    pub saturation: Option<f32>,
+    /// Contrast factor around the midtone (`1.0` unchanged, `0.0` flat gray).
+    pub contrast: Option<f32>,
+    /// Gamma exponent (`1.0` unchanged, larger values brighten midtones).
+    pub gamma: Option<f32>,
     /// Pixelate factor for the image.
     pub pixelate: Option<u32>,
+    /// Whether to convert the image to grayscale before the watermark is applied.
+    pub grayscale: bool,
     pub watermark: Option<Watermark>,
     /// Optional URL for a watermark image.
     pub watermark_url: Option<String>,
+    /// Copyright line rendered along the bottom of the output, which is then saved without EXIF
+    /// metadata at print resolution.
+    pub copyright: Option<String>,
+    /// Optional text label drawn at a fixed font size over the image.
+    pub label: Option<LabelOptions>,
     /// Resizing algorithm to use (nearest, linear, cubic, lanczos2, lanczos3).
     pub resizing_algorithm: Option<String>,
+    /// Whether to snap output dimensions to even numbers for chroma-subsampled formats.
+    /// `None` defers to the server default.
+    pub even_dimensions: Option<bool>,
+    /// Whether to return a tiny base64 preview of the output in a response header.
+    pub lqip: bool,
+    /// Whether `encode:datauri` asked for a JSON body holding a base64 data URI instead of the
+    /// raw image bytes.
+    pub data_uri: bool,
+    /// Bits per sample for PNG output (1, 2, 4, 8 or 16). `None` writes 8-bit PNGs.
+    pub bitdepth: Option<u8>,
+    /// Zero-based page to load from a multi-page TIFF source. Ignored for other formats.
+    pub page: Option<u32>,
+    /// Loader forced for the source (for example `png`), bypassing format detection. `None`
+    /// lets libvips sniff the bytes.
+    pub source_format: Option<String>,
+    /// Columns of the static grid an animated source's frames are laid out in. Ignored for
+    /// still images.
+    pub contact_sheet: Option<u32>,
+    /// Radius in pixels of the rounded corners cut out of the output.
+    pub border_radius: Option<u32>,
+    /// Whether to drop EXIF, XMP and IPTC metadata from the output. `None` defers to the server
+    /// default.
+    pub strip_metadata: Option<bool>,
+    /// Whether stripped outputs keep their ICC profile. Outputs that keep their metadata always
+    /// keep the profile.
+    pub keep_profile: bool,
+    /// Whether to convert the output to sRGB before encoding. `None` defers to the server
+    /// default.
+    pub srgb: Option<bool>,
+    /// Whether `colorspace:srgb` asked for the source to be converted to sRGB before any other
+    /// step, so resizing and effects work on sRGB pixels.
+    pub normalize_colorspace: bool,
+    /// Whether small outputs may be rendered from a thumbnail embedded in the source. `None`
+    /// defers to the server default.
+    pub use_embedded_thumbnail: Option<bool>,
+    /// Whether large upscales use nearest-neighbour so pixel art keeps hard edges.
+    pub pixel_art: bool,
+    /// Smallest output width and height, in pixels, that still gets a watermark. Set from the
+    /// server config rather than the URL.
+    pub watermark_min_size: Option<u32>,
+    /// Largest blur sigma applied; larger `blur` values are clamped. Set from the server config
+    /// rather than the URL.
+    pub max_blur_sigma: Option<f32>,
+    /// Server qualities used when `quality` is not set. Set from the server config rather than
+    /// the URL.
+    pub quality_defaults: QualityDefaults,
 }
 
 impl Default for ParsedOptions {
@@ -216,30 +462,66 @@ impl Default for ParsedOptions {
             resize: None,
             blur: None,
             crop: None,
+            region: None,
+            trim: None,
             format: None,
+            prefer_accept: false,
             quality: None,
+            auto_quality: false,
+            auto_quality_target: None,
+            max_bytes: None,
+            max_bytes_format_ladder: Vec::new(),
             background: None,
+            checkerboard: false,
             width: None,
             height: None,
             gravity: None,
+            focus_point: None,
             enlarge: false,
             extend: false,
             padding: None,
+            border: None,
             rotation: None,
             auto_rotate: true,
+            exif_orientation: None,
+            flip: false,
+            flop: false,
             raw: false,
             max_src_resolution: None,
             max_src_file_size: None,
             cache_buster: None,
-            dpr: Some(1.0),
+            dpr: None,
             min_width: None,
             min_height: None,
             zoom: None,
             sharpen: None,
+            saturation: None,
+            contrast: None,
+            gamma: None,
             pixelate: None,
+            grayscale: false,
             watermark: None,
             watermark_url: None,
+            copyright: None,
+            label: None,
             resizing_algorithm: Some("lanczos3".to_string()),
+            even_dimensions: None,
+            lqip: false,
+            data_uri: false,
+            bitdepth: None,
+            page: None,
+            source_format: None,
+            contact_sheet: None,
+            border_radius: None,
+            strip_metadata: None,
+            keep_profile: true,
+            srgb: None,
+            normalize_colorspace: false,
+            use_embedded_thumbnail: None,
+            pixel_art: false,
+            watermark_min_size: None,
+            max_blur_sigma: None,
+            quality_defaults: QualityDefaults::default(),
         }
     }
 }
@@ -258,6 +540,7 @@ impl Default for ParsedOptions {
 /// A `Result` containing the `ParsedOptions` on success, or an error message as a `String`.
 pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions, String> {
     let mut parsed_options = ParsedOptions::default();
+    let mut extension_format: Option<String> = None;
 
     for option in options {
         debug!("Parsing option: {} with args: {:?}", option.name, option.args);
@@ -358,6 +641,30 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     parsed_options.resize = Some(resize);
                 }
             }
+            CSS_SIZE | CSS_SIZE_SHORT => {
+                if option.args.len() < 2 {
+                    error!("Css_size option requires width and height arguments");
+                    return Err("css_size option requires width and height arguments".to_string());
+                }
+                let parse_dimension = |arg: &String, name: &str| {
+                    if arg.is_empty() {
+                        return Ok(0);
+                    }
+                    arg.parse::<u32>().map_err(|e| {
+                        error!("Invalid {} for css_size: {}", name, e);
+                        e.to_string()
+                    })
+                };
+                // The DPR scaling in the pipeline turns these CSS pixels into device pixels
+                parsed_options.resize = Some(Resize {
+                    resizing_type: "fit".to_string(),
+                    width: parse_dimension(&option.args[0], "width")?,
+                    height: parse_dimension(&option.args[1], "height")?,
+                });
+                if let Some(arg) = option.args.get(2).filter(|arg| !arg.is_empty()) {
+                    parsed_options.dpr = parse_dpr(arg)?;
+                }
+            }
             WIDTH | WIDTH_SHORT => {
                 let width_arg = option.args.first().map(|s| s.as_str()).unwrap_or("0");
                 let width = if width_arg.is_empty() {
@@ -387,6 +694,11 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     error!("Gravity option requires one argument");
                     return Err("gravity option requires one argument".to_string());
                 }
+                parsed_options.focus_point = if option.args[0] == FOCUS_POINT_GRAVITY {
+                    Some(parse_focus_point(&option.args[1..])?)
+                } else {
+                    None
+                };
                 parsed_options.gravity = Some(option.args[0].clone());
             }
             ENLARGE | ENLARGE_SHORT => {
@@ -428,6 +740,28 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     }
                 });
             }
+            BORDER => {
+                if option.args.is_empty() {
+                    error!("Border option requires at least one argument");
+                    return Err("border option requires at least one argument".to_string());
+                }
+                let width = option.args[0].parse::<u32>().map_err(|e| {
+                    error!("Invalid border width: {}", e);
+                    format!("invalid border width '{}': {}", option.args[0], e)
+                })?;
+                if width > MAX_BORDER_WIDTH {
+                    error!("Border width out of range: {}", width);
+                    return Err(format!("border width must be at most {}", MAX_BORDER_WIDTH));
+                }
+                let color = match option.args.get(1).map(String::as_str) {
+                    None | Some("") => [0, 0, 0, 255],
+                    Some(color) => super::utils::parse_color(color).map_err(|e| {
+                        error!("Invalid color for border: {}", e);
+                        format!("invalid border color '{}': {}", color, e)
+                    })?,
+                };
+                parsed_options.border = Some(Border { width, color }).filter(|border| border.width > 0);
+            }
             ROTATE | ROTATE_SHORT => {
                 if option.args.is_empty() {
                     error!("Rotation option requires one argument");
@@ -446,6 +780,35 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                 }
                 parsed_options.auto_rotate = super::utils::parse_boolean(&option.args[0]);
             }
+            EXIF_ORIENTATION | EXIF_ORIENTATION_SHORT => {
+                if option.args.is_empty() {
+                    error!("Exif_orientation option requires one argument");
+                    return Err("exif_orientation option requires one argument".to_string());
+                }
+                let orientation = option.args[0].parse::<u32>().map_err(|e| {
+                    error!("Invalid exif_orientation: {}", e);
+                    e.to_string()
+                })?;
+                if !super::save::EXIF_ORIENTATIONS.contains(&orientation) {
+                    error!("Invalid exif_orientation: {}", orientation);
+                    return Err("exif_orientation must be between 1 and 8".to_string());
+                }
+                parsed_options.exif_orientation = Some(orientation);
+            }
+            FLIP => {
+                if option.args.is_empty() {
+                    error!("Flip option requires one argument");
+                    return Err("flip option requires one argument".to_string());
+                }
+                parsed_options.flip = super::utils::parse_boolean(&option.args[0]);
+            }
+            FLOP => {
+                if option.args.is_empty() {
+                    error!("Flop option requires one argument");
+                    return Err("flop option requires one argument".to_string());
+                }
+                parsed_options.flop = super::utils::parse_boolean(&option.args[0]);
+            }
             RAW => {
                 parsed_options.raw = true;
             }
@@ -483,6 +846,52 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     })?,
                 });
             }
+            REGION => {
+                if option.args.len() != 4 {
+                    error!("Region option requires four arguments");
+                    return Err("region option requires four arguments: x, y, width, height".to_string());
+                }
+                let values = option
+                    .args
+                    .iter()
+                    .map(|arg| arg.parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| {
+                        error!("Invalid region: {}", e);
+                        format!("invalid region: {}", e)
+                    })?;
+                if values[2] == 0 || values[3] == 0 {
+                    error!("Region has an empty area");
+                    return Err("region width and height must be greater than 0".to_string());
+                }
+                parsed_options.region = Some(Crop {
+                    x: values[0],
+                    y: values[1],
+                    width: values[2],
+                    height: values[3],
+                });
+            }
+            TRIM | TRIM_SHORT => {
+                let threshold = match option.args.first().filter(|arg| !arg.is_empty()) {
+                    Some(arg) => arg.parse::<f64>().map_err(|e| {
+                        error!("Invalid threshold for trim: {}", e);
+                        e.to_string()
+                    })?,
+                    None => DEFAULT_TRIM_THRESHOLD,
+                };
+                if !(threshold.is_finite() && threshold >= 0.0) {
+                    error!("Invalid threshold for trim: {}", threshold);
+                    return Err("trim threshold must be a non-negative number".to_string());
+                }
+                let background = match option.args.get(1).map(String::as_str) {
+                    None | Some("") | Some(TRIM_AUTO_BACKGROUND) => None,
+                    Some(color) => Some(super::utils::parse_color(color).map_err(|e| {
+                        error!("Invalid color for trim: {}", e);
+                        e.to_string()
+                    })?),
+                };
+                parsed_options.trim = Some(Trim { threshold, background });
+            }
             FORMAT => {
                 if option.args.is_empty() {
                     error!("Format option requires one argument");
@@ -490,30 +899,58 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                 }
                 parsed_options.format = Some(option.args[0].clone());
             }
+            EXTENSION_FORMAT => {
+                if option.args.is_empty() {
+                    error!("Extension format option requires one argument");
+                    return Err("extension_format option requires one argument".to_string());
+                }
+                extension_format = Some(option.args[0].clone());
+            }
             QUALITY | QUALITY_SHORT => {
                 if option.args.is_empty() {
                     error!("Quality option requires one argument");
                     return Err("quality option requires one argument".to_string());
                 }
-                parsed_options.quality = Some(
-                    option.args[0]
-                        .parse::<u8>()
-                        .map_err(|e| {
-                            error!("Invalid quality: {}", e);
+                if option.args[0] == "auto" {
+                    parsed_options.auto_quality = true;
+                    if let Some(target) = option.args.get(1) {
+                        let target = target.parse::<f64>().map_err(|e| {
+                            error!("Invalid quality target: {}", e);
                             e.to_string()
-                        })?
-                        .clamp(1, 100),
-                );
+                        })?;
+                        if !(target > 0.0 && target <= 1.0) {
+                            error!("Invalid quality target: {}", target);
+                            return Err("quality target must be greater than 0 and at most 1".to_string());
+                        }
+                        parsed_options.auto_quality_target = Some(target);
+                    }
+                } else {
+                    parsed_options.quality = Some(
+                        option.args[0]
+                            .parse::<u8>()
+                            .map_err(|e| {
+                                error!("Invalid quality: {}", e);
+                                e.to_string()
+                            })?
+                            .clamp(1, 100),
+                    );
+                }
             }
             BACKGROUND | BACKGROUND_SHORT => {
                 if option.args.is_empty() {
                     error!("Background option requires one argument");
                     return Err("background option requires one argument".to_string());
                 }
-                parsed_options.background = Some(super::utils::parse_hex_color(&option.args[0]).map_err(|e| {
-                    error!("Invalid hex color for background: {}", e);
-                    e.to_string()
-                })?);
+                if option.args[0].eq_ignore_ascii_case(CHECKERBOARD) {
+                    parsed_options.checkerboard = true;
+                    parsed_options.background = None;
+                } else {
+                    parsed_options.checkerboard = false;
+                    parsed_options.background = Some(super::utils::parse_color(&option.args[0]).map_err(|e| {
+                        error!("Invalid color for background: {}", e);
+                        e.to_string()
+                    })?);
+                }
             }
             MAX_SRC_RESOLUTION => {
                 if option.args.is_empty() {
@@ -549,15 +986,7 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     error!("DPR option requires one argument");
                     return Err("dpr option requires one argument".to_string());
                 }
-                let dpr = option.args[0].parse::<f32>().map_err(|e| {
-                    error!("Invalid dpr value: {}", e);
-                    e.to_string()
-                })?;
-                if !(1.0..=5.0).contains(&dpr) {
-                    error!("DPR value must be between 1.0 and 5.0, received: {}", dpr);
-                    return Err("dpr value must be between 1.0 and 5.0".to_string());
-                }
-                parsed_options.dpr = Some(dpr);
+                parsed_options.dpr = parse_dpr(&option.args[0])?;
             }
             MIN_WIDTH | MIN_WIDTH_SHORT => {
                 if option.args.is_empty() {
@@ -599,6 +1028,42 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     e.to_string()
                 })?);
             }
+            SATURATION | SATURATION_SHORT => {
+                if option.args.is_empty() {
+                    error!("Saturation option requires one argument");
+                    return Err("saturation option requires one argument".to_string());
+                }
+                parsed_options.saturation = Some(parse_color_adjustment(&option.args[0], "saturation")?);
+            }
+            CONTRAST | CONTRAST_SHORT => {
+                if option.args.is_empty() {
+                    error!("Contrast option requires one argument");
+                    return Err("contrast option requires one argument".to_string());
+                }
+                parsed_options.contrast = Some(parse_color_adjustment(&option.args[0], "contrast")?);
+            }
+            GAMMA => {
+                if option.args.is_empty() {
+                    error!("Gamma option requires one argument");
+                    return Err("gamma option requires one argument".to_string());
+                }
+                let gamma = option.args[0].parse::<f32>().map_err(|e| {
+                    error!("Invalid gamma value: {}", e);
+                    e.to_string()
+                })?;
+                if !(0.1..=5.0).contains(&gamma) {
+                    error!("Gamma value must be between 0.1 and 5.0, received: {}", gamma);
+                    return Err("gamma value must be between 0.1 and 5.0".to_string());
+                }
+                parsed_options.gamma = Some(gamma);
+            }
+            GRAYSCALE | GRAYSCALE_SHORT => {
+                if option.args.is_empty() {
+                    error!("Grayscale option requires one argument");
+                    return Err("grayscale option requires one argument".to_string());
+                }
+                parsed_options.grayscale = super::utils::parse_boolean(&option.args[0]);
+            }
             PIXELATE | PIXELATE_SHORT => {
                 if option.args.is_empty() {
                     error!("Pixelate option requires one argument");
@@ -614,12 +1079,45 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                     error!("Watermark option requires two arguments: opacity, position");
                     return Err("watermark option requires two arguments: opacity, position".to_string());
                 }
+                let scale = match option.args.get(2).filter(|arg| !arg.is_empty()) {
+                    Some(arg) => {
+                        let scale = arg.parse::<f32>().map_err(|e| {
+                            error!("Invalid scale for watermark: {}", e);
+                            e.to_string()
+                        })?;
+                        if !(scale > 0.0 && scale <= 1.0) {
+                            error!("Invalid scale for watermark: {}", scale);
+                            return Err("watermark scale must be greater than 0 and at most 1".to_string());
+                        }
+                        Some(scale)
+                    }
+                    None => None,
+                };
+                let angle = match option.args.get(3).filter(|arg| !arg.is_empty()) {
+                    Some(arg) => arg.parse::<f32>().map_err(|e| {
+                        error!("Invalid angle for watermark: {}", e);
+                        e.to_string()
+                    })?,
+                    None => 0.0,
+                };
+                if !angle.is_finite() {
+                    error!("Invalid angle for watermark: {}", angle);
+                    return Err("watermark angle must be a finite number".to_string());
+                }
+                let offset = if option.args[1].contains(',') {
+                    Some(parse_watermark_offset(&option.args[1])?)
+                } else {
+                    None
+                };
                 parsed_options.watermark = Some(Watermark {
                     opacity: option.args[0].parse::<f32>().map_err(|e| {
                         error!("Invalid opacity for watermark: {}", e);
                         e.to_string()
                     })?,
                     position: option.args[1].clone(),
+                    scale,
+                    angle,
+                    offset,
                 });
             }
             WATERMARK_URL | WATERMARK_URL_SHORT => {
@@ -637,6 +1135,75 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                 })?;
                 parsed_options.watermark_url = Some(url);
             }
+            COPYRIGHT => {
+                if option.args.is_empty() {
+                    error!("Copyright option requires one argument");
+                    return Err("copyright option requires one argument".to_string());
+                }
+                let decoded_text = general_purpose::URL_SAFE_NO_PAD.decode(&option.args[0]).map_err(|e| {
+                    error!("Invalid base64 for copyright: {}", e);
+                    e.to_string()
+                })?;
+                let text = String::from_utf8(decoded_text).map_err(|e| {
+                    error!("Invalid UTF-8 for copyright: {}", e);
+                    e.to_string()
+                })?;
+                if text.trim().is_empty() {
+                    error!("Copyright text is empty");
+                    return Err("copyright text must not be empty".to_string());
+                }
+                parsed_options.copyright = Some(text);
+            }
+            LABEL => {
+                if option.args.is_empty() {
+                    error!("Label option requires at least one argument");
+                    return Err("label option requires at least one argument".to_string());
+                }
+                let decoded_text = general_purpose::URL_SAFE_NO_PAD.decode(&option.args[0]).map_err(|e| {
+                    error!("Invalid base64 for label: {}", e);
+                    e.to_string()
+                })?;
+                let text = String::from_utf8(decoded_text).map_err(|e| {
+                    error!("Invalid UTF-8 for label: {}", e);
+                    e.to_string()
+                })?;
+                if text.trim().is_empty() {
+                    error!("Label text is empty");
+                    return Err("label text must not be empty".to_string());
+                }
+                let size = match option.args.get(1).filter(|arg| !arg.is_empty()) {
+                    Some(arg) => arg.parse::<u32>().map_err(|e| {
+                        error!("Invalid label size: {}", e);
+                        format!("invalid label size '{}': {}", arg, e)
+                    })?,
+                    None => DEFAULT_LABEL_SIZE,
+                };
+                if size == 0 || size > MAX_LABEL_SIZE {
+                    error!("Label size out of range: {}", size);
+                    return Err(format!("label size must be between 1 and {}", MAX_LABEL_SIZE));
+                }
+                let color = match option.args.get(2).map(String::as_str) {
+                    None | Some("") => [255, 255, 255, 255],
+                    Some(color) => super::utils::parse_color(color).map_err(|e| {
+                        error!("Invalid color for label: {}", e);
+                        format!("invalid label color '{}': {}", color, e)
+                    })?,
+                };
+                let position = match option.args.get(3).map(String::as_str) {
+                    None | Some("") => DEFAULT_LABEL_POSITION,
+                    Some(position) if LABEL_POSITIONS.contains(&position) => position,
+                    Some(position) => {
+                        error!("Invalid label position: {}", position);
+                        return Err(format!("invalid label position '{}'", position));
+                    }
+                };
+                parsed_options.label = Some(LabelOptions {
+                    text,
+                    size,
+                    color,
+                    position: position.to_string(),
+                });
+            }
             RESIZING_ALGORITHM | RESIZING_ALGORITHM_SHORT => {
                 if option.args.is_empty() {
                     error!("Resizing algorithm option requires one argument");
@@ -645,25 +1212,232 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
                 let algorithm = option.args[0].to_lowercase();
                 if !matches!(
                     algorithm.as_str(),
-                    "nearest" | "linear" | "cubic" | "lanczos2" | "lanczos3"
+                    "nearest" | "linear" | "cubic" | "lanczos2" | "lanczos3" | "auto"
                 ) {
                     error!(
-                        "Invalid resizing algorithm: {}. Must be one of: nearest, linear, cubic, lanczos2, lanczos3",
+                        "Invalid resizing algorithm: {}. Must be one of: nearest, linear, cubic, lanczos2, lanczos3, auto",
                         algorithm
                     );
                     return Err(format!(
-                        "Invalid resizing algorithm: {}. Must be one of: nearest, linear, cubic, lanczos2, lanczos3",
+                        "Invalid resizing algorithm: {}. Must be one of: nearest, linear, cubic, lanczos2, lanczos3, auto",
                         algorithm
                     ));
                 }
                 parsed_options.resizing_algorithm = Some(algorithm);
             }
+            EVEN_DIMENSIONS | EVEN_DIMENSIONS_SHORT => {
+                if option.args.is_empty() {
+                    error!("Even_dimensions option requires one argument");
+                    return Err("even_dimensions option requires one argument".to_string());
+                }
+                parsed_options.even_dimensions = Some(super::utils::parse_boolean(&option.args[0]));
+            }
+            LQIP => {
+                if option.args.is_empty() {
+                    error!("Lqip option requires one argument");
+                    return Err("lqip option requires one argument".to_string());
+                }
+                parsed_options.lqip = super::utils::parse_boolean(&option.args[0]);
+            }
+            ENCODE => {
+                if option.args.is_empty() {
+                    error!("Encode option requires one argument");
+                    return Err("encode option requires one argument".to_string());
+                }
+                parsed_options.data_uri = match option.args[0].to_lowercase().as_str() {
+                    "datauri" => true,
+                    "binary" => false,
+                    other => {
+                        error!("Invalid encode value: {}", other);
+                        return Err(format!("invalid encode value '{}': expected datauri or binary", other));
+                    }
+                };
+            }
+            BITDEPTH => {
+                if option.args.is_empty() {
+                    error!("Bitdepth option requires one argument");
+                    return Err("bitdepth option requires one argument".to_string());
+                }
+                let bitdepth = option.args[0].parse::<u8>().map_err(|e| {
+                    error!("Invalid bitdepth: {}", e);
+                    e.to_string()
+                })?;
+                if !super::save::PNG_BIT_DEPTHS.contains(&bitdepth) {
+                    error!("Invalid bitdepth: {}", bitdepth);
+                    return Err("bitdepth must be one of 1, 2, 4, 8 or 16".to_string());
+                }
+                parsed_options.bitdepth = Some(bitdepth);
+            }
+            STRIP_METADATA | STRIP_METADATA_SHORT | STRIP => {
+                if option.args.is_empty() {
+                    error!("Strip_metadata option requires one argument");
+                    return Err("strip_metadata option requires one argument".to_string());
+                }
+                parsed_options.strip_metadata = Some(super::utils::parse_boolean(&option.args[0]));
+            }
+            KEEP_PROFILE => {
+                if option.args.is_empty() {
+                    error!("Keep_profile option requires one argument");
+                    return Err("keep_profile option requires one argument".to_string());
+                }
+                parsed_options.keep_profile = super::utils::parse_boolean(&option.args[0]);
+            }
+            MAX_BYTES | MAX_BYTES_SHORT => {
+                if option.args.is_empty() {
+                    error!("Max_bytes option requires one argument");
+                    return Err("max_bytes option requires one argument".to_string());
+                }
+                let max_bytes = option.args[0].parse::<usize>().map_err(|e| {
+                    error!("Invalid max_bytes: {}", e);
+                    e.to_string()
+                })?;
+                if max_bytes == 0 {
+                    error!("Invalid max_bytes: 0");
+                    return Err("max_bytes must be greater than 0".to_string());
+                }
+                parsed_options.max_bytes = Some(max_bytes);
+            }
+            SRGB => {
+                if option.args.is_empty() {
+                    error!("Srgb option requires one argument");
+                    return Err("srgb option requires one argument".to_string());
+                }
+                parsed_options.srgb = Some(super::utils::parse_boolean(&option.args[0]));
+            }
+            COLORSPACE => {
+                if option.args.is_empty() {
+                    error!("Colorspace option requires one argument");
+                    return Err("colorspace option requires one argument".to_string());
+                }
+                parsed_options.normalize_colorspace = match option.args[0].to_lowercase().as_str() {
+                    "srgb" => true,
+                    "keep" => false,
+                    other => {
+                        error!("Invalid colorspace: {}", other);
+                        return Err(format!("invalid colorspace '{}': expected srgb or keep", other));
+                    }
+                };
+            }
+            USE_EMBEDDED_THUMBNAIL => {
+                if option.args.is_empty() {
+                    error!("Use_embedded_thumbnail option requires one argument");
+                    return Err("use_embedded_thumbnail option requires one argument".to_string());
+                }
+                parsed_options.use_embedded_thumbnail = Some(super::utils::parse_boolean(&option.args[0]));
+            }
+            PIXEL_ART => {
+                if option.args.is_empty() {
+                    error!("Pixel_art option requires one argument");
+                    return Err("pixel_art option requires one argument".to_string());
+                }
+                parsed_options.pixel_art = super::utils::parse_boolean(&option.args[0]);
+            }
+            PAGE | PAGE_SHORT => {
+                if option.args.is_empty() {
+                    error!("Page option requires one argument");
+                    return Err("page option requires one argument".to_string());
+                }
+                parsed_options.page = Some(option.args[0].parse::<u32>().map_err(|e| {
+                    error!("Invalid page: {}", e);
+                    e.to_string()
+                })?);
+            }
+            SOURCE_FORMAT | SOURCE_FORMAT_SHORT => {
+                if option.args.is_empty() {
+                    error!("Source_format option requires one argument");
+                    return Err("source_format option requires one argument".to_string());
+                }
+                let format = match option.args[0].to_lowercase().as_str() {
+                    "jpg" => "jpeg".to_string(),
+                    other => other.to_string(),
+                };
+                if !super::decode::SOURCE_FORMATS.contains(&format.as_str()) {
+                    error!("Invalid source_format: {}", format);
+                    return Err(format!(
+                        "source_format must be one of: {}",
+                        super::decode::SOURCE_FORMATS.join(", ")
+                    ));
+                }
+                parsed_options.source_format = Some(format);
+            }
+            CONTACT_SHEET => {
+                let columns = match option.args.first().filter(|arg| !arg.is_empty()) {
+                    Some(arg) => arg.parse::<u32>().map_err(|e| {
+                        error!("Invalid columns for contact_sheet: {}", e);
+                        e.to_string()
+                    })?,
+                    None => DEFAULT_CONTACT_SHEET_COLUMNS,
+                };
+                if columns == 0 {
+                    error!("Invalid columns for contact_sheet: 0");
+                    return Err("contact_sheet columns must be at least 1".to_string());
+                }
+                parsed_options.contact_sheet = Some(columns);
+            }
+            BORDER_RADIUS | BORDER_RADIUS_SHORT => {
+                if option.args.is_empty() {
+                    error!("Border_radius option requires one argument");
+                    return Err("border_radius option requires one argument".to_string());
+                }
+                let radius = option.args[0].parse::<u32>().map_err(|e| {
+                    error!("Invalid border_radius value: {}", e);
+                    e.to_string()
+                })?;
+                parsed_options.border_radius = Some(radius).filter(|radius| *radius > 0);
+            }
             _ => {
                 debug!("Unknown option: {}", option.name);
             }
         }
     }
 
+    // The URL extension only supplies the format when no explicit `format` option was given
+    if parsed_options.format.is_none() {
+        parsed_options.format = extension_format;
+    }
+    // `auto` is resolved against the request's Accept header once options are parsed
+    if parsed_options.format.as_deref() == Some("auto") {
+        parsed_options.format = None;
+        parsed_options.prefer_accept = true;
+    }
+
+    // Region coordinates refer to the source itself, which crop and trim would move
+    if parsed_options.region.is_some() && (parsed_options.crop.is_some() || parsed_options.trim.is_some()) {
+        error!("region cannot be combined with crop or trim");
+        return Err("region cannot be combined with crop or trim".to_string());
+    }
+
+    // Rounded corners are transparent, so formats without alpha need a colour to flatten them onto
+    if parsed_options.border_radius.is_some()
+        && parsed_options.background.is_none()
+        && !parsed_options.checkerboard
+        && parsed_options
+            .format
+            .as_deref()
+            .is_some_and(|format| !super::save::supports_alpha(format))
+    {
+        error!("border_radius requires an output format with alpha or a background");
+        return Err(
+            "border_radius requires an output format with alpha, or a background to fill the corners".to_string(),
+        );
+    }
+
+    // The tag describes the pixels as they are, so rotating them as well would apply it twice
+    if parsed_options.exif_orientation.is_some() {
+        if parsed_options.auto_rotate {
+            debug!("Disabling auto_rotate as exif_orientation is set");
+        }
+        parsed_options.auto_rotate = false;
+        if parsed_options
+            .format
+            .as_deref()
+            .is_some_and(|format| !matches!(format, "jpeg" | "jpg"))
+        {
+            error!("exif_orientation requires JPEG output");
+            return Err("exif_orientation is only supported for JPEG output".to_string());
+        }
+    }
+
     // Default resize type is `fit`
     if parsed_options.resize.is_none() && (parsed_options.width.is_some() || parsed_options.height.is_some()) {
         debug!("Applying default 'fit' resize due to width/height options");
@@ -676,3 +1450,74 @@ pub fn parse_all_options(options: Vec<ProcessingOption>) -> Result<ParsedOptions
 
     Ok(parsed_options)
 }
+
+/// Parses a device pixel ratio between 1.0 and 5.0. A ratio of 1.0 is a no-op, so it is returned
+/// the same way as an absent `dpr`.
+fn parse_dpr(value: &str) -> Result<Option<f32>, String> {
+    let dpr = value.parse::<f32>().map_err(|e| {
+        error!("Invalid dpr value: {}", e);
+        e.to_string()
+    })?;
+    if !(1.0..=5.0).contains(&dpr) {
+        error!("DPR value must be between 1.0 and 5.0, received: {}", dpr);
+        return Err("dpr value must be between 1.0 and 5.0".to_string());
+    }
+    Ok((dpr > 1.0).then_some(dpr))
+}
+
+/// Parses the `x` and `y` fractions of a focus-point gravity, both between 0 and 1.
+fn parse_focus_point(args: &[String]) -> Result<(f64, f64), String> {
+    if args.len() < 2 {
+        error!("Focus point gravity requires two arguments: x, y");
+        return Err("fp gravity requires two arguments: x, y".to_string());
+    }
+    let parse = |arg: &str| {
+        let value = arg.parse::<f64>().map_err(|e| {
+            error!("Invalid focus point '{}': {}", arg, e);
+            format!("invalid focus point '{}': {}", arg, e)
+        })?;
+        if !(0.0..=1.0).contains(&value) {
+            error!("Focus point out of range: {}", value);
+            return Err("focus point coordinates must be between 0 and 1".to_string());
+        }
+        Ok(value)
+    };
+    Ok((parse(&args[0])?, parse(&args[1])?))
+}
+
+/// Parses a watermark position of the form `<x>,<y>`, where both values are percentages between
+/// `0` and `100` and may carry a trailing `%`.
+fn parse_watermark_offset(position: &str) -> Result<(f32, f32), String> {
+    let parse_percent = |value: &str| -> Result<f32, String> {
+        let percent = value.trim().trim_end_matches('%').parse::<f32>().map_err(|e| {
+            error!("Invalid watermark position '{}': {}", position, e);
+            format!("invalid watermark position '{}': {}", position, e)
+        })?;
+        if !(0.0..=100.0).contains(&percent) {
+            error!("Watermark position out of range: {}", position);
+            return Err("watermark position percentages must be between 0 and 100".to_string());
+        }
+        Ok(percent)
+    };
+
+    match position.split_once(',') {
+        Some((x, y)) if !y.contains(',') => Ok((parse_percent(x)?, parse_percent(y)?)),
+        _ => {
+            error!("Invalid watermark position: {}", position);
+            Err(format!("invalid watermark position '{}': expected <x>,<y>", position))
+        }
+    }
+}
+
+/// Parses a saturation or contrast factor, clamped to `0..=MAX_COLOR_ADJUSTMENT`.
+fn parse_color_adjustment(value: &str, name: &str) -> Result<f32, String> {
+    let factor = value.parse::<f32>().map_err(|e| {
+        error!("Invalid {}: {}", name, e);
+        e.to_string()
+    })?;
+    if !factor.is_finite() {
+        error!("Invalid {}: {}", name, factor);
+        return Err(format!("{} must be a finite number", name));
+    }
+    Ok(factor.clamp(0.0, MAX_COLOR_ADJUSTMENT))
+}
@@ -1,15 +1,21 @@
 use crate::processing::options::ProcessingOption;
+use crate::processing::utils::parse_boolean;
 use std::collections::HashMap;
 use tracing::debug;
 
 const PRESET: &str = "preset";
 const PRESET_SHORT: &str = "pr";
+/// Option name that leaves the `default` preset out of a request.
+const SKIP_DEFAULT: &str = "skip_default";
 
 /// Expands preset references in processing options.
 ///
 /// This function takes a list of processing options and expands any preset references
-/// by looking them up in the presets map and replacing them with the preset's options.
-/// If a "default" preset exists, it is applied first.
+/// by looking them up in the presets map. If a "default" preset exists, it is applied first,
+/// followed by referenced presets in URL order. Inline URL options are placed after every
+/// preset, so they override preset values regardless of where the preset reference appears.
+/// A `skip_default` option leaves the "default" preset out and is consumed here; presets-only
+/// mode rejects it like any other inline option.
 ///
 /// # Arguments
 ///
@@ -26,12 +32,16 @@ pub fn expand_presets(
     only_presets: bool,
 ) -> Result<Vec<ProcessingOption>, String> {
     let mut expanded = Vec::new();
+    let mut inline = Vec::new();
     let mut has_preset_reference = false;
 
-    // First, apply the default preset if it exists
-    if let Some(default_options) = presets.get("default") {
+    // First, apply the default preset if it exists and the request does not skip it
+    let default_options = presets.get("default").filter(|_| !skips_default(&options));
+    if let Some(default_options) = default_options {
         debug!("Applying default preset with {} options", default_options.len());
         expanded.extend(default_options.iter().cloned());
+    } else if presets.contains_key("default") {
+        debug!("Skipping default preset as requested");
     }
 
     // Then process the URL options
@@ -56,20 +66,45 @@ pub fn expand_presets(
                 "only preset references are allowed in only_presets mode, found: {}",
                 option.name
             ));
-        } else {
-            expanded.push(option);
+        } else if option.name != SKIP_DEFAULT {
+            inline.push(option);
         }
     }
+    expanded.extend(inline);
 
     // If only_presets is enabled, and we have options but no preset reference,
     // and no default preset, reject the request
-    if only_presets && !has_preset_reference && !presets.contains_key("default") && !expanded.is_empty() {
+    if only_presets && !has_preset_reference && default_options.is_none() && !expanded.is_empty() {
         return Err("only preset references are allowed in only_presets mode".to_string());
     }
 
     Ok(expanded)
 }
 
+/// Returns the names of the presets `expand_presets` applies to `options`, in application order.
+///
+/// The `default` preset comes first when it exists and is not skipped, followed by each URL
+/// reference. Unknown references are left out; `expand_presets` rejects them.
+pub fn applied_presets(options: &[ProcessingOption], presets: &HashMap<String, Vec<ProcessingOption>>) -> Vec<String> {
+    let default = (presets.contains_key("default") && !skips_default(options)).then(|| "default".to_string());
+    let referenced = options
+        .iter()
+        .filter(|option| option.name == PRESET || option.name == PRESET_SHORT)
+        .filter_map(|option| option.args.first())
+        .filter(|name| presets.contains_key(*name))
+        .cloned();
+    default.into_iter().chain(referenced).collect()
+}
+
+/// Returns whether `options` leave out the default preset: the last `skip_default` option wins,
+/// and one without arguments counts as `skip_default:true`.
+fn skips_default(options: &[ProcessingOption]) -> bool {
+    options
+        .iter()
+        .rfind(|option| option.name == SKIP_DEFAULT)
+        .is_some_and(|option| option.args.first().is_none_or(|arg| parse_boolean(arg)))
+}
+
 /// Parses a preset options string into a vector of ProcessingOption.
 ///
 /// Preset options are separated by '/' and follow the same format as URL options.
@@ -183,6 +218,36 @@ mod tests {
         assert_eq!(expanded[2].name, "blur");
     }
 
+    #[test]
+    fn test_expand_presets_skip_default() {
+        let mut presets = HashMap::new();
+        presets.insert("default".to_string(), parse_options_string("quality:90/dpr:2").unwrap());
+        presets.insert(
+            "thumbnail".to_string(),
+            parse_options_string("resize:fit:150:150").unwrap(),
+        );
+
+        let options = parse_options_string("skip_default/pr:thumbnail/blur:5").unwrap();
+        let expanded = expand_presets(options, &presets, false).unwrap();
+        let names: Vec<&str> = expanded.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["resize", "blur"]);
+
+        let options = parse_options_string("skip_default:1/skip_default:false/blur:5").unwrap();
+        let expanded = expand_presets(options, &presets, false).unwrap();
+        let names: Vec<&str> = expanded.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["quality", "dpr", "blur"]);
+    }
+
+    #[test]
+    fn test_expand_presets_only_presets_mode_rejects_skip_default() {
+        let mut presets = HashMap::new();
+        presets.insert("default".to_string(), parse_options_string("quality:90").unwrap());
+
+        let options = parse_options_string("skip_default").unwrap();
+        let result = expand_presets(options, &presets, true);
+        assert!(result.unwrap_err().contains("only preset references are allowed"));
+    }
+
     #[test]
     fn test_expand_presets_unknown_preset() {
         let presets: HashMap<String, Vec<ProcessingOption>> = HashMap::new();
@@ -266,4 +331,48 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("requires a preset name"));
     }
+
+    #[test]
+    fn test_expand_presets_inline_options_follow_presets() {
+        let mut presets = HashMap::new();
+        presets.insert(
+            "thumbnail".to_string(),
+            parse_options_string("resize:fit:150:150/quality:90").unwrap(),
+        );
+
+        let options = vec![
+            ProcessingOption {
+                name: "quality".to_string(),
+                args: vec!["70".to_string()],
+            },
+            ProcessingOption {
+                name: "preset".to_string(),
+                args: vec!["thumbnail".to_string()],
+            },
+        ];
+
+        let expanded = expand_presets(options, &presets, false).unwrap();
+        let names: Vec<&str> = expanded.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["resize", "quality", "quality"]);
+        assert_eq!(expanded[2].args, vec!["70"]);
+    }
+
+    #[test]
+    fn test_applied_presets_lists_default_then_references() {
+        let mut presets = HashMap::new();
+        presets.insert("default".to_string(), parse_options_string("quality:90").unwrap());
+        presets.insert(
+            "thumbnail".to_string(),
+            parse_options_string("resize:fit:150:150").unwrap(),
+        );
+        let options = parse_options_string("pr:thumbnail/blur:2/preset:missing").unwrap();
+
+        assert_eq!(applied_presets(&options, &presets), vec!["default", "thumbnail"]);
+        let skipping = parse_options_string("skip_default:true/pr:thumbnail").unwrap();
+        assert_eq!(applied_presets(&skipping, &presets), vec!["thumbnail"]);
+
+        presets.remove("default");
+        assert_eq!(applied_presets(&options, &presets), vec!["thumbnail"]);
+        assert!(applied_presets(&[], &presets).is_empty());
+    }
 }
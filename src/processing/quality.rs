@@ -0,0 +1,303 @@
+use crate::processing::error::ProcessingError;
+use crate::processing::save;
+use libvips::{ops, VipsImage};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Quality used when neither the request nor the server configures one.
+pub const DEFAULT_QUALITY: u8 = 85;
+/// SSIM target used for `quality:auto` when neither the request nor the config sets one.
+pub const DEFAULT_TARGET_SSIM: f64 = 0.97;
+/// Lowest quality the automatic search will consider.
+const MIN_AUTO_QUALITY: u8 = 30;
+/// Highest quality the automatic search will consider, and the fallback when no candidate reaches the target.
+const MAX_AUTO_QUALITY: u8 = 95;
+/// Lowest quality `max_bytes` lowers an encode to before moving on to the next format.
+const MIN_BUDGET_QUALITY: u8 = MIN_AUTO_QUALITY;
+/// Longest edge of the grayscale thumbnails compared when scoring a candidate encode.
+const SSIM_SAMPLE_SIZE: f64 = 256.0;
+/// Side length of the square windows SSIM statistics are computed over.
+const SSIM_WINDOW: usize = 8;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Server-configured qualities for requests that do not set `quality` themselves.
+#[derive(Debug, Clone, Default)]
+pub struct QualityDefaults {
+    /// Quality per output format, keyed by lowercase format name (`jpeg`, `webp`, ...).
+    pub per_format: HashMap<String, u8>,
+    /// Quality for formats without a per-format entry.
+    pub global: Option<u8>,
+}
+
+/// Resolves the encoding quality for `format`.
+///
+/// `requested` already reflects both the URL and any presets, since inline options are applied
+/// after presets. The lookup order is: requested, per-format default, global default, then
+/// `DEFAULT_QUALITY`.
+pub fn resolve_quality(requested: Option<u8>, format: &str, defaults: &QualityDefaults) -> u8 {
+    let format = match format.to_lowercase().as_str() {
+        "jpg" => "jpeg".to_string(),
+        other => other.to_string(),
+    };
+
+    requested
+        .or_else(|| defaults.per_format.get(&format).copied())
+        .or(defaults.global)
+        .unwrap_or(DEFAULT_QUALITY)
+}
+
+/// Grayscale 8-bit samples of an image, downscaled for fast comparison.
+pub struct LumaSamples {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// Returns whether `save::save_image` maps quality onto a lossy encoder for this format.
+pub fn supports_quality_search(format: &str) -> bool {
+    matches!(
+        format.to_lowercase().as_str(),
+        "jpeg" | "jpg" | "webp" | "avif" | "tiff"
+    )
+}
+
+/// Reduces an image to downscaled grayscale samples suitable for `ssim`.
+pub fn luma_samples(img: &VipsImage) -> Result<LumaSamples, ProcessingError> {
+    let longest = img.get_width().max(img.get_height()) as f64;
+    let scaled = if longest > SSIM_SAMPLE_SIZE {
+        ops::resize(img, SSIM_SAMPLE_SIZE / longest)
+            .map_err(|e| ProcessingError::Encode(format!("Error scaling image for SSIM: {}", e)))?
+    } else {
+        ops::copy(img).map_err(|e| ProcessingError::Encode(format!("Error copying image for SSIM: {}", e)))?
+    };
+
+    let gray = ops::colourspace(&scaled, ops::Interpretation::BW)
+        .map_err(|e| ProcessingError::Encode(format!("Error converting image to grayscale for SSIM: {}", e)))?;
+    let luma = ops::extract_band(&gray, 0)
+        .map_err(|e| ProcessingError::Encode(format!("Error extracting luma band for SSIM: {}", e)))?;
+    let luma = ops::cast(&luma, ops::BandFormat::Uchar)
+        .map_err(|e| ProcessingError::Encode(format!("Error casting luma for SSIM: {}", e)))?;
+
+    Ok(LumaSamples {
+        width: luma.get_width() as usize,
+        height: luma.get_height() as usize,
+        pixels: luma.image_write_to_memory(),
+    })
+}
+
+/// Computes the mean structural similarity of two equally sized sample sets.
+///
+/// Statistics are gathered over non-overlapping windows, which is coarser than the reference
+/// Gaussian-weighted SSIM but cheap enough to run once per candidate encode.
+pub fn ssim(a: &LumaSamples, b: &LumaSamples) -> Result<f64, ProcessingError> {
+    if a.width != b.width || a.height != b.height {
+        return Err(ProcessingError::Encode(format!(
+            "Cannot compare {}x{} samples with {}x{} samples",
+            a.width, a.height, b.width, b.height
+        )));
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0usize;
+    for y0 in (0..a.height).step_by(SSIM_WINDOW) {
+        for x0 in (0..a.width).step_by(SSIM_WINDOW) {
+            let y1 = (y0 + SSIM_WINDOW).min(a.height);
+            let x1 = (x0 + SSIM_WINDOW).min(a.width);
+            let n = ((y1 - y0) * (x1 - x0)) as f64;
+
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let pa = a.pixels[y * a.width + x] as f64;
+                    let pb = b.pixels[y * b.width + x] as f64;
+                    sum_a += pa;
+                    sum_b += pb;
+                    sum_aa += pa * pa;
+                    sum_bb += pb * pb;
+                    sum_ab += pa * pb;
+                }
+            }
+
+            let mean_a = sum_a / n;
+            let mean_b = sum_b / n;
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covar = sum_ab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covar + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        return Err(ProcessingError::Encode(
+            "Cannot compute SSIM of an empty image".to_string(),
+        ));
+    }
+    Ok(total / windows as f64)
+}
+
+/// Encodes `img` at the lowest quality whose decoded result reaches `target` SSIM against `img`.
+///
+/// Binary-searches the quality range, so it costs several encode/decode round trips. Returns the
+/// encoded bytes and the quality that produced them.
+pub fn encode_with_target_ssim(
+    img: &VipsImage,
+    format: &str,
+    target: f64,
+    options: save::SaveOptions,
+) -> Result<(Vec<u8>, u8), ProcessingError> {
+    let reference = luma_samples(img)?;
+    let mut low = MIN_AUTO_QUALITY;
+    let mut high = MAX_AUTO_QUALITY;
+    let mut best: Option<(Vec<u8>, u8)> = None;
+
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let copy =
+            ops::copy(img).map_err(|e| ProcessingError::Encode(format!("Error copying image for encoding: {}", e)))?;
+        let encoded = save::save_image_with_options(copy, format, quality, options)?;
+        let decoded = VipsImage::new_from_buffer(&encoded, "")
+            .map_err(|e| ProcessingError::Encode(format!("Error decoding candidate encode for SSIM: {}", e)))?;
+        let score = ssim(&reference, &luma_samples(&decoded)?)?;
+        debug!("Auto quality candidate q={} scored SSIM {:.4}", quality, score);
+
+        if score >= target {
+            best = Some((encoded, quality));
+            high = quality - 1;
+        } else {
+            low = quality + 1;
+        }
+    }
+
+    match best {
+        Some(found) => Ok(found),
+        None => {
+            let copy = ops::copy(img)
+                .map_err(|e| ProcessingError::Encode(format!("Error copying image for encoding: {}", e)))?;
+            Ok((
+                save::save_image_with_options(copy, format, MAX_AUTO_QUALITY, options)?,
+                MAX_AUTO_QUALITY,
+            ))
+        }
+    }
+}
+
+/// An encode produced by `encode_within_budget`.
+#[derive(Debug)]
+pub struct BudgetedEncode {
+    pub bytes: Vec<u8>,
+    /// Format the bytes are encoded in, which differs from the requested one after a switch.
+    pub format: String,
+    pub quality: u8,
+}
+
+/// Encodes `img` in at most `max_bytes`, keeping as much quality as possible.
+///
+/// The requested format is tried first, lowering quality from `quality` down to
+/// `MIN_BUDGET_QUALITY`. If that cannot fit, the formats listed after it in `ladder` are tried
+/// in order the same way; formats this libvips build cannot encode are skipped. When nothing
+/// fits, the smallest encode is returned so the request still succeeds.
+pub fn encode_within_budget(
+    img: &VipsImage,
+    format: &str,
+    quality: u8,
+    max_bytes: usize,
+    ladder: &[String],
+    options: save::SaveOptions,
+) -> Result<BudgetedEncode, ProcessingError> {
+    let mut smallest: Option<BudgetedEncode> = None;
+    for candidate in budget_formats(format, ladder) {
+        let encoded = match encode_largest_fitting(img, &candidate, quality, max_bytes, options) {
+            Ok(encoded) => encoded,
+            Err(ProcessingError::Unsupported(e)) if candidate != format => {
+                debug!("Skipping {} for max_bytes: {}", candidate, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if encoded.bytes.len() <= max_bytes {
+            return Ok(encoded);
+        }
+        debug!(
+            "Smallest {} encode is {} bytes, over the {} byte budget",
+            candidate,
+            encoded.bytes.len(),
+            max_bytes
+        );
+        if smallest
+            .as_ref()
+            .is_none_or(|best| encoded.bytes.len() < best.bytes.len())
+        {
+            smallest = Some(encoded);
+        }
+    }
+
+    smallest.ok_or_else(|| ProcessingError::Encode(format!("No encode produced for format '{}'", format)))
+}
+
+/// Lists the formats `encode_within_budget` tries: the requested one, then those after it in
+/// the ladder. A format missing from the ladder never switches.
+fn budget_formats(format: &str, ladder: &[String]) -> Vec<String> {
+    let format = match format.to_lowercase().as_str() {
+        "jpg" => "jpeg".to_string(),
+        other => other.to_string(),
+    };
+    let next = ladder
+        .iter()
+        .position(|entry| *entry == format)
+        .map_or(&[][..], |index| &ladder[index + 1..]);
+    std::iter::once(format).chain(next.iter().cloned()).collect()
+}
+
+/// Encodes `img` at the highest quality up to `quality` whose output fits in `max_bytes`, or at
+/// the lowest quality tried when none does. Formats without a quality setting are encoded once.
+fn encode_largest_fitting(
+    img: &VipsImage,
+    format: &str,
+    quality: u8,
+    max_bytes: usize,
+    options: save::SaveOptions,
+) -> Result<BudgetedEncode, ProcessingError> {
+    let encode = |quality: u8| {
+        let copy =
+            ops::copy(img).map_err(|e| ProcessingError::Encode(format!("Error copying image for encoding: {}", e)))?;
+        save::save_image_with_options(copy, format, quality, options).map(|bytes| BudgetedEncode {
+            bytes,
+            format: format.to_string(),
+            quality,
+        })
+    };
+
+    let first = encode(quality)?;
+    if first.bytes.len() <= max_bytes || !supports_quality_search(format) || quality <= MIN_BUDGET_QUALITY {
+        return Ok(first);
+    }
+
+    let mut low = MIN_BUDGET_QUALITY;
+    let mut high = quality - 1;
+    let mut best: Option<BudgetedEncode> = None;
+    let mut smallest = first;
+    while low <= high {
+        let candidate = encode(low + (high - low) / 2)?;
+        debug!(
+            "Max bytes candidate {} q={} is {} bytes",
+            format,
+            candidate.quality,
+            candidate.bytes.len()
+        );
+        if candidate.bytes.len() <= max_bytes {
+            low = candidate.quality + 1;
+            best = Some(candidate);
+        } else {
+            high = candidate.quality - 1;
+            if candidate.bytes.len() < smallest.bytes.len() {
+                smallest = candidate;
+            }
+        }
+    }
+
+    Ok(best.unwrap_or(smallest))
+}
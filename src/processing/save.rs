@@ -1,42 +1,265 @@
+use crate::processing::error::ProcessingError;
+use exif::{In, Tag, Value};
 use libvips::{bindings, ops, VipsImage};
 use std::collections::HashSet;
 use std::ffi::CString;
+use std::io::Cursor;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::OnceLock;
 
+/// Prefix of errors raised when an encoder hands back an unusable buffer.
+const INVALID_OUTPUT_ERROR: &str = "Encoded output is invalid";
+/// Bits per sample accepted for PNG output.
+pub const PNG_BIT_DEPTHS: [u8; 5] = [1, 2, 4, 8, 16];
+/// PNG bit depth used when none is requested.
+const DEFAULT_PNG_BIT_DEPTH: u8 = 8;
+/// Bits per sample for AVIF output. libvips would otherwise write 12-bit files, which fewer
+/// decoders handle.
+const AVIF_BIT_DEPTH: i32 = 8;
+/// zlib level used for PNG output at quality 100, the libvips default.
+const MIN_PNG_COMPRESSION: i32 = 6;
+/// zlib level used for PNG output at the lowest quality.
+const MAX_PNG_COMPRESSION: i32 = 9;
+/// Valid EXIF orientation values, from upright (1) to rotated 90° counter-clockwise (8).
+pub const EXIF_ORIENTATIONS: std::ops::RangeInclusive<u32> = 1..=8;
+/// Identifier opening a JPEG APP1 segment that carries EXIF data.
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+/// JPEG marker of the JFIF APP0 segment, which must stay first.
+const JPEG_APP0: u8 = 0xE0;
+/// JPEG marker of the APP1 segment used for EXIF.
+const JPEG_APP1: u8 = 0xE1;
+/// JPEG start-of-scan marker, after which entropy-coded data follows.
+const JPEG_SOS: u8 = 0xDA;
+
+/// Encoder settings beyond format and quality.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaveOptions {
+    /// Bits per sample for PNG output. `None` keeps the 8-bit default; other formats ignore it.
+    pub bitdepth: Option<u8>,
+    /// Drop EXIF, XMP and IPTC metadata from the output, keeping only the ICC profile.
+    pub strip_metadata: bool,
+    /// Also drop the ICC profile when `strip_metadata` is set. Ignored otherwise, as the savers
+    /// cannot keep the other metadata without the profile.
+    pub strip_profile: bool,
+    /// Resolution to record in the output, in dots per inch. `None` keeps the image's own.
+    pub dpi: Option<f64>,
+}
+
 /// Saves an image to bytes in the specified format.
-pub fn save_image(img: VipsImage, format: &str, quality: u8) -> Result<Vec<u8>, String> {
-    let format = format.to_lowercase();
+pub fn save_image(img: VipsImage, format: &str, quality: u8) -> Result<Vec<u8>, ProcessingError> {
+    save_image_with_options(img, format, quality, SaveOptions::default())
+}
+
+/// Saves an image like `save_image`, applying the extra encoder settings in `options`.
+pub fn save_image_with_options(
+    img: VipsImage,
+    format: &str,
+    quality: u8,
+    options: SaveOptions,
+) -> Result<Vec<u8>, ProcessingError> {
+    let img = match options.dpi {
+        Some(dpi) => with_resolution(img, dpi)?,
+        None => img,
+    };
+    let encoded = encode_with_format(img, format, quality, options)?;
+    validate_encoded_output(&encoded, format)?;
+    Ok(encoded)
+}
 
+/// Saves an animation stored as a vertical strip of `frame_height` pixel frames.
+///
+/// Only formats that carry animation are accepted. Frame delays and the loop count are kept from
+/// the source metadata; the quality setting does not apply.
+pub fn save_animation(
+    img: VipsImage,
+    format: &str,
+    frame_height: i32,
+    options: SaveOptions,
+) -> Result<Vec<u8>, ProcessingError> {
+    let format = format.to_lowercase();
+    if !matches!(format.as_str(), "webp" | "gif") {
+        return Err(ProcessingError::Unsupported(format!(
+            "Animated output is not supported for format '{}'",
+            format
+        )));
+    }
     if !is_format_supported(&format) {
-        return Err(format!(
+        return Err(ProcessingError::Unsupported(format!(
             "Output format '{}' is not supported by this libvips build",
             format
+        )));
+    }
+
+    let keep = keep_option(options);
+    // The saver reads the frame layout from its options, as the image's own page height refers
+    // to the source frames.
+    let suffix = format!(".{}[page-height={},keep={}]", format, frame_height, keep);
+    let encoded = encode_image("animation", || img.image_write_to_buffer(&suffix))?;
+    validate_encoded_output(&encoded, &format)?;
+    Ok(encoded)
+}
+
+/// Sets the EXIF orientation tag of an encoded JPEG without touching its pixels.
+///
+/// The other primary-image EXIF fields are kept; any existing EXIF segment is replaced, and the
+/// EXIF thumbnail is dropped.
+pub fn set_jpeg_orientation(encoded: &[u8], orientation: u32) -> Result<Vec<u8>, ProcessingError> {
+    if !encoded.starts_with(&[0xFF, 0xD8]) {
+        return Err(ProcessingError::Encode(
+            "Cannot set EXIF orientation: output is not a JPEG".to_string(),
         ));
     }
 
+    let existing = exif::Reader::new().read_from_container(&mut Cursor::new(encoded)).ok();
+    let orientation_field = exif::Field {
+        tag: Tag::Orientation,
+        ifd_num: In::PRIMARY,
+        value: Value::Short(vec![orientation as u16]),
+    };
+    let mut writer = exif::experimental::Writer::new();
+    writer.push_field(&orientation_field);
+    if let Some(ref existing) = existing {
+        for field in existing
+            .fields()
+            .filter(|field| field.ifd_num == In::PRIMARY && field.tag != Tag::Orientation)
+        {
+            writer.push_field(field);
+        }
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    let little_endian = existing.as_ref().is_none_or(|exif| exif.little_endian());
+    writer
+        .write(&mut tiff, little_endian)
+        .map_err(|e| ProcessingError::Encode(format!("Error writing EXIF orientation: {}", e)))?;
+    let tiff = tiff.into_inner();
+
+    let segment_len = u16::try_from(2 + JPEG_EXIF_HEADER.len() + tiff.len())
+        .map_err(|_| ProcessingError::Encode("EXIF metadata is too large for a JPEG segment".to_string()))?;
+    let mut exif_segment = vec![0xFF, JPEG_APP1];
+    exif_segment.extend_from_slice(&segment_len.to_be_bytes());
+    exif_segment.extend_from_slice(JPEG_EXIF_HEADER);
+    exif_segment.extend_from_slice(&tiff);
+
+    // Walk the header segments up to the scan data, dropping old EXIF and placing the new segment
+    // after the JFIF APP0 segment, which readers expect first.
+    let mut output = Vec::with_capacity(encoded.len() + exif_segment.len());
+    output.extend_from_slice(&encoded[..2]);
+    let mut pos = 2;
+    let mut inserted = false;
+    loop {
+        let Some(&[0xFF, marker, len_hi, len_lo]) = encoded.get(pos..pos + 4) else {
+            return Err(ProcessingError::Encode(
+                "Cannot set EXIF orientation: malformed JPEG header".to_string(),
+            ));
+        };
+        if marker == JPEG_SOS {
+            break;
+        }
+        let end = pos + 2 + u16::from_be_bytes([len_hi, len_lo]) as usize;
+        let Some(segment) = encoded.get(pos..end) else {
+            return Err(ProcessingError::Encode(
+                "Cannot set EXIF orientation: truncated JPEG segment".to_string(),
+            ));
+        };
+        if !inserted && marker != JPEG_APP0 {
+            output.extend_from_slice(&exif_segment);
+            inserted = true;
+        }
+        let is_exif = marker == JPEG_APP1 && segment.get(4..).is_some_and(|data| data.starts_with(JPEG_EXIF_HEADER));
+        if !is_exif {
+            output.extend_from_slice(segment);
+        }
+        pos = end;
+    }
+    if !inserted {
+        output.extend_from_slice(&exif_segment);
+    }
+    output.extend_from_slice(&encoded[pos..]);
+    Ok(output)
+}
+
+/// Checks that an encoded buffer is non-empty and, in debug builds, that libvips can decode it again.
+pub fn validate_encoded_output(encoded: &[u8], format: &str) -> Result<(), ProcessingError> {
+    if encoded.is_empty() {
+        return Err(ProcessingError::Encode(format!(
+            "{}: {} encoder returned an empty buffer",
+            INVALID_OUTPUT_ERROR, format
+        )));
+    }
+
+    if cfg!(debug_assertions) {
+        VipsImage::new_from_buffer(encoded, "").map_err(|e| {
+            ProcessingError::Encode(format!(
+                "{}: {} output cannot be decoded: {}",
+                INVALID_OUTPUT_ERROR, format, e
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+fn encode_with_format(
+    img: VipsImage,
+    format: &str,
+    quality: u8,
+    options: SaveOptions,
+) -> Result<Vec<u8>, ProcessingError> {
+    let format = format.to_lowercase();
+
+    if !is_format_supported(&format) {
+        return Err(ProcessingError::Unsupported(format!(
+            "Output format '{}' is not supported by this libvips build",
+            format
+        )));
+    }
+
     // map quality to effort (1-10), higher quality = more effort
     let effort = ((quality as i32).clamp(1, 100) / 10).clamp(1, 10);
+    let keep = match keep_option(options) {
+        "none" => ops::ForeignKeep::None,
+        "icc" => ops::ForeignKeep::Icc,
+        _ => ops::ForeignKeep::All,
+    };
     match format.as_str() {
         "jpeg" | "jpg" => encode_image("JPEG", || {
             let opts = ops::JpegsaveBufferOptions {
                 q: quality as i32,
                 optimize_coding: true,
+                keep,
                 ..Default::default()
             };
             ops::jpegsave_buffer_with_opts(&img, &opts)
         }),
-        "png" => encode_image("PNG", || {
-            let opts = ops::PngsaveBufferOptions {
-                effort,
-                ..Default::default()
-            };
-            ops::pngsave_buffer_with_opts(&img, &opts)
-        }),
+        "png" => {
+            let bitdepth = options.bitdepth.unwrap_or(DEFAULT_PNG_BIT_DEPTH);
+            if !PNG_BIT_DEPTHS.contains(&bitdepth) {
+                return Err(ProcessingError::Unsupported(format!(
+                    "Unsupported PNG bit depth: {}",
+                    bitdepth
+                )));
+            }
+            let img = if bitdepth == 16 { to_16_bit(img)? } else { img };
+            encode_image("PNG", || {
+                let opts = ops::PngsaveBufferOptions {
+                    compression: png_compression(quality),
+                    effort,
+                    bitdepth: bitdepth as i32,
+                    keep,
+                    ..Default::default()
+                };
+                ops::pngsave_buffer_with_opts(&img, &opts)
+            })
+        }
         "webp" => encode_image("WebP", || {
-            // Note: WebpsaveBufferOptions in libvips 1.7.1 causes crashes when used with _with_opts.
-            // Using default save for WebP until the library is updated.
-            ops::webpsave_buffer(&img)
+            // WebpsaveBufferOptions crashes when passed to webpsave_buffer_with_opts, so the
+            // settings go through the saver's option string instead.
+            let suffix = format!(
+                ".webp[Q={},keep={}]",
+                (quality as i32).clamp(1, 100),
+                keep_option(options)
+            );
+            img.image_write_to_buffer(&suffix)
         }),
         "tiff" => encode_image("TIFF", || {
             let clamped_quality = (quality as i32).clamp(1, 100);
@@ -50,33 +273,121 @@ pub fn save_image(img: VipsImage, format: &str, quality: u8) -> Result<Vec<u8>,
             let opts = ops::TiffsaveBufferOptions {
                 q: clamped_quality,
                 compression,
+                keep,
                 ..Default::default()
             };
 
             ops::tiffsave_buffer_with_opts(&img, &opts)
         }),
+        "avif" => encode_image("AVIF", || {
+            let opts = ops::HeifsaveBufferOptions {
+                q: (quality as i32).clamp(1, 100),
+                bitdepth: AVIF_BIT_DEPTH,
+                compression: ops::ForeignHeifCompression::Av1,
+                keep,
+                ..Default::default()
+            };
+            ops::heifsave_buffer_with_opts(&img, &opts)
+        }),
         "gif" => encode_image("GIF", || {
             let opts = ops::GifsaveBufferOptions {
                 effort,
+                keep,
                 ..Default::default()
             };
 
             ops::gifsave_buffer_with_opts(&img, &opts)
         }),
-        _ => Err(format!("Unsupported output format: {}", format)),
+        _ => Err(ProcessingError::Unsupported(format!(
+            "Unsupported output format: {}",
+            format
+        ))),
     }
 }
 
-fn encode_image<F>(label: &str, op: F) -> Result<Vec<u8>, String>
+/// Returns the metadata the savers keep, in libvips option-string form.
+fn keep_option(options: SaveOptions) -> &'static str {
+    match (options.strip_metadata, options.strip_profile) {
+        (false, _) => "all",
+        (true, false) => "icc",
+        (true, true) => "none",
+    }
+}
+
+/// Maps quality onto a zlib level for PNG output. PNG stays lossless, so lower qualities only
+/// spend more encode time on a smaller file.
+fn png_compression(quality: u8) -> i32 {
+    let spread = MAX_PNG_COMPRESSION - MIN_PNG_COMPRESSION;
+    MAX_PNG_COMPRESSION - ((quality as i32).clamp(1, 100) * spread / 100)
+}
+
+/// Returns whether the output format typically encodes chroma at half resolution (4:2:0),
+/// which makes odd output dimensions prone to edge artifacts.
+pub fn uses_chroma_subsampling(format: &str) -> bool {
+    matches!(
+        format.to_lowercase().as_str(),
+        "jpeg" | "jpg" | "webp" | "avif" | "heif"
+    )
+}
+
+/// Widens 8-bit images to 16 bits per sample so 16-bit PNG output keeps the full value range.
+/// Images that are already 16-bit pass through untouched.
+fn to_16_bit(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    if matches!(img.get_format(), Ok(ops::BandFormat::Ushort)) {
+        return Ok(img);
+    }
+
+    let interpretation = if img.get_bands() <= 2 {
+        ops::Interpretation::Grey16
+    } else {
+        ops::Interpretation::Rgb16
+    };
+    ops::colourspace(&img, interpretation)
+        .map_err(|e| ProcessingError::Encode(format!("Error converting image to 16-bit: {}", e)))
+}
+
+/// Sets the image resolution, which encoders write as JFIF density, PNG `pHYs` or TIFF resolution.
+fn with_resolution(img: VipsImage, dpi: f64) -> Result<VipsImage, ProcessingError> {
+    let pixels_per_mm = dpi / 25.4;
+    let opts = ops::CopyOptions {
+        width: img.get_width(),
+        height: img.get_height(),
+        bands: img.get_bands(),
+        format: img
+            .get_format()
+            .map_err(|e| ProcessingError::Encode(format!("Error reading image format: {}", e)))?,
+        coding: img
+            .get_coding()
+            .map_err(|e| ProcessingError::Encode(format!("Error reading image coding: {}", e)))?,
+        interpretation: img
+            .get_interpretation()
+            .map_err(|e| ProcessingError::Encode(format!("Error reading image interpretation: {}", e)))?,
+        xres: pixels_per_mm,
+        yres: pixels_per_mm,
+        xoffset: img.get_xoffset(),
+        yoffset: img.get_yoffset(),
+    };
+    ops::copy_with_opts(&img, &opts)
+        .map_err(|e| ProcessingError::Encode(format!("Error setting image resolution: {}", e)))
+}
+
+/// Returns whether the output format can store an alpha channel. Images saved in other formats
+/// lose transparency, so they are flattened onto the background first.
+pub fn supports_alpha(format: &str) -> bool {
+    !matches!(format.to_lowercase().as_str(), "jpeg" | "jpg")
+}
+
+fn encode_image<F>(label: &str, op: F) -> Result<Vec<u8>, ProcessingError>
 where
     F: FnOnce() -> libvips::Result<Vec<u8>>,
 {
     catch_unwind(AssertUnwindSafe(op))
-        .map_err(|_| format!("Error encoding {}: libvips call panicked", label))?
-        .map_err(|e| format!("Error encoding {}: {}", label, e))
+        .map_err(|_| ProcessingError::Encode(format!("Error encoding {}: libvips call panicked", label)))?
+        .map_err(|e| ProcessingError::Encode(format!("Error encoding {}: {}", label, e)))
 }
 
-fn is_format_supported(format: &str) -> bool {
+/// Returns whether this libvips build can encode `format`.
+pub fn is_format_supported(format: &str) -> bool {
     let lower = format.to_lowercase();
     let supported = supported_formats();
     if supported.contains(&lower) {
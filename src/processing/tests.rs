@@ -29,3 +29,7 @@ mod watermark_tests;
 #[cfg(test)]
 #[path = "tests/pipeline_tests.rs"]
 mod pipeline_tests;
+
+#[cfg(test)]
+#[path = "tests/quality_tests.rs"]
+mod quality_tests;
@@ -28,15 +28,158 @@ fn test_apply_rotation() {
     assert_eq!(rotated_img.get_height(), 100);
 }
 
+#[test]
+fn test_apply_flop_swaps_left_and_right() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_quadrant_test_image(4, 4), "").unwrap();
+    let before = decode_rgba(&img);
+    let flopped = decode_rgba(&transform::apply_flop(img).unwrap());
+    assert_eq!(rgba_pixel(&flopped, 0, 0), rgba_pixel(&before, 3, 0));
+    assert_eq!(rgba_pixel(&flopped, 3, 0), rgba_pixel(&before, 0, 0));
+    assert_eq!(rgba_pixel(&flopped, 0, 0), [0, 255, 0, 255]);
+}
+
+#[test]
+fn test_apply_flip_swaps_top_and_bottom() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_quadrant_test_image(4, 4), "").unwrap();
+    let flipped = decode_rgba(&transform::apply_flip(img).unwrap());
+    assert_eq!(rgba_pixel(&flipped, 0, 0), [0, 0, 255, 255]);
+    assert_eq!(rgba_pixel(&flipped, 0, 3), [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_apply_grayscale_drops_to_single_band() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image_jpeg(40, 30), "").unwrap();
+    assert_eq!(img.get_bands(), 3);
+    let gray = transform::apply_grayscale(img).unwrap();
+    assert_eq!(gray.get_bands(), 1);
+    assert_eq!((gray.get_width(), gray.get_height()), (40, 30));
+
+    // Alpha survives the conversion
+    let img = VipsImage::new_from_buffer(&create_test_image(40, 30), "").unwrap();
+    assert_eq!(transform::apply_grayscale(img).unwrap().get_bands(), 2);
+}
+
+const MUTED_BLUE: [u8; 4] = [100, 150, 200, 255];
+
+#[test]
+fn test_apply_contrast_scales_around_midtone() {
+    init_vips();
+    let source = create_framed_test_image(4, 4, 0, MUTED_BLUE, MUTED_BLUE);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let boosted = decode_rgba(&transform::apply_contrast(img, 2.0).unwrap());
+    assert_eq!(rgba_pixel(&boosted, 1, 1), [72, 172, 255, 255]);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let flat = decode_rgba(&transform::apply_contrast(img, 0.0).unwrap());
+    assert_eq!(rgba_pixel(&flat, 1, 1), [128, 128, 128, 255]);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let unchanged = decode_rgba(&transform::apply_contrast(img, 1.0).unwrap());
+    assert_eq!(rgba_pixel(&unchanged, 1, 1), MUTED_BLUE);
+}
+
+#[test]
+fn test_apply_saturation_scales_chroma() {
+    init_vips();
+    let source = create_framed_test_image(4, 4, 0, MUTED_BLUE, MUTED_BLUE);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let desaturated = transform::apply_saturation(img, 0.0).unwrap();
+    assert_eq!(desaturated.get_bands(), 4);
+    let [r, g, b, a] = rgba_pixel(&decode_rgba(&desaturated), 1, 1);
+    assert!(
+        r.abs_diff(g) <= 2 && g.abs_diff(b) <= 2,
+        "expected gray, got {:?}",
+        [r, g, b]
+    );
+    assert_eq!(a, 255);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let [r, _, b, _] = rgba_pixel(&decode_rgba(&transform::apply_saturation(img, 2.0).unwrap()), 1, 1);
+    assert!(b - r > 100, "expected a stronger blue, got r={} b={}", r, b);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let same = rgba_pixel(&decode_rgba(&transform::apply_saturation(img, 1.0).unwrap()), 1, 1);
+    assert!(same.iter().zip(MUTED_BLUE).all(|(got, want)| got.abs_diff(want) <= 2));
+}
+
+#[test]
+fn test_apply_saturation_skips_grayscale_images() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image_jpeg(8, 8), "").unwrap();
+    let gray = transform::apply_grayscale(img).unwrap();
+    assert_eq!(transform::apply_saturation(gray, 3.0).unwrap().get_bands(), 1);
+}
+
+#[test]
+fn test_apply_gamma_preserves_dimensions_and_alpha() {
+    init_vips();
+    let source = create_framed_test_image(40, 30, 0, [100, 150, 200, 128], [100, 150, 200, 128]);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let brightened = transform::apply_gamma(img, 2.2).unwrap();
+    assert_eq!(brightened.get_width(), 40);
+    assert_eq!(brightened.get_height(), 30);
+    assert_eq!(brightened.get_bands(), 4);
+    let [r, g, b, a] = rgba_pixel(&decode_rgba(&brightened), 1, 1);
+    assert!(
+        r > 100 && g > 150 && b > 200,
+        "expected brighter midtones, got {:?}",
+        [r, g, b]
+    );
+    assert_eq!(a, 128);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let unchanged = rgba_pixel(&decode_rgba(&transform::apply_gamma(img, 1.0).unwrap()), 1, 1);
+    assert!(unchanged
+        .iter()
+        .zip([100, 150, 200, 128])
+        .all(|(got, want)| got.abs_diff(want) <= 1));
+}
+
 #[test]
 fn test_apply_blur() {
     init_vips();
     let img = VipsImage::new_from_buffer(&create_test_image(100, 100), "").unwrap();
-    let blurred_img = transform::apply_blur(img, 5.0).unwrap();
+    let blurred_img = transform::apply_blur(img, 5.0, None).unwrap();
     assert_eq!(blurred_img.get_width(), 100);
     assert_eq!(blurred_img.get_height(), 100);
 }
 
+#[test]
+fn test_apply_blur_does_not_bleed_hidden_colour() {
+    init_vips();
+    // Opaque red on the left; the right half is fully transparent but hides green
+    let source = image::RgbaImage::from_fn(100, 100, |x, _| {
+        if x < 50 {
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            image::Rgba([0, 255, 0, 0])
+        }
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    source
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&bytes, "").unwrap();
+
+    let blurred = decode_rgba(&transform::apply_blur(img, 8.0, None).unwrap());
+
+    for (x, y, pixel) in blurred.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a > 0 {
+            assert!(r >= 250 && g <= 2 && b <= 2, "pixel ({}, {}) is {:?}", x, y, pixel.0);
+        }
+    }
+    // The blur spreads coverage into the transparent half without reaching its far edge
+    assert!(rgba_pixel(&blurred, 55, 50)[3] > 0);
+    assert_eq!(rgba_pixel(&blurred, 99, 50)[3], 0);
+}
+
 #[test]
 fn test_apply_background_color() {
     init_vips();
@@ -54,6 +197,16 @@ fn test_apply_background_color_no_alpha() {
     assert_eq!(bg_applied_img.get_bands(), bands_before);
 }
 
+#[test]
+fn test_apply_background_color_leaves_cmyk_bands_alone() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_cmyk_test_jpeg(40, 30), "").unwrap();
+    assert_eq!(img.get_bands(), 4);
+    let bg_applied_img = transform::apply_background_color(img, [255, 255, 255, 255]).unwrap();
+    // The black channel is not alpha, so flattening it away would corrupt the colours
+    assert_eq!(bg_applied_img.get_bands(), 4);
+}
+
 #[test]
 fn test_apply_min_dimensions() {
     init_vips();
@@ -234,7 +387,7 @@ fn test_apply_zoom_scale_up() {
 fn test_apply_blur_minimal() {
     init_vips();
     let img = VipsImage::new_from_buffer(&create_test_image(100, 100), "").unwrap();
-    let blurred = transform::apply_blur(img, 0.1).unwrap();
+    let blurred = transform::apply_blur(img, 0.1, None).unwrap();
     assert_eq!(blurred.get_width(), 100);
     assert_eq!(blurred.get_height(), 100);
 }
@@ -243,11 +396,32 @@ fn test_apply_blur_minimal() {
 fn test_apply_blur_extreme() {
     init_vips();
     let img = VipsImage::new_from_buffer(&create_test_image(100, 100), "").unwrap();
-    let blurred = transform::apply_blur(img, 50.0).unwrap();
+    let blurred = transform::apply_blur(img, 50.0, None).unwrap();
     assert_eq!(blurred.get_width(), 100);
     assert_eq!(blurred.get_height(), 100);
 }
 
+#[test]
+fn test_apply_blur_clamps_sigma_to_max() {
+    init_vips();
+    let source = image::RgbaImage::from_fn(100, 100, |x, _| {
+        if x < 50 {
+            image::Rgba([0, 0, 0, 255])
+        } else {
+            image::Rgba([255, 255, 255, 255])
+        }
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    source
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+
+    let clamped = transform::apply_blur(VipsImage::new_from_buffer(&bytes, "").unwrap(), 1000.0, Some(4.0)).unwrap();
+    let direct = transform::apply_blur(VipsImage::new_from_buffer(&bytes, "").unwrap(), 4.0, None).unwrap();
+
+    assert_eq!(decode_rgba(&clamped), decode_rgba(&direct));
+}
+
 // Sharpen edge cases
 #[test]
 fn test_apply_sharpen_minimal() {
@@ -285,3 +459,71 @@ fn test_apply_background_color_with_transparency() {
     // Should flatten to 3 bands (RGB)
     assert_eq!(result.get_bands(), 3);
 }
+
+#[test]
+fn test_apply_checkerboard_background_fills_transparent_regions() {
+    init_vips();
+    // Left half opaque red, right half fully transparent.
+    let source = image::RgbaImage::from_fn(32, 32, |x, _| {
+        if x < 16 {
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    source
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&bytes, "").unwrap();
+
+    let result = transform::apply_checkerboard_background(img).unwrap();
+    assert_eq!(result.get_bands(), 3);
+
+    let decoded = decode_rgba(&result);
+    assert_eq!(rgba_pixel(&decoded, 4, 4), [255, 0, 0, 255]);
+    // 8px squares alternate white and light gray across and down.
+    assert_eq!(rgba_pixel(&decoded, 20, 4), [255, 255, 255, 255]);
+    assert_eq!(rgba_pixel(&decoded, 28, 4), [204, 204, 204, 255]);
+    assert_eq!(rgba_pixel(&decoded, 20, 12), [204, 204, 204, 255]);
+    assert_eq!(rgba_pixel(&decoded, 28, 12), [255, 255, 255, 255]);
+}
+
+#[test]
+fn test_apply_checkerboard_background_no_alpha() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image_jpeg(20, 20), "").unwrap();
+    let result = transform::apply_checkerboard_background(img).unwrap();
+    assert_eq!(result.get_bands(), 3);
+}
+
+#[test]
+fn test_apply_border_radius_masks_corners() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image_jpeg(100, 100), "").unwrap();
+    assert_eq!(img.get_bands(), 3);
+
+    let rounded = transform::apply_border_radius(img, 20).unwrap();
+    assert_eq!((rounded.get_width(), rounded.get_height()), (100, 100));
+    assert_eq!(rounded.get_bands(), 4);
+    let decoded = decode_rgba(&rounded);
+    for (x, y) in [(0, 0), (99, 0), (0, 99), (99, 99), (2, 2)] {
+        assert_eq!(rgba_pixel(&decoded, x, y)[3], 0, "corner {},{}", x, y);
+    }
+    for (x, y) in [(50, 50), (50, 0), (0, 50), (20, 20)] {
+        assert_eq!(rgba_pixel(&decoded, x, y)[3], 255, "inside {},{}", x, y);
+    }
+}
+
+#[test]
+fn test_apply_border_radius_keeps_existing_transparency() {
+    init_vips();
+    let source = create_framed_test_image(40, 40, 0, [0, 0, 255, 128], [0, 0, 255, 128]);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+
+    // Oversized radii are capped at half the shorter side, producing a circle
+    let rounded = decode_rgba(&transform::apply_border_radius(img, 500).unwrap());
+    assert_eq!(rgba_pixel(&rounded, 20, 20)[3], 128);
+    assert_eq!(rgba_pixel(&rounded, 20, 3)[3], 128);
+    assert_eq!(rgba_pixel(&rounded, 3, 3)[3], 0);
+}
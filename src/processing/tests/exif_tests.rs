@@ -1,4 +1,8 @@
-use crate::processing::transform;
+use crate::processing::options::ParsedOptions;
+use crate::processing::{process_image, save, transform};
+use crate::utils::read_exif_orientation;
+use bytes::Bytes;
+use image::ImageEncoder;
 use libvips::VipsImage;
 
 use super::tests_support::*;
@@ -58,3 +62,194 @@ fn test_apply_exif_rotation_without_orientation_keeps_image_unchanged() {
     let decoded = decode_rgba(&rotated);
     assert_eq!(collect_rgba_pixels(&decoded), expected);
 }
+
+/// 30x20 JPEG, red on the left half and blue on the right, tagged with EXIF orientation 6.
+fn create_jpeg_with_orientation_6() -> Vec<u8> {
+    let img = image::RgbImage::from_fn(30, 20, |x, _| {
+        if x < 15 {
+            image::Rgb([255, 0, 0])
+        } else {
+            image::Rgb([0, 0, 255])
+        }
+    });
+
+    // Little-endian TIFF header with a single IFD0 entry: Orientation (SHORT) = 6.
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&0x0112u16.to_le_bytes());
+    exif.extend_from_slice(&3u16.to_le_bytes());
+    exif.extend_from_slice(&1u32.to_le_bytes());
+    exif.extend_from_slice(&[6, 0, 0, 0]);
+    exif.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 95);
+    encoder.set_exif_metadata(exif).unwrap();
+    encoder
+        .write_image(img.as_raw(), 30, 20, image::ExtendedColorType::Rgb8)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_auto_rotate_with_strip_metadata_bakes_rotation_and_drops_orientation() {
+    init_vips();
+    let source = create_jpeg_with_orientation_6();
+    assert_eq!(read_exif_orientation(&source), Some(6));
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("jpeg".to_string()),
+        auto_rotate: true,
+        strip_metadata: Some(true),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    assert_eq!(read_exif_orientation(&output), None);
+    let decoded = image::load_from_memory(&output).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (20, 30));
+    // Orientation 6 turns the original left edge into the top edge.
+    let top = decoded.get_pixel(10, 4).0;
+    let bottom = decoded.get_pixel(10, 25).0;
+    assert!(top[0] > 200 && top[2] < 60, "top should be red, got {:?}", top);
+    assert!(
+        bottom[2] > 200 && bottom[0] < 60,
+        "bottom should be blue, got {:?}",
+        bottom
+    );
+}
+
+#[test]
+fn test_auto_rotate_keeping_metadata_resets_orientation() {
+    init_vips();
+    let source = create_jpeg_with_orientation_6();
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("jpeg".to_string()),
+        auto_rotate: true,
+        strip_metadata: Some(false),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    // The pixels are already upright, so a kept orientation of 6 would rotate them again
+    assert_eq!(read_exif_orientation(&output).unwrap_or(1), 1);
+    let decoded = image::load_from_memory(&output).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (20, 30));
+    let top = decoded.get_pixel(10, 4).0;
+    assert!(top[0] > 200 && top[2] < 60, "top should be red, got {:?}", top);
+}
+
+/// 40x30 JPEG whose EXIF carries a 16 KB ImageDescription.
+fn create_jpeg_with_large_exif() -> Vec<u8> {
+    let img = image::RgbImage::from_pixel(40, 30, image::Rgb([40, 120, 200]));
+    let mut description = vec![b'x'; 16 * 1024];
+    description.push(0);
+
+    // IFD0 holds one entry, ImageDescription (ASCII), whose text follows the IFD at offset 26.
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&0x010Eu16.to_le_bytes());
+    exif.extend_from_slice(&2u16.to_le_bytes());
+    exif.extend_from_slice(&(description.len() as u32).to_le_bytes());
+    exif.extend_from_slice(&26u32.to_le_bytes());
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    exif.extend_from_slice(&description);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 90);
+    encoder.set_exif_metadata(exif).unwrap();
+    encoder
+        .write_image(img.as_raw(), 40, 30, image::ExtendedColorType::Rgb8)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_strip_metadata_drops_large_exif_block() {
+    init_vips();
+    let source = Bytes::from(create_jpeg_with_large_exif());
+    let encode = |strip_metadata: Option<bool>| {
+        let img = VipsImage::new_from_buffer(&source, "").unwrap();
+        let parsed_options = ParsedOptions {
+            format: Some("jpeg".to_string()),
+            strip_metadata,
+            ..Default::default()
+        };
+        process_image(img, parsed_options, &source, None).unwrap()
+    };
+
+    let kept = encode(Some(false));
+    let stripped = encode(Some(true));
+    assert!(
+        stripped.len() + 16 * 1024 <= kept.len(),
+        "stripped output is {} bytes, kept output {}",
+        stripped.len(),
+        kept.len()
+    );
+    // Stripping is the default
+    assert_eq!(encode(None).len(), stripped.len());
+}
+
+#[test]
+fn test_keep_profile_controls_icc_profile_of_stripped_output() {
+    let source = Bytes::from(create_display_p3_test_image(16, 16));
+    let encode = |keep_profile: bool| {
+        let img = VipsImage::new_from_buffer(&source, "").unwrap();
+        let parsed_options = ParsedOptions {
+            format: Some("png".to_string()),
+            strip_metadata: Some(true),
+            keep_profile,
+            ..Default::default()
+        };
+        process_image(img, parsed_options, &source, None).unwrap()
+    };
+
+    assert!(png_icc_profile(&encode(true)).is_some());
+    assert!(png_icc_profile(&encode(false)).is_none());
+}
+
+#[test]
+fn test_exif_orientation_sets_tag_without_changing_pixels() {
+    init_vips();
+    let source = Bytes::from(create_jpeg_with_orientation_6());
+    let encode = |exif_orientation: Option<u32>| {
+        let img = VipsImage::new_from_buffer(&source, "").unwrap();
+        let parsed_options = ParsedOptions {
+            format: Some("jpeg".to_string()),
+            auto_rotate: false,
+            exif_orientation,
+            strip_metadata: Some(true),
+            ..Default::default()
+        };
+        process_image(img, parsed_options, &source, None).unwrap()
+    };
+
+    let plain = encode(None);
+    let tagged = encode(Some(3));
+    assert_eq!(read_exif_orientation(&plain), None);
+    assert_eq!(read_exif_orientation(&tagged), Some(3));
+
+    let plain_pixels = image::load_from_memory(&plain).unwrap().to_rgb8();
+    let tagged_pixels = image::load_from_memory(&tagged).unwrap().to_rgb8();
+    assert_eq!(tagged_pixels.dimensions(), (30, 20));
+    assert_eq!(tagged_pixels, plain_pixels);
+}
+
+#[test]
+fn test_set_jpeg_orientation_replaces_existing_exif_segment() {
+    let source = create_jpeg_with_orientation_6();
+    let output = save::set_jpeg_orientation(&source, 8).unwrap();
+
+    assert_eq!(read_exif_orientation(&output), Some(8));
+    assert_eq!(output.windows(6).filter(|window| window == b"Exif\0\0").count(), 1);
+    assert_eq!(
+        image::load_from_memory(&output).unwrap().to_rgb8(),
+        image::load_from_memory(&source).unwrap().to_rgb8()
+    );
+    assert!(save::set_jpeg_orientation(&create_test_image(4, 4), 8).is_err());
+}
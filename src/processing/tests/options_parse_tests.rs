@@ -1,5 +1,9 @@
-use crate::processing::options::{parse_all_options, ProcessingOption};
+use crate::processing::options::{
+    parse_all_options, LabelOptions, ProcessingOption, DEFAULT_CONTACT_SHEET_COLUMNS, DEFAULT_TRIM_THRESHOLD,
+    MAX_COLOR_ADJUSTMENT,
+};
 use crate::processing::utils;
+use crate::url::parse_path;
 
 #[test]
 fn test_parse_all_options_empty() {
@@ -103,6 +107,25 @@ fn test_parse_gravity_option() {
     assert_eq!(parsed.gravity, Some("north".to_string()));
 }
 
+#[test]
+fn test_parse_focus_point_gravity() {
+    let gravity = |args: &[&str]| {
+        parse_all_options(vec![ProcessingOption {
+            name: "gravity".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }])
+    };
+
+    let parsed = gravity(&["fp", "0.5", "0.3"]).unwrap();
+    assert_eq!(parsed.gravity.as_deref(), Some("fp"));
+    assert_eq!(parsed.focus_point, Some((0.5, 0.3)));
+    assert_eq!(gravity(&["north"]).unwrap().focus_point, None);
+
+    assert!(gravity(&["fp", "0.5"]).is_err());
+    assert!(gravity(&["fp", "1.5", "0.3"]).is_err());
+    assert!(gravity(&["fp", "left", "0.3"]).is_err());
+}
+
 #[test]
 fn test_parse_crop_option() {
     let options = vec![ProcessingOption {
@@ -137,6 +160,49 @@ fn test_parse_dpr_option() {
     assert_eq!(parsed.dpr, Some(2.5));
 }
 
+#[test]
+fn test_parse_dpr_one_matches_default() {
+    let options = vec![ProcessingOption {
+        name: "dpr".to_string(),
+        args: vec!["1".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.dpr, None);
+    assert_eq!(parsed.dpr, parse_all_options(vec![]).unwrap().dpr);
+}
+
+#[test]
+fn test_parse_css_size_option() {
+    let parsed = parse_all_options(vec![ProcessingOption {
+        name: "css_size".to_string(),
+        args: vec!["320".to_string(), "180".to_string()],
+    }])
+    .unwrap();
+    let resize = parsed.resize.unwrap();
+    assert_eq!(
+        (resize.resizing_type.as_str(), resize.width, resize.height),
+        ("fit", 320, 180)
+    );
+    assert_eq!(parsed.dpr, None);
+
+    let parsed = parse_all_options(vec![ProcessingOption {
+        name: "cs".to_string(),
+        args: vec!["320".to_string(), "".to_string(), "2".to_string()],
+    }])
+    .unwrap();
+    let resize = parsed.resize.unwrap();
+    assert_eq!((resize.width, resize.height), (320, 0));
+    assert_eq!(parsed.dpr, Some(2.0));
+
+    for args in [vec!["320"], vec!["320", "180", "6"], vec!["wide", "180"]] {
+        assert!(parse_all_options(vec![ProcessingOption {
+            name: "css_size".to_string(),
+            args: args.into_iter().map(String::from).collect(),
+        }])
+        .is_err());
+    }
+}
+
 #[test]
 fn test_parse_auto_rotate_option() {
     let options = vec![ProcessingOption {
@@ -147,6 +213,125 @@ fn test_parse_auto_rotate_option() {
     assert!(!parsed.auto_rotate);
 }
 
+#[test]
+fn test_parse_exif_orientation_option() {
+    let parsed = parse_all_options(vec![ProcessingOption {
+        name: "exif_orientation".to_string(),
+        args: vec!["6".to_string()],
+    }])
+    .unwrap();
+    assert_eq!(parsed.exif_orientation, Some(6));
+    // The tag replaces rotation, even when auto_rotate is requested explicitly
+    assert!(!parsed.auto_rotate);
+
+    let parsed = parse_all_options(vec![
+        ProcessingOption {
+            name: "eo".to_string(),
+            args: vec!["2".to_string()],
+        },
+        ProcessingOption {
+            name: "auto_rotate".to_string(),
+            args: vec!["true".to_string()],
+        },
+    ])
+    .unwrap();
+    assert_eq!(parsed.exif_orientation, Some(2));
+    assert!(!parsed.auto_rotate);
+
+    for value in ["0", "9", "up"] {
+        assert!(parse_all_options(vec![ProcessingOption {
+            name: "exif_orientation".to_string(),
+            args: vec![value.to_string()],
+        }])
+        .is_err());
+    }
+
+    let result = parse_all_options(vec![
+        ProcessingOption {
+            name: "exif_orientation".to_string(),
+            args: vec!["6".to_string()],
+        },
+        ProcessingOption {
+            name: "format".to_string(),
+            args: vec!["png".to_string()],
+        },
+    ]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_parse_flip_and_flop_options() {
+    let parsed = parse_all_options(vec![]).unwrap();
+    assert!(!parsed.flip && !parsed.flop);
+
+    let options = vec![
+        ProcessingOption {
+            name: "flip".to_string(),
+            args: vec!["true".to_string()],
+        },
+        ProcessingOption {
+            name: "flop".to_string(),
+            args: vec!["1".to_string()],
+        },
+    ];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.flip);
+    assert!(parsed.flop);
+
+    let options = vec![ProcessingOption {
+        name: "flop".to_string(),
+        args: vec![],
+    }];
+    assert!(parse_all_options(options).is_err());
+}
+
+#[test]
+fn test_parse_grayscale_option() {
+    let options = vec![ProcessingOption {
+        name: "gs".to_string(),
+        args: vec!["true".to_string()],
+    }];
+    assert!(parse_all_options(options).unwrap().grayscale);
+
+    let options = vec![ProcessingOption {
+        name: "grayscale".to_string(),
+        args: vec!["false".to_string()],
+    }];
+    assert!(!parse_all_options(options).unwrap().grayscale);
+    assert!(!parse_all_options(vec![]).unwrap().grayscale);
+}
+
+#[test]
+fn test_parse_saturation_and_contrast() {
+    let parse = |name: &str, value: &str| {
+        parse_all_options(vec![ProcessingOption {
+            name: name.to_string(),
+            args: vec![value.to_string()],
+        }])
+    };
+
+    assert_eq!(parse("sat", "1.5").unwrap().saturation, Some(1.5));
+    assert_eq!(parse("saturation", "-1").unwrap().saturation, Some(0.0));
+    assert_eq!(parse("co", "0.8").unwrap().contrast, Some(0.8));
+    assert_eq!(parse("contrast", "50").unwrap().contrast, Some(MAX_COLOR_ADJUSTMENT));
+    assert!(parse("contrast", "high").is_err());
+    assert!(parse("saturation", "NaN").is_err());
+}
+
+#[test]
+fn test_parse_gamma_option() {
+    let parse = |value: &str| {
+        parse_all_options(vec![ProcessingOption {
+            name: "gamma".to_string(),
+            args: vec![value.to_string()],
+        }])
+    };
+    assert_eq!(parse("2.2").unwrap().gamma, Some(2.2));
+    assert!(parse("0.05").is_err());
+    assert!(parse("5.5").is_err());
+    assert!(parse("bright").is_err());
+}
+
 #[test]
 fn test_parse_raw_option() {
     let options = vec![ProcessingOption {
@@ -361,6 +546,37 @@ fn test_parse_dpr_below_minimum() {
     assert!(parse_all_options(options).is_err());
 }
 
+#[test]
+fn test_parse_region_option() {
+    let region = |args: &[&str]| ProcessingOption {
+        name: "region".to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    };
+    let parsed = parse_all_options(vec![region(&["256", "512", "256", "256"])]).unwrap();
+    let parsed_region = parsed.region.unwrap();
+    assert_eq!(
+        (
+            parsed_region.x,
+            parsed_region.y,
+            parsed_region.width,
+            parsed_region.height
+        ),
+        (256, 512, 256, 256)
+    );
+
+    assert!(parse_all_options(vec![region(&["0", "0", "256"])]).is_err());
+    assert!(parse_all_options(vec![region(&["0", "0", "0", "256"])])
+        .unwrap_err()
+        .contains("greater than 0"));
+    let crop = ProcessingOption {
+        name: "crop".to_string(),
+        args: vec!["0".to_string(), "0".to_string(), "10".to_string(), "10".to_string()],
+    };
+    assert!(parse_all_options(vec![region(&["0", "0", "256", "256"]), crop])
+        .unwrap_err()
+        .contains("cannot be combined"));
+}
+
 #[test]
 fn test_parse_crop_invalid_args() {
     let options = vec![ProcessingOption {
@@ -403,6 +619,37 @@ fn test_parse_hex_color_wrong_length() {
     assert!(utils::parse_hex_color("fffffff").is_err());
 }
 
+#[test]
+fn test_parse_color_named() {
+    assert_eq!(utils::parse_color("white").unwrap(), [255, 255, 255, 255]);
+    assert_eq!(utils::parse_color("Black").unwrap(), [0, 0, 0, 255]);
+    assert_eq!(utils::parse_color("red").unwrap(), [255, 0, 0, 255]);
+    assert_eq!(utils::parse_color("transparent").unwrap(), [0, 0, 0, 0]);
+    assert_eq!(utils::parse_color("grey").unwrap(), utils::parse_color("gray").unwrap());
+}
+
+#[test]
+fn test_parse_color_falls_back_to_hex() {
+    assert_eq!(utils::parse_color("#00ff00").unwrap(), [0, 255, 0, 255]);
+    assert!(utils::parse_color("fff").is_err());
+}
+
+#[test]
+fn test_parse_color_unknown_name() {
+    let err = utils::parse_color("notacolor").unwrap_err();
+    assert_eq!(err, "Unknown color: notacolor");
+}
+
+#[test]
+fn test_parse_background_named_color() {
+    let options = vec![ProcessingOption {
+        name: "bg".to_string(),
+        args: vec!["white".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.background, Some([255, 255, 255, 255]));
+}
+
 #[test]
 fn test_parse_boolean_true_variants() {
     assert!(utils::parse_boolean("1"));
@@ -654,6 +901,16 @@ fn test_parse_resizing_algorithm_case_insensitive() {
     assert_eq!(parsed.resizing_algorithm, Some("lanczos3".to_string()));
 }
 
+#[test]
+fn test_parse_resizing_algorithm_auto() {
+    let options = vec![ProcessingOption {
+        name: "ra".to_string(),
+        args: vec!["auto".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.resizing_algorithm, Some("auto".to_string()));
+}
+
 #[test]
 fn test_parse_resizing_algorithm_invalid() {
     let options = vec![ProcessingOption {
@@ -664,3 +921,549 @@ fn test_parse_resizing_algorithm_invalid() {
     assert!(result.is_err());
     assert!(result.unwrap_err().contains("Invalid resizing algorithm"));
 }
+
+#[test]
+fn test_parse_even_dimensions() {
+    let options = vec![ProcessingOption {
+        name: "ed".to_string(),
+        args: vec!["1".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.even_dimensions, Some(true));
+}
+
+#[test]
+fn test_parse_even_dimensions_defaults_to_server_setting() {
+    let parsed = parse_all_options(vec![]).unwrap();
+    assert_eq!(parsed.even_dimensions, None);
+}
+
+#[test]
+fn test_parse_quality_auto() {
+    let options = vec![ProcessingOption {
+        name: "q".to_string(),
+        args: vec!["auto".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.auto_quality);
+    assert_eq!(parsed.auto_quality_target, None);
+    assert_eq!(parsed.quality, None);
+}
+
+#[test]
+fn test_parse_quality_auto_with_target() {
+    let options = vec![ProcessingOption {
+        name: "quality".to_string(),
+        args: vec!["auto".to_string(), "0.95".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.auto_quality);
+    assert_eq!(parsed.auto_quality_target, Some(0.95));
+}
+
+#[test]
+fn test_parse_quality_auto_invalid_target() {
+    let options = vec![ProcessingOption {
+        name: "quality".to_string(),
+        args: vec!["auto".to_string(), "1.5".to_string()],
+    }];
+    let result = parse_all_options(options);
+    assert!(result.is_err());
+    assert!(result.unwrap_err().contains("quality target"));
+}
+
+#[test]
+fn test_parse_lqip() {
+    let options = vec![ProcessingOption {
+        name: "lqip".to_string(),
+        args: vec!["true".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.lqip);
+}
+
+#[test]
+fn test_parse_encode_datauri() {
+    let options = vec![ProcessingOption {
+        name: "encode".to_string(),
+        args: vec!["datauri".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.data_uri);
+
+    let options = vec![ProcessingOption {
+        name: "encode".to_string(),
+        args: vec!["hex".to_string()],
+    }];
+    assert!(parse_all_options(options).unwrap_err().contains("invalid encode value"));
+}
+
+#[test]
+fn test_parse_explicit_format_wins_over_url_extension() {
+    let url_parts = parse_path("unsafe/format:png/plain/https://example.com/image.jpg@webp").unwrap();
+    let parsed = parse_all_options(url_parts.processing_options).unwrap();
+    assert_eq!(parsed.format, Some("png".to_string()));
+}
+
+#[test]
+fn test_parse_url_extension_sets_format_when_not_explicit() {
+    let url_parts = parse_path("unsafe/resize:fit:100:100/plain/https://example.com/image.jpg@webp").unwrap();
+    let parsed = parse_all_options(url_parts.processing_options).unwrap();
+    assert_eq!(parsed.format, Some("webp".to_string()));
+}
+
+#[test]
+fn test_parse_watermark_with_scale_and_angle() {
+    let options = vec![ProcessingOption {
+        name: "wm".to_string(),
+        args: vec![
+            "0.4".to_string(),
+            "tile".to_string(),
+            "0.1".to_string(),
+            "-45".to_string(),
+        ],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    let watermark = parsed.watermark.unwrap();
+    assert_eq!(watermark.position, "tile");
+    assert_eq!(watermark.scale, Some(0.1));
+    assert_eq!(watermark.angle, -45.0);
+}
+
+#[test]
+fn test_parse_watermark_invalid_scale() {
+    let options = vec![ProcessingOption {
+        name: "watermark".to_string(),
+        args: vec!["0.4".to_string(), "center".to_string(), "2".to_string()],
+    }];
+    let result = parse_all_options(options);
+    assert!(result.is_err());
+    assert!(result.unwrap_err().contains("watermark scale"));
+}
+
+#[test]
+fn test_parse_watermark_rejects_non_finite_angle() {
+    for angle in ["NaN", "inf", "-inf"] {
+        let options = vec![ProcessingOption {
+            name: "watermark".to_string(),
+            args: vec![
+                "0.4".to_string(),
+                "center".to_string(),
+                String::new(),
+                angle.to_string(),
+            ],
+        }];
+        let err = parse_all_options(options).unwrap_err();
+        assert!(
+            err.contains("watermark angle must be a finite number"),
+            "{}: {}",
+            angle,
+            err
+        );
+    }
+}
+
+#[test]
+fn test_parse_watermark_percentage_position() {
+    let options = vec![ProcessingOption {
+        name: "wm".to_string(),
+        args: vec!["0.5".to_string(), "25%,75%".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.watermark.unwrap().offset, Some((25.0, 75.0)));
+
+    let options = vec![ProcessingOption {
+        name: "watermark".to_string(),
+        args: vec!["0.5".to_string(), "0,12.5".to_string(), "0.1".to_string()],
+    }];
+    let watermark = parse_all_options(options).unwrap().watermark.unwrap();
+    assert_eq!(watermark.offset, Some((0.0, 12.5)));
+    assert_eq!(watermark.scale, Some(0.1));
+}
+
+#[test]
+fn test_parse_watermark_named_position_has_no_offset() {
+    let options = vec![ProcessingOption {
+        name: "wm".to_string(),
+        args: vec!["0.5".to_string(), "south_east".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.watermark.unwrap().offset, None);
+}
+
+#[test]
+fn test_parse_watermark_invalid_percentage_position() {
+    for position in ["150,10", "10,-5", "10,abc", "10,20,30"] {
+        let options = vec![ProcessingOption {
+            name: "wm".to_string(),
+            args: vec!["0.5".to_string(), position.to_string()],
+        }];
+        let result = parse_all_options(options);
+        assert!(result.is_err(), "position {} should be rejected", position);
+        assert!(result.unwrap_err().contains("watermark position"));
+    }
+}
+
+#[test]
+fn test_parse_trim() {
+    let parse = |args: &[&str]| {
+        parse_all_options(vec![ProcessingOption {
+            name: "t".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }])
+    };
+
+    let trim = parse(&[]).unwrap().trim.unwrap();
+    assert_eq!(trim.threshold, DEFAULT_TRIM_THRESHOLD);
+    assert_eq!(trim.background, None);
+
+    let trim = parse(&["25", "auto"]).unwrap().trim.unwrap();
+    assert_eq!(trim.threshold, 25.0);
+    assert_eq!(trim.background, None);
+
+    let trim = parse(&["5", "00ff00"]).unwrap().trim.unwrap();
+    assert_eq!(trim.background, Some([0, 255, 0, 255]));
+
+    assert!(parse(&["-1"]).is_err());
+    assert!(parse(&["wide"]).is_err());
+    assert!(parse(&["10", "notacolor"]).is_err());
+}
+
+#[test]
+fn test_parse_contact_sheet() {
+    let parse = |args: &[&str]| {
+        parse_all_options(vec![ProcessingOption {
+            name: "contact_sheet".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }])
+    };
+
+    assert_eq!(parse(&[]).unwrap().contact_sheet, Some(DEFAULT_CONTACT_SHEET_COLUMNS));
+    assert_eq!(parse(&["3"]).unwrap().contact_sheet, Some(3));
+    assert!(parse(&["0"]).is_err());
+    assert!(parse(&["wide"]).is_err());
+    assert_eq!(parse_all_options(vec![]).unwrap().contact_sheet, None);
+}
+
+#[test]
+fn test_parse_border() {
+    let parse = |args: &[&str]| {
+        parse_all_options(vec![ProcessingOption {
+            name: "border".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }])
+    };
+
+    let border = parse(&["5", "ff0000"]).unwrap().border.unwrap();
+    assert_eq!((border.width, border.color), (5, [255, 0, 0, 255]));
+    assert_eq!(parse(&["2"]).unwrap().border.unwrap().color, [0, 0, 0, 255]);
+    assert_eq!(parse(&["0", "ffffff"]).unwrap().border, None);
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["thick"]).is_err());
+    assert_eq!(parse(&["1000"]).unwrap().border.unwrap().width, 1000);
+    let err = parse(&["2000000000"]).unwrap_err();
+    assert!(err.contains("border width must be at most 1000"), "{}", err);
+
+    let err = parse(&["5", "notacolor"]).unwrap_err();
+    assert!(err.contains("invalid border color 'notacolor'"), "{}", err);
+}
+
+#[test]
+fn test_parse_border_radius() {
+    let parse = |options: &[(&str, &str)]| {
+        parse_all_options(
+            options
+                .iter()
+                .map(|(name, arg)| ProcessingOption {
+                    name: name.to_string(),
+                    args: vec![arg.to_string()],
+                })
+                .collect(),
+        )
+    };
+
+    assert_eq!(parse(&[("border_radius", "12")]).unwrap().border_radius, Some(12));
+    assert_eq!(parse(&[("br_radius", "4")]).unwrap().border_radius, Some(4));
+    assert_eq!(parse(&[("border_radius", "0")]).unwrap().border_radius, None);
+    assert!(parse(&[("border_radius", "-3")]).is_err());
+
+    // JPEG cannot keep transparent corners unless they are flattened onto a background
+    assert!(parse(&[("border_radius", "12"), ("format", "jpeg")]).is_err());
+    assert!(parse(&[("border_radius", "12"), ("format", "jpg"), ("background", "ffffff")]).is_ok());
+    assert!(parse(&[("border_radius", "12"), ("format", "png")]).is_ok());
+}
+
+#[test]
+fn test_parse_avif_format_and_extension() {
+    let options = vec![ProcessingOption {
+        name: "format".to_string(),
+        args: vec!["avif".to_string()],
+    }];
+    assert_eq!(parse_all_options(options).unwrap().format, Some("avif".to_string()));
+
+    let url_parts = parse_path("unsafe/resize:fit:100:100/plain/https://example.com/image.jpg@avif").unwrap();
+    let parsed = parse_all_options(url_parts.processing_options).unwrap();
+    assert_eq!(parsed.format, Some("avif".to_string()));
+}
+
+#[test]
+fn test_parse_auto_format_prefers_accept() {
+    let options = vec![ProcessingOption {
+        name: "format".to_string(),
+        args: vec!["auto".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.prefer_accept);
+    assert_eq!(parsed.format, None);
+
+    let url_parts = parse_path("unsafe/resize:fit:100:100/plain/https://example.com/image.jpg@auto").unwrap();
+    let parsed = parse_all_options(url_parts.processing_options).unwrap();
+    assert!(parsed.prefer_accept);
+
+    let options = vec![ProcessingOption {
+        name: "format".to_string(),
+        args: vec!["webp".to_string()],
+    }];
+    assert!(!parse_all_options(options).unwrap().prefer_accept);
+}
+
+#[test]
+fn test_parse_max_bytes() {
+    let options = vec![ProcessingOption {
+        name: "mb".to_string(),
+        args: vec!["20000".to_string()],
+    }];
+    assert_eq!(parse_all_options(options).unwrap().max_bytes, Some(20000));
+
+    for value in ["0", "-1", "big"] {
+        let options = vec![ProcessingOption {
+            name: "max_bytes".to_string(),
+            args: vec![value.to_string()],
+        }];
+        assert!(
+            parse_all_options(options).is_err(),
+            "max_bytes {} should be rejected",
+            value
+        );
+    }
+}
+
+#[test]
+fn test_parse_bitdepth() {
+    let options = vec![ProcessingOption {
+        name: "bitdepth".to_string(),
+        args: vec!["16".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.bitdepth, Some(16));
+}
+
+#[test]
+fn test_parse_bitdepth_rejects_unsupported_depth() {
+    let options = vec![ProcessingOption {
+        name: "bitdepth".to_string(),
+        args: vec!["12".to_string()],
+    }];
+    let result = parse_all_options(options);
+    assert!(result.is_err());
+    assert!(result.unwrap_err().contains("bitdepth must be one of"));
+}
+
+#[test]
+fn test_parse_background_checkerboard() {
+    let options = vec![ProcessingOption {
+        name: "bg".to_string(),
+        args: vec!["checkerboard".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.checkerboard);
+    assert_eq!(parsed.background, None);
+}
+
+#[test]
+fn test_parse_background_color_replaces_checkerboard() {
+    let options = vec![
+        ProcessingOption {
+            name: "background".to_string(),
+            args: vec!["checkerboard".to_string()],
+        },
+        ProcessingOption {
+            name: "background".to_string(),
+            args: vec!["ffffff".to_string()],
+        },
+    ];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(!parsed.checkerboard);
+    assert_eq!(parsed.background, Some([255, 255, 255, 255]));
+}
+
+#[test]
+fn test_parse_page() {
+    let options = vec![ProcessingOption {
+        name: "pg".to_string(),
+        args: vec!["2".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.page, Some(2));
+}
+
+#[test]
+fn test_parse_strip_metadata() {
+    let options = vec![ProcessingOption {
+        name: "sm".to_string(),
+        args: vec!["1".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.strip_metadata, Some(true));
+}
+
+#[test]
+fn test_parse_colorspace() {
+    let colorspace = |value: &str| {
+        parse_all_options(vec![ProcessingOption {
+            name: "colorspace".to_string(),
+            args: vec![value.to_string()],
+        }])
+    };
+    assert!(colorspace("srgb").unwrap().normalize_colorspace);
+    assert!(!colorspace("keep").unwrap().normalize_colorspace);
+    assert!(colorspace("cmyk").unwrap_err().contains("invalid colorspace"));
+}
+
+#[test]
+fn test_parse_source_format() {
+    let source_format = |name: &str, value: &str| {
+        parse_all_options(vec![ProcessingOption {
+            name: name.to_string(),
+            args: vec![value.to_string()],
+        }])
+    };
+    assert_eq!(parse_all_options(vec![]).unwrap().source_format, None);
+    assert_eq!(
+        source_format("source_format", "PNG").unwrap().source_format.as_deref(),
+        Some("png")
+    );
+    assert_eq!(
+        source_format("sf", "jpg").unwrap().source_format.as_deref(),
+        Some("jpeg")
+    );
+    assert!(source_format("sf", "bmp")
+        .unwrap_err()
+        .contains("source_format must be one of"));
+}
+
+#[test]
+fn test_parse_strip_and_keep_profile() {
+    let options = vec![
+        ProcessingOption {
+            name: "strip".to_string(),
+            args: vec!["true".to_string()],
+        },
+        ProcessingOption {
+            name: "keep_profile".to_string(),
+            args: vec!["false".to_string()],
+        },
+    ];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.strip_metadata, Some(true));
+    assert!(!parsed.keep_profile);
+    assert!(parse_all_options(vec![]).unwrap().keep_profile);
+}
+
+#[test]
+fn test_parse_srgb() {
+    let options = vec![ProcessingOption {
+        name: "srgb".to_string(),
+        args: vec!["true".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.srgb, Some(true));
+}
+
+#[test]
+fn test_parse_srgb_requires_argument() {
+    let options = vec![ProcessingOption {
+        name: "srgb".to_string(),
+        args: vec![],
+    }];
+    assert!(parse_all_options(options).is_err());
+}
+
+#[test]
+fn test_parse_use_embedded_thumbnail() {
+    let options = vec![ProcessingOption {
+        name: "use_embedded_thumbnail".to_string(),
+        args: vec!["true".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.use_embedded_thumbnail, Some(true));
+}
+
+#[test]
+fn test_parse_use_embedded_thumbnail_requires_argument() {
+    let options = vec![ProcessingOption {
+        name: "use_embedded_thumbnail".to_string(),
+        args: vec![],
+    }];
+    assert!(parse_all_options(options).is_err());
+}
+
+#[test]
+fn test_parse_pixel_art() {
+    let options = vec![ProcessingOption {
+        name: "pixel_art".to_string(),
+        args: vec!["true".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert!(parsed.pixel_art);
+}
+
+#[test]
+fn test_parse_copyright() {
+    let options = vec![ProcessingOption {
+        name: "copyright".to_string(),
+        args: vec!["KGMpIDIwMjYgQWNtZSA8UGhvdG8-".to_string()],
+    }];
+    let parsed = parse_all_options(options).unwrap();
+    assert_eq!(parsed.copyright.as_deref(), Some("(c) 2026 Acme <Photo>"));
+
+    let invalid = vec![ProcessingOption {
+        name: "copyright".to_string(),
+        args: vec!["not base64!".to_string()],
+    }];
+    assert!(parse_all_options(invalid).is_err());
+}
+
+#[test]
+fn test_parse_label() {
+    let label = |args: &[&str]| {
+        parse_all_options(vec![ProcessingOption {
+            name: "label".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }])
+    };
+
+    // "© 2025"
+    let parsed = label(&["wqkgMjAyNQ", "32", "ff0000", "north_west"]).unwrap();
+    assert_eq!(
+        parsed.label,
+        Some(LabelOptions {
+            text: "\u{a9} 2025".to_string(),
+            size: 32,
+            color: [255, 0, 0, 255],
+            position: "north_west".to_string(),
+        })
+    );
+
+    let defaults = label(&["wqkgMjAyNQ"]).unwrap().label.unwrap();
+    assert_eq!(defaults.size, 24);
+    assert_eq!(defaults.color, [255, 255, 255, 255]);
+    assert_eq!(defaults.position, "south_east");
+
+    // Empty and whitespace-only text
+    assert!(label(&[""]).is_err());
+    assert!(label(&["ICA"]).is_err());
+    assert!(label(&["wqkgMjAyNQ", "0"]).is_err());
+    assert!(label(&["wqkgMjAyNQ", "24", "zzzzzz"]).is_err());
+    assert!(label(&["wqkgMjAyNQ", "24", "ffffff", "tile"]).is_err());
+}
@@ -1,4 +1,7 @@
-use crate::processing::transform;
+use crate::processing::error::ProcessingError;
+use crate::processing::options::{Border, ParsedOptions, Resize, Trim, DEFAULT_TRIM_THRESHOLD};
+use crate::processing::{process_image, transform};
+use bytes::Bytes;
 use libvips::VipsImage;
 
 use super::tests_support::*;
@@ -22,6 +25,51 @@ fn test_apply_padding() {
     assert_eq!(padded_img.get_height(), 140);
 }
 
+#[test]
+fn test_apply_border_grows_each_axis_by_twice_the_width() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_quadrant_test_image(40, 30), "").unwrap();
+    let border = Border {
+        width: 5,
+        color: [255, 255, 255, 255],
+    };
+    let framed = transform::apply_border(img, &border).unwrap();
+    assert_eq!(framed.get_width(), 50);
+    assert_eq!(framed.get_height(), 40);
+
+    let decoded = decode_rgba(&framed);
+    for (x, y) in [(0, 0), (4, 20), (49, 39), (25, 35)] {
+        assert_eq!(
+            rgba_pixel(&decoded, x, y),
+            [255, 255, 255, 255],
+            "border at {},{}",
+            x,
+            y
+        );
+    }
+    // The top-left quadrant of the source starts right inside the border
+    assert_eq!(rgba_pixel(&decoded, 5, 5), [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_border_runs_after_padding() {
+    init_vips();
+    let source = create_test_image(100, 80);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        padding: Some((10, 10, 10, 10)),
+        border: Some(Border {
+            width: 3,
+            color: [0, 0, 0, 255],
+        }),
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+    let decoded = image::load_from_memory(&output).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (126, 106));
+}
+
 #[test]
 fn test_apply_padding_position_and_background_color() {
     init_vips();
@@ -78,10 +126,9 @@ fn test_extend_image_returns_error_when_target_smaller_than_source() {
     init_vips();
     let img = VipsImage::new_from_buffer(&create_test_image(100, 80), "").unwrap();
     let result = transform::extend_image(img, 90, 120, &Some("center".to_string()), &Some([0, 0, 0, 0]));
-    assert!(result.is_err());
     assert!(
-        result.unwrap_err().contains("must be at least source"),
-        "unexpected error message for extend guard"
+        matches!(result, Err(ProcessingError::Geometry(ref message)) if message.contains("must be at least source")),
+        "unexpected error for extend guard"
     );
 }
 
@@ -104,3 +151,78 @@ fn test_extend_with_different_gravities() {
         assert_eq!(extended.get_height(), 200);
     }
 }
+
+const FRAME_BLUE: [u8; 4] = [0, 0, 255, 255];
+const CONTENT_RED: [u8; 4] = [255, 0, 0, 255];
+
+fn auto_trim() -> Trim {
+    Trim {
+        threshold: DEFAULT_TRIM_THRESHOLD,
+        background: None,
+    }
+}
+
+#[test]
+fn test_trim_image_removes_frame_sampled_from_corner() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_framed_test_image(100, 80, 20, FRAME_BLUE, CONTENT_RED), "").unwrap();
+    let trimmed = transform::trim_image(img, &auto_trim()).unwrap();
+    assert_eq!((trimmed.get_width(), trimmed.get_height()), (60, 40));
+
+    let decoded = decode_rgba(&trimmed);
+    assert_eq!(rgba_pixel(&decoded, 0, 0), CONTENT_RED);
+    assert_eq!(rgba_pixel(&decoded, 59, 39), CONTENT_RED);
+}
+
+#[test]
+fn test_trim_image_uses_explicit_background() {
+    init_vips();
+    let source = create_framed_test_image(100, 80, 20, FRAME_BLUE, CONTENT_RED);
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let trim = Trim {
+        background: Some(FRAME_BLUE),
+        ..auto_trim()
+    };
+    let trimmed = transform::trim_image(img, &trim).unwrap();
+    assert_eq!((trimmed.get_width(), trimmed.get_height()), (60, 40));
+
+    // A background matching nothing in the image leaves it whole
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let trim = Trim {
+        background: Some([255, 255, 255, 255]),
+        ..auto_trim()
+    };
+    let untouched = transform::trim_image(img, &trim).unwrap();
+    assert_eq!((untouched.get_width(), untouched.get_height()), (100, 80));
+}
+
+#[test]
+fn test_trim_image_keeps_uniform_image() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image(50, 40), "").unwrap();
+    let trimmed = transform::trim_image(img, &auto_trim()).unwrap();
+    assert_eq!((trimmed.get_width(), trimmed.get_height()), (50, 40));
+}
+
+#[test]
+fn test_process_image_trims_before_resize() {
+    init_vips();
+    let source = Bytes::from(create_framed_test_image(100, 80, 20, FRAME_BLUE, CONTENT_RED));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let options = ParsedOptions {
+        trim: Some(auto_trim()),
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 30,
+            height: 30,
+        }),
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(decoded.dimensions(), (30, 20));
+    assert!(collect_rgba_pixels(&decoded).iter().all(|pixel| *pixel == CONTENT_RED));
+}
@@ -1,7 +1,10 @@
-use crate::processing::options::{Crop, Resize, Watermark};
+use crate::processing::error::ProcessingError;
+use crate::processing::options::{parse_all_options, Crop, ParsedOptions, ProcessingOption, Resize, Watermark};
 use crate::processing::transform;
 use crate::processing::watermark;
-use libvips::VipsImage;
+use crate::processing::{decode, process_image, save};
+use bytes::Bytes;
+use libvips::{ops, VipsImage};
 
 use super::tests_support::*;
 
@@ -26,6 +29,36 @@ fn test_crop_then_resize() {
     assert_eq!(final_img.get_height(), 100);
 }
 
+#[test]
+fn test_process_image_extracts_region_then_resizes() {
+    init_vips();
+    let source = Bytes::from(create_quadrant_test_image(200, 200));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    // The top-right quadrant is green
+    let options = ParsedOptions {
+        format: Some("png".to_string()),
+        region: Some(Crop {
+            x: 100,
+            y: 0,
+            width: 100,
+            height: 100,
+        }),
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 40,
+            height: 40,
+        }),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(decoded.dimensions(), (40, 40));
+    for (x, y) in [(0, 0), (39, 0), (0, 39), (39, 39), (20, 20)] {
+        assert_eq!(rgba_pixel(&decoded, x, y), [0, 255, 0, 255], "pixel ({}, {})", x, y);
+    }
+}
+
 #[test]
 fn test_resize_then_blur() {
     init_vips();
@@ -36,7 +69,7 @@ fn test_resize_then_blur() {
         height: 100,
     };
     let resized = transform::apply_resize(img, &resize, &None, &None).unwrap();
-    let blurred = transform::apply_blur(resized, 3.0).unwrap();
+    let blurred = transform::apply_blur(resized, 3.0, None).unwrap();
     assert_eq!(blurred.get_width(), 100);
     assert_eq!(blurred.get_height(), 100);
 }
@@ -93,7 +126,7 @@ fn test_complex_pipeline_crop_resize_blur_rotate() {
     let img = transform::apply_resize(img, &resize, &None, &None).unwrap();
     assert_eq!(img.get_width(), 200);
 
-    let img = transform::apply_blur(img, 2.0).unwrap();
+    let img = transform::apply_blur(img, 2.0, None).unwrap();
     let img = transform::apply_rotation(img, 90).unwrap();
     assert_eq!(img.get_width(), 200);
     assert_eq!(img.get_height(), 200);
@@ -119,7 +152,593 @@ fn test_complex_pipeline_resize_padding_watermark() {
     let watermark_opts = Watermark {
         opacity: 0.7,
         position: "south_east".to_string(),
+        ..Default::default()
     };
     let img = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
     assert_eq!(img.get_width(), 170);
 }
+
+fn create_test_image_qoi(width: u32, height: u32) -> Vec<u8> {
+    let img = image::RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 255, 255]));
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Qoi)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_load_image_without_fallback_rejects_unsupported_format() {
+    init_vips();
+    let qoi = create_test_image_qoi(64, 48);
+    let result = decode::load_image(&qoi, false);
+    assert!(
+        matches!(result, Err(ProcessingError::Decode(ref message)) if message.contains("Error loading image from memory"))
+    );
+}
+
+#[test]
+fn test_load_image_fallback_decodes_and_processes_qoi() {
+    init_vips();
+    let qoi = create_test_image_qoi(64, 48);
+    let img = decode::load_image(&qoi, true).unwrap();
+    assert_eq!((img.get_width(), img.get_height()), (64, 48));
+
+    let parsed_options = ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 32,
+            height: 24,
+        }),
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(qoi), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(decoded.dimensions(), (32, 24));
+    assert_eq!(decoded.get_pixel(16, 12).0, [0, 0, 255, 255]);
+}
+
+#[test]
+fn test_validate_encoded_output_rejects_empty_buffer() {
+    init_vips();
+    let err = save::validate_encoded_output(&[], "png").unwrap_err();
+    assert!(matches!(err, ProcessingError::Encode(ref message) if message.contains("empty buffer")));
+}
+
+#[test]
+fn test_validate_encoded_output_rejects_undecodable_buffer() {
+    init_vips();
+    let err = save::validate_encoded_output(b"not an image", "jpeg").unwrap_err();
+    assert!(matches!(err, ProcessingError::Encode(ref message) if message.contains("jpeg output cannot be decoded")));
+}
+
+#[test]
+fn test_save_image_output_passes_validation() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image(20, 20), "").unwrap();
+    let encoded = save::save_image(img, "png", 90).unwrap();
+    assert!(save::validate_encoded_output(&encoded, "png").is_ok());
+}
+
+fn create_16_bit_test_image(width: u32, height: u32) -> Vec<u8> {
+    let img: image::ImageBuffer<image::Rgb<u16>, Vec<u16>> =
+        image::ImageBuffer::from_pixel(width, height, image::Rgb([1000, 30000, 65000]));
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_process_image_preserves_16_bit_png() {
+    init_vips();
+    let source = create_16_bit_test_image(20, 20);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("png".to_string()),
+        bitdepth: Some(16),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap();
+    assert_eq!(decoded.color(), image::ColorType::Rgb16);
+    assert_eq!(decoded.to_rgb16().get_pixel(10, 10).0, [1000, 30000, 65000]);
+}
+
+#[test]
+fn test_process_image_writes_8_bit_png_by_default() {
+    init_vips();
+    let source = create_16_bit_test_image(20, 20);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap();
+    assert_eq!(decoded.color(), image::ColorType::Rgb8);
+}
+
+#[test]
+fn test_process_image_dpr_one_matches_no_dpr() {
+    init_vips();
+    let source = create_test_image(40, 30);
+    let options_for = |dpr_args: Vec<ProcessingOption>| {
+        let mut options = vec![
+            ProcessingOption {
+                name: "resize".to_string(),
+                args: vec!["fit".to_string(), "20".to_string(), "20".to_string()],
+            },
+            ProcessingOption {
+                name: "padding".to_string(),
+                args: vec!["3".to_string()],
+            },
+            ProcessingOption {
+                name: "format".to_string(),
+                args: vec!["png".to_string()],
+            },
+        ];
+        options.extend(dpr_args);
+        parse_all_options(options).unwrap()
+    };
+    let render = |parsed_options: ParsedOptions| {
+        let img = VipsImage::new_from_buffer(&source, "").unwrap();
+        process_image(img, parsed_options, &Bytes::from(source.clone()), None).unwrap()
+    };
+
+    let without_dpr = render(options_for(vec![]));
+    let with_dpr_one = render(options_for(vec![ProcessingOption {
+        name: "dpr".to_string(),
+        args: vec!["1".to_string()],
+    }]));
+
+    assert_eq!(without_dpr, with_dpr_one);
+}
+
+#[test]
+fn test_read_dimensions_uses_header() {
+    init_vips();
+    let png = create_test_image(64, 48);
+    assert_eq!(decode::read_dimensions(&png, false).unwrap(), (64, 48));
+}
+
+#[test]
+fn test_read_dimensions_fallback_reads_qoi_header() {
+    init_vips();
+    let qoi = create_test_image_qoi(64, 48);
+    assert_eq!(decode::read_dimensions(&qoi, true).unwrap(), (64, 48));
+}
+
+#[test]
+fn test_load_with_format_decodes_with_forced_loader() {
+    init_vips();
+    let png = create_test_image(64, 48);
+    let img = decode::load_with_format(&png, "png").unwrap();
+    assert_eq!((img.get_width(), img.get_height()), (64, 48));
+}
+
+#[test]
+fn test_load_with_format_rejects_mismatched_source() {
+    init_vips();
+    let png = create_test_image(64, 48);
+    let result = decode::load_with_format(&png, "jpeg");
+    assert!(matches!(result, Err(ProcessingError::Decode(ref message)) if message.contains("not a valid jpeg image")));
+}
+
+/// Opaque red image whose corners outside an inscribed circle are transparent, like a
+/// rounded-corner avatar.
+fn create_rounded_corner_test_image(size: u32) -> Vec<u8> {
+    let radius = size as f32 / 2.0;
+    let img = image::RgbaImage::from_fn(size, size, |x, y| {
+        let (dx, dy) = (x as f32 + 0.5 - radius, y as f32 + 0.5 - radius);
+        if dx * dx + dy * dy <= radius * radius {
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn test_process_image_fills_transparent_corners_with_background_for_jpeg() {
+    init_vips();
+    let source = create_rounded_corner_test_image(100);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 50,
+            height: 50,
+        }),
+        format: Some("jpg".to_string()),
+        background: Some([0, 0, 255, 255]),
+        even_dimensions: Some(true),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgb8();
+    let corner = decoded.get_pixel(1, 1).0;
+    assert!(
+        corner[2] > 200 && corner[0] < 50,
+        "corner should be the background, got {:?}",
+        corner
+    );
+    let center = decoded.get_pixel(25, 25).0;
+    assert!(
+        center[0] > 200 && center[2] < 50,
+        "center should stay red, got {:?}",
+        center
+    );
+}
+
+#[test]
+fn test_process_image_keeps_alpha_for_png_with_background() {
+    init_vips();
+    let source = create_rounded_corner_test_image(100);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("png".to_string()),
+        background: Some([0, 0, 255, 255]),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(decoded.get_pixel(1, 1).0[3], 0);
+}
+
+#[test]
+fn test_process_image_flattens_rounded_corners_for_jpeg_with_background() {
+    init_vips();
+    let source = create_test_image_jpeg(100, 100);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("jpeg".to_string()),
+        background: Some([255, 255, 255, 255]),
+        border_radius: Some(30),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (100, 100));
+    let corner = decoded.get_pixel(1, 1).0;
+    assert!(
+        corner.iter().all(|channel| *channel > 240),
+        "corner should be white, got {:?}",
+        corner
+    );
+}
+
+#[test]
+fn test_is_dimensionless_svg_checks_root_size_attributes() {
+    let dimensionless = [
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><rect width="10" height="10"/></svg>"#,
+        r#"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="40"><rect stroke-width="2" height="10"/></svg>"#,
+    ];
+    for svg in dimensionless {
+        assert!(decode::is_dimensionless_svg(svg.as_bytes()), "{}", svg);
+    }
+
+    let sized = [
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="30"/>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 40 30"/>"#,
+    ];
+    for svg in sized {
+        assert!(!decode::is_dimensionless_svg(svg.as_bytes()), "{}", svg);
+    }
+    assert!(!decode::is_dimensionless_svg(&create_test_image(10, 10)));
+}
+
+#[test]
+fn test_page_count_reads_multi_page_tiff() {
+    init_vips();
+    let tiff = create_two_page_tiff();
+    assert!(decode::is_tiff(&tiff));
+    assert_eq!(decode::page_count(&tiff).unwrap(), 2);
+    assert!(!decode::is_tiff(&create_test_image(20, 20)));
+}
+
+#[test]
+fn test_load_tiff_page_selects_page() {
+    init_vips();
+    let tiff = create_two_page_tiff();
+
+    let first = decode::load_tiff_page(&tiff, 0).unwrap();
+    let second = decode::load_tiff_page(&tiff, 1).unwrap();
+    assert_eq!((second.get_width(), second.get_height()), (20, 20));
+
+    assert_eq!(rgba_pixel(&decode_rgba(&first), 10, 10), [255, 0, 0, 255]);
+    assert_eq!(rgba_pixel(&decode_rgba(&second), 10, 10), [0, 0, 255, 255]);
+}
+
+#[test]
+fn test_load_embedded_thumbnail_uses_exif_thumbnail_for_small_targets() {
+    init_vips();
+    let source = create_jpeg_with_exif_thumbnail(1);
+
+    let thumbnail = decode::load_embedded_thumbnail(&source, 100, 75).unwrap();
+    assert_eq!((thumbnail.get_width(), thumbnail.get_height()), (160, 120));
+    let pixel = rgba_pixel(&decode_rgba(&thumbnail), 80, 60);
+    assert!(
+        pixel[1] > 200 && pixel[0] < 50,
+        "expected green thumbnail, got {:?}",
+        pixel
+    );
+
+    // Targets larger than the thumbnail fall back to the full decode.
+    assert!(decode::load_embedded_thumbnail(&source, 400, 0).is_none());
+    assert!(decode::load_embedded_thumbnail(&create_test_image(20, 20), 10, 10).is_none());
+}
+
+#[test]
+fn test_validate_geometry_rejects_region_outside_source() {
+    let parsed_options = ParsedOptions {
+        region: Some(Crop {
+            x: 300,
+            y: 0,
+            width: 200,
+            height: 100,
+        }),
+        ..Default::default()
+    };
+    let err = transform::validate_geometry(&parsed_options, 400, 300).unwrap_err();
+    assert_eq!(
+        err,
+        ProcessingError::Geometry(
+            "Invalid geometry: region 200x100 at (300, 0) lies outside the 400x300 source".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_validate_geometry_rejects_crop_larger_than_source() {
+    let parsed_options = ParsedOptions {
+        crop: Some(Crop {
+            x: 0,
+            y: 0,
+            width: 500,
+            height: 200,
+        }),
+        ..Default::default()
+    };
+    let err = transform::validate_geometry(&parsed_options, 400, 300).unwrap_err();
+    assert_eq!(
+        err,
+        ProcessingError::Geometry(
+            "Invalid geometry: crop 500x200 at (0, 0) extends past the 400x300 source".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_process_image_rejects_resize_out_of_bounds_after_crop() {
+    init_vips();
+    let source = create_test_image(400, 300);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        crop: Some(Crop {
+            x: 0,
+            y: 0,
+            width: 400,
+            height: 1,
+        }),
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 100,
+            height: 0,
+        }),
+        ..Default::default()
+    };
+    let err = process_image(img, parsed_options, &Bytes::from(source), None).unwrap_err();
+    assert_eq!(
+        err,
+        ProcessingError::Geometry(
+            "Invalid geometry: resize fit:100:0 of the 400x1 cropped image: resize resolved to zero dimension"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_process_image_srgb_converts_display_p3_source() {
+    init_vips();
+    let source = Bytes::from(create_display_p3_test_image(16, 16));
+    let p3_pixel = rgba_pixel(&image::load_from_memory(&source).unwrap().to_rgba8(), 8, 8);
+    assert!(
+        p3_pixel[0] < 250,
+        "fixture should hold P3-encoded red, got {:?}",
+        p3_pixel
+    );
+
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let options = ParsedOptions {
+        format: Some("png".to_string()),
+        srgb: Some(true),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let pixel = rgba_pixel(&image::load_from_memory(&output).unwrap().to_rgba8(), 8, 8);
+    assert!(
+        pixel[0] >= 250 && pixel[1] <= 5 && pixel[2] <= 5,
+        "expected sRGB red, got {:?}",
+        pixel
+    );
+    let profile = png_icc_profile(&output).expect("output should carry an ICC profile");
+    assert!(profile.windows(4).any(|w| w == b"sRGB"));
+}
+
+#[test]
+fn test_process_image_converts_cmyk_source_to_srgb() {
+    let source = Bytes::from(create_cmyk_test_jpeg(40, 30));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    assert_eq!(img.get_bands(), 4);
+    // A background would flatten the black channel away if it were read as alpha
+    let options = ParsedOptions {
+        format: Some("jpeg".to_string()),
+        background: Some([255, 255, 255, 255]),
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 20,
+            height: 15,
+        }),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let decoded = VipsImage::new_from_buffer(&output, "").unwrap();
+    assert_eq!(decoded.get_bands(), 3);
+    assert!(matches!(decoded.get_interpretation(), Ok(ops::Interpretation::Srgb)));
+    let pixel = rgba_pixel(&image::load_from_memory(&output).unwrap().to_rgba8(), 10, 7);
+    assert!(
+        pixel[0] > 200 && pixel[1] < 80 && pixel[2] < 80,
+        "expected red, got {:?}",
+        pixel
+    );
+}
+
+#[test]
+fn test_process_image_colorspace_srgb_converts_before_resizing() {
+    let source = Bytes::from(create_display_p3_test_image(16, 16));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let options = ParsedOptions {
+        format: Some("png".to_string()),
+        normalize_colorspace: true,
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 8,
+            height: 8,
+        }),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let pixel = rgba_pixel(&image::load_from_memory(&output).unwrap().to_rgba8(), 4, 4);
+    assert!(
+        pixel[0] >= 250 && pixel[1] <= 5 && pixel[2] <= 5,
+        "expected sRGB red, got {:?}",
+        pixel
+    );
+    let profile = png_icc_profile(&output).expect("output should carry an ICC profile");
+    assert!(profile.windows(4).any(|w| w == b"sRGB"));
+}
+
+#[test]
+fn test_process_image_srgb_assumes_srgb_without_profile() {
+    init_vips();
+    let source = Bytes::from(create_test_image(16, 16));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let options = ParsedOptions {
+        format: Some("png".to_string()),
+        srgb: Some(true),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let pixel = rgba_pixel(&image::load_from_memory(&output).unwrap().to_rgba8(), 8, 8);
+    assert_eq!(pixel, [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_shrink_on_load_decodes_large_jpeg_at_reduced_scale() {
+    init_vips();
+    let source = Bytes::from(ops::jpegsave_buffer(&ops::gaussnoise(4000, 3000).unwrap()).unwrap());
+    let options = || ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 200,
+            height: 150,
+        }),
+        ..Default::default()
+    };
+
+    let full = decode::load_image(&source, false).unwrap();
+    assert_eq!((full.get_width(), full.get_height()), (4000, 3000));
+    let full_pixels = full.get_width() * full.get_height();
+    let full_output = process_image(full, options(), &source, None).unwrap();
+
+    let shrunk = decode::load_shrunk_jpeg(&source, 200, 150).expect("large JPEG should shrink on load");
+    assert_eq!((shrunk.get_width(), shrunk.get_height()), (500, 375));
+    let shrunk_pixels = shrunk.get_width() * shrunk.get_height();
+    let shrunk_output = process_image(shrunk, options(), &source, None).unwrap();
+
+    for output in [&full_output, &shrunk_output] {
+        let decoded = VipsImage::new_from_buffer(output, "").unwrap();
+        assert_eq!((decoded.get_width(), decoded.get_height()), (200, 150));
+    }
+    assert_eq!(full_pixels / shrunk_pixels, 64);
+}
+
+#[test]
+fn test_shrink_on_load_falls_back_when_target_needs_full_resolution() {
+    init_vips();
+    let jpeg = create_test_image_jpeg(400, 300);
+    assert!(decode::load_shrunk_jpeg(&jpeg, 300, 200).is_none());
+    assert_eq!(
+        decode::load_shrunk_jpeg(&jpeg, 100, 0).map(|img| img.get_width()),
+        Some(100)
+    );
+    assert!(decode::load_shrunk_jpeg(&create_test_image(400, 300), 50, 50).is_none());
+}
+
+#[test]
+fn test_process_image_fill_with_background_on_jpeg_source() {
+    init_vips();
+    let source = Bytes::from(create_test_image_jpeg(100, 80));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let options = ParsedOptions {
+        background: Some([255, 255, 255, 255]),
+        resize: Some(Resize {
+            resizing_type: "fill".to_string(),
+            width: 50,
+            height: 50,
+        }),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let decoded = VipsImage::new_from_buffer(&output, "").unwrap();
+    assert_eq!((decoded.get_width(), decoded.get_height()), (50, 50));
+    assert_eq!(decoded.get_bands(), 3);
+    let pixel = rgba_pixel(&image::load_from_memory(&output).unwrap().to_rgba8(), 25, 25);
+    assert!(
+        pixel[0] > 200 && pixel[1] < 60 && pixel[2] < 60,
+        "expected red, got {:?}",
+        pixel
+    );
+}
+
+#[test]
+fn test_process_image_grayscale_flattens_and_saves_jpeg() {
+    init_vips();
+    let source = Bytes::from(create_test_image(40, 30));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let options = ParsedOptions {
+        grayscale: true,
+        background: Some([255, 255, 255, 255]),
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: 20,
+            height: 20,
+        }),
+        ..Default::default()
+    };
+    let output = process_image(img, options, &source, None).unwrap();
+
+    let decoded = VipsImage::new_from_buffer(&output, "").unwrap();
+    assert_eq!(decoded.get_bands(), 1);
+    assert_eq!((decoded.get_width(), decoded.get_height()), (20, 15));
+    // Opaque red turns mid gray; the white background must not show through
+    let luma = ops::avg(&decoded).unwrap();
+    assert!((20.0..235.0).contains(&luma), "unexpected gray level {}", luma);
+}
@@ -0,0 +1,198 @@
+use crate::processing::error::ProcessingError;
+use crate::processing::options::{parse_all_options, ParsedOptions, ProcessingOption};
+use crate::processing::presets::{expand_presets, parse_options_string};
+use crate::processing::quality::{QualityDefaults, DEFAULT_QUALITY};
+use crate::processing::{process_image, quality, save};
+use bytes::Bytes;
+use libvips::VipsImage;
+use std::collections::HashMap;
+
+use super::tests_support::*;
+
+#[test]
+fn test_ssim_of_identical_images_is_one() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_gradient_test_image(64, 48), "").unwrap();
+    let samples = quality::luma_samples(&img).unwrap();
+    let score = quality::ssim(&samples, &samples).unwrap();
+    assert!((score - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_encode_with_target_ssim_uses_lower_quality_than_max() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_gradient_test_image(200, 150), "").unwrap();
+    let (_, chosen_quality) =
+        quality::encode_with_target_ssim(&img, "jpeg", 0.9, save::SaveOptions::default()).unwrap();
+    assert!(chosen_quality < 95);
+}
+
+#[test]
+fn test_quality_auto_output_within_ssim_band() {
+    init_vips();
+    let target = 0.95;
+    let source = Bytes::from(create_gradient_test_image(200, 150));
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        format: Some("jpeg".to_string()),
+        auto_quality: true,
+        auto_quality_target: Some(target),
+        ..Default::default()
+    };
+
+    let output = process_image(img, parsed_options, &source, None).unwrap();
+
+    let reference = VipsImage::new_from_buffer(&source, "").unwrap();
+    let decoded = VipsImage::new_from_buffer(&output, "").unwrap();
+    let score = quality::ssim(
+        &quality::luma_samples(&reference).unwrap(),
+        &quality::luma_samples(&decoded).unwrap(),
+    )
+    .unwrap();
+    assert!(
+        (target..=1.0).contains(&score),
+        "SSIM {} outside [{}, 1.0]",
+        score,
+        target
+    );
+}
+
+fn quality_defaults() -> QualityDefaults {
+    QualityDefaults {
+        per_format: HashMap::from([("jpeg".to_string(), 70)]),
+        global: Some(50),
+    }
+}
+
+/// Parses `url_options` after expanding them against a `compact` preset that sets quality 60.
+fn requested_quality(url_options: &str) -> Option<u8> {
+    let presets = HashMap::from([("compact".to_string(), parse_options_string("quality:60").unwrap())]);
+    let options: Vec<ProcessingOption> = parse_options_string(url_options).unwrap();
+    let expanded = expand_presets(options, &presets, false).unwrap();
+    parse_all_options(expanded).unwrap().quality
+}
+
+#[test]
+fn test_resolve_quality_prefers_explicit_option_over_preset() {
+    let requested = requested_quality("quality:90/preset:compact");
+    assert_eq!(quality::resolve_quality(requested, "jpeg", &quality_defaults()), 90);
+}
+
+#[test]
+fn test_resolve_quality_prefers_preset_over_config() {
+    let requested = requested_quality("preset:compact");
+    assert_eq!(quality::resolve_quality(requested, "jpeg", &quality_defaults()), 60);
+}
+
+#[test]
+fn test_resolve_quality_prefers_per_format_over_global() {
+    assert_eq!(quality::resolve_quality(None, "jpeg", &quality_defaults()), 70);
+    assert_eq!(quality::resolve_quality(None, "JPG", &quality_defaults()), 70);
+}
+
+#[test]
+fn test_resolve_quality_falls_back_to_global_then_hardcoded_default() {
+    assert_eq!(quality::resolve_quality(None, "webp", &quality_defaults()), 50);
+    assert_eq!(
+        quality::resolve_quality(None, "webp", &QualityDefaults::default()),
+        DEFAULT_QUALITY
+    );
+    assert_eq!(DEFAULT_QUALITY, 85);
+}
+
+#[test]
+fn test_process_image_uses_configured_format_quality() {
+    init_vips();
+    let source = create_gradient_test_image(128, 96);
+    let encode = |jpeg_quality: u8| {
+        let img = VipsImage::new_from_buffer(&source, "").unwrap();
+        let parsed_options = ParsedOptions {
+            format: Some("jpeg".to_string()),
+            quality_defaults: QualityDefaults {
+                per_format: HashMap::from([("jpeg".to_string(), jpeg_quality)]),
+                global: Some(95),
+            },
+            ..Default::default()
+        };
+        process_image(img, parsed_options, &Bytes::from(source.clone()), None)
+            .unwrap()
+            .len()
+    };
+
+    assert!(encode(10) < encode(95));
+}
+
+#[test]
+fn test_save_image_quality_changes_lossy_output_size() {
+    init_vips();
+    for format in ["jpeg", "webp"] {
+        let encode = |quality| {
+            let img = VipsImage::new_from_buffer(&create_gradient_test_image(200, 150), "").unwrap();
+            save::save_image(img, format, quality)
+        };
+        let low = match encode(20) {
+            Ok(encoded) => encoded,
+            Err(ProcessingError::Unsupported(_)) => continue,
+            Err(e) => panic!("encoding {} failed: {}", format, e),
+        };
+        let high = encode(95).unwrap();
+        assert!(
+            high.len() > low.len() * 3 / 2,
+            "{} at quality 95 ({} bytes) should be much larger than at quality 20 ({} bytes)",
+            format,
+            high.len(),
+            low.len()
+        );
+    }
+}
+
+#[test]
+fn test_save_image_png_quality_keeps_pixels() {
+    init_vips();
+    let source = create_gradient_test_image(64, 48);
+    let expected = image::load_from_memory(&source).unwrap().to_rgba8();
+    for quality in [1, 100] {
+        let img = VipsImage::new_from_buffer(&source, "").unwrap();
+        let encoded = save::save_image(img, "png", quality).unwrap();
+        assert_eq!(image::load_from_memory(&encoded).unwrap().to_rgba8(), expected);
+    }
+}
+
+fn jpeg_size_at(img: &VipsImage, quality: u8) -> usize {
+    save::save_image(libvips::ops::copy(img).unwrap(), "jpeg", quality)
+        .unwrap()
+        .len()
+}
+
+#[test]
+fn test_encode_within_budget_lowers_quality_to_fit() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_gradient_test_image(200, 150), "").unwrap();
+    let budget = jpeg_size_at(&img, 85) / 2;
+
+    let encoded = quality::encode_within_budget(&img, "jpeg", 85, budget, &[], save::SaveOptions::default()).unwrap();
+    assert_eq!(encoded.format, "jpeg");
+    assert!(encoded.quality < 85);
+    assert!(encoded.bytes.len() <= budget);
+    assert!(jpeg_size_at(&img, encoded.quality + 1) > budget);
+}
+
+#[test]
+fn test_encode_within_budget_switches_format_when_quality_is_not_enough() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_gradient_test_image(200, 150), "").unwrap();
+    // Smaller than the lowest-quality JPEG the budget search will produce.
+    let budget = jpeg_size_at(&img, 30) - 1;
+
+    // Without a ladder the smallest JPEG is served even though it is over budget.
+    let encoded = quality::encode_within_budget(&img, "jpeg", 85, budget, &[], save::SaveOptions::default()).unwrap();
+    assert_eq!(encoded.format, "jpeg");
+    assert!(encoded.bytes.len() > budget);
+
+    let ladder = vec!["jpeg".to_string(), "webp".to_string()];
+    let encoded =
+        quality::encode_within_budget(&img, "jpg", 85, budget, &ladder, save::SaveOptions::default()).unwrap();
+    assert_eq!(encoded.format, "webp");
+    assert!(encoded.bytes.len() <= budget);
+    assert_eq!(&encoded.bytes[8..12], b"WEBP");
+}
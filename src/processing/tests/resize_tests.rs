@@ -1,6 +1,8 @@
-use crate::processing::options::Resize;
-use crate::processing::transform;
-use libvips::VipsImage;
+use crate::processing::error::ProcessingError;
+use crate::processing::options::{ParsedOptions, Resize};
+use crate::processing::{process_image, transform};
+use bytes::Bytes;
+use libvips::{ops, VipsImage};
 
 use super::tests_support::*;
 
@@ -32,6 +34,60 @@ fn test_apply_resize_fill() {
     assert_eq!(resized_img.get_height(), 200);
 }
 
+/// Fills a 200x200 quadrant image into `width` x `height` around `focus_point` and returns the
+/// colours near the top-left and bottom-right corners of the output.
+fn fill_corners_with_focus(width: u32, height: u32, focus_point: (f64, f64)) -> ([u8; 4], [u8; 4]) {
+    let img = VipsImage::new_from_buffer(&create_quadrant_test_image(200, 200), "").unwrap();
+    let resize = Resize {
+        resizing_type: "fill".to_string(),
+        width,
+        height,
+    };
+    let gravity = Some("fp".to_string());
+    let resized = transform::apply_resize_with_focus(img, &resize, &gravity, Some(focus_point), &None).unwrap();
+    assert_eq!(
+        (resized.get_width() as u32, resized.get_height() as u32),
+        (width, height)
+    );
+    let decoded = decode_rgba(&resized);
+    (
+        rgba_pixel(&decoded, 10, 10),
+        rgba_pixel(&decoded, width - 11, height - 11),
+    )
+}
+
+#[test]
+fn test_apply_resize_fill_focus_point_corners() {
+    init_vips();
+    let (red, green, blue, yellow) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 0, 255]);
+
+    // A wide target crops rows: 0,0 keeps the top half and 1,1 the bottom half
+    assert_eq!(fill_corners_with_focus(100, 50, (0.0, 0.0)), (red, green));
+    assert_eq!(fill_corners_with_focus(100, 50, (1.0, 1.0)), (blue, yellow));
+    // A tall target crops columns: 0,0 keeps the left half and 1,1 the right half
+    assert_eq!(fill_corners_with_focus(50, 100, (0.0, 0.0)), (red, blue));
+    assert_eq!(fill_corners_with_focus(50, 100, (1.0, 1.0)), (green, yellow));
+}
+
+#[test]
+fn test_apply_resize_css_aliases_match_fit_and_fill() {
+    init_vips();
+    for (alias, canonical, expected) in [("contain", "fit", (200, 150)), ("cover", "fill", (200, 200))] {
+        let dimensions = |resizing_type: &str| {
+            let img = VipsImage::new_from_buffer(&create_test_image(400, 300), "").unwrap();
+            let resize = Resize {
+                resizing_type: resizing_type.to_string(),
+                width: 200,
+                height: 200,
+            };
+            let resized = transform::apply_resize(img, &resize, &None, &None).unwrap();
+            (resized.get_width(), resized.get_height())
+        };
+        assert_eq!(dimensions(alias), expected, "{}", alias);
+        assert_eq!(dimensions(canonical), expected, "{}", canonical);
+    }
+}
+
 #[test]
 fn test_apply_resize_fill_width_only() {
     init_vips();
@@ -111,8 +167,9 @@ fn test_apply_resize_unknown_type_error() {
         height: 100,
     };
     let result = transform::apply_resize(img, &resize, &None, &None);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().contains("Unknown resize type"));
+    assert!(
+        matches!(result, Err(ProcessingError::Unsupported(ref message)) if message.contains("Unknown resize type"))
+    );
 }
 
 #[test]
@@ -123,8 +180,7 @@ fn test_resolve_resize_dimensions_rejects_both_zero() {
         height: 0,
     };
     let result = transform::resolve_resize_dimensions(&resize, 400, 300);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().contains("at least one non-zero"));
+    assert!(matches!(result, Err(ProcessingError::Geometry(ref message)) if message.contains("at least one non-zero")));
 }
 
 #[test]
@@ -355,3 +411,212 @@ fn test_apply_resize_with_invalid_kernel_falls_back_to_default() {
     assert_eq!(resized_img.get_width(), 200);
     assert_eq!(resized_img.get_height(), 150);
 }
+
+#[test]
+fn test_fit_to_odd_target_snaps_to_even_dimensions() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image(202, 150), "").unwrap();
+    let resize = Resize {
+        resizing_type: "force".to_string(),
+        width: 101,
+        height: 75,
+    };
+    let resized = transform::apply_resize(img, &resize, &None, &None).unwrap();
+    assert_eq!(resized.get_width(), 101);
+    assert_eq!(resized.get_height(), 75);
+
+    let snapped = transform::snap_to_even_dimensions(resized).unwrap();
+    assert_eq!(snapped.get_width(), 100);
+    assert_eq!(snapped.get_height(), 74);
+}
+
+#[test]
+fn test_snap_to_even_dimensions_keeps_even_and_single_pixel_sizes() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image(100, 1), "").unwrap();
+    let snapped = transform::snap_to_even_dimensions(img).unwrap();
+    assert_eq!(snapped.get_width(), 100);
+    assert_eq!(snapped.get_height(), 1);
+}
+
+/// 8x8 PNG with alternating black and white pixels.
+fn create_pixel_art_image() -> Vec<u8> {
+    let img = image::RgbImage::from_fn(8, 8, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgb([0, 0, 0])
+        } else {
+            image::Rgb([255, 255, 255])
+        }
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+fn upscale_pixel_art(pixel_art: bool) -> image::RgbaImage {
+    let source = create_pixel_art_image();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "force".to_string(),
+            width: 80,
+            height: 80,
+        }),
+        enlarge: true,
+        format: Some("png".to_string()),
+        pixel_art,
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+    image::load_from_memory(&output).unwrap().to_rgba8()
+}
+
+#[test]
+fn test_pixel_art_upscale_keeps_sharp_edges() {
+    init_vips();
+    let upscaled = upscale_pixel_art(true);
+    assert_eq!(upscaled.dimensions(), (80, 80));
+
+    // Every source pixel becomes a solid 10x10 block, right up to the block edges.
+    for (x, y) in [(0, 0), (9, 9), (10, 0), (19, 9), (45, 45), (79, 79)] {
+        let expected = if (x / 10 + y / 10) % 2 == 0 { 0 } else { 255 };
+        assert_eq!(
+            upscaled.get_pixel(x, y).0,
+            [expected, expected, expected, 255],
+            "at {},{}",
+            x,
+            y
+        );
+    }
+
+    // The default lanczos3 kernel blends neighbouring blocks at their edges.
+    let smoothed = upscale_pixel_art(false);
+    let edge = smoothed.get_pixel(9, 5).0[0];
+    assert!(edge > 0 && edge < 255, "expected blended edge, got {}", edge);
+}
+
+#[test]
+fn test_auto_resizing_algorithm_picks_kernel_by_scale() {
+    let auto = Some(transform::AUTO_ALGORITHM.to_string());
+    assert!(matches!(
+        transform::get_resize_kernel(&auto, 0.25),
+        ops::Kernel::Lanczos3
+    ));
+    assert!(matches!(
+        transform::get_resize_kernel(&auto, 1.0),
+        ops::Kernel::Lanczos3
+    ));
+    assert!(matches!(transform::get_resize_kernel(&auto, 2.0), ops::Kernel::Cubic));
+    assert!(matches!(transform::get_resize_kernel(&auto, 8.0), ops::Kernel::Nearest));
+
+    // Explicit kernels ignore the scale factor.
+    let cubic = Some("cubic".to_string());
+    assert!(matches!(transform::get_resize_kernel(&cubic, 0.25), ops::Kernel::Cubic));
+    assert!(matches!(transform::get_resize_kernel(&cubic, 8.0), ops::Kernel::Cubic));
+}
+
+#[test]
+fn test_auto_resizing_algorithm_resizes_to_expected_dimensions() {
+    init_vips();
+    let auto = Some(transform::AUTO_ALGORITHM.to_string());
+    let img = VipsImage::new_from_buffer(&create_test_image(400, 300), "").unwrap();
+    for (scale, width, height) in [(0.5, 200, 150), (2.0, 800, 600), (5.0, 2000, 1500)] {
+        let resized = transform::resize_with_algorithm(&img, scale, None, &auto, "Error resizing").unwrap();
+        assert_eq!(
+            (resized.get_width(), resized.get_height()),
+            (width, height),
+            "scale {}",
+            scale
+        );
+    }
+
+    // Extreme upscales use nearest-neighbour, so pixel art keeps its hard edges.
+    let source = create_pixel_art_image();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "force".to_string(),
+            width: 80,
+            height: 80,
+        }),
+        enlarge: true,
+        format: Some("png".to_string()),
+        resizing_algorithm: auto,
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+    let upscaled = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(upscaled.get_pixel(9, 5).0, [0, 0, 0, 255]);
+    assert_eq!(upscaled.get_pixel(10, 5).0, [255, 255, 255, 255]);
+}
+
+#[test]
+fn test_apply_resize_shrink_only_downscales() {
+    init_vips();
+    for resizing_type in ["shrink", "fit-down"] {
+        let resize = Resize {
+            resizing_type: resizing_type.to_string(),
+            width: 200,
+            height: 200,
+        };
+
+        let larger = VipsImage::new_from_buffer(&create_test_image(400, 300), "").unwrap();
+        let shrunk = transform::apply_resize(larger, &resize, &None, &None).unwrap();
+        assert_eq!(
+            (shrunk.get_width(), shrunk.get_height()),
+            (200, 150),
+            "{}",
+            resizing_type
+        );
+
+        let smaller = VipsImage::new_from_buffer(&create_test_image(120, 80), "").unwrap();
+        let untouched = transform::apply_resize(smaller, &resize, &None, &None).unwrap();
+        assert_eq!(
+            (untouched.get_width(), untouched.get_height()),
+            (120, 80),
+            "{}",
+            resizing_type
+        );
+    }
+}
+
+#[test]
+fn test_process_image_shrink_ignores_enlarge() {
+    init_vips();
+    let source = create_test_image(120, 80);
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let parsed_options = ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "shrink".to_string(),
+            width: 300,
+            height: 300,
+        }),
+        enlarge: true,
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+    let decoded = image::load_from_memory(&output).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (120, 80));
+}
+
+#[test]
+fn test_apply_resize_observes_scale_histogram() {
+    init_vips();
+    let capture = HistogramCapture::default();
+    metrics::with_local_recorder(&capture, || {
+        for (resizing_type, width) in [("fit", 200), ("force", 800)] {
+            let img = VipsImage::new_from_buffer(&create_test_image(400, 300), "").unwrap();
+            let resize = Resize {
+                resizing_type: resizing_type.to_string(),
+                width,
+                height: 0,
+            };
+            transform::apply_resize(img, &resize, &None, &None).unwrap();
+        }
+    });
+
+    // `force` with a zero height keeps the source height, so its smaller axis scale is 1.
+    assert_eq!(capture.values("imgforge_resize_scale"), vec![0.5, 1.0]);
+}
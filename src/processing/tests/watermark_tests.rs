@@ -1,6 +1,7 @@
 use crate::constants::ENV_WATERMARK_PATH;
-use crate::processing::options::Watermark;
-use crate::processing::watermark;
+use crate::processing::options::{LabelOptions, ParsedOptions, Resize, Watermark};
+use crate::processing::{process_image, watermark};
+use bytes::Bytes;
 use libvips::VipsImage;
 use std::io::Write;
 use tempfile::NamedTempFile;
@@ -19,6 +20,7 @@ fn test_apply_watermark() {
     let watermark_opts = Watermark {
         opacity: 0.5,
         position: "center".to_string(),
+        ..Default::default()
     };
     let watermarked_img = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
 
@@ -48,6 +50,7 @@ fn test_watermark_all_positions() {
         let watermark_opts = Watermark {
             opacity: 0.5,
             position: position.to_string(),
+            ..Default::default()
         };
         let watermarked = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
         assert_eq!(watermarked.get_width(), 200);
@@ -63,6 +66,7 @@ fn test_watermark_full_opacity() {
     let watermark_opts = Watermark {
         opacity: 1.0,
         position: "center".to_string(),
+        ..Default::default()
     };
     let watermarked = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
     assert_eq!(watermarked.get_width(), 200);
@@ -77,8 +81,188 @@ fn test_watermark_zero_opacity() {
     let watermark_opts = Watermark {
         opacity: 0.0,
         position: "center".to_string(),
+        ..Default::default()
     };
     let watermarked = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
     assert_eq!(watermarked.get_width(), 200);
     assert_eq!(watermarked.get_height(), 200);
 }
+
+#[test]
+fn test_watermark_tiled_rotated_keeps_dimensions() {
+    init_vips();
+    let img = VipsImage::new_from_buffer(&create_test_image(300, 200), "").unwrap();
+    let watermark = cached_watermark_from_bytes(create_quadrant_test_image(60, 20));
+    let watermark_opts = Watermark {
+        opacity: 0.5,
+        position: "tile".to_string(),
+        scale: Some(0.2),
+        angle: 45.0,
+        offset: None,
+    };
+    let watermarked = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
+    assert_eq!(watermarked.get_width(), 300);
+    assert_eq!(watermarked.get_height(), 200);
+}
+
+#[test]
+fn test_watermark_tiled_covers_whole_surface_at_opacity() {
+    init_vips();
+    let blue = image::RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 255, 255]));
+    let mut source: Vec<u8> = Vec::new();
+    blue.write_to(&mut std::io::Cursor::new(&mut source), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let watermark = cached_watermark_from_bytes(create_test_image(50, 50));
+    let watermark_opts = Watermark {
+        opacity: 0.5,
+        position: "tile".to_string(),
+        scale: Some(0.25),
+        ..Default::default()
+    };
+    let watermarked = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
+    let decoded = decode_rgba(&watermarked);
+
+    // Sixteen 50x50 red tiles at half opacity blend every pixel of the blue source to purple
+    for (x, y, pixel) in decoded.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        assert!(
+            r.abs_diff(128) <= 2 && g == 0 && b.abs_diff(128) <= 2 && a == 255,
+            "pixel ({}, {}) is {:?}",
+            x,
+            y,
+            pixel.0
+        );
+    }
+}
+
+#[test]
+fn test_watermark_percentage_position_lands_at_offset() {
+    init_vips();
+    let blue = image::RgbaImage::from_pixel(200, 100, image::Rgba([0, 0, 255, 255]));
+    let mut source: Vec<u8> = Vec::new();
+    blue.write_to(&mut std::io::Cursor::new(&mut source), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+    let watermark = cached_watermark_from_bytes(create_test_image(50, 50));
+    let watermark_opts = Watermark {
+        opacity: 1.0,
+        position: "25,40".to_string(),
+        offset: Some((25.0, 40.0)),
+        ..Default::default()
+    };
+    let watermarked = watermark::apply_watermark(img, &watermark, &watermark_opts, &None).unwrap();
+    let decoded = decode_rgba(&watermarked);
+
+    // The 50x50 watermark starts at x = 25% of 200 = 50. At 40% of 100 it would overflow the
+    // bottom edge, so it is pulled up to y = 100 - 50 = 50.
+    assert_eq!(rgba_pixel(&decoded, 52, 52), [255, 0, 0, 255]);
+    assert_eq!(rgba_pixel(&decoded, 97, 97), [255, 0, 0, 255]);
+    assert_eq!(rgba_pixel(&decoded, 47, 52), [0, 0, 255, 255]);
+    assert_eq!(rgba_pixel(&decoded, 52, 47), [0, 0, 255, 255]);
+    assert_eq!(rgba_pixel(&decoded, 102, 52), [0, 0, 255, 255]);
+}
+
+/// Processes a blue 200x200 source resized to `size` with a red centred watermark and returns the
+/// centre pixel of the output.
+fn watermarked_center_pixel(size: u32, watermark_min_size: Option<u32>) -> [u8; 4] {
+    let blue = image::RgbaImage::from_pixel(200, 200, image::Rgba([0, 0, 255, 255]));
+    let mut source: Vec<u8> = Vec::new();
+    blue.write_to(&mut std::io::Cursor::new(&mut source), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+
+    let watermark = cached_watermark_from_bytes(create_test_image(50, 50));
+    let parsed_options = ParsedOptions {
+        resize: Some(Resize {
+            resizing_type: "fit".to_string(),
+            width: size,
+            height: size,
+        }),
+        watermark: Some(Watermark {
+            opacity: 1.0,
+            position: "center".to_string(),
+            ..Default::default()
+        }),
+        watermark_min_size,
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), Some(&watermark)).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(decoded.dimensions(), (size, size));
+    rgba_pixel(&decoded, size / 2, size / 2)
+}
+
+#[test]
+fn test_watermark_skipped_below_min_size() {
+    init_vips();
+    assert_eq!(watermarked_center_pixel(200, Some(100)), [255, 0, 0, 255]);
+    assert_eq!(watermarked_center_pixel(40, Some(100)), [0, 0, 255, 255]);
+    assert_eq!(watermarked_center_pixel(40, None), [255, 0, 0, 255]);
+    // Minimums past i32::MAX must still skip the watermark rather than wrap negative
+    assert_eq!(watermarked_center_pixel(40, Some(u32::MAX)), [0, 0, 255, 255]);
+}
+
+#[test]
+fn test_copyright_renders_along_bottom_without_resizing() {
+    init_vips();
+    let blue = image::RgbaImage::from_pixel(400, 300, image::Rgba([0, 0, 255, 255]));
+    let mut source: Vec<u8> = Vec::new();
+    blue.write_to(&mut std::io::Cursor::new(&mut source), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+
+    let parsed_options = ParsedOptions {
+        copyright: Some("(c) 2026 Acme <Photo>".to_string()),
+        format: Some("png".to_string()),
+        ..Default::default()
+    };
+    let output = process_image(img, parsed_options, &Bytes::from(source), None).unwrap();
+
+    let decoded = image::load_from_memory(&output).unwrap().to_rgba8();
+    assert_eq!(decoded.dimensions(), (400, 300));
+    let bottom_changed = (0..400)
+        .flat_map(|x| (280..300).map(move |y| (x, y)))
+        .any(|(x, y)| rgba_pixel(&decoded, x, y) != [0, 0, 255, 255]);
+    assert!(bottom_changed, "copyright text should be composited along the bottom");
+    let top_untouched = (0..400)
+        .flat_map(|x| (0..20).map(move |y| (x, y)))
+        .all(|(x, y)| rgba_pixel(&decoded, x, y) == [0, 0, 255, 255]);
+    assert!(top_untouched);
+
+    let vips_output = VipsImage::new_from_buffer(&output, "").unwrap();
+    let dpi = vips_output.get_xres() * 25.4;
+    assert!((dpi - watermark::COPYRIGHT_DPI).abs() < 1.0, "unexpected dpi {}", dpi);
+}
+
+#[test]
+fn test_text_label_renders_in_requested_corner_and_colour() {
+    init_vips();
+    let blue = image::RgbaImage::from_pixel(300, 200, image::Rgba([0, 0, 255, 255]));
+    let mut source: Vec<u8> = Vec::new();
+    blue.write_to(&mut std::io::Cursor::new(&mut source), image::ImageFormat::Png)
+        .unwrap();
+    let img = VipsImage::new_from_buffer(&source, "").unwrap();
+
+    let label = LabelOptions {
+        text: "\u{a9} 2025".to_string(),
+        size: 24,
+        color: [255, 0, 0, 255],
+        position: "north_west".to_string(),
+    };
+    let labelled = watermark::apply_text_label(img, &label).unwrap();
+    assert_eq!((labelled.get_width(), labelled.get_height()), (300, 200));
+
+    let decoded = decode_rgba(&labelled);
+    let top_left_red = (0..150).flat_map(|x| (0..50).map(move |y| (x, y))).any(|(x, y)| {
+        let [r, _, b, _] = rgba_pixel(&decoded, x, y);
+        r > 200 && b < 55
+    });
+    assert!(top_left_red, "label glyphs should be drawn in the top-left corner");
+    let bottom_untouched = (0..300)
+        .flat_map(|x| (100..200).map(move |y| (x, y)))
+        .all(|(x, y)| rgba_pixel(&decoded, x, y) == [0, 0, 255, 255]);
+    assert!(bottom_untouched);
+}
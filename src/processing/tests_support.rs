@@ -1,9 +1,11 @@
 use crate::processing::save;
 use crate::processing::watermark;
 use bytes::Bytes;
-use image::{ImageBuffer, Rgba, RgbaImage};
+use image::{ImageBuffer, ImageEncoder, Rgba, RgbaImage};
 use lazy_static::lazy_static;
 use libvips::{ops, VipsApp, VipsImage};
+use metrics::{Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+use std::sync::{Arc, Mutex};
 
 lazy_static! {
     static ref APP: VipsApp = {
@@ -47,6 +49,20 @@ pub fn create_quadrant_test_image(width: u32, height: u32) -> Vec<u8> {
     bytes
 }
 
+pub fn create_gradient_test_image(width: u32, height: u32) -> Vec<u8> {
+    let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(width, height);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let r = (x * 255 / width.max(1)) as u8;
+        let g = (y * 255 / height.max(1)) as u8;
+        let b = ((x * 7 + y * 13) % 256) as u8;
+        *pixel = Rgba([r, g, b, 255]);
+    }
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
 pub fn create_orientation_test_image() -> Vec<u8> {
     let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(3, 2);
     img.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
@@ -62,6 +78,18 @@ pub fn create_orientation_test_image() -> Vec<u8> {
     bytes
 }
 
+/// PNG filled with `inner` and surrounded by a `frame`-pixel border of `border`.
+pub fn create_framed_test_image(width: u32, height: u32, frame: u32, border: [u8; 4], inner: [u8; 4]) -> Vec<u8> {
+    let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| {
+        let in_frame = x < frame || y < frame || x >= width - frame || y >= height - frame;
+        Rgba(if in_frame { border } else { inner })
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
 pub fn create_test_image_jpeg(width: u32, height: u32) -> Vec<u8> {
     let mut img: ImageBuffer<image::Rgb<u8>, Vec<u8>> = ImageBuffer::new(width, height);
     for (_x, _y, pixel) in img.enumerate_pixels_mut() {
@@ -73,6 +101,37 @@ pub fn create_test_image_jpeg(width: u32, height: u32) -> Vec<u8> {
     bytes
 }
 
+/// Red PNG whose pixels are encoded in Display P3 and tagged with the P3 ICC profile.
+pub fn create_display_p3_test_image(width: u32, height: u32) -> Vec<u8> {
+    init_vips();
+    let srgb = VipsImage::new_from_buffer(&create_test_image(width, height), "").unwrap();
+    let opts = ops::IccTransformOptions {
+        input_profile: "srgb".to_string(),
+        ..Default::default()
+    };
+    let p3 = ops::icc_transform_with_opts(&srgb, "p3", &opts).unwrap();
+    ops::pngsave_buffer(&p3).unwrap()
+}
+
+/// Red JPEG stored as CMYK pixels, converted with the generic CMYK profile.
+pub fn create_cmyk_test_jpeg(width: u32, height: u32) -> Vec<u8> {
+    init_vips();
+    let srgb = VipsImage::new_from_buffer(&create_test_image(width, height), "").unwrap();
+    let opts = ops::IccTransformOptions {
+        input_profile: "srgb".to_string(),
+        ..Default::default()
+    };
+    let cmyk = ops::icc_transform_with_opts(&ops::flatten(&srgb).unwrap(), "cmyk", &opts).unwrap();
+    ops::jpegsave_buffer(&cmyk).unwrap()
+}
+
+/// Returns the ICC profile embedded in a PNG, if any.
+pub fn png_icc_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    use image::ImageDecoder;
+    let mut decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(bytes)).unwrap();
+    decoder.icc_profile().unwrap()
+}
+
 pub fn decode_rgba(img: &VipsImage) -> RgbaImage {
     let img_copy = ops::copy(img).unwrap();
     let png_bytes = save::save_image(img_copy, "png", 90).unwrap();
@@ -94,3 +153,120 @@ pub fn collect_rgba_pixels(decoded: &RgbaImage) -> Vec<[u8; 4]> {
 pub fn cached_watermark_from_bytes(bytes: Vec<u8>) -> watermark::CachedWatermark {
     watermark::CachedWatermark::from_bytes(Bytes::from(bytes))
 }
+
+fn create_solid_rgb_image(color: [u8; 3]) -> VipsImage {
+    let img = image::RgbImage::from_pixel(20, 20, image::Rgb(color));
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    VipsImage::new_from_buffer(&bytes, "").unwrap()
+}
+
+/// Two 20x20 pages: red first, blue second.
+pub fn create_two_page_tiff() -> Vec<u8> {
+    let stacked = ops::join(
+        &create_solid_rgb_image([255, 0, 0]),
+        &create_solid_rgb_image([0, 0, 255]),
+        ops::Direction::Vertical,
+    )
+    .unwrap();
+    let opts = ops::TiffsaveBufferOptions {
+        page_height: 20,
+        ..Default::default()
+    };
+    ops::tiffsave_buffer_with_opts(&stacked, &opts).unwrap()
+}
+
+/// 1600x1200 red JPEG with the given EXIF orientation, whose IFD1 carries a 160x120 green JPEG
+/// thumbnail.
+pub fn create_jpeg_with_exif_thumbnail(orientation: u16) -> Vec<u8> {
+    let encode = |width: u32, height: u32, color: [u8; 3], exif: Option<Vec<u8>>| {
+        let img = image::RgbImage::from_pixel(width, height, image::Rgb(color));
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 90);
+        if let Some(exif) = exif {
+            encoder.set_exif_metadata(exif).unwrap();
+        }
+        encoder
+            .write_image(img.as_raw(), width, height, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        bytes
+    };
+    let thumbnail = encode(160, 120, [0, 255, 0], None);
+
+    // Little-endian TIFF header; IFD0 holds the orientation and links to IFD1 at offset 26,
+    // whose two LONG entries point at the thumbnail appended at offset 56.
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&0x0112u16.to_le_bytes());
+    exif.extend_from_slice(&3u16.to_le_bytes());
+    exif.extend_from_slice(&1u32.to_le_bytes());
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0, 0]);
+    exif.extend_from_slice(&26u32.to_le_bytes());
+    exif.extend_from_slice(&2u16.to_le_bytes());
+    for (tag, value) in [(0x0201u16, 56u32), (0x0202, thumbnail.len() as u32)] {
+        exif.extend_from_slice(&tag.to_le_bytes());
+        exif.extend_from_slice(&4u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&value.to_le_bytes());
+    }
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    exif.extend_from_slice(&thumbnail);
+
+    encode(1600, 1200, [255, 0, 0], Some(exif))
+}
+
+/// Metrics recorder that keeps every histogram observation, keyed by metric name.
+///
+/// Install it with `metrics::with_local_recorder` so only the current thread's metrics are seen.
+#[derive(Default)]
+pub struct HistogramCapture {
+    observations: Arc<Mutex<Vec<(String, f64)>>>,
+}
+
+impl HistogramCapture {
+    /// Returns the values recorded for the histogram `name`, in observation order.
+    pub fn values(&self, name: &str) -> Vec<f64> {
+        self.observations
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+}
+
+struct CapturedHistogram {
+    name: String,
+    observations: Arc<Mutex<Vec<(String, f64)>>>,
+}
+
+impl HistogramFn for CapturedHistogram {
+    fn record(&self, value: f64) {
+        self.observations.lock().unwrap().push((self.name.clone(), value));
+    }
+}
+
+impl Recorder for HistogramCapture {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, _key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::noop()
+    }
+
+    fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(Arc::new(CapturedHistogram {
+            name: key.name().to_string(),
+            observations: self.observations.clone(),
+        }))
+    }
+}
@@ -1,13 +1,39 @@
-use crate::processing::options::{Crop, Resize};
+use crate::monitoring::observe_resize_scale;
+use crate::processing::error::ProcessingError;
+use crate::processing::options::{Border, Crop, ParsedOptions, Resize, Trim};
 use crate::utils::read_exif_orientation;
 use libvips::{ops, VipsImage};
-use tracing::debug;
+use tracing::{debug, warn};
 
 const SCALE_EPSILON: f64 = 1e-6;
-
-/// Converts a resizing algorithm string to a libvips Kernel enum.
-fn get_resize_kernel(algorithm: &Option<String>) -> ops::Kernel {
+/// Gravity that crops `fill` results around the most salient region.
+const SMART_GRAVITY: &str = "sm";
+/// Side length in pixels of one checkerboard square.
+const CHECKERBOARD_CELL_SIZE: i32 = 8;
+/// Checkerboard shades, matching the white and light gray used by image editors.
+const CHECKERBOARD_LIGHT: u8 = 255;
+const CHECKERBOARD_DARK: u8 = 204;
+/// Resizing algorithm used for `pixel_art` requests: nearest-neighbour for large upscales and
+/// the default lanczos3 otherwise.
+pub const PIXEL_ART_ALGORITHM: &str = "pixel_art";
+/// Smallest upscale factor at which `pixel_art` switches to nearest-neighbour.
+const PIXEL_ART_MIN_UPSCALE: f64 = 2.0;
+/// Resizing algorithm that picks the kernel from the scale factor: lanczos3 for downscales,
+/// cubic for moderate upscales, and nearest-neighbour for extreme upscales.
+pub const AUTO_ALGORITHM: &str = "auto";
+/// Smallest upscale factor at which `auto` switches from cubic to nearest-neighbour.
+const AUTO_NEAREST_MIN_UPSCALE: f64 = 4.0;
+/// Name of the built-in libvips sRGB ICC profile.
+const SRGB_PROFILE: &str = "srgb";
+/// Name of the built-in libvips CMYK ICC profile, assumed for CMYK sources without their own.
+const CMYK_PROFILE: &str = "cmyk";
+
+/// Converts a resizing algorithm string to a libvips Kernel enum for the given scale factor.
+pub(crate) fn get_resize_kernel(algorithm: &Option<String>, scale: f64) -> ops::Kernel {
     match algorithm.as_deref().unwrap_or("lanczos3") {
+        PIXEL_ART_ALGORITHM if scale >= PIXEL_ART_MIN_UPSCALE => ops::Kernel::Nearest,
+        AUTO_ALGORITHM if scale >= AUTO_NEAREST_MIN_UPSCALE => ops::Kernel::Nearest,
+        AUTO_ALGORITHM if scale > 1.0 => ops::Kernel::Cubic,
         "nearest" => ops::Kernel::Nearest,
         "linear" => ops::Kernel::Linear,
         "cubic" => ops::Kernel::Cubic,
@@ -40,58 +66,76 @@ pub fn resize_with_algorithm(
     vscale: Option<f64>,
     resizing_algorithm: &Option<String>,
     error_context: &str,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
+    let vscale = vscale.unwrap_or(hscale);
     let options = ops::ResizeOptions {
-        kernel: get_resize_kernel(resizing_algorithm),
-        vscale: vscale.unwrap_or(hscale),
+        kernel: get_resize_kernel(resizing_algorithm, hscale.min(vscale)),
+        vscale,
         ..Default::default()
     };
 
-    ops::resize_with_opts(img, hscale, &options).map_err(|e| format!("{error_context}: {}", e))
+    ops::resize_with_opts(img, hscale, &options).map_err(|e| ProcessingError::Vips(format!("{error_context}: {}", e)))
 }
 
 /// Applies EXIF rotation to an image based on orientation data.
-pub fn apply_exif_rotation(image_bytes: &[u8], mut img: VipsImage) -> Result<VipsImage, String> {
+pub fn apply_exif_rotation(image_bytes: &[u8], mut img: VipsImage) -> Result<VipsImage, ProcessingError> {
     if let Some(orientation) = read_exif_orientation(image_bytes) {
         debug!("Found EXIF orientation: {:?}", orientation);
+        // When the decoder kept the tag, libvips' autorot also resets it to 1, so outputs that keep
+        // metadata are not rotated a second time by viewers
+        if orientation != 1 && img.get_orientation() == orientation as i32 {
+            return ops::autorot(&img).map_err(|e| ProcessingError::Vips(format!("Error auto-rotating: {}", e)));
+        }
         img = apply_exif_orientation(img, orientation)?;
     }
     Ok(img)
 }
 
-pub(crate) fn apply_exif_orientation(mut img: VipsImage, orientation: u32) -> Result<VipsImage, String> {
+pub(crate) fn apply_exif_orientation(mut img: VipsImage, orientation: u32) -> Result<VipsImage, ProcessingError> {
     match orientation {
         2 => {
             img = ops::flip(&img, ops::Direction::Horizontal)
-                .map_err(|e| format!("Error flipping horizontally: {}", e))?
+                .map_err(|e| ProcessingError::Vips(format!("Error flipping horizontally: {}", e)))?
+        }
+        3 => {
+            img = ops::rot(&img, ops::Angle::D180)
+                .map_err(|e| ProcessingError::Vips(format!("Error rotating 180: {}", e)))?
         }
-        3 => img = ops::rot(&img, ops::Angle::D180).map_err(|e| format!("Error rotating 180: {}", e))?,
         4 => {
-            img = ops::flip(&img, ops::Direction::Vertical).map_err(|e| format!("Error flipping vertically: {}", e))?
+            img = ops::flip(&img, ops::Direction::Vertical)
+                .map_err(|e| ProcessingError::Vips(format!("Error flipping vertically: {}", e)))?
         }
         5 => {
             img = ops::flip(
-                &ops::rot(&img, ops::Angle::D90).map_err(|e| format!("Error rotating 90: {}", e))?,
+                &ops::rot(&img, ops::Angle::D90)
+                    .map_err(|e| ProcessingError::Vips(format!("Error rotating 90: {}", e)))?,
                 ops::Direction::Horizontal,
             )
-            .map_err(|e| format!("Error flipping after rotate: {}", e))?
+            .map_err(|e| ProcessingError::Vips(format!("Error flipping after rotate: {}", e)))?
+        }
+        6 => {
+            img = ops::rot(&img, ops::Angle::D90)
+                .map_err(|e| ProcessingError::Vips(format!("Error rotating 90: {}", e)))?
         }
-        6 => img = ops::rot(&img, ops::Angle::D90).map_err(|e| format!("Error rotating 90: {}", e))?,
         7 => {
             img = ops::flip(
-                &ops::rot(&img, ops::Angle::D270).map_err(|e| format!("Error rotating 270: {}", e))?,
+                &ops::rot(&img, ops::Angle::D270)
+                    .map_err(|e| ProcessingError::Vips(format!("Error rotating 270: {}", e)))?,
                 ops::Direction::Horizontal,
             )
-            .map_err(|e| format!("Error flipping after rotate: {}", e))?
+            .map_err(|e| ProcessingError::Vips(format!("Error flipping after rotate: {}", e)))?
+        }
+        8 => {
+            img = ops::rot(&img, ops::Angle::D270)
+                .map_err(|e| ProcessingError::Vips(format!("Error rotating 270: {}", e)))?
         }
-        8 => img = ops::rot(&img, ops::Angle::D270).map_err(|e| format!("Error rotating 270: {}", e))?,
         _ => {}
     }
     Ok(img)
 }
 
 /// Crops an image to the specified dimensions.
-pub fn crop_image(img: VipsImage, crop: Crop) -> Result<VipsImage, String> {
+pub fn crop_image(img: VipsImage, crop: Crop) -> Result<VipsImage, ProcessingError> {
     ops::extract_area(
         &img,
         crop.x as i32,
@@ -99,16 +143,137 @@ pub fn crop_image(img: VipsImage, crop: Crop) -> Result<VipsImage, String> {
         crop.width as i32,
         crop.height as i32,
     )
-    .map_err(|e| format!("Error cropping image: {}", e))
+    .map_err(|e| ProcessingError::Vips(format!("Error cropping image: {}", e)))
+}
+
+/// Removes borders that stay within `trim.threshold` of the background colour.
+///
+/// An image that is uniform throughout has no content to keep, so it is returned unchanged.
+pub fn trim_image(img: VipsImage, trim: &Trim) -> Result<VipsImage, ProcessingError> {
+    // libvips flattens alpha before searching, so the background only covers the colour bands
+    let color_bands = img.get_bands() - i32::from(img.image_hasalpha());
+    let background = match trim.background {
+        Some([r, g, b, _]) if color_bands < 3 => vec![(r as f64 + g as f64 + b as f64) / 3.0],
+        Some([r, g, b, _]) => vec![r as f64, g as f64, b as f64],
+        None => corner_color(&img, color_bands)?,
+    };
+
+    let (left, top, width, height) = ops::find_trim_with_opts(
+        &img,
+        &ops::FindTrimOptions {
+            threshold: trim.threshold,
+            background,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| ProcessingError::Vips(format!("Error finding trim bounds: {}", e)))?;
+
+    if width <= 0 || height <= 0 {
+        debug!("Trim found no content in uniform image, leaving it unchanged");
+        return Ok(img);
+    }
+    if (width, height) == (img.get_width(), img.get_height()) {
+        return Ok(img);
+    }
+    debug!(
+        "Trimming {}x{} image to {}x{} at ({}, {})",
+        img.get_width(),
+        img.get_height(),
+        width,
+        height,
+        left,
+        top
+    );
+    ops::extract_area(&img, left, top, width, height)
+        .map_err(|e| ProcessingError::Vips(format!("Error trimming image: {}", e)))
+}
+
+/// Reads the first `bands` values of the top-left pixel.
+fn corner_color(img: &VipsImage, bands: i32) -> Result<Vec<f64>, ProcessingError> {
+    let corner = ops::extract_area(img, 0, 0, 1, 1)
+        .map_err(|e| ProcessingError::Vips(format!("Error sampling trim background: {}", e)))?;
+    (0..bands)
+        .map(|band| {
+            ops::extract_band(&corner, band)
+                .and_then(|value| ops::avg(&value))
+                .map_err(|e| ProcessingError::Vips(format!("Error sampling trim background: {}", e)))
+        })
+        .collect()
+}
+
+/// Checks that the crop and resize requested in `options` fit a `src_width` x `src_height` image.
+///
+/// Simulates the dimensions each step would produce, so impossible combinations fail up front
+/// with a single message listing every conflicting operation instead of a raw libvips error.
+pub fn validate_geometry(options: &ParsedOptions, src_width: u32, src_height: u32) -> Result<(), ProcessingError> {
+    let mut conflicts = Vec::new();
+    let (mut width, mut height) = (src_width, src_height);
+    let mut cropped = false;
+
+    if let Some(ref crop) = options.crop {
+        let right = crop.x as u64 + crop.width as u64;
+        let bottom = crop.y as u64 + crop.height as u64;
+        if crop.width == 0 || crop.height == 0 {
+            conflicts.push(format!("crop {}x{} has an empty area", crop.width, crop.height));
+        } else if right > src_width as u64 || bottom > src_height as u64 {
+            conflicts.push(format!(
+                "crop {}x{} at ({}, {}) extends past the {}x{} source",
+                crop.width, crop.height, crop.x, crop.y, src_width, src_height
+            ));
+        } else {
+            (width, height) = (crop.width, crop.height);
+            cropped = true;
+        }
+    }
+
+    // Parsing rejects empty regions and regions combined with crop
+    if let Some(ref region) = options.region {
+        let right = region.x as u64 + region.width as u64;
+        let bottom = region.y as u64 + region.height as u64;
+        if right > src_width as u64 || bottom > src_height as u64 {
+            conflicts.push(format!(
+                "region {}x{} at ({}, {}) lies outside the {}x{} source",
+                region.width, region.height, region.x, region.y, src_width, src_height
+            ));
+        } else {
+            (width, height) = (region.width, region.height);
+            cropped = true;
+        }
+    }
+
+    if let Some(ref resize) = options.resize {
+        if let Err(e) = resolve_resize_dimensions(resize, width, height) {
+            let input = if cropped { "cropped image" } else { "source" };
+            conflicts.push(format!(
+                "resize {}:{}:{} of the {}x{} {}: {}",
+                resize.resizing_type, resize.width, resize.height, width, height, input, e
+            ));
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(ProcessingError::Geometry(format!(
+            "Invalid geometry: {}",
+            conflicts.join("; ")
+        )))
+    }
 }
 
 /// Resolves target resize dimensions, filling in zero values according to imgproxy rules.
-pub fn resolve_resize_dimensions(resize: &Resize, src_width: u32, src_height: u32) -> Result<(u32, u32), String> {
+pub fn resolve_resize_dimensions(
+    resize: &Resize,
+    src_width: u32,
+    src_height: u32,
+) -> Result<(u32, u32), ProcessingError> {
     let mut width = resize.width;
     let mut height = resize.height;
 
     if width == 0 && height == 0 {
-        return Err("resize requires at least one non-zero dimension".to_string());
+        return Err(ProcessingError::Geometry(
+            "resize requires at least one non-zero dimension".to_string(),
+        ));
     }
 
     let aspect = src_width as f64 / src_height as f64;
@@ -130,7 +295,9 @@ pub fn resolve_resize_dimensions(resize: &Resize, src_width: u32, src_height: u3
     }
 
     if width == 0 || height == 0 {
-        return Err("resize resolved to zero dimension".to_string());
+        return Err(ProcessingError::Geometry(
+            "resize resolved to zero dimension".to_string(),
+        ));
     }
 
     Ok((width, height))
@@ -142,21 +309,47 @@ pub fn apply_resize(
     resize: &Resize,
     gravity: &Option<String>,
     resizing_algorithm: &Option<String>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
+    apply_resize_with_focus(img, resize, gravity, None, resizing_algorithm)
+}
+
+/// Applies resize operation based on the resize type, centring `fill` crops on `focus_point`
+/// when one is given instead of following `gravity`.
+pub fn apply_resize_with_focus(
+    img: VipsImage,
+    resize: &Resize,
+    gravity: &Option<String>,
+    focus_point: Option<(f64, f64)>,
+    resizing_algorithm: &Option<String>,
+) -> Result<VipsImage, ProcessingError> {
     let src_width = img.get_width() as u32;
     let src_height = img.get_height() as u32;
     let (target_w, target_h) = resolve_resize_dimensions(resize, src_width, src_height)?;
 
+    // `cover` and `contain` mirror the CSS `object-fit` names for `fill` and `fit`.
     match resize.resizing_type.as_str() {
-        "fill" => resize_to_fill(
+        "fill" | "cover" => resize_to_fill(
             img,
             target_w,
             target_h,
             gravity.as_deref().unwrap_or("center"),
+            focus_point,
             resizing_algorithm,
         ),
-        "fit" => resize_to_fit(img, target_w, target_h, resizing_algorithm),
+        "fit" | "contain" => resize_to_fit(img, target_w, target_h, resizing_algorithm),
         "force" => resize_to_force(img, target_w, target_h, resizing_algorithm),
+        // Like `fit`, but never upscales, even with `enlarge`.
+        "shrink" | "fit-down" => {
+            if src_width <= target_w && src_height <= target_h {
+                debug!(
+                    "Shrink resize: source already fits {}x{}, leaving it untouched",
+                    target_w, target_h
+                );
+                Ok(img)
+            } else {
+                resize_to_fit(img, target_w, target_h, resizing_algorithm)
+            }
+        }
         "auto" => {
             let src_is_portrait = super::utils::is_portrait(src_width, src_height);
             let target_is_portrait = super::utils::is_portrait(target_w, target_h);
@@ -168,6 +361,7 @@ pub fn apply_resize(
                     target_w,
                     target_h,
                     gravity.as_deref().unwrap_or("center"),
+                    focus_point,
                     resizing_algorithm,
                 )
             } else {
@@ -175,18 +369,26 @@ pub fn apply_resize(
                 resize_to_fit(img, target_w, target_h, resizing_algorithm)
             }
         }
-        _ => Err(format!("Unknown resize type: {}", resize.resizing_type)),
+        _ => Err(ProcessingError::Unsupported(format!(
+            "Unknown resize type: {}",
+            resize.resizing_type
+        ))),
     }
 }
 
 /// Resizes an image to fill the target dimensions, cropping if necessary.
+///
+/// A focus point keeps the crop window centred on that fraction of the image, pulled back inside
+/// the resized bounds near the edges. The `sm` gravity lets libvips place the window on the most
+/// salient region instead, falling back to a centred crop if that fails.
 fn resize_to_fill(
     img: VipsImage,
     width: u32,
     height: u32,
     gravity: &str,
+    focus_point: Option<(f64, f64)>,
     resizing_algorithm: &Option<String>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let (img_w, img_h) = (img.get_width() as u32, img.get_height() as u32);
     let aspect_ratio = img_w as f32 / img_h as f32;
     let target_aspect_ratio = width as f32 / height as f32;
@@ -198,6 +400,8 @@ fn resize_to_fill(
     };
     // Bump the scale slightly so kernels that round down still cover the target.
     scale *= 1.0 + SCALE_EPSILON;
+    debug!("Resizing to fill with scale {}", scale);
+    observe_resize_scale(scale);
 
     let resized_img = resize_with_algorithm(&img, scale, None, resizing_algorithm, "Error resizing for fill")?;
 
@@ -205,29 +409,53 @@ fn resize_to_fill(
     let resized_h = resized_img.get_height() as u32;
 
     if resized_w < width || resized_h < height {
-        return Err(format!(
+        return Err(ProcessingError::Vips(format!(
             "Resized image {}x{} is smaller than fill target {}x{}",
             resized_w, resized_h, width, height
-        ));
+        )));
+    }
+
+    if gravity == SMART_GRAVITY && focus_point.is_none() {
+        let options = ops::SmartcropOptions {
+            interesting: ops::Interesting::Attention,
+            ..Default::default()
+        };
+        match ops::smartcrop_with_opts(&resized_img, width as i32, height as i32, &options) {
+            Ok(cropped) => return Ok(cropped),
+            Err(e) => warn!("Smart crop failed, falling back to a centred crop: {}", e),
+        }
     }
 
     let extra_w = resized_w - width;
     let extra_h = resized_h - height;
 
-    let crop_x = match gravity {
-        "west" => 0,
-        "east" => extra_w,
-        _ => extra_w / 2,
-    };
-
-    let crop_y = match gravity {
-        "north" => 0,
-        "south" => extra_h,
-        _ => extra_h / 2,
+    let (crop_x, crop_y) = match focus_point {
+        Some((focus_x, focus_y)) => {
+            let offset = |focus: f64, resized: u32, target: u32, extra: u32| {
+                ((focus * resized as f64 - target as f64 / 2.0).round().max(0.0) as u32).min(extra)
+            };
+            (
+                offset(focus_x, resized_w, width, extra_w),
+                offset(focus_y, resized_h, height, extra_h),
+            )
+        }
+        None => {
+            let crop_x = match gravity {
+                "west" => 0,
+                "east" => extra_w,
+                _ => extra_w / 2,
+            };
+            let crop_y = match gravity {
+                "north" => 0,
+                "south" => extra_h,
+                _ => extra_h / 2,
+            };
+            (crop_x, crop_y)
+        }
     };
 
     ops::extract_area(&resized_img, crop_x as i32, crop_y as i32, width as i32, height as i32)
-        .map_err(|e| format!("Error cropping after fill resize: {}", e))
+        .map_err(|e| ProcessingError::Vips(format!("Error cropping after fill resize: {}", e)))
 }
 
 /// Resizes an image to the exact target dimensions, allowing aspect ratio changes.
@@ -236,7 +464,7 @@ fn resize_to_force(
     width: u32,
     height: u32,
     resizing_algorithm: &Option<String>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let (src_w, src_h) = (img.get_width() as f64, img.get_height() as f64);
     let scale_x = width as f64 / src_w;
     let scale_y = height as f64 / src_h;
@@ -244,6 +472,7 @@ fn resize_to_force(
     if (scale_x - 1.0).abs() < SCALE_EPSILON && (scale_y - 1.0).abs() < SCALE_EPSILON {
         return Ok(img);
     }
+    observe_resize_scale(scale_x.min(scale_y));
     resize_with_algorithm(&img, scale_x, Some(scale_y), resizing_algorithm, "Error force resizing")
 }
 
@@ -253,7 +482,7 @@ fn resize_to_fit(
     width: u32,
     height: u32,
     resizing_algorithm: &Option<String>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let (img_w, img_h) = (img.get_width() as u32, img.get_height() as u32);
     let aspect_ratio = img_w as f32 / img_h as f32;
 
@@ -269,6 +498,7 @@ fn resize_to_fit(
     let scale_w = target_w as f64 / img_w as f64;
     let scale_h = target_h as f64 / img_h as f64;
     let scale = scale_w.min(scale_h);
+    observe_resize_scale(scale);
 
     resize_with_algorithm(&img, scale, None, resizing_algorithm, "Error fitting resize")
 }
@@ -280,15 +510,15 @@ pub fn extend_image(
     height: u32,
     gravity: &Option<String>,
     background: &Option<[u8; 4]>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let bg_color = background.unwrap_or([0, 0, 0, 0]);
     let src_w = img.get_width() as u32;
     let src_h = img.get_height() as u32;
     if width < src_w || height < src_h {
-        return Err(format!(
+        return Err(ProcessingError::Geometry(format!(
             "extend target {}x{} must be at least source {}x{}",
             width, height, src_w, src_h
-        ));
+        )));
     }
 
     let gravity = gravity.as_deref().unwrap_or("center");
@@ -305,10 +535,26 @@ pub fn extend_image(
     let options = ops::EmbedOptions {
         extend: ops::Extend::Background,
         background: bg_color_for_bands(bg_color, img.get_bands()),
-        ..Default::default()
     };
     ops::embed_with_opts(&img, x as i32, y as i32, width as i32, height as i32, &options)
-        .map_err(|e| format!("Error extending image: {}", e))
+        .map_err(|e| ProcessingError::Vips(format!("Error extending image: {}", e)))
+}
+
+/// Trims a trailing row and/or column so both dimensions are even.
+///
+/// Dimensions of 1 pixel are left untouched since they cannot be snapped down.
+pub fn snap_to_even_dimensions(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    let width = img.get_width();
+    let height = img.get_height();
+    let even_width = if width > 1 { width - width % 2 } else { width };
+    let even_height = if height > 1 { height - height % 2 } else { height };
+
+    if even_width == width && even_height == height {
+        return Ok(img);
+    }
+
+    ops::extract_area(&img, 0, 0, even_width, even_height)
+        .map_err(|e| ProcessingError::Vips(format!("Error snapping to even dimensions: {}", e)))
 }
 
 /// Applies padding to an image.
@@ -319,12 +565,11 @@ pub fn apply_padding(
     bottom: u32,
     left: u32,
     background: &Option<[u8; 4]>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let bg_color = background.unwrap_or([0, 0, 0, 0]);
     let options = ops::EmbedOptions {
         extend: ops::Extend::Background,
         background: bg_color_for_bands(bg_color, img.get_bands()),
-        ..Default::default()
     };
 
     ops::embed_with_opts(
@@ -335,41 +580,348 @@ pub fn apply_padding(
         img.get_height() + top as i32 + bottom as i32,
         &options,
     )
-    .map_err(|e| format!("Error applying padding: {}", e))
+    .map_err(|e| ProcessingError::Vips(format!("Error applying padding: {}", e)))
+}
+
+/// Draws a solid border of `border.width` pixels around an image, growing each axis by twice
+/// the width.
+pub fn apply_border(img: VipsImage, border: &Border) -> Result<VipsImage, ProcessingError> {
+    let overflow = || ProcessingError::Vips(format!("Border width {} is too large for the image", border.width));
+    let width = i32::try_from(border.width).map_err(|_| overflow())?;
+    let grow = |side: i32| {
+        width
+            .checked_mul(2)
+            .and_then(|total| side.checked_add(total))
+            .ok_or_else(overflow)
+    };
+    let (canvas_width, canvas_height) = (grow(img.get_width())?, grow(img.get_height())?);
+    let options = ops::EmbedOptions {
+        extend: ops::Extend::Background,
+        background: bg_color_for_bands(border.color, img.get_bands()),
+    };
+
+    ops::embed_with_opts(&img, width, width, canvas_width, canvas_height, &options)
+        .map_err(|e| ProcessingError::Vips(format!("Error applying border: {}", e)))
 }
 
 /// Applies rotation to an image.
-pub fn apply_rotation(img: VipsImage, rotation: u16) -> Result<VipsImage, String> {
+pub fn apply_rotation(img: VipsImage, rotation: u16) -> Result<VipsImage, ProcessingError> {
     match rotation {
-        90 => ops::rot(&img, ops::Angle::D90).map_err(|e| format!("Error rotating 90: {}", e)),
-        180 => ops::rot(&img, ops::Angle::D180).map_err(|e| format!("Error rotating 180: {}", e)),
-        270 => ops::rot(&img, ops::Angle::D270).map_err(|e| format!("Error rotating 270: {}", e)),
+        90 => ops::rot(&img, ops::Angle::D90).map_err(|e| ProcessingError::Vips(format!("Error rotating 90: {}", e))),
+        180 => {
+            ops::rot(&img, ops::Angle::D180).map_err(|e| ProcessingError::Vips(format!("Error rotating 180: {}", e)))
+        }
+        270 => {
+            ops::rot(&img, ops::Angle::D270).map_err(|e| ProcessingError::Vips(format!("Error rotating 270: {}", e)))
+        }
         _ => Ok(img), // No rotation
     }
 }
 
-/// Applies blur to an image.
-pub fn apply_blur(img: VipsImage, sigma: f32) -> Result<VipsImage, String> {
-    ops::gaussblur(&img, sigma as f64).map_err(|e| format!("Error applying blur: {}", e))
+/// Mirrors an image vertically, swapping its top and bottom rows.
+pub fn apply_flip(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    ops::flip(&img, ops::Direction::Vertical).map_err(|e| ProcessingError::Vips(format!("Error flipping image: {}", e)))
 }
 
-/// Applies background color to an image (useful for JPEG output).
-pub fn apply_background_color(img: VipsImage, _bg_color: [u8; 4]) -> Result<VipsImage, String> {
-    // Only flatten if the image has an alpha channel (bands == 4 for RGBA or bands == 2 for grayscale+alpha)
+/// Mirrors an image horizontally, swapping its left and right columns.
+pub fn apply_flop(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    ops::flip(&img, ops::Direction::Horizontal)
+        .map_err(|e| ProcessingError::Vips(format!("Error flopping image: {}", e)))
+}
+
+/// Applies blur to an image, clamping `sigma` to `max_sigma` when one is given.
+///
+/// Images with alpha are blurred premultiplied, so colour hidden under transparent pixels does
+/// not bleed into visible edges.
+pub fn apply_blur(img: VipsImage, sigma: f32, max_sigma: Option<f32>) -> Result<VipsImage, ProcessingError> {
+    // The blur mask grows with the sigma, so huge values cost far more CPU than they change pixels
+    let sigma = match max_sigma {
+        Some(max_sigma) if sigma > max_sigma => {
+            warn!("Clamping blur sigma {} to the configured maximum {}", sigma, max_sigma);
+            max_sigma
+        }
+        _ => sigma,
+    };
+    with_premultiplied_alpha(img, |img| ops::gaussblur(img, sigma as f64))
+        .map_err(|e| ProcessingError::Vips(format!("Error applying blur: {}", e)))
+}
+
+/// Runs a neighbourhood filter on `img`, premultiplying by alpha first when there is an alpha
+/// band and restoring the original band format afterwards.
+fn with_premultiplied_alpha(
+    img: VipsImage,
+    filter: impl FnOnce(&VipsImage) -> libvips::Result<VipsImage>,
+) -> libvips::Result<VipsImage> {
+    if !img.image_hasalpha() {
+        return filter(&img);
+    }
+    let format = img.get_format()?;
+    let max_alpha = if matches!(format, ops::BandFormat::Ushort) {
+        65535.0
+    } else {
+        255.0
+    };
+    let premultiplied = ops::premultiply_with_opts(&img, &ops::PremultiplyOptions { max_alpha })?;
+    let filtered = filter(&premultiplied)?;
+    let options = ops::UnpremultiplyOptions {
+        max_alpha,
+        ..Default::default()
+    };
+    let unpremultiplied = ops::unpremultiply_with_opts(&filtered, &options)?;
+    ops::cast(&unpremultiplied, format)
+}
+
+/// Scales colourfulness by `factor` by multiplying chroma in LCh space.
+///
+/// Alpha is left untouched, and grayscale images, which have no chroma, are returned as-is.
+pub fn apply_saturation(img: VipsImage, factor: f32) -> Result<VipsImage, ProcessingError> {
     let bands = img.get_bands();
-    if bands != 4 && bands != 2 {
+    if bands - i32::from(img.image_hasalpha()) < 3 {
+        return Ok(img);
+    }
+    let format = img
+        .get_format()
+        .map_err(|e| ProcessingError::Vips(format!("Error reading image format: {}", e)))?;
+    let lch = ops::colourspace(&img, ops::Interpretation::Lch)
+        .map_err(|e| ProcessingError::Vips(format!("Error converting to LCh: {}", e)))?;
+
+    let mut multipliers = vec![1.0; bands as usize];
+    multipliers[1] = factor as f64;
+    let saturated = ops::linear(&lch, &mut multipliers, &mut vec![0.0; bands as usize])
+        .map_err(|e| ProcessingError::Vips(format!("Error applying saturation: {}", e)))?;
+
+    let rgb = if matches!(format, ops::BandFormat::Ushort) {
+        ops::Interpretation::Rgb16
+    } else {
+        ops::Interpretation::Srgb
+    };
+    let converted = ops::colourspace(&saturated, rgb)
+        .map_err(|e| ProcessingError::Vips(format!("Error converting from LCh: {}", e)))?;
+    ops::cast(&converted, format).map_err(|e| ProcessingError::Vips(format!("Error applying saturation: {}", e)))
+}
+
+/// Scales pixel values away from (or towards) the midtone by `factor`.
+///
+/// Alpha is left untouched, and results are clamped to the range of the image's band format.
+pub fn apply_contrast(img: VipsImage, factor: f32) -> Result<VipsImage, ProcessingError> {
+    let bands = img.get_bands() as usize;
+    let color_bands = bands - usize::from(img.image_hasalpha());
+    let format = img
+        .get_format()
+        .map_err(|e| ProcessingError::Vips(format!("Error reading image format: {}", e)))?;
+    let midpoint = if matches!(format, ops::BandFormat::Ushort) {
+        32768.0
+    } else {
+        128.0
+    };
+
+    let factor = factor as f64;
+    let mut multipliers = vec![1.0; bands];
+    let mut offsets = vec![0.0; bands];
+    multipliers[..color_bands].fill(factor);
+    offsets[..color_bands].fill(midpoint * (1.0 - factor));
+    let adjusted = ops::linear(&img, &mut multipliers, &mut offsets)
+        .map_err(|e| ProcessingError::Vips(format!("Error applying contrast: {}", e)))?;
+    ops::cast(&adjusted, format).map_err(|e| ProcessingError::Vips(format!("Error applying contrast: {}", e)))
+}
+
+/// Raises normalised pixel values to the power `1 / gamma`, brightening midtones for values
+/// above `1.0` and darkening them below it. Black and white points stay fixed.
+///
+/// Alpha is split off first so transparency is not curved along with the colour bands.
+pub fn apply_gamma(img: VipsImage, gamma: f32) -> Result<VipsImage, ProcessingError> {
+    let options = ops::GammaOptions { exponent: gamma as f64 };
+    if !img.image_hasalpha() {
+        return ops::gamma_with_opts(&img, &options)
+            .map_err(|e| ProcessingError::Vips(format!("Error applying gamma: {}", e)));
+    }
+
+    let color_bands = img.get_bands() - 1;
+    let color = ops::extract_band_with_opts(&img, 0, &ops::ExtractBandOptions { n: color_bands })
+        .map_err(|e| ProcessingError::Vips(format!("Error extracting color bands: {}", e)))?;
+    let alpha = ops::extract_band(&img, color_bands)
+        .map_err(|e| ProcessingError::Vips(format!("Error extracting alpha band: {}", e)))?;
+    let corrected = ops::gamma_with_opts(&color, &options)
+        .map_err(|e| ProcessingError::Vips(format!("Error applying gamma: {}", e)))?;
+    ops::bandjoin(&mut [corrected, alpha]).map_err(|e| ProcessingError::Vips(format!("Error applying gamma: {}", e)))
+}
+
+/// Converts an image to grayscale, keeping any alpha channel.
+///
+/// Opaque images end up with a single band, images with alpha with two.
+pub fn apply_grayscale(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    ops::colourspace(&img, ops::Interpretation::BW)
+        .map_err(|e| ProcessingError::Vips(format!("Error converting to grayscale: {}", e)))
+}
+
+/// Applies background color to an image (useful for JPEG output).
+pub fn apply_background_color(img: VipsImage, bg_color: [u8; 4]) -> Result<VipsImage, ProcessingError> {
+    // Band counts alone are ambiguous (CMYK has 4 bands without alpha), so ask libvips
+    if !img.image_hasalpha() {
         // No alpha channel, nothing to flatten - return as-is
         return Ok(img);
     }
 
     // Use libvips flatten to composite over a solid background, dropping alpha.
-    // Only RGB is used; input alpha is ignored for the background color itself.
-    let bg = vec![_bg_color[0] as f64, _bg_color[1] as f64, _bg_color[2] as f64];
+    // Only the colour bands are used; input alpha is ignored for the background color itself.
+    let bg = bg_color_for_bands(bg_color, img.get_bands() - 1);
     let opts = ops::FlattenOptions {
         background: bg,
         ..Default::default()
     };
-    ops::flatten_with_opts(&img, &opts).map_err(|e| format!("Error applying background color: {}", e))
+    ops::flatten_with_opts(&img, &opts)
+        .map_err(|e| ProcessingError::Vips(format!("Error applying background color: {}", e)))
+}
+
+/// Converts an image to sRGB and tags it with the sRGB profile.
+///
+/// The embedded ICC profile is used as the source colour space; images without one are assumed
+/// to be sRGB already, or generic CMYK for CMYK sources. Images the profile cannot be applied to,
+/// such as greyscale sources, go through a plain colourspace conversion instead.
+pub fn convert_to_srgb(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    let depth = if matches!(img.get_format(), Ok(ops::BandFormat::Ushort)) {
+        16
+    } else {
+        8
+    };
+    let input_profile = if is_cmyk(&img) { CMYK_PROFILE } else { SRGB_PROFILE };
+    let opts = ops::IccTransformOptions {
+        embedded: true,
+        input_profile: input_profile.to_string(),
+        depth,
+        ..Default::default()
+    };
+    match ops::icc_transform_with_opts(&img, SRGB_PROFILE, &opts) {
+        Ok(converted) => Ok(converted),
+        Err(e) => {
+            debug!("ICC transform to sRGB failed ({}), converting colourspace instead", e);
+            ops::colourspace(&img, ops::Interpretation::Srgb)
+                .map_err(|e| ProcessingError::Vips(format!("Error converting image to sRGB: {}", e)))
+        }
+    }
+}
+
+/// Returns whether the image holds CMYK pixels, whose four bands carry no alpha.
+pub fn is_cmyk(img: &VipsImage) -> bool {
+    matches!(img.get_interpretation(), Ok(ops::Interpretation::Cmyk))
+}
+
+/// Flattens transparent areas onto a gray and white checkerboard, the way image editors preview
+/// transparency. Images without an alpha channel are returned unchanged.
+pub fn apply_checkerboard_background(img: VipsImage) -> Result<VipsImage, ProcessingError> {
+    let bands = img.get_bands();
+    if bands != 4 && bands != 2 {
+        return Ok(img);
+    }
+
+    let img = if bands == 2 {
+        ops::colourspace(&img, ops::Interpretation::Srgb)
+            .map_err(|e| ProcessingError::Vips(format!("Error converting image for checkerboard: {}", e)))?
+    } else {
+        img
+    };
+    let pattern = checkerboard_pattern(img.get_width(), img.get_height())?;
+    // The pattern is opaque, so the composite is fully opaque and flattening only drops alpha.
+    let composed = ops::composite_2(&pattern, &img, ops::BlendMode::Over)
+        .map_err(|e| ProcessingError::Vips(format!("Error compositing onto checkerboard: {}", e)))?;
+    ops::flatten(&composed).map_err(|e| ProcessingError::Vips(format!("Error applying checkerboard background: {}", e)))
+}
+
+/// Builds an sRGB checkerboard of `width` x `height` pixels, light square first.
+fn checkerboard_pattern(width: i32, height: i32) -> Result<VipsImage, ProcessingError> {
+    let cells = [
+        CHECKERBOARD_LIGHT,
+        CHECKERBOARD_DARK,
+        CHECKERBOARD_DARK,
+        CHECKERBOARD_LIGHT,
+    ];
+    let tile = VipsImage::new_from_memory(&cells, 2, 2, 1, ops::BandFormat::Uchar)
+        .and_then(VipsImage::image_copy_memory)
+        .map_err(|e| ProcessingError::Vips(format!("Error creating checkerboard tile: {}", e)))?;
+    let tile = ops::zoom(&tile, CHECKERBOARD_CELL_SIZE, CHECKERBOARD_CELL_SIZE)
+        .map_err(|e| ProcessingError::Vips(format!("Error scaling checkerboard tile: {}", e)))?;
+
+    let tile_size = 2 * CHECKERBOARD_CELL_SIZE;
+    let across = (width + tile_size - 1) / tile_size;
+    let down = (height + tile_size - 1) / tile_size;
+    let tiled = ops::replicate(&tile, across, down)
+        .map_err(|e| ProcessingError::Vips(format!("Error tiling checkerboard: {}", e)))?;
+    let pattern = ops::extract_area(&tiled, 0, 0, width, height)
+        .map_err(|e| ProcessingError::Vips(format!("Error cropping checkerboard: {}", e)))?;
+    ops::colourspace(&pattern, ops::Interpretation::Srgb)
+        .map_err(|e| ProcessingError::Vips(format!("Error converting checkerboard: {}", e)))
+}
+
+/// Cuts rounded corners of `radius` pixels out of an image by scaling its alpha with a mask.
+///
+/// An opaque alpha band is added first when the image has none. The radius is capped at half the
+/// shorter side, and corner edges are anti-aliased.
+pub fn apply_border_radius(img: VipsImage, radius: u32) -> Result<VipsImage, ProcessingError> {
+    let (width, height) = (img.get_width(), img.get_height());
+    let radius = radius.min(width.min(height) as u32 / 2);
+    if radius == 0 {
+        return Ok(img);
+    }
+    let format = img
+        .get_format()
+        .map_err(|e| ProcessingError::Vips(format!("Error reading image format: {}", e)))?;
+    let img = if img.image_hasalpha() {
+        img
+    } else {
+        let opaque = if matches!(format, ops::BandFormat::Ushort) {
+            65535.0
+        } else {
+            255.0
+        };
+        ops::bandjoin_const(&img, &mut [opaque])
+            .map_err(|e| ProcessingError::Vips(format!("Error adding alpha band: {}", e)))?
+    };
+
+    let bands = img.get_bands();
+    let color = ops::extract_band_with_opts(&img, 0, &ops::ExtractBandOptions { n: bands - 1 })
+        .map_err(|e| ProcessingError::Vips(format!("Error extracting color bands: {}", e)))?;
+    let alpha = ops::extract_band(&img, bands - 1)
+        .map_err(|e| ProcessingError::Vips(format!("Error extracting alpha band: {}", e)))?;
+    let mask = rounded_corner_mask(width, height, radius)?;
+    let masked = ops::multiply(&alpha, &mask)
+        .and_then(|product| ops::linear(&product, &mut [1.0 / 255.0], &mut [0.0]))
+        .and_then(|scaled| ops::cast(&scaled, format))
+        .map_err(|e| ProcessingError::Vips(format!("Error masking corners: {}", e)))?;
+    ops::bandjoin(&mut [color, masked]).map_err(|e| ProcessingError::Vips(format!("Error masking corners: {}", e)))
+}
+
+/// Builds a one-band mask that is 255 inside a rounded rectangle and 0 outside it.
+///
+/// Only the `radius`-sized corner tiles are rendered in memory; the opaque rest of the mask is a
+/// constant libvips image, so the cost does not grow with the image size.
+fn rounded_corner_mask(width: i32, height: i32, radius: u32) -> Result<VipsImage, ProcessingError> {
+    let r = radius as usize;
+    let mut pixels = vec![0u8; r * r];
+    let center = radius as f64;
+    for dy in 0..r {
+        for dx in 0..r {
+            // Coverage of the pixel by the corner circle, approximated from its centre's distance
+            let distance = ((center - dx as f64 - 0.5).powi(2) + (center - dy as f64 - 0.5).powi(2)).sqrt();
+            pixels[dy * r + dx] = ((center - distance + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+
+    let radius = radius as i32;
+    let top_left = VipsImage::new_from_memory(&pixels, radius, radius, 1, ops::BandFormat::Uchar)
+        .and_then(VipsImage::image_copy_memory)
+        .map_err(|e| ProcessingError::Vips(format!("Error creating corner mask: {}", e)))?;
+    let top_right = ops::flip(&top_left, ops::Direction::Horizontal);
+    let bottom_left = ops::flip(&top_left, ops::Direction::Vertical);
+    let bottom_right = ops::rot(&top_left, ops::Angle::D180);
+    let (right, bottom) = (width - radius, height - radius);
+    ops::black(width, height)
+        .and_then(|black| ops::linear(&black, &mut [1.0], &mut [255.0]))
+        .and_then(|opaque| ops::cast(&opaque, ops::BandFormat::Uchar))
+        .and_then(|mask| ops::insert(&mask, &top_left, 0, 0))
+        .and_then(|mask| ops::insert(&mask, &top_right?, right, 0))
+        .and_then(|mask| ops::insert(&mask, &bottom_left?, 0, bottom))
+        .and_then(|mask| ops::insert(&mask, &bottom_right?, right, bottom))
+        .map_err(|e| ProcessingError::Vips(format!("Error creating corner mask: {}", e)))
 }
 
 /// Applies min-width and min-height constraints to an image.
@@ -378,7 +930,7 @@ pub fn apply_min_dimensions(
     min_width: Option<u32>,
     min_height: Option<u32>,
     resizing_algorithm: &Option<String>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let mut current_img = img;
     let (img_w, img_h) = (current_img.get_width() as u32, current_img.get_height() as u32);
 
@@ -411,22 +963,32 @@ pub fn apply_min_dimensions(
 }
 
 /// Applies zoom to an image.
-pub fn apply_zoom(img: VipsImage, zoom: f32, resizing_algorithm: &Option<String>) -> Result<VipsImage, String> {
+pub fn apply_zoom(
+    img: VipsImage,
+    zoom: f32,
+    resizing_algorithm: &Option<String>,
+) -> Result<VipsImage, ProcessingError> {
     resize_with_algorithm(&img, zoom as f64, None, resizing_algorithm, "Error applying zoom")
 }
 
-/// Sharpens an image.
-pub fn apply_sharpen(img: VipsImage, sigma: f32) -> Result<VipsImage, String> {
+/// Sharpens an image, premultiplied by alpha like [`apply_blur`] so edges next to transparency
+/// do not pick up hidden colour.
+pub fn apply_sharpen(img: VipsImage, sigma: f32) -> Result<VipsImage, ProcessingError> {
     let clamped_sigma = sigma.clamp(0.1, 10.0);
     let opts = ops::SharpenOptions {
         sigma: clamped_sigma as f64,
         ..Default::default()
     };
-    ops::sharpen_with_opts(&img, &opts).map_err(|e| format!("Error applying sharpen: {}", e))
+    with_premultiplied_alpha(img, |img| ops::sharpen_with_opts(img, &opts))
+        .map_err(|e| ProcessingError::Vips(format!("Error applying sharpen: {}", e)))
 }
 
 /// Pixelates an image.
-pub fn apply_pixelate(img: VipsImage, amount: u32, resizing_algorithm: &Option<String>) -> Result<VipsImage, String> {
+pub fn apply_pixelate(
+    img: VipsImage,
+    amount: u32,
+    resizing_algorithm: &Option<String>,
+) -> Result<VipsImage, ProcessingError> {
     if amount == 0 {
         return Ok(img);
     }
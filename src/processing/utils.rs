@@ -18,6 +18,57 @@ pub fn parse_hex_color(hex: &str) -> Result<[u8; 4], String> {
     Ok([r, g, b, 255])
 }
 
+/// CSS color names accepted wherever a color is expected, with their RGBA values.
+const NAMED_COLORS: &[(&str, [u8; 4])] = &[
+    ("transparent", [0, 0, 0, 0]),
+    ("black", [0, 0, 0, 255]),
+    ("white", [255, 255, 255, 255]),
+    ("red", [255, 0, 0, 255]),
+    ("green", [0, 128, 0, 255]),
+    ("lime", [0, 255, 0, 255]),
+    ("blue", [0, 0, 255, 255]),
+    ("navy", [0, 0, 128, 255]),
+    ("yellow", [255, 255, 0, 255]),
+    ("orange", [255, 165, 0, 255]),
+    ("purple", [128, 0, 128, 255]),
+    ("cyan", [0, 255, 255, 255]),
+    ("aqua", [0, 255, 255, 255]),
+    ("magenta", [255, 0, 255, 255]),
+    ("fuchsia", [255, 0, 255, 255]),
+    ("teal", [0, 128, 128, 255]),
+    ("maroon", [128, 0, 0, 255]),
+    ("olive", [128, 128, 0, 255]),
+    ("silver", [192, 192, 192, 255]),
+    ("gray", [128, 128, 128, 255]),
+    ("grey", [128, 128, 128, 255]),
+    ("pink", [255, 192, 203, 255]),
+    ("brown", [165, 42, 42, 255]),
+    ("gold", [255, 215, 0, 255]),
+];
+
+/// Parses a color given either as a CSS color name or as a hexadecimal string.
+///
+/// # Arguments
+///
+/// * `value` - A color name (e.g., "white", "transparent"; case-insensitive) or a hex string accepted by `parse_hex_color`.
+///
+/// # Returns
+///
+/// A `Result` containing the RGBA array on success, or an error message as a `String`.
+pub fn parse_color(value: &str) -> Result<[u8; 4], String> {
+    let lower = value.to_ascii_lowercase();
+    if let Some((_, rgba)) = NAMED_COLORS.iter().find(|(name, _)| *name == lower) {
+        return Ok(*rgba);
+    }
+
+    let hex = value.trim_start_matches('#');
+    if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return parse_hex_color(value);
+    }
+
+    Err(format!("Unknown color: {}", value))
+}
+
 /// Parses a string into a boolean value.
 ///
 /// # Arguments
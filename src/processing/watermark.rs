@@ -1,8 +1,21 @@
-use crate::processing::options::Watermark;
+use crate::processing::error::ProcessingError;
+use crate::processing::options::{LabelOptions, Watermark};
 use crate::processing::transform::resize_with_algorithm;
 use bytes::Bytes;
 use libvips::{ops, VipsImage};
 
+/// Fraction of the image width a watermark is scaled to when no scale is requested.
+const DEFAULT_WATERMARK_SCALE: f32 = 0.25;
+/// Watermark position that repeats the watermark across the whole image.
+const TILE_POSITION: &str = "tile";
+/// Height of a copyright line as a fraction of the image height.
+const COPYRIGHT_HEIGHT_RATIO: f64 = 0.03;
+/// Widest a copyright line may get, as a fraction of the image width.
+const COPYRIGHT_MAX_SCALE: f64 = 0.9;
+const COPYRIGHT_OPACITY: f32 = 0.8;
+/// Print resolution recorded in outputs carrying a copyright line.
+pub const COPYRIGHT_DPI: f64 = 300.0;
+
 #[derive(Clone)]
 pub struct PreparedWatermark {
     bytes: Bytes,
@@ -13,9 +26,9 @@ pub struct PreparedWatermark {
 }
 
 impl PreparedWatermark {
-    fn to_image(&self) -> Result<VipsImage, String> {
+    fn to_image(&self) -> Result<VipsImage, ProcessingError> {
         VipsImage::new_from_memory(&self.bytes, self.width, self.height, self.bands, self.format)
-            .map_err(|e| format!("Failed to load watermark from prepared bytes: {}", e))
+            .map_err(|e| ProcessingError::Decode(format!("Failed to load watermark from prepared bytes: {}", e)))
     }
 }
 
@@ -41,13 +54,13 @@ impl CachedWatermark {
     }
 }
 
-pub fn load_watermark_image(watermark_bytes: &[u8]) -> Result<VipsImage, String> {
+pub fn load_watermark_image(watermark_bytes: &[u8]) -> Result<VipsImage, ProcessingError> {
     let watermark_img = VipsImage::new_from_buffer(watermark_bytes, "")
-        .map_err(|e| format!("Failed to load watermark image from buffer: {}", e))?;
+        .map_err(|e| ProcessingError::Decode(format!("Failed to load watermark image from buffer: {}", e)))?;
     ensure_alpha_channel(watermark_img)
 }
 
-pub fn prepare_cached_watermark(bytes: Bytes) -> Result<CachedWatermark, String> {
+pub fn prepare_cached_watermark(bytes: Bytes) -> Result<CachedWatermark, ProcessingError> {
     let watermark_img = load_watermark_image(bytes.as_ref())?;
     let prepared_rgba = build_prepared_watermark_image(watermark_img)?;
     Ok(CachedWatermark::from_prepared(bytes, prepared_rgba))
@@ -59,11 +72,12 @@ pub fn apply_watermark(
     watermark: &CachedWatermark,
     watermark_opts: &Watermark,
     resizing_algorithm: &Option<String>,
-) -> Result<VipsImage, String> {
+) -> Result<VipsImage, ProcessingError> {
     let watermark_img = resolve_watermark_image(watermark)?;
 
-    // Resize watermark to be 1/4 of the main image's width, maintaining aspect ratio
-    let factor = (img.get_width() as f64 / 4.0) / watermark_img.get_width() as f64;
+    // Resize watermark to the requested fraction (default 1/4) of the main image's width, maintaining aspect ratio
+    let scale = watermark_opts.scale.unwrap_or(DEFAULT_WATERMARK_SCALE) as f64;
+    let factor = (img.get_width() as f64 * scale) / watermark_img.get_width() as f64;
     let watermark_resized = resize_with_algorithm(
         &watermark_img,
         factor,
@@ -79,33 +93,129 @@ pub fn apply_watermark(
     let multipliers = &mut [1.0, 1.0, 1.0, watermark_opts.opacity as f64];
     let adders = &mut [0.0, 0.0, 0.0, 0.0];
     let watermark_with_opacity = ops::linear(&watermark_with_alpha, multipliers, adders)
-        .map_err(|e| format!("Failed to apply opacity to watermark: {}", e))?;
+        .map_err(|e| ProcessingError::Vips(format!("Failed to apply opacity to watermark: {}", e)))?;
+
+    // Rotate the watermark, filling the enlarged corners with transparency
+    let watermark_rotated = if watermark_opts.angle.rem_euclid(360.0) != 0.0 {
+        let options = ops::RotateOptions {
+            background: vec![0.0; watermark_with_opacity.get_bands() as usize],
+            ..Default::default()
+        };
+        ops::rotate_with_opts(&watermark_with_opacity, watermark_opts.angle as f64, &options)
+            .map_err(|e| ProcessingError::Vips(format!("Failed to rotate watermark: {}", e)))?
+    } else {
+        watermark_with_opacity
+    };
+
+    let watermark_on_canvas = if watermark_opts.position == TILE_POSITION {
+        tile_watermark(&watermark_rotated, img.get_width(), img.get_height())?
+    } else {
+        // Calculate position
+        let (x, y) = match watermark_opts.offset {
+            Some(offset) => calculate_watermark_offset(&img, &watermark_rotated, offset),
+            None => calculate_watermark_position(&img, &watermark_rotated, &watermark_opts.position),
+        };
 
-    // Calculate position
-    let (x, y) = calculate_watermark_position(&img, &watermark_with_opacity, &watermark_opts.position);
+        embed_on_canvas(&watermark_rotated, x, y, &img)?
+    };
 
     // Composite watermark
-    let bg = &mut [0.0, 0.0, 0.0, 0.0]; // transparent
+    ops::composite_2(&img, &watermark_on_canvas, ops::BlendMode::Over)
+        .map_err(|e| ProcessingError::Vips(format!("Failed to composite watermark: {}", e)))
+}
+
+/// Renders `text` as white glyphs on a transparent background, ready to pass to `apply_watermark`.
+pub fn render_text_watermark(text: &str) -> Result<CachedWatermark, ProcessingError> {
+    let coverage =
+        ops::text(&escape_markup(text)).map_err(|e| ProcessingError::Vips(format!("Failed to render text: {}", e)))?;
+    let white = VipsImage::new_from_image(&coverage, &[255.0, 255.0, 255.0])
+        .map_err(|e| ProcessingError::Vips(format!("Failed to create text colour: {}", e)))?;
+    let rgba = ops::bandjoin(&mut [white, coverage])
+        .map_err(|e| ProcessingError::Vips(format!("Failed to build text image: {}", e)))?;
+
+    let prepared_rgba = build_prepared_watermark_image(rgba)?;
+    Ok(CachedWatermark::from_prepared(Bytes::new(), prepared_rgba))
+}
+
+/// Draws a text label at a fixed font size and colour at one of the named positions.
+///
+/// Unlike a copyright line, the label is not scaled with the image, so the same size renders
+/// the same on every output.
+pub fn apply_text_label(img: VipsImage, label: &LabelOptions) -> Result<VipsImage, ProcessingError> {
+    // At 72 DPI a font size in points equals its size in pixels.
+    let options = ops::TextOptions {
+        font: format!("sans {}", label.size),
+        dpi: 72,
+        ..Default::default()
+    };
+    let coverage = ops::text_with_opts(&escape_markup(&label.text), &options)
+        .map_err(|e| ProcessingError::Vips(format!("Failed to render label: {}", e)))?;
+    let [r, g, b, a] = label.color.map(f64::from);
+    let color = VipsImage::new_from_image(&coverage, &[r, g, b])
+        .map_err(|e| ProcessingError::Vips(format!("Failed to create label colour: {}", e)))?;
+    let alpha = ops::linear(&coverage, &mut [a / 255.0], &mut [0.0])
+        .and_then(|alpha| ops::cast(&alpha, ops::BandFormat::Uchar))
+        .map_err(|e| ProcessingError::Vips(format!("Failed to apply label opacity: {}", e)))?;
+    let text_img = ops::bandjoin(&mut [color, alpha])
+        .map_err(|e| ProcessingError::Vips(format!("Failed to build label image: {}", e)))?;
+
+    let (x, y) = calculate_watermark_position(&img, &text_img, &label.position);
+    let label_on_canvas = embed_on_canvas(&text_img, x, y, &img)?;
+    ops::composite_2(&img, &label_on_canvas, ops::BlendMode::Over)
+        .map_err(|e| ProcessingError::Vips(format!("Failed to composite label: {}", e)))
+}
+
+/// Escapes the characters Pango markup would interpret, since libvips renders text as markup.
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Places `overlay` at (`x`, `y`) on a transparent canvas the size of `img`.
+fn embed_on_canvas(overlay: &VipsImage, x: u32, y: u32, img: &VipsImage) -> Result<VipsImage, ProcessingError> {
     let options = ops::EmbedOptions {
         extend: ops::Extend::Background,
-        background: bg.to_vec(),
+        background: vec![0.0; overlay.get_bands() as usize],
     };
+    ops::embed_with_opts(overlay, x as i32, y as i32, img.get_width(), img.get_height(), &options)
+        .map_err(|e| ProcessingError::Vips(format!("Failed to embed watermark on canvas: {}", e)))
+}
 
-    let watermark_on_canvas = ops::embed_with_opts(
-        &watermark_with_opacity,
-        x as i32,
-        y as i32,
-        img.get_width(),
-        img.get_height(),
-        &options,
-    )
-    .map_err(|e| format!("Failed to embed watermark on canvas: {}", e))?;
+/// Renders a copyright line centred along the bottom of the image.
+///
+/// The text height follows the image height, so the line stays legible on large outputs without
+/// dominating small ones; long lines are narrowed to fit the width.
+pub fn apply_copyright(
+    img: VipsImage,
+    text: &str,
+    resizing_algorithm: &Option<String>,
+) -> Result<VipsImage, ProcessingError> {
+    let watermark = render_text_watermark(text)?;
+    let text_img = resolve_watermark_image(&watermark)?;
 
-    ops::composite_2(&img, &watermark_on_canvas, ops::BlendMode::Over)
-        .map_err(|e| format!("Failed to composite watermark: {}", e))
+    let text_width =
+        img.get_height() as f64 * COPYRIGHT_HEIGHT_RATIO * text_img.get_width() as f64 / text_img.get_height() as f64;
+    let scale = (text_width / img.get_width() as f64).min(COPYRIGHT_MAX_SCALE);
+    let watermark_opts = Watermark {
+        opacity: COPYRIGHT_OPACITY,
+        position: "south".to_string(),
+        scale: Some(scale as f32),
+        angle: 0.0,
+        offset: None,
+    };
+    apply_watermark(img, &watermark, &watermark_opts, resizing_algorithm)
 }
 
-fn resolve_watermark_image(watermark: &CachedWatermark) -> Result<VipsImage, String> {
+/// Repeats the watermark across a `width` x `height` canvas, starting from the top-left corner.
+fn tile_watermark(watermark_img: &VipsImage, width: i32, height: i32) -> Result<VipsImage, ProcessingError> {
+    let across = (width + watermark_img.get_width() - 1) / watermark_img.get_width();
+    let down = (height + watermark_img.get_height() - 1) / watermark_img.get_height();
+    let tiled = ops::replicate(watermark_img, across, down)
+        .map_err(|e| ProcessingError::Vips(format!("Failed to tile watermark: {}", e)))?;
+    ops::extract_area(&tiled, 0, 0, width, height)
+        .map_err(|e| ProcessingError::Vips(format!("Failed to crop tiled watermark: {}", e)))
+}
+
+fn resolve_watermark_image(watermark: &CachedWatermark) -> Result<VipsImage, ProcessingError> {
     if let Some(prepared_rgba) = &watermark.prepared_rgba {
         return prepared_rgba.to_image();
     }
@@ -113,18 +223,19 @@ fn resolve_watermark_image(watermark: &CachedWatermark) -> Result<VipsImage, Str
     load_watermark_image(watermark.bytes.as_ref())
 }
 
-fn ensure_alpha_channel(watermark_img: VipsImage) -> Result<VipsImage, String> {
+fn ensure_alpha_channel(watermark_img: VipsImage) -> Result<VipsImage, ProcessingError> {
     if watermark_img.get_bands() == 4 || watermark_img.get_bands() == 2 {
         return Ok(watermark_img);
     }
 
-    ops::bandjoin_const(&watermark_img, &mut [255.0]).map_err(|e| format!("Failed to add alpha to watermark: {}", e))
+    ops::bandjoin_const(&watermark_img, &mut [255.0])
+        .map_err(|e| ProcessingError::Vips(format!("Failed to add alpha to watermark: {}", e)))
 }
 
-fn build_prepared_watermark_image(watermark_img: VipsImage) -> Result<PreparedWatermark, String> {
+fn build_prepared_watermark_image(watermark_img: VipsImage) -> Result<PreparedWatermark, ProcessingError> {
     let format = watermark_img
         .get_format()
-        .map_err(|e| format!("Failed to determine watermark format: {}", e))?;
+        .map_err(|e| ProcessingError::Vips(format!("Failed to determine watermark format: {}", e)))?;
     let prepared = PreparedWatermark {
         bytes: Bytes::from(watermark_img.image_write_to_memory()),
         width: watermark_img.get_width(),
@@ -136,6 +247,19 @@ fn build_prepared_watermark_image(watermark_img: VipsImage) -> Result<PreparedWa
     Ok(prepared)
 }
 
+/// Places the watermark's top-left corner at `offset` percent of the image width and height,
+/// pulled back where needed so the watermark stays inside the image.
+fn calculate_watermark_offset(main_img: &VipsImage, watermark_img: &VipsImage, offset: (f32, f32)) -> (u32, u32) {
+    let main_w = main_img.get_width() as u32;
+    let main_h = main_img.get_height() as u32;
+    let x = (main_w as f32 * offset.0 / 100.0).round() as u32;
+    let y = (main_h as f32 * offset.1 / 100.0).round() as u32;
+    (
+        x.min(main_w.saturating_sub(watermark_img.get_width() as u32)),
+        y.min(main_h.saturating_sub(watermark_img.get_height() as u32)),
+    )
+}
+
 fn calculate_watermark_position(main_img: &VipsImage, watermark_img: &VipsImage, position: &str) -> (u32, u32) {
     let main_w = main_img.get_width() as u32;
     let main_h = main_img.get_height() as u32;
@@ -144,15 +268,18 @@ fn calculate_watermark_position(main_img: &VipsImage, watermark_img: &VipsImage,
     let margin = (main_w.min(main_h) as f32 * 0.05).round() as u32; // 5% margin
 
     match position {
-        "north" => ((main_w - wm_w) / 2, margin),
-        "south" => ((main_w - wm_w) / 2, main_h - wm_h - margin),
-        "east" => (main_w - wm_w - margin, (main_h - wm_h) / 2),
-        "west" => (margin, (main_h - wm_h) / 2),
+        "north" => (main_w.saturating_sub(wm_w) / 2, margin),
+        "south" => (main_w.saturating_sub(wm_w) / 2, main_h.saturating_sub(wm_h + margin)),
+        "east" => (main_w.saturating_sub(wm_w + margin), main_h.saturating_sub(wm_h) / 2),
+        "west" => (margin, main_h.saturating_sub(wm_h) / 2),
         "north_west" => (margin, margin),
-        "north_east" => (main_w - wm_w - margin, margin),
-        "south_west" => (margin, main_h - wm_h - margin),
-        "south_east" => (main_w - wm_w - margin, main_h - wm_h - margin),
-        "center" => ((main_w - wm_w) / 2, (main_h - wm_h) / 2),
-        _ => ((main_w - wm_w) / 2, (main_h - wm_h) / 2),
+        "north_east" => (main_w.saturating_sub(wm_w + margin), margin),
+        "south_west" => (margin, main_h.saturating_sub(wm_h + margin)),
+        "south_east" => (
+            main_w.saturating_sub(wm_w + margin),
+            main_h.saturating_sub(wm_h + margin),
+        ),
+        "center" => (main_w.saturating_sub(wm_w) / 2, main_h.saturating_sub(wm_h) / 2),
+        _ => (main_w.saturating_sub(wm_w) / 2, main_h.saturating_sub(wm_h) / 2),
     }
 }
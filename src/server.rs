@@ -1,15 +1,19 @@
-use crate::app::Imgforge;
+use crate::app::{AppState, Imgforge};
 use crate::caching::config::CacheConfig;
 use crate::config::Config;
 use crate::constants::*;
-use crate::handlers::{image_forge_handler, info_handler, status_handler};
+use crate::handlers::{image_forge_handler, info_handler, srcset_handler, status_handler};
 use crate::middleware;
 use crate::monitoring;
 use axum::http::StatusCode;
 use axum::{extract::Request, routing::get, Router};
+use axum_prometheus::metrics_exporter_prometheus::PrometheusHandle;
 use axum_prometheus::PrometheusMetricLayer;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpListener;
+use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{info, info_span, warn};
@@ -32,59 +36,24 @@ pub async fn start() {
 
     info!("Starting imgforge server with {} workers...", state.config.workers);
 
-    let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
-    monitoring::register_metrics();
-
-    let main_metric_handle = metric_handle.clone();
-    let main_state = state.clone();
+    let metrics = if state.config.enable_metrics {
+        let pair = PrometheusMetricLayer::pair();
+        monitoring::register_metrics();
+        Some(pair)
+    } else {
+        info!("Metrics are disabled; /metrics will not be served");
+        None
+    };
+    let metric_handle = metrics.as_ref().map(|(_, handle)| handle.clone());
 
-    let app = Router::new()
-        .route("/status", get(status_handler))
-        .route("/info/{*path}", get(info_handler))
-        .route(
-            "/{*path}",
-            get(image_forge_handler)
-                .layer(axum::middleware::from_fn_with_state(
-                    state.clone(),
-                    middleware::rate_limit_middleware,
-                ))
-                .layer(axum::middleware::from_fn(middleware::status_code_metric_middleware)),
-        )
-        .route(
-            "/metrics",
-            get(move || async move {
-                monitoring::update_vips_metrics(&main_state.vips_app);
-                main_metric_handle.render()
-            }),
-        )
-        .with_state(state.clone())
-        .layer(prometheus_layer)
-        .layer(
-            TraceLayer::new_for_http().make_span_with(|request: &Request<axum::body::Body>| {
-                let request_id = request
-                    .extensions()
-                    .get::<middleware::RequestId>()
-                    .map(|id| id.0.clone())
-                    .unwrap_or_else(|| "unknown".to_string());
-                info_span!(
-                    "request",
-                    id = %request_id,
-                    method = %request.method(),
-                    uri = %request.uri(),
-                )
-            }),
-        )
-        .layer(axum::middleware::from_fn(middleware::request_id_middleware))
-        .layer(TimeoutLayer::with_status_code(
-            StatusCode::REQUEST_TIMEOUT,
-            Duration::from_secs(state.config.timeout),
-        ));
+    let app = build_router(state.clone(), metrics);
     let listener = TcpListener::bind(&state.config.bind_address).await.unwrap();
     info!("Listening on http://{}", &state.config.bind_address);
 
-    let main_server = axum::serve(listener, app);
+    let main_server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>());
 
-    if let Some(prometheus_bind_address) = &state.config.prometheus_bind_address {
+    let prometheus_target = state.config.prometheus_bind_address.as_ref().zip(metric_handle);
+    if let Some((prometheus_bind_address, metric_handle)) = prometheus_target {
         match TcpListener::bind(prometheus_bind_address).await {
             Ok(prometheus_listener) => {
                 info!(
@@ -120,3 +89,79 @@ pub async fn start() {
         main_server.await.unwrap();
     }
 }
+
+/// Builds the HTTP router serving imgforge requests.
+///
+/// When `metrics` is provided, its Prometheus layer records every request and `/metrics` renders
+/// the handle; otherwise neither is attached.
+pub fn build_router(
+    state: Arc<AppState>,
+    metrics: Option<(PrometheusMetricLayer<'static>, PrometheusHandle)>,
+) -> Router {
+    let mut app = Router::new()
+        .route("/status", get(status_handler))
+        .route("/info/{*path}", get(info_handler))
+        .route("/srcset/{*path}", get(srcset_handler))
+        .route(
+            "/{*path}",
+            get(image_forge_handler)
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    middleware::rate_limit_middleware,
+                ))
+                .layer(axum::middleware::from_fn(middleware::status_code_metric_middleware)),
+        );
+
+    if let Some((prometheus_layer, metric_handle)) = metrics {
+        let metrics_state = state.clone();
+        app = app
+            .route(
+                "/metrics",
+                get(move || async move {
+                    monitoring::update_vips_metrics(&metrics_state.vips_app);
+                    metric_handle.render()
+                }),
+            )
+            .layer(prometheus_layer);
+    } else {
+        // Keep `/metrics` from falling through to the image route when metrics are disabled.
+        app = app.route("/metrics", get(|| async { StatusCode::NOT_FOUND }));
+    }
+
+    let timeout = Duration::from_secs(state.config.timeout);
+    let max_request_body_size = state.config.max_request_body_size;
+    app.with_state(state.clone())
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request<axum::body::Body>| {
+                let request_id = request
+                    .extensions()
+                    .get::<middleware::RequestId>()
+                    .map(|id| id.0.clone())
+                    .unwrap_or_else(|| "unknown".to_string());
+                let client_ip = request
+                    .extensions()
+                    .get::<middleware::ClientIp>()
+                    .map(|ip| ip.0.to_string())
+                    .unwrap_or_else(|| "unknown".to_string());
+                info_span!(
+                    "request",
+                    id = %request_id,
+                    client_ip = %client_ip,
+                    method = %request.method(),
+                    uri = %request.uri(),
+                )
+            }),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::client_ip_middleware,
+        ))
+        // Oversized requests are turned away before any handler work
+        .layer(RequestBodyLimitLayer::new(max_request_body_size))
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::path_length_middleware,
+        ))
+        .layer(axum::middleware::from_fn(middleware::request_id_middleware))
+        .layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, timeout))
+}
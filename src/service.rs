@@ -1,20 +1,38 @@
 use crate::app::AppState;
 use crate::caching::cache::{CachedImage, CachedMetadata, ImgforgeCache, MetadataCache};
-use crate::fetch::fetch_image;
+use crate::config::ErrorImageMode;
+use crate::fetch::{
+    check_source_host, check_source_url_length, decode_data_source, fetch_image, fetch_image_with_retry,
+    is_data_source, is_s3_source, local_source_path, read_local_image, revalidate_source, validate_source_url,
+    validate_source_url_target, FetchError, FetchedImage, SourceCacheHeaders,
+};
+use crate::monitoring::increment_requests;
+use crate::processing::error::ProcessingError;
+use crate::processing::error_image::{render_error_image, DEFAULT_ERROR_IMAGE_SIZE};
 use crate::processing::options::{parse_all_options, ParsedOptions};
-use crate::processing::presets::expand_presets;
-use crate::processing::process_image;
+use crate::processing::presets::{applied_presets, expand_presets};
+use crate::processing::quality::QualityDefaults;
+use crate::processing::utils::parse_boolean;
 use crate::processing::watermark::{self, CachedWatermark};
-use crate::url::{parse_path, validate_signature, ImgforgeUrl};
-use crate::utils::{content_type_to_format, format_to_content_type, read_exif_orientation};
+use crate::processing::{decode, lqip, save};
+use crate::processing::{process_image_timed, EncodedImage};
+use crate::url::{build_path_to_sign, parse_path, sign_paths, validate_signature, ImgforgeUrl};
+use crate::utils::{
+    content_type_to_format, format_to_content_type, read_exif_orientation, read_source_metadata, SourceMetadata,
+};
 use axum::http::StatusCode;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
 use bytes::Bytes;
-use libvips::VipsImage;
+use sha2::{Digest, Sha256};
 use std::error::Error;
 use std::fmt::Display;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
-use tracing::{debug, error, info};
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::{debug, error, info, warn};
 
 /// Indicates whether the response was served from cache.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -33,10 +51,42 @@ impl CacheStatus {
 }
 
 /// Result of processing an image request.
+#[derive(Clone)]
 pub struct ProcessedImage {
+    /// Response status; only error images served through `IMGFORGE_ERROR_IMAGE_MODE` differ from `200`.
+    pub status: StatusCode,
     pub bytes: Bytes,
     pub content_type: &'static str,
     pub cache_status: CacheStatus,
+    /// Base64 data URI of a tiny preview, present when the request set `lqip`.
+    pub lqip: Option<String>,
+    /// Time spent in `process_image`; `None` for cache hits and raw passthrough.
+    pub processing_duration: Option<Duration>,
+    /// Whether the format was negotiated from the Accept header, so responses must `Vary` on it.
+    pub vary_accept: bool,
+    /// Names of the presets applied to the request, `default` first.
+    pub presets: Vec<String>,
+    /// Whether `encode:datauri` asked for the image wrapped in JSON; see [`encode_data_uri`].
+    pub data_uri: bool,
+    /// Origin Cache-Control passed on to the client when `IMGFORGE_UPSTREAM_CACHE_HEADERS` is set,
+    /// or the short-lived one of a fallback image.
+    pub cache_control: Option<String>,
+    /// Whether `IMGFORGE_FALLBACK_IMAGE` stands in for a source that could not be fetched or decoded.
+    pub fallback: bool,
+    /// Request paths of the same image at the other `IMGFORGE_PRELOAD_BREAKPOINTS` widths.
+    pub preload_links: Vec<String>,
+    /// Whether this is an `IMGFORGE_ERROR_IMAGE_MODE` image rendered in place of a failed request.
+    pub is_error_image: bool,
+}
+
+/// Largest encoded image, in bytes, that `encode:datauri` will inline.
+pub const MAX_DATA_URI_BYTES: usize = 512 * 1024;
+
+/// A processed image inlined as a base64 `data:` URI, with its pixel dimensions.
+pub struct DataUri {
+    pub data_uri: String,
+    pub width: u32,
+    pub height: u32,
 }
 
 /// Result of fetching image metadata.
@@ -49,18 +99,62 @@ pub struct ImageInfo {
     pub channels: u32,
     pub has_alpha: bool,
     pub orientation: Option<u32>,
+    /// Whether the source is a GIF or WebP with more than one frame.
+    pub animated: bool,
+    /// ICC and EXIF details, read only when the path carries `metadata:true`.
+    pub source_metadata: Option<SourceMetadata>,
+}
+
+/// Option asking for ICC and EXIF details. Only the `/info` endpoint reads it.
+const INFO_METADATA_OPTION: &str = "metadata";
+
+/// Largest number of widths a srcset manifest may list.
+pub const MAX_SRCSET_WIDTHS: usize = 16;
+/// Option listing the widths of a srcset manifest. Only the `/srcset` endpoint reads it.
+const SRCSET_WIDTHS_OPTION: &str = "widths";
+/// Options that set the output size, which the widths of a srcset manifest or of preload links replace.
+const SRCSET_SIZE_OPTIONS: [&str; 12] = [
+    "resize", "rs", "size", "s", "sz", "css_size", "cs", "width", "w", "height", "h", "dpr",
+];
+
+/// One image of a srcset manifest.
+pub struct SrcsetImage {
+    pub width: u32,
+    /// Request path for this width, authorized the same way as the manifest request.
+    pub path: String,
+}
+
+/// Request paths for the same source and options at several widths.
+pub struct SrcsetManifest {
+    pub images: Vec<SrcsetImage>,
+}
+
+impl SrcsetManifest {
+    /// Formats the images as an HTML `srcset` attribute value, e.g. `/a 320w, /b 640w`.
+    pub fn srcset(&self) -> String {
+        self.images
+            .iter()
+            .map(|image| format!("{} {}w", image.path, image.width))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 /// Request context for processing or info retrieval.
 pub struct ProcessRequest<'a> {
     pub path: &'a str,
     pub bearer_token: Option<&'a str>,
+    /// Value of the request's `Accept` header, consulted by `format:auto`.
+    pub accept: Option<&'a str>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ServiceError {
     status: StatusCode,
     message: String,
+    /// Whether the origin could not serve the source or it could not be decoded, so
+    /// `IMGFORGE_FALLBACK_IMAGE` may stand in.
+    source_unavailable: bool,
 }
 
 impl ServiceError {
@@ -68,6 +162,28 @@ impl ServiceError {
         Self {
             status,
             message: message.into(),
+            source_unavailable: false,
+        }
+    }
+
+    /// Maps a failed source read, marking the failures the origin is responsible for: error
+    /// statuses, missing files and failed connections. Policy and size rejections stay unmarked.
+    fn from_fetch_error(error: &FetchError, context: &str) -> Self {
+        Self {
+            status: fetch_error_status(error),
+            message: format!("{}: {}", context, error),
+            source_unavailable: matches!(
+                error,
+                FetchError::Status(_) | FetchError::Connect(_) | FetchError::NotFound(_)
+            ),
+        }
+    }
+
+    /// Maps a source that could not be decoded, which the fallback image may replace.
+    fn undecodable(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            source_unavailable: true,
+            ..Self::new(status, message)
         }
     }
 
@@ -88,12 +204,41 @@ impl Display for ServiceError {
 
 impl Error for ServiceError {}
 
+impl From<ProcessingError> for ServiceError {
+    fn from(error: ProcessingError) -> Self {
+        Self::new(
+            processing_error_status(&error),
+            format!("Error processing image: {}", error),
+        )
+    }
+}
+
+/// Maps each kind of processing failure to the HTTP status that describes it best.
+///
+/// Encoder faults are the server's problem; everything else stems from the source or the request.
+pub fn processing_error_status(error: &ProcessingError) -> StatusCode {
+    match error {
+        ProcessingError::Decode(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ProcessingError::Unsupported(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ProcessingError::Geometry(_) | ProcessingError::Vips(_) => StatusCode::BAD_REQUEST,
+        ProcessingError::Encode(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Names the source format from its magic bytes, trusting the declared content type only when the
+/// bytes are not recognised, since origins often mislabel images.
 fn detect_image_format(content_type: Option<&str>, image_bytes: &[u8]) -> String {
-    if let Some(format) = content_type.and_then(content_type_to_format) {
+    if let Some(format) = sniff_image_format(image_bytes) {
         return format.to_string();
     }
+    if decode::is_svg(image_bytes) {
+        return "svg".to_string();
+    }
 
-    sniff_image_format(image_bytes).unwrap_or("unknown").to_string()
+    content_type
+        .and_then(content_type_to_format)
+        .unwrap_or("unknown")
+        .to_string()
 }
 
 fn sniff_image_format(image_bytes: &[u8]) -> Option<&'static str> {
@@ -147,22 +292,21 @@ pub async fn process_path(state: Arc<AppState>, request: ProcessRequest<'_>) ->
     info!("Imgforge request received path={}", path);
 
     let url_parts = parse_and_authorize(config, path, request.bearer_token)?;
+    let decoded_url = decode_source_url(config, &url_parts)?;
 
-    if let Some(cached_image) = state.cache.get(path).await {
-        debug!("Image found in cache for path={}", path);
-
-        return Ok(ProcessedImage {
-            bytes: cached_image.bytes,
-            content_type: cached_image.content_type,
-            cache_status: CacheStatus::Hit,
-        });
-    }
-
-    let decoded_url = url_parts.source_url.decode().map_err(|e| {
-        error!("Error decoding URL: {}", e);
-        ServiceError::new(StatusCode::BAD_REQUEST, format!("Error decoding URL: {}", e))
-    })?;
-
+    let presets = applied_presets(&url_parts.processing_options, &config.presets);
+    increment_requests(
+        if url_parts.signature == "unsafe" {
+            "unsigned"
+        } else {
+            "signed"
+        },
+        if presets.iter().any(|name| name != "default") {
+            "preset"
+        } else {
+            "inline"
+        },
+    );
     let expanded_options = expand_presets(
         url_parts.processing_options.clone(),
         &config.presets,
@@ -175,18 +319,222 @@ pub async fn process_path(state: Arc<AppState>, request: ProcessRequest<'_>) ->
 
     debug!("Processing image forge request for URL: {}", decoded_url);
 
-    let parsed_options = parse_all_options(expanded_options).map_err(|e| {
+    let mut parsed_options = parse_all_options(expanded_options).map_err(|e| {
         error!("Error parsing processing options: {}", e);
         ServiceError::new(StatusCode::BAD_REQUEST, e)
     })?;
+    apply_config_defaults(config, &mut parsed_options);
 
-    let max_src_file_size = resolve_max_src_file_size(config, &parsed_options);
-    let (image_bytes, source_content_type) = fetch_image(&state.http_client, &decoded_url, max_src_file_size)
-        .await
-        .map_err(|e| {
-            error!("Error fetching image: {}", e);
-            ServiceError::new(StatusCode::BAD_REQUEST, format!("Error fetching image: {}", e))
-        })?;
+    // `format:auto` picks the format from the Accept header, so each variant is cached separately
+    let vary_accept = parsed_options.prefer_accept;
+    let data_uri = parsed_options.data_uri;
+    let preload_links = if data_uri {
+        Vec::new()
+    } else {
+        preload_links(config, path, &url_parts.signature, &parsed_options)
+    };
+    let cache_key = if vary_accept {
+        let format = negotiate_format(request.accept);
+        debug!("Negotiated output format {} from Accept {:?}", format, request.accept);
+        parsed_options.format = Some(format.to_string());
+        format!("{}#{}", normalized_cache_path(path), format)
+    } else {
+        normalized_cache_path(path)
+    };
+
+    let mut serving_stale = false;
+    let cached = match state.cache.get(&cache_key).await {
+        Some(cached_image) if config.upstream_cache_headers => {
+            if is_stale_while_revalidate(config, &cached_image) {
+                serving_stale = true;
+                Some(cached_image)
+            } else {
+                revalidate_cached_image(state.as_ref(), &decoded_url, &cache_key, cached_image).await
+            }
+        }
+        cached => cached,
+    };
+    if let Some(cached_image) = cached {
+        debug!("Image found in cache for path={}", path);
+        if serving_stale {
+            debug!("Serving stale cached image for path={} while refreshing it", path);
+            refresh_in_background(&state, path, &cache_key, &decoded_url, parsed_options);
+        }
+
+        return Ok(ProcessedImage {
+            status: StatusCode::OK,
+            bytes: cached_image.bytes,
+            content_type: cached_image.content_type,
+            cache_status: CacheStatus::Hit,
+            lqip: cached_image.lqip,
+            processing_duration: None,
+            vary_accept,
+            presets,
+            data_uri,
+            cache_control: cached_image
+                .source_cache_headers
+                .and_then(|cache_headers| cache_headers.cache_control),
+            fallback: false,
+            preload_links,
+            is_error_image: false,
+        });
+    }
+
+    // Raw passthrough never takes a processing permit; it has its own optional limit held across
+    // the fetch so bulk raw traffic cannot starve processed requests or flood origins.
+    let _raw_permit = if parsed_options.raw {
+        acquire_raw_permit(state.as_ref()).await?
+    } else {
+        None
+    };
+
+    let error_image = config.error_image_mode.map(|mode| {
+        (
+            mode,
+            error_image_dimensions(&parsed_options),
+            parsed_options.format.clone(),
+        )
+    });
+    // Identical requests arriving before the cache is populated share a single run
+    let result = state
+        .coalescer
+        .run(
+            &cache_key,
+            fetch_and_process(&state, path, &cache_key, &decoded_url, parsed_options),
+        )
+        .await;
+    match (result, error_image) {
+        (Err(err), Some((mode, (width, height), format))) => {
+            error!("Serving error image for path={} error={}", path, err);
+            render_error_response(err, mode, width, height, format.as_deref())
+        }
+        // A fallback image is the same at every width, so there is nothing worth preloading
+        (Ok(processed), _) if !processed.fallback => Ok(ProcessedImage {
+            preload_links,
+            ..processed
+        }),
+        (result, _) => result,
+    }
+    .map(|processed| ProcessedImage {
+        vary_accept,
+        presets,
+        data_uri,
+        ..processed
+    })
+}
+
+/// Returns the request path processed images are cached under.
+///
+/// A `dpr` of 1 renders the same image as no `dpr` at all, so those option segments are dropped
+/// and both spellings share a cache entry. Only segments before the source URL are considered.
+fn normalized_cache_path(path: &str) -> String {
+    let mut in_options = true;
+    path.split('/')
+        .enumerate()
+        .filter(|(index, segment)| {
+            // The first segment is the signature; options end where `parse_path` finds the source
+            if *index == 0 || !in_options {
+                return true;
+            }
+            if *segment == "plain" || !segment.contains(':') {
+                in_options = false;
+                return true;
+            }
+            !segment
+                .strip_prefix("dpr:")
+                .is_some_and(|ratio| ratio.parse::<f32>() == Ok(1.0))
+        })
+        .map(|(_, segment)| segment)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Wraps a processed image as a base64 `data:` URI for inlining in emails or markup.
+///
+/// Outputs over [`MAX_DATA_URI_BYTES`] are refused, since inlined images that large bloat every
+/// document they appear in; resize or lower the quality instead.
+pub fn encode_data_uri(image: &ProcessedImage) -> Result<DataUri, ServiceError> {
+    if image.bytes.len() > MAX_DATA_URI_BYTES {
+        error!(
+            "Output of {} bytes exceeds the data URI limit of {} bytes",
+            image.bytes.len(),
+            MAX_DATA_URI_BYTES
+        );
+        return Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Output of {} bytes is too large for a data URI (limit {} bytes)",
+                image.bytes.len(),
+                MAX_DATA_URI_BYTES
+            ),
+        ));
+    }
+    let (width, height) = decode::read_dimensions(&image.bytes, false).map_err(|e| {
+        error!("Failed to read output dimensions for data URI: {}", e);
+        ServiceError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+
+    Ok(DataUri {
+        data_uri: format!("data:{};base64,{}", image.content_type, STANDARD.encode(&image.bytes)),
+        width,
+        height,
+    })
+}
+
+/// Fetches the source and runs the processing pipeline for an authorized, parsed request.
+async fn fetch_and_process(
+    state: &Arc<AppState>,
+    path: &str,
+    cache_key: &str,
+    decoded_url: &str,
+    parsed_options: ParsedOptions,
+) -> Result<ProcessedImage, ServiceError> {
+    let config = &state.config;
+    let enforce_limits = !parsed_options.raw || config.raw_enforce_limits;
+    let max_src_file_size = resolve_max_src_file_size(config, &parsed_options).filter(|_| enforce_limits);
+    let fetched = fetch_source_image(state.as_ref(), decoded_url, max_src_file_size).await;
+    let Some(fallback_image) = config.fallback_image.as_deref() else {
+        return process_source(state, path, cache_key, fetched?, parsed_options, false).await;
+    };
+
+    // Undecodable sources only fail once processing starts, so the fallback is tried afterwards
+    let processed = match fetched {
+        Ok(source) => process_source(state, path, cache_key, source, parsed_options.clone(), false).await,
+        Err(err) => Err(err),
+    };
+    match processed {
+        Err(err) => match substitute_fallback(state.as_ref(), path, fallback_image, err).await {
+            Ok(fallback) => process_source(state, path, cache_key, fallback, parsed_options, true).await,
+            Err(err) => Err(err),
+        },
+        processed => processed,
+    }
+}
+
+/// Runs the processing pipeline on a fetched source, or passes it through for `raw` requests.
+///
+/// `fallback` marks the configured fallback image standing in for the requested source, which is
+/// never cached and is served with its own short lifetime.
+async fn process_source(
+    state: &Arc<AppState>,
+    path: &str,
+    cache_key: &str,
+    source: FetchedImage,
+    mut parsed_options: ParsedOptions,
+    fallback: bool,
+) -> Result<ProcessedImage, ServiceError> {
+    let config = &state.config;
+    let enforce_limits = !parsed_options.raw || config.raw_enforce_limits;
+    let FetchedImage {
+        bytes: image_bytes,
+        content_type: source_content_type,
+        cache_headers,
+    } = source;
+    // The origin's caching headers only reach the cache and the response when enabled
+    let source_cache_headers = config
+        .upstream_cache_headers
+        .then_some(cache_headers)
+        .filter(|_| !fallback);
 
     debug!(
         "Source image MIME type: {:?}, size: {} bytes",
@@ -195,7 +543,19 @@ pub async fn process_path(state: Arc<AppState>, request: ProcessRequest<'_>) ->
     );
 
     if parsed_options.raw {
-        return serve_raw_response(state.as_ref(), path, image_bytes, source_content_type).await;
+        // Raw bytes are never decoded, so the resolution limit cannot apply
+        if enforce_limits {
+            check_allowed_mime_type(config, source_content_type.as_deref())?;
+        }
+        return serve_raw_response(
+            state.as_ref(),
+            cache_key,
+            image_bytes,
+            source_content_type,
+            source_cache_headers,
+            fallback,
+        )
+        .await;
     }
 
     let watermark = if needs_watermark(&parsed_options) {
@@ -211,38 +571,130 @@ pub async fn process_path(state: Arc<AppState>, request: ProcessRequest<'_>) ->
         .await
         .map_err(|_| ServiceError::new(StatusCode::INTERNAL_SERVER_ERROR, "Semaphore closed"))?;
 
-    let output_format = parsed_options.format.clone().unwrap_or_else(|| "jpeg".to_string());
+    // Animated GIFs without an explicit format become animated WebP, which is far smaller.
+    let animate_to_webp = config.gif_to_webp
+        && parsed_options.format.is_none()
+        && parsed_options.contact_sheet.is_none()
+        && decode::is_animated_gif(&image_bytes);
+    if animate_to_webp {
+        debug!("Converting animated GIF source to animated WebP");
+        parsed_options.format = Some("webp".to_string());
+    }
+    // Rounded corners need alpha, so they switch the JPEG default to PNG
+    if parsed_options.border_radius.is_some() && parsed_options.format.is_none() {
+        debug!("Using PNG output to keep transparent rounded corners");
+        parsed_options.format = Some("png".to_string());
+    }
+    // Animated GIF and WebP sources keep every frame when the output format can carry them
+    let keep_animation = parsed_options.contact_sheet.is_none()
+        && parsed_options
+            .format
+            .as_deref()
+            .is_some_and(|format| format.eq_ignore_ascii_case("gif") || format.eq_ignore_ascii_case("webp"))
+        && (animate_to_webp || decode::is_animated(&image_bytes));
+    // Contact sheets only apply to animations; multi-page TIFFs are served page by page
+    let contact_sheet_columns = parsed_options
+        .contact_sheet
+        .filter(|_| !decode::is_tiff(&image_bytes) && decode::page_count(&image_bytes).is_ok_and(|pages| pages > 1));
+    // Kept animations decode up to `max_animation_frames` frames and contact sheets up to
+    // `MAX_CONTACT_SHEET_FRAMES`, all of which count as source pixels
+    let decoded_frames = if keep_animation {
+        decode::page_count(&image_bytes)
+            .unwrap_or(1)
+            .min(config.max_animation_frames)
+    } else if contact_sheet_columns.is_some() {
+        decode::page_count(&image_bytes)
+            .unwrap_or(1)
+            .min(decode::MAX_CONTACT_SHEET_FRAMES)
+    } else {
+        1
+    };
 
-    let processed_image_bytes = {
-        let source_image = VipsImage::new_from_buffer(&image_bytes, "").map_err(|e| {
-            let response = format!("Error loading image from memory: {}", e);
-            error!("{}", response);
-            ServiceError::new(StatusCode::INTERNAL_SERVER_ERROR, response)
-        })?;
+    let wants_lqip = parsed_options.lqip;
 
+    // The byte budget may switch formats, so the response is labelled by what was encoded
+    let EncodedImage {
+        bytes: processed_image_bytes,
+        format: output_format,
+        duration: processing_duration,
+    } = {
+        // Limits only need the header, so oversized sources are rejected before the full decode.
         enforce_security_constraints(
             state.as_ref(),
             &parsed_options,
             &image_bytes,
             source_content_type.as_deref(),
-            Some(&source_image),
+            decoded_frames,
         )?;
 
-        process_image(source_image, parsed_options, &image_bytes, watermark.as_ref()).map_err(|e| {
+        let tiff_page = resolve_tiff_page(config, &parsed_options, &image_bytes)?;
+        let embedded_thumbnail = embedded_thumbnail_target(&parsed_options, &image_bytes)
+            .and_then(|(width, height)| decode::load_embedded_thumbnail(&image_bytes, width, height));
+        // A forced loader replaces every format-specific decode path, including reduced decodes
+        let forced_loader = parsed_options.source_format.is_some();
+        let source_image = match parsed_options.source_format.as_deref() {
+            Some(format) => decode::load_with_format(&image_bytes, format),
+            None => match (tiff_page, embedded_thumbnail) {
+                (Some(page), _) => decode::load_tiff_page(&image_bytes, page),
+                (None, Some(thumbnail)) => Ok(thumbnail),
+                (None, None) if keep_animation => decode::load_animation(&image_bytes, decoded_frames),
+                (None, None) => match contact_sheet_columns {
+                    Some(columns) => decode::load_contact_sheet(&image_bytes, columns),
+                    // Dimensionless SVGs render tiny, so the request decides how large to rasterize
+                    // them, within the source resolution limit
+                    None if decode::is_dimensionless_svg(&image_bytes) => {
+                        let (width, height) = reduced_decode_target(&parsed_options)
+                            .unwrap_or((config.svg_default_size, config.svg_default_size));
+                        let max_pixels = resolve_max_src_resolution(config, &parsed_options)
+                            .map(|megapixels| megapixels as f64 * 1_000_000.0);
+                        decode::load_svg_at_size(&image_bytes, width, height, max_pixels)
+                    }
+                    None => shrink_on_load_target(&parsed_options, &image_bytes)
+                        .and_then(|(width, height)| decode::load_shrunk_jpeg(&image_bytes, width, height))
+                        .map_or_else(|| decode::load_image(&image_bytes, config.decode_fallback), Ok),
+                },
+            },
+        }
+        .map_err(|e| {
+            error!("{}", e);
+            // A forced loader is the request's choice, so its failures are not the source's fault
+            match e {
+                ProcessingError::Decode(_) if !forced_loader => {
+                    ServiceError::undecodable(processing_error_status(&e), e.to_string())
+                }
+                _ => ServiceError::new(processing_error_status(&e), e.to_string()),
+            }
+        })?;
+
+        // Reduced decodes only change how the source is loaded; every source goes through the
+        // same pipeline so encode options such as quality and strip_metadata always apply
+        process_image_timed(source_image, parsed_options, &image_bytes, watermark.as_ref()).map_err(|e| {
             error!("Error processing image: {}", e);
-            ServiceError::new(StatusCode::BAD_REQUEST, format!("Error processing image: {}", e))
+            ServiceError::from(e)
         })?
     };
 
+    let lqip = if wants_lqip {
+        lqip::generate_lqip(&processed_image_bytes).unwrap_or_else(|e| {
+            error!("Error generating LQIP: {}", e);
+            None
+        })
+    } else {
+        None
+    };
+
     let content_type = format_to_content_type(&output_format);
-    if !matches!(state.cache, ImgforgeCache::None) {
+    // Fallback images stand in for a broken source that may soon be fixed, so they are never cached
+    if !fallback && is_cacheable(state, source_cache_headers.as_ref()) {
         if let Err(err) = state
             .cache
             .insert(
-                path.to_string(),
+                cache_key.to_string(),
                 CachedImage {
                     bytes: processed_image_bytes.clone(),
                     content_type,
+                    lqip: lqip.clone(),
+                    source_cache_headers: source_cache_headers.clone(),
                 },
             )
             .await
@@ -259,22 +711,319 @@ pub async fn process_path(state: Arc<AppState>, request: ProcessRequest<'_>) ->
     );
 
     Ok(ProcessedImage {
+        status: StatusCode::OK,
         bytes: processed_image_bytes,
         content_type,
         cache_status: CacheStatus::Miss,
+        lqip,
+        processing_duration: Some(processing_duration),
+        vary_accept: false,
+        presets: Vec::new(),
+        data_uri: false,
+        cache_control: response_cache_control(config, fallback, source_cache_headers),
+        fallback,
+        preload_links: Vec::new(),
+        is_error_image: false,
+    })
+}
+
+/// Replaces a source that its origin could not serve, or that could not be decoded, with the
+/// configured fallback image.
+///
+/// Rejections by the source rules, size limits and the circuit breaker are kept, as the fallback
+/// would hide them. Raw passthrough never decodes its source and forced loaders fail on the
+/// request's choice, so neither gets the fallback for undecodable bytes. When the fallback itself
+/// cannot be loaded, the original error is kept.
+async fn substitute_fallback(
+    state: &AppState,
+    path: &str,
+    fallback_image: &str,
+    err: ServiceError,
+) -> Result<FetchedImage, ServiceError> {
+    if !err.source_unavailable {
+        return Err(err);
+    }
+
+    match load_fallback_image(state, fallback_image).await {
+        Ok(bytes) => {
+            warn!("Serving fallback image for path={} error={}", path, err.message());
+            let content_type = sniff_image_format(&bytes).map(|format| format_to_content_type(format).to_string());
+            Ok(FetchedImage {
+                bytes,
+                content_type,
+                cache_headers: SourceCacheHeaders::default(),
+            })
+        }
+        Err(e) => {
+            error!("Failed to load fallback image from {}: {}", fallback_image, e);
+            Err(err)
+        }
+    }
+}
+
+/// Returns the bytes of the fallback image, reading or fetching it only on first use.
+async fn load_fallback_image(state: &AppState, fallback_image: &str) -> Result<Bytes, String> {
+    if let Some(cached) = state.fallback_image_cache.lock().await.clone() {
+        return Ok(cached);
+    }
+
+    debug!("Loading fallback image from {} (cached on first load)", fallback_image);
+    let bytes = if fallback_image.starts_with("http://") || fallback_image.starts_with("https://") {
+        fetch_image(&state.http_client, fallback_image, None)
+            .await
+            .map(|(bytes, _)| bytes)
+            .map_err(|e| e.to_string())?
+    } else {
+        fs::read(fallback_image)
+            .await
+            .map(Bytes::from)
+            .map_err(|e| e.to_string())?
+    };
+    *state.fallback_image_cache.lock().await = Some(bytes.clone());
+    Ok(bytes)
+}
+
+/// Returns the Cache-Control sent with a response: the fallback image's short lifetime, or the
+/// origin's own when `IMGFORGE_UPSTREAM_CACHE_HEADERS` is set.
+fn response_cache_control(
+    config: &crate::config::Config,
+    fallback: bool,
+    source_cache_headers: Option<SourceCacheHeaders>,
+) -> Option<String> {
+    if fallback {
+        Some(format!("public, max-age={}", config.fallback_ttl))
+    } else {
+        source_cache_headers.and_then(|cache_headers| cache_headers.cache_control)
+    }
+}
+
+/// Returns whether a response may be stored in the processed image cache.
+///
+/// Origins answering with `Cache-Control: no-store` keep their images, and anything derived
+/// from them, out of the cache.
+fn is_cacheable(state: &AppState, source_cache_headers: Option<&SourceCacheHeaders>) -> bool {
+    !matches!(state.cache, ImgforgeCache::None) && !source_cache_headers.is_some_and(SourceCacheHeaders::is_no_store)
+}
+
+/// Returns whether a cached image's source is stale but still within the configured
+/// stale-while-revalidate window.
+fn is_stale_while_revalidate(config: &crate::config::Config, cached_image: &CachedImage) -> bool {
+    cached_image.source_cache_headers.as_ref().is_some_and(|cache_headers| {
+        cache_headers.is_stale() && cache_headers.is_within_stale_window(config.stale_while_revalidate)
+    })
+}
+
+/// Fetches and processes a request again on a background task, replacing its cached image.
+///
+/// Refreshes share the request coalescer, so a burst of requests for the same stale image
+/// triggers a single fetch. Failures leave the stale image in place until the window runs out.
+fn refresh_in_background(
+    state: &Arc<AppState>,
+    path: &str,
+    cache_key: &str,
+    decoded_url: &str,
+    parsed_options: ParsedOptions,
+) {
+    let state = Arc::clone(state);
+    let (path, cache_key, decoded_url) = (path.to_string(), cache_key.to_string(), decoded_url.to_string());
+    tokio::spawn(async move {
+        let _raw_permit = if parsed_options.raw {
+            match acquire_raw_permit(state.as_ref()).await {
+                Ok(permit) => permit,
+                Err(err) => {
+                    warn!("Background refresh failed for path={}: {}", path, err);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let refresh = fetch_and_process(&state, &path, &cache_key, &decoded_url, parsed_options);
+        if let Err(err) = state.coalescer.run(&cache_key, refresh).await {
+            warn!("Background refresh failed for path={}: {}", path, err);
+        }
+    });
+}
+
+/// Checks a cached image against its source, returning it when it may still be served.
+///
+/// Images whose source is still fresh are served as they are. Once the origin's `max-age` runs
+/// out, a conditional request asks whether the source changed: on `304 Not Modified` the image
+/// is served again with refreshed headers, and otherwise it is treated as a cache miss. If the
+/// origin cannot be reached the cached image is served rather than failing the request.
+async fn revalidate_cached_image(
+    state: &AppState,
+    url: &str,
+    cache_key: &str,
+    cached_image: CachedImage,
+) -> Option<CachedImage> {
+    let Some(cache_headers) = cached_image.source_cache_headers.as_ref() else {
+        return Some(cached_image);
+    };
+    if !cache_headers.is_stale() {
+        return Some(cached_image);
+    }
+    if !cache_headers.has_validators() {
+        debug!("Cached image for url={} is stale and cannot be revalidated", url);
+        return None;
+    }
+
+    let _host_permit = match &state.host_limiter {
+        Some(limiter) => limiter.acquire(url).await,
+        None => None,
+    };
+    match revalidate_source(&state.http_client, state.config.s3.as_ref(), url, cache_headers).await {
+        Ok(Some(refreshed)) => {
+            debug!("Source unchanged for url={}, serving cached image", url);
+            let revalidated = CachedImage {
+                source_cache_headers: Some(refreshed),
+                ..cached_image
+            };
+            if let Err(err) = state.cache.insert(cache_key.to_string(), revalidated.clone()).await {
+                error!("Failed to cache revalidated image: {}", err);
+            }
+            Some(revalidated)
+        }
+        Ok(None) => {
+            debug!("Source changed for url={}, processing it again", url);
+            None
+        }
+        Err(e) => {
+            warn!("Serving stale cached image for url={}: {}", url, e);
+            Some(cached_image)
+        }
+    }
+}
+
+/// Returns the entity tag of a response body, derived from its bytes.
+pub fn output_etag(bytes: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(&Sha256::digest(bytes)[..16]))
+}
+
+/// Returns whether an `If-None-Match` header value matches `etag`, comparing weakly.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Formats `format:auto` may pick, most efficient first.
+const NEGOTIABLE_FORMATS: [&str; 2] = ["avif", "webp"];
+/// Format `format:auto` falls back to when the client accepts nothing better.
+const NEGOTIATION_FALLBACK_FORMAT: &str = "jpeg";
+
+/// Picks the most efficient output format the client accepts and this build can encode.
+///
+/// Media types are matched exactly; wildcards do not count, as browsers send `*/*` along with the
+/// image types they really support. Types listed with `q=0` are refused.
+fn negotiate_format(accept: Option<&str>) -> &'static str {
+    let accepted: Vec<&str> = accept
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let media_type = parts.next()?;
+            let refused = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q <= 0.0)
+            });
+            (!refused).then_some(media_type)
+        })
+        .collect();
+
+    NEGOTIABLE_FORMATS
+        .into_iter()
+        .find(|format| {
+            accepted
+                .iter()
+                .any(|media_type| media_type.eq_ignore_ascii_case(&format!("image/{}", format)))
+                && save::is_format_supported(format)
+        })
+        .unwrap_or(NEGOTIATION_FALLBACK_FORMAT)
+}
+
+/// Returns the output size requested by `resize`, `width` or `height`, for sizing error images.
+/// A missing dimension follows the other one, so the image is square.
+fn error_image_dimensions(parsed_options: &ParsedOptions) -> (u32, u32) {
+    let (width, height) = parsed_options
+        .resize
+        .as_ref()
+        .map_or((0, 0), |resize| (resize.width, resize.height));
+    match (width, height) {
+        (0, 0) => (DEFAULT_ERROR_IMAGE_SIZE, DEFAULT_ERROR_IMAGE_SIZE),
+        (0, height) => (height, height),
+        (width, 0) => (width, width),
+        dimensions => dimensions,
+    }
+}
+
+/// Answers a failed request with an image showing the error message instead of a plain-text body.
+///
+/// Falls back to the original error when the image itself cannot be rendered.
+fn render_error_response(
+    err: ServiceError,
+    mode: ErrorImageMode,
+    width: u32,
+    height: u32,
+    format: Option<&str>,
+) -> Result<ProcessedImage, ServiceError> {
+    let (bytes, format) = match render_error_image(err.message(), width, height, format.unwrap_or("jpeg")) {
+        Ok(rendered) => rendered,
+        Err(render_err) => {
+            error!("Error rendering error image: {}", render_err);
+            return Err(err);
+        }
+    };
+
+    Ok(ProcessedImage {
+        status: match mode {
+            ErrorImageMode::KeepStatus => err.status(),
+            ErrorImageMode::Ok => StatusCode::OK,
+        },
+        bytes: Bytes::from(bytes),
+        content_type: format_to_content_type(&format),
+        cache_status: CacheStatus::Miss,
+        lqip: None,
+        processing_duration: None,
+        vary_accept: false,
+        presets: Vec::new(),
+        data_uri: false,
+        cache_control: None,
+        fallback: false,
+        preload_links: Vec::new(),
+        is_error_image: true,
     })
 }
 
 /// Retrieve metadata for an image without processing it.
+///
+/// Metadata is cached by source URL, so every path pointing at the same source shares one entry
+/// and repeated lookups skip the fetch and decode.
 pub async fn image_info(state: Arc<AppState>, request: ProcessRequest<'_>) -> Result<ImageInfo, ServiceError> {
     let config = &state.config;
     let path = request.path;
 
     debug!("Info path captured: {}", path);
     let url_parts = parse_and_authorize(config, path, request.bearer_token)?;
+    let include_source_metadata = url_parts
+        .processing_options
+        .iter()
+        .any(|option| option.name == INFO_METADATA_OPTION && option.args.first().is_some_and(|arg| parse_boolean(arg)));
+
+    let decoded_url = decode_source_url(config, &url_parts)?;
 
-    if let Some(cached_metadata) = state.metadata_cache.get(path).await {
-        debug!("Metadata found in cache for path={}", path);
+    // Entries without the ICC and EXIF details can't answer a request for them
+    let cached = state
+        .metadata_cache
+        .get(&decoded_url)
+        .await
+        .filter(|cached_metadata| !include_source_metadata || cached_metadata.source_metadata.is_some());
+    if let Some(cached_metadata) = cached {
+        debug!("Metadata found in cache for url={}", decoded_url);
         return Ok(ImageInfo {
             width: cached_metadata.width,
             height: cached_metadata.height,
@@ -284,28 +1033,27 @@ pub async fn image_info(state: Arc<AppState>, request: ProcessRequest<'_>) -> Re
             channels: cached_metadata.channels,
             has_alpha: cached_metadata.has_alpha,
             orientation: (cached_metadata.orientation != 0).then_some(cached_metadata.orientation),
+            animated: cached_metadata.animated,
+            source_metadata: cached_metadata.source_metadata.filter(|_| include_source_metadata),
         });
     }
 
-    let decoded_url = url_parts.source_url.decode().map_err(|e| {
-        error!("Error decoding URL: {}", e);
-        ServiceError::new(StatusCode::BAD_REQUEST, format!("Error decoding URL: {}", e))
-    })?;
+    let FetchedImage {
+        bytes: image_bytes,
+        content_type,
+        ..
+    } = fetch_source_image(state.as_ref(), &decoded_url, None).await?;
 
-    let _permit = state
+    // Decoding shares the worker cap with image processing
+    let permit = state
         .semaphore
         .clone()
         .acquire_owned()
         .await
         .map_err(|_| ServiceError::new(StatusCode::INTERNAL_SERVER_ERROR, "Semaphore closed"))?;
 
-    let (image_bytes, content_type) = fetch_image(&state.http_client, &decoded_url, None).await.map_err(|e| {
-        error!("Error fetching image: {}", e);
-        ServiceError::new(StatusCode::BAD_REQUEST, format!("Error fetching image: {}", e))
-    })?;
-
     let (width, height, image_format, channels, has_alpha, orientation, cacheable) =
-        match VipsImage::new_from_buffer(&image_bytes, "") {
+        match decode::load_image(&image_bytes, config.decode_fallback) {
             Ok(img) => {
                 let format_str = detect_image_format(content_type.as_deref(), &image_bytes);
                 let channels = img.get_bands() as u32;
@@ -325,6 +1073,9 @@ pub async fn image_info(state: Arc<AppState>, request: ProcessRequest<'_>) -> Re
             }
         };
 
+    let animated = cacheable && decode::is_animated(&image_bytes);
+    let source_metadata = include_source_metadata.then(|| read_source_metadata(&image_bytes));
+    drop(permit);
     let metadata = CachedMetadata {
         width,
         height,
@@ -334,17 +1085,19 @@ pub async fn image_info(state: Arc<AppState>, request: ProcessRequest<'_>) -> Re
         channels,
         has_alpha,
         orientation: orientation.unwrap_or(0),
+        animated,
+        source_metadata: source_metadata.clone(),
     };
 
     if cacheable && !matches!(state.metadata_cache, MetadataCache::None) {
-        if let Err(err) = state.metadata_cache.insert(path.to_string(), metadata).await {
+        if let Err(err) = state.metadata_cache.insert(decoded_url, metadata).await {
             error!("Failed to cache metadata: {}", err);
         }
     }
 
     info!(
-        "Imgforge info served path={} width={} height={} format={} size_bytes={} channels={} has_alpha={} orientation={:?}",
-        path, width, height, image_format, image_bytes.len(), channels, has_alpha, orientation
+        "Imgforge info served path={} width={} height={} format={} size_bytes={} channels={} has_alpha={} orientation={:?} animated={}",
+        path, width, height, image_format, image_bytes.len(), channels, has_alpha, orientation, animated
     );
 
     Ok(ImageInfo {
@@ -356,9 +1109,127 @@ pub async fn image_info(state: Arc<AppState>, request: ProcessRequest<'_>) -> Re
         channels,
         has_alpha,
         orientation,
+        animated,
+        source_metadata,
+    })
+}
+
+/// Builds the request paths of a responsive image set without fetching the source.
+///
+/// The path has the usual `<signature>/<options>/<source>` form, with a `widths:<w1>:<w2>:...`
+/// option listing the widths. Every image keeps the other options and adds `width:<w>`. Signed
+/// requests get signed paths back; `unsafe` requests, when allowed, get `unsafe` paths.
+pub fn srcset_manifest(
+    config: &crate::config::Config,
+    request: ProcessRequest<'_>,
+) -> Result<SrcsetManifest, ServiceError> {
+    let path = request.path;
+    let url_parts = parse_and_authorize(config, path, request.bearer_token)?;
+
+    let bad_request = |message: String| {
+        error!("Invalid srcset request path={}: {}", path, message);
+        ServiceError::new(StatusCode::BAD_REQUEST, message)
+    };
+    let mut widths = None;
+    for option in &url_parts.processing_options {
+        if option.name == SRCSET_WIDTHS_OPTION {
+            widths = Some(&option.args);
+        } else if SRCSET_SIZE_OPTIONS.contains(&option.name.as_str()) {
+            return Err(bad_request(format!(
+                "srcset widths replace the '{}' option; remove it from the path",
+                option.name
+            )));
+        }
+    }
+    let widths = widths.ok_or_else(|| bad_request("srcset requires a widths option".to_string()))?;
+    if widths.is_empty() || widths.len() > MAX_SRCSET_WIDTHS {
+        return Err(bad_request(format!(
+            "srcset requires between 1 and {} widths, received {}",
+            MAX_SRCSET_WIDTHS,
+            widths.len()
+        )));
+    }
+    let widths = widths
+        .iter()
+        .map(|width| {
+            width
+                .parse::<u32>()
+                .ok()
+                .filter(|width| *width > 0)
+                .ok_or_else(|| bad_request(format!("invalid srcset width '{}'", width)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let paths = width_variant_paths(config, path, &url_parts.signature, &widths);
+    Ok(SrcsetManifest {
+        images: widths
+            .into_iter()
+            .zip(paths)
+            .map(|(width, path)| SrcsetImage { width, path })
+            .collect(),
     })
 }
 
+/// Returns the request paths listed as `Link` preloads for a processed image: one for each
+/// configured breakpoint other than the width the request asked for.
+fn preload_links(
+    config: &crate::config::Config,
+    path: &str,
+    signature: &str,
+    parsed_options: &ParsedOptions,
+) -> Vec<String> {
+    let requested_width = parsed_options
+        .width
+        .or_else(|| parsed_options.resize.as_ref().map(|resize| resize.width));
+    let widths: Vec<u32> = config
+        .preload_breakpoints
+        .iter()
+        .copied()
+        .filter(|width| Some(*width) != requested_width)
+        .collect();
+    if widths.is_empty() {
+        return Vec::new();
+    }
+    width_variant_paths(config, path, signature, &widths)
+}
+
+/// Builds a request path for each width from `path`, with its size options replaced by
+/// `width:<w>`. Signed requests get signed paths back and `unsafe` requests `unsafe` paths.
+fn width_variant_paths(config: &crate::config::Config, path: &str, signature: &str, widths: &[u32]) -> Vec<String> {
+    // Rebuild each image path from the request's own segments, so the source is kept verbatim
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+    let source_start = segments
+        .iter()
+        .position(|segment| *segment == "plain" || !segment.contains(':'))
+        .unwrap_or(segments.len());
+    let options: Vec<&str> = segments[..source_start]
+        .iter()
+        .copied()
+        .filter(|segment| {
+            let name = segment.split(':').next().unwrap_or_default();
+            name != SRCSET_WIDTHS_OPTION && !SRCSET_SIZE_OPTIONS.contains(&name)
+        })
+        .collect();
+    let source = segments[source_start..].join("/");
+    let image_paths: Vec<String> = widths
+        .iter()
+        .map(|width| {
+            let width_option = format!("width:{}", width);
+            let mut segments = options.clone();
+            segments.push(&width_option);
+            segments.push(&source);
+            segments.join("/")
+        })
+        .collect();
+
+    let paths = if signature == "unsafe" {
+        image_paths.iter().map(|path| format!("unsafe/{}", path)).collect()
+    } else {
+        sign_paths(config, &image_paths)
+    };
+    paths.into_iter().map(|path| format!("/{}", path)).collect()
+}
+
 fn parse_and_authorize(
     config: &crate::config::Config,
     path: &str,
@@ -407,8 +1278,151 @@ fn parse_and_authorize(
     Ok(url_parts)
 }
 
-fn build_path_to_sign(path: &str) -> Option<String> {
-    path.find('/').map(|idx| format!("/{}", &path[idx + 1..]))
+fn decode_source_url(config: &crate::config::Config, url_parts: &ImgforgeUrl) -> Result<String, ServiceError> {
+    let decoded_url = url_parts.source_url.decode().map_err(|e| {
+        error!("Error decoding URL: {}", e);
+        ServiceError::new(StatusCode::BAD_REQUEST, e)
+    })?;
+
+    // Inline data needs no fetch, and local and S3 sources are read from places the server
+    // configures, so the remote URL checks do not apply. Inline data still counts toward the
+    // URL length limit, as it is decoded in memory.
+    if is_data_source(&decoded_url) {
+        check_source_url_length(&decoded_url).map_err(|e| {
+            error!("Rejected data URI source: {}", e);
+            ServiceError::new(StatusCode::BAD_REQUEST, e)
+        })?;
+        return Ok(decoded_url);
+    }
+    if local_source_path(&decoded_url).is_some() {
+        if config.local_root.is_none() {
+            error!("Rejected source URL {}: local sources are not enabled", decoded_url);
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                "Local sources are not enabled",
+            ));
+        }
+        return Ok(decoded_url);
+    }
+    if is_s3_source(&decoded_url) {
+        if config.s3.is_none() {
+            error!("Rejected source URL {}: S3 sources are not enabled", decoded_url);
+            return Err(ServiceError::new(StatusCode::BAD_REQUEST, "S3 sources are not enabled"));
+        }
+        return Ok(decoded_url);
+    }
+
+    validate_source_url(&decoded_url)
+        .and_then(|_| validate_source_url_target(&decoded_url, config))
+        .map_err(|e| {
+            error!("Rejected source URL {}: {}", decoded_url, e);
+            ServiceError::new(StatusCode::BAD_REQUEST, e)
+        })?;
+
+    Ok(decoded_url)
+}
+
+async fn fetch_source_image(
+    state: &AppState,
+    url: &str,
+    max_bytes: Option<usize>,
+) -> Result<FetchedImage, ServiceError> {
+    if is_data_source(url) {
+        debug!("Decoding inline data URI source");
+        let mut inline = decode_data_source(url, max_bytes).map_err(|e| {
+            error!("Error decoding data URI source: {}", e);
+            ServiceError::from_fetch_error(&e, "Error reading image")
+        })?;
+        if inline.content_type.is_none() {
+            inline.content_type =
+                sniff_image_format(&inline.bytes).map(|format| format_to_content_type(format).to_string());
+        }
+        return Ok(inline);
+    }
+
+    if let (Some(root), Some(path)) = (&state.config.local_root, local_source_path(url)) {
+        debug!("Reading local source path={}", path);
+        let mut local = read_local_image(Path::new(root), path, max_bytes).await.map_err(|e| {
+            error!("Error reading local image: {}", e);
+            ServiceError::from_fetch_error(&e, "Error reading image")
+        })?;
+        local.content_type = sniff_image_format(&local.bytes).map(|format| format_to_content_type(format).to_string());
+        return Ok(local);
+    }
+
+    // A cached source larger than this request's limit falls through to a fresh fetch, which
+    // applies the limit and reports the error exactly as an uncached request would. So does one
+    // the origin considers stale, so refreshed images are built from the current source.
+    if let Some(source) = state.source_cache.get(url) {
+        let stale = state.config.upstream_cache_headers && source.cache_headers.is_stale();
+        if !stale && max_bytes.is_none_or(|max| source.bytes.len() <= max) {
+            debug!("Serving source bytes from source cache for url={}", url);
+            return Ok(FetchedImage {
+                bytes: source.bytes,
+                content_type: source.content_type,
+                cache_headers: source.cache_headers,
+            });
+        }
+    }
+
+    // S3 sources go to the server's own endpoint; every other remote host is checked before the
+    // first byte is requested
+    if !is_s3_source(url) {
+        check_source_host(url, &state.config).await.map_err(|e| {
+            error!("Rejected source URL {}: {}", url, e);
+            ServiceError::new(StatusCode::FORBIDDEN, e)
+        })?;
+    }
+
+    if let Some(breaker) = &state.circuit_breaker {
+        breaker.check(url).map_err(|e| {
+            error!("Skipping fetch for url={}: {}", url, e);
+            ServiceError::new(StatusCode::SERVICE_UNAVAILABLE, e)
+        })?;
+    }
+
+    // Held until the body is read, so retries of this fetch keep their place in the host's queue
+    let _host_permit = match &state.host_limiter {
+        Some(limiter) => limiter.acquire(url).await,
+        None => None,
+    };
+    let fetched = fetch_image_with_retry(
+        &state.http_client,
+        state.config.s3.as_ref(),
+        url,
+        max_bytes,
+        state.circuit_breaker.as_ref(),
+        state.config.fetch_retries,
+    )
+    .await
+    .map_err(|e| {
+        error!("Error fetching image: {}", e);
+        ServiceError::from_fetch_error(&e, "Error fetching image")
+    })?;
+
+    if !(state.config.upstream_cache_headers && fetched.cache_headers.is_no_store()) {
+        state.source_cache.insert(
+            url.to_string(),
+            fetched.bytes.clone(),
+            fetched.content_type.clone(),
+            fetched.cache_headers.clone(),
+        );
+    }
+    Ok(fetched)
+}
+
+/// Maps a failed source fetch to a response status.
+///
+/// Missing sources stay `404`; any other upstream error status means the origin could not serve
+/// the image, which is a gateway failure from the client's point of view.
+fn fetch_error_status(error: &FetchError) -> StatusCode {
+    match error {
+        FetchError::Status(404 | 410) => StatusCode::NOT_FOUND,
+        FetchError::Status(_) => StatusCode::BAD_GATEWAY,
+        FetchError::Connect(_) | FetchError::Failed(_) => StatusCode::BAD_REQUEST,
+        FetchError::Forbidden(_) => StatusCode::FORBIDDEN,
+        FetchError::NotFound(_) => StatusCode::NOT_FOUND,
+    }
 }
 
 fn enforce_security_constraints(
@@ -416,7 +1430,7 @@ fn enforce_security_constraints(
     parsed_options: &ParsedOptions,
     image_bytes: &Bytes,
     source_content_type: Option<&str>,
-    decoded_image: Option<&VipsImage>,
+    decoded_frames: u32,
 ) -> Result<(), ServiceError> {
     let config = &state.config;
 
@@ -432,33 +1446,21 @@ fn enforce_security_constraints(
         }
     }
 
-    if let Some(allowed_types) = &config.allowed_mime_types {
-        if let Some(content_type) = source_content_type {
-            if !allowed_types.contains(&content_type.to_string()) {
-                error!("Source image MIME type is not allowed: {}", content_type);
-                return Err(ServiceError::new(
-                    StatusCode::BAD_REQUEST,
-                    "Source image MIME type is not allowed",
-                ));
-            }
-        }
-    }
+    check_allowed_mime_type(config, source_content_type)?;
 
     let max_src_resolution = resolve_max_src_resolution(config, parsed_options);
 
     if let Some(max_res) = max_src_resolution {
-        let (w, h) = match decoded_image {
-            Some(img) => (img.get_width(), img.get_height()),
-            None => {
-                error!("Failed to load image for resolution check");
-                return Err(ServiceError::new(
-                    StatusCode::BAD_REQUEST,
-                    "Failed to load image for resolution check",
-                ));
+        let (w, h) = decode::read_dimensions(image_bytes, config.decode_fallback).map_err(|e| {
+            error!("Failed to load image for resolution check: {}", e);
+            let message = "Failed to load image for resolution check";
+            match parsed_options.source_format {
+                Some(_) => ServiceError::new(StatusCode::BAD_REQUEST, message),
+                None => ServiceError::undecodable(StatusCode::BAD_REQUEST, message),
             }
-        };
-        debug!("Image resolution: {}x{}", w, h);
-        let res_mp = (w * h) as f32 / 1_000_000.0;
+        })?;
+        debug!("Image resolution: {}x{} x {} frames", w, h, decoded_frames);
+        let res_mp = (w as u64 * h as u64 * decoded_frames as u64) as f32 / 1_000_000.0;
         if res_mp > max_res {
             error!("Source image resolution is too large");
             return Err(ServiceError::new(
@@ -471,6 +1473,121 @@ fn enforce_security_constraints(
     Ok(())
 }
 
+/// Returns the smallest source size that renders the request without upscaling, when an
+/// embedded thumbnail may be used for it, in stored (unrotated) pixels.
+fn embedded_thumbnail_target(parsed_options: &ParsedOptions, image_bytes: &[u8]) -> Option<(u32, u32)> {
+    // A forced loader decodes the full source, so there is no thumbnail to stand in for it
+    if !parsed_options.use_embedded_thumbnail.unwrap_or(false) || parsed_options.source_format.is_some() {
+        return None;
+    }
+    stored_decode_target(parsed_options, image_bytes)
+}
+
+/// Returns the smallest source size a JPEG may be shrunk to on load, in stored (unrotated) pixels.
+///
+/// Pixel art is excluded, as the loader's DCT scaling would smooth the hard edges it keeps.
+fn shrink_on_load_target(parsed_options: &ParsedOptions, image_bytes: &[u8]) -> Option<(u32, u32)> {
+    if parsed_options.pixel_art {
+        return None;
+    }
+    stored_decode_target(parsed_options, image_bytes)
+}
+
+/// Returns `reduced_decode_target` in stored pixels, swapping the axes of sources whose EXIF
+/// orientation (5-8) is transposed by auto-rotation.
+fn stored_decode_target(parsed_options: &ParsedOptions, image_bytes: &[u8]) -> Option<(u32, u32)> {
+    let (width, height) = reduced_decode_target(parsed_options)?;
+    let swaps_axes = parsed_options.auto_rotate && read_exif_orientation(image_bytes).is_some_and(|o| o >= 5);
+    Some(if swaps_axes { (height, width) } else { (width, height) })
+}
+
+/// Returns the smallest source size that renders the request without upscaling, when a reduced
+/// decode can stand in for the full-size source.
+///
+/// Reduced decodes need a resize target and no crop or region, whose coordinates refer to the
+/// full-size source. Minimum dimensions, DPR and zoom enlarge the target accordingly.
+fn reduced_decode_target(parsed_options: &ParsedOptions) -> Option<(u32, u32)> {
+    if parsed_options.crop.is_some() || parsed_options.region.is_some() {
+        return None;
+    }
+    let resize = parsed_options.resize.as_ref()?;
+    // A `force` resize keeps the source size along a 0 dimension, so it must see the full source
+    if resize.resizing_type == "force" && (resize.width == 0 || resize.height == 0) {
+        return None;
+    }
+
+    let scale = parsed_options.dpr.unwrap_or(1.0).max(1.0) * parsed_options.zoom.unwrap_or(1.0).max(1.0);
+    let width = resize.width.max(parsed_options.min_width.unwrap_or(0));
+    let height = resize.height.max(parsed_options.min_height.unwrap_or(0));
+    if width == 0 && height == 0 {
+        return None;
+    }
+
+    Some((
+        (width as f32 * scale).ceil() as u32,
+        (height as f32 * scale).ceil() as u32,
+    ))
+}
+
+/// Validates the requested `page` against `max_tiff_pages` and the source's page count.
+///
+/// Returns the page to load, or `None` when no page was requested or the source is not a TIFF.
+fn resolve_tiff_page(
+    config: &crate::config::Config,
+    parsed_options: &ParsedOptions,
+    image_bytes: &Bytes,
+) -> Result<Option<u32>, ServiceError> {
+    let Some(page) = parsed_options.page else {
+        return Ok(None);
+    };
+    if !decode::is_tiff(image_bytes) {
+        debug!("Ignoring page {} for non-TIFF source", page);
+        return Ok(None);
+    }
+
+    if page >= config.max_tiff_pages {
+        error!("Requested TIFF page {} exceeds limit {}", page, config.max_tiff_pages);
+        return Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Requested page {} exceeds the maximum of {} TIFF pages",
+                page, config.max_tiff_pages
+            ),
+        ));
+    }
+
+    let pages = decode::page_count(image_bytes).map_err(|e| {
+        error!("Failed to read TIFF page count: {}", e);
+        ServiceError::new(StatusCode::BAD_REQUEST, "Failed to read TIFF page count")
+    })?;
+    if page >= pages {
+        error!("Requested TIFF page {} but source has {} pages", page, pages);
+        return Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Requested page {} but the source has {} pages", page, pages),
+        ));
+    }
+
+    Ok(Some(page))
+}
+
+/// Rejects sources whose declared content type is missing from `allowed_mime_types`.
+fn check_allowed_mime_type(
+    config: &crate::config::Config,
+    source_content_type: Option<&str>,
+) -> Result<(), ServiceError> {
+    if let (Some(allowed_types), Some(content_type)) = (&config.allowed_mime_types, source_content_type) {
+        if !allowed_types.contains(&content_type.to_string()) {
+            error!("Source image MIME type is not allowed: {}", content_type);
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                "Source image MIME type is not allowed",
+            ));
+        }
+    }
+    Ok(())
+}
+
 fn resolve_max_src_file_size(config: &crate::config::Config, parsed_options: &ParsedOptions) -> Option<usize> {
     if config.allow_security_options {
         parsed_options.max_src_file_size.or(config.max_src_file_size)
@@ -487,6 +1604,33 @@ fn resolve_max_src_resolution(config: &crate::config::Config, parsed_options: &P
     }
 }
 
+/// Fills request options left unset with the server-wide defaults from `Config`.
+fn apply_config_defaults(config: &crate::config::Config, parsed_options: &mut ParsedOptions) {
+    parsed_options.even_dimensions.get_or_insert(config.even_dimensions);
+    parsed_options
+        .strip_metadata
+        .get_or_insert(config.strip_metadata_default);
+    parsed_options.srgb.get_or_insert(config.force_srgb);
+    parsed_options
+        .use_embedded_thumbnail
+        .get_or_insert(config.use_embedded_thumbnail);
+    parsed_options.watermark_min_size = config.watermark_min_size;
+    parsed_options.max_blur_sigma = Some(config.max_blur_sigma);
+    parsed_options.max_bytes_format_ladder = config.max_bytes_format_ladder.clone();
+    parsed_options.quality_defaults = QualityDefaults {
+        per_format: config.format_quality.clone(),
+        global: config.default_quality,
+    };
+    // The quality search re-encodes several times, so it only runs when the server opts in.
+    if config.auto_quality {
+        parsed_options
+            .auto_quality_target
+            .get_or_insert(config.auto_quality_target);
+    } else {
+        parsed_options.auto_quality = false;
+    }
+}
+
 fn needs_watermark(parsed_options: &ParsedOptions) -> bool {
     parsed_options.watermark.is_some() || parsed_options.watermark_url.is_some()
 }
@@ -496,6 +1640,17 @@ async fn resolve_watermark(
     parsed_options: &ParsedOptions,
 ) -> Result<Option<CachedWatermark>, ServiceError> {
     if let Some(url) = &parsed_options.watermark_url {
+        // Watermark URLs come from the request, so they face the same checks as source URLs
+        validate_source_url(url)
+            .and_then(|_| validate_source_url_target(url, &state.config))
+            .map_err(|e| {
+                error!("Rejected watermark URL {}: {}", url, e);
+                ServiceError::new(StatusCode::BAD_REQUEST, e)
+            })?;
+        check_source_host(url, &state.config).await.map_err(|e| {
+            error!("Rejected watermark URL {}: {}", url, e);
+            ServiceError::new(StatusCode::FORBIDDEN, e)
+        })?;
         debug!("Fetching watermark from URL: {}", url);
         match fetch_image(&state.http_client, url, None).await {
             Ok((bytes, _)) => Ok(Some(CachedWatermark::from_bytes(bytes))),
@@ -540,18 +1695,32 @@ async fn resolve_watermark(
     }
 }
 
+async fn acquire_raw_permit(state: &AppState) -> Result<Option<OwnedSemaphorePermit>, ServiceError> {
+    match &state.raw_semaphore {
+        Some(semaphore) => semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map(Some)
+            .map_err(|_| ServiceError::new(StatusCode::INTERNAL_SERVER_ERROR, "Semaphore closed")),
+        None => Ok(None),
+    }
+}
+
 async fn serve_raw_response(
     state: &AppState,
     path: &str,
     image_bytes: Bytes,
     source_content_type: Option<String>,
+    source_cache_headers: Option<SourceCacheHeaders>,
+    fallback: bool,
 ) -> Result<ProcessedImage, ServiceError> {
     let content_type = source_content_type
         .as_deref()
         .map(format_to_content_type)
         .unwrap_or("image/jpeg");
 
-    if !matches!(state.cache, ImgforgeCache::None) {
+    if !fallback && is_cacheable(state, source_cache_headers.as_ref()) {
         if let Err(err) = state
             .cache
             .insert(
@@ -559,6 +1728,8 @@ async fn serve_raw_response(
                 CachedImage {
                     bytes: image_bytes.clone(),
                     content_type,
+                    lqip: None,
+                    source_cache_headers: source_cache_headers.clone(),
                 },
             )
             .await
@@ -570,8 +1741,18 @@ async fn serve_raw_response(
     info!("Imgforge served raw path={} bytes={}", path, image_bytes.len());
 
     Ok(ProcessedImage {
+        status: StatusCode::OK,
         bytes: image_bytes,
         content_type,
         cache_status: CacheStatus::Miss,
+        lqip: None,
+        processing_duration: None,
+        vary_accept: false,
+        presets: Vec::new(),
+        data_uri: false,
+        cache_control: response_cache_control(&state.config, fallback, source_cache_headers),
+        fallback,
+        preload_links: Vec::new(),
+        is_error_image: false,
     })
 }
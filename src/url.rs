@@ -1,8 +1,15 @@
-use crate::processing::options::ProcessingOption;
+use crate::config::Config;
+use crate::processing::options::{ProcessingOption, EXTENSION_FORMAT};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hmac::{Hmac, Mac};
 use percent_encoding::percent_decode_str;
 use sha2::Sha256;
+use tracing::debug;
+
+/// Error returned when a base64 source URL cannot be decoded.
+pub const INVALID_BASE64_SOURCE_URL: &str = "invalid base64 source URL";
+/// Error returned when a source URL decodes to bytes that are not UTF-8.
+pub const INVALID_UTF8_SOURCE_URL: &str = "source URL is not valid UTF-8";
 
 /// Information about the source URL, including its type and extension.
 #[derive(Debug)]
@@ -15,17 +22,30 @@ pub enum SourceUrlInfo {
 
 impl SourceUrlInfo {
     /// Decodes the source URL based on its type.
-    /// Returns the decoded URL as a String or an error message.
+    ///
+    /// Failures return one of the stable messages [`INVALID_BASE64_SOURCE_URL`] or
+    /// [`INVALID_UTF8_SOURCE_URL`], so clients can tell the two apart; details are only logged.
     pub fn decode(&self) -> Result<String, String> {
         match self {
-            SourceUrlInfo::Plain { url, .. } => percent_decode_str(url)
-                .decode_utf8()
-                .map(|s| s.to_string())
-                .map_err(|e| e.to_string()),
-            SourceUrlInfo::Base64 { encoded_url, .. } => URL_SAFE_NO_PAD
-                .decode(encoded_url)
-                .map_err(|e| e.to_string())
-                .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string())),
+            SourceUrlInfo::Plain { url, .. } => {
+                percent_decode_str(url)
+                    .decode_utf8()
+                    .map(|s| s.to_string())
+                    .map_err(|e| {
+                        debug!("Plain source URL is not UTF-8: {}", e);
+                        INVALID_UTF8_SOURCE_URL.to_string()
+                    })
+            }
+            SourceUrlInfo::Base64 { encoded_url, .. } => {
+                let bytes = URL_SAFE_NO_PAD.decode(encoded_url).map_err(|e| {
+                    debug!("Source URL is not valid base64: {}", e);
+                    INVALID_BASE64_SOURCE_URL.to_string()
+                })?;
+                String::from_utf8(bytes).map_err(|e| {
+                    debug!("Base64 source URL is not UTF-8: {}", e);
+                    INVALID_UTF8_SOURCE_URL.to_string()
+                })
+            }
         }
     }
 }
@@ -56,6 +76,71 @@ pub fn validate_signature(key: &[u8], salt: &[u8], signature: &str, path: &str)
     mac.verify_slice(&decoded_signature).is_ok()
 }
 
+/// Computes the URL-safe base64 HMAC-SHA256 signature of `path`, as checked by
+/// [`validate_signature`].
+pub fn sign_path(key: &[u8], salt: &[u8], path: &str) -> String {
+    type HmacSha256 = Hmac<Sha256>;
+
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(salt);
+    mac.update(path.as_bytes());
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+/// Returns the part of a request path covered by its signature: everything after the signature
+/// segment, with a leading slash.
+pub fn build_path_to_sign(path: &str) -> Option<String> {
+    path.find('/').map(|idx| format!("/{}", &path[idx + 1..]))
+}
+
+/// Paths sorted by whether their signature would be accepted, as returned by
+/// [`verify_signed_paths`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SignatureReport {
+    /// Paths the server would accept.
+    pub valid: Vec<String>,
+    /// Paths the server would reject as malformed, unsigned or wrongly signed.
+    pub invalid: Vec<String>,
+}
+
+/// Signs each path (`/<options>/<source>`, without a signature segment) with the configured key
+/// and salt, returning request paths in the form `<signature>/<options>/<source>`.
+pub fn sign_paths<S: AsRef<str>>(config: &Config, paths: &[S]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| {
+            let path = format!("/{}", path.as_ref().trim_start_matches('/'));
+            format!("{}{}", sign_path(&config.key, &config.salt, &path), path)
+        })
+        .collect()
+}
+
+/// Checks request paths (`<signature>/<options>/<source>`, a leading slash is optional) the way
+/// the server does, without fetching anything.
+///
+/// `unsafe` paths count as valid only when the config allows unsigned URLs.
+pub fn verify_signed_paths<S: AsRef<str>>(config: &Config, paths: &[S]) -> SignatureReport {
+    let mut report = SignatureReport::default();
+    for path in paths {
+        let path = path.as_ref();
+        let request_path = path.trim_start_matches('/');
+        let valid = match parse_path(request_path) {
+            Some(url) if url.signature == "unsafe" => config.allow_unsigned,
+            Some(url) => build_path_to_sign(request_path).is_some_and(|path_to_sign| {
+                validate_signature(&config.key, &config.salt, &url.signature, &path_to_sign)
+            }),
+            None => false,
+        };
+        if valid {
+            report.valid.push(path.to_string());
+        } else {
+            debug!("Signature check failed for path: {}", path);
+            report.invalid.push(path.to_string());
+        }
+    }
+    report
+}
+
 /// Parses the incoming URL path into its imgforge components.
 pub fn parse_path(path: &str) -> Option<ImgforgeUrl> {
     let parts: Vec<&str> = path.split('/').collect();
@@ -88,7 +173,7 @@ pub fn parse_path(path: &str) -> Option<ImgforgeUrl> {
 
     if let Some(ext) = extension {
         processing_options.push(ProcessingOption {
-            name: "format".to_string(),
+            name: EXTENSION_FORMAT.to_string(),
             args: vec![ext.clone()],
         });
     }
@@ -118,7 +203,9 @@ fn parse_source_url_path(parts: &[&str]) -> Option<(SourceUrlInfo, Option<String
         Some((SourceUrlInfo::Plain { url }, extension))
     } else {
         let path = parts.join("/");
-        let (encoded_url, extension) = match path.rsplit_once('.') {
+        // base64url never contains `@`, so it marks the extension unambiguously; `.` remains
+        // supported for existing URLs.
+        let (encoded_url, extension) = match path.rsplit_once('@').or_else(|| path.rsplit_once('.')) {
             Some((url, ext)) => (url.to_string(), Some(ext.to_string())),
             None => (path.to_string(), None),
         };
@@ -148,6 +235,27 @@ mod tests {
         assert_eq!(decoded, "https://example.com/image.jpg");
     }
 
+    #[test]
+    fn test_source_url_info_decode_rejects_malformed_base64() {
+        let source = SourceUrlInfo::Base64 {
+            encoded_url: "not*base64!".to_string(),
+        };
+        assert_eq!(source.decode().unwrap_err(), INVALID_BASE64_SOURCE_URL);
+    }
+
+    #[test]
+    fn test_source_url_info_decode_rejects_invalid_utf8() {
+        let source = SourceUrlInfo::Base64 {
+            encoded_url: URL_SAFE_NO_PAD.encode([0x68, 0x74, 0xff, 0xfe, 0x70]),
+        };
+        assert_eq!(source.decode().unwrap_err(), INVALID_UTF8_SOURCE_URL);
+
+        let plain = SourceUrlInfo::Plain {
+            url: "https%3A%2F%2Fexample.com%2F%FF.jpg".to_string(),
+        };
+        assert_eq!(plain.decode().unwrap_err(), INVALID_UTF8_SOURCE_URL);
+    }
+
     #[test]
     fn test_source_url_info_decode_base64() {
         let url = "https://example.com/image.jpg";
@@ -208,6 +316,66 @@ mod tests {
         assert!(!validate_signature(key, salt, &signature, wrong_path));
     }
 
+    #[test]
+    fn test_sign_path_matches_validate_signature() {
+        let path = "/resize:fill:300:200/plain/https://example.com/image.jpg";
+        let signature = sign_path(b"test_key", b"test_salt", path);
+        assert!(validate_signature(b"test_key", b"test_salt", &signature, path));
+        assert!(!validate_signature(b"other_key", b"test_salt", &signature, path));
+    }
+
+    #[test]
+    fn test_sign_paths_round_trips_through_verify() {
+        let config = Config::new(b"test_key".to_vec(), b"test_salt".to_vec());
+        let signed = sign_paths(
+            &config,
+            &[
+                "/resize:fit:100:100/plain/https://example.com/a.jpg",
+                "rotate:90/aHR0cHM6Ly9leGFtcGxlLmNvbS9iLnBuZw",
+            ],
+        );
+        assert!(signed[1].ends_with("/rotate:90/aHR0cHM6Ly9leGFtcGxlLmNvbS9iLnBuZw"));
+
+        let report = verify_signed_paths(&config, &signed);
+        assert_eq!(report.valid, signed);
+        assert!(report.invalid.is_empty());
+    }
+
+    #[test]
+    fn test_verify_signed_paths_sorts_valid_and_invalid() {
+        let config = Config::new(b"test_key".to_vec(), b"test_salt".to_vec());
+        let good = sign_paths(&config, &["/resize:fill:300:200/plain/https://example.com/image.jpg"]).remove(0);
+        let tampered = good.replace("300", "301");
+        let other_key = sign_paths(
+            &Config::new(b"other_key".to_vec(), b"test_salt".to_vec()),
+            &["/plain/https://example.com/image.jpg"],
+        )
+        .remove(0);
+        let paths = vec![
+            format!("/{}", good),
+            tampered.clone(),
+            other_key.clone(),
+            "not*base64/plain/https://example.com/image.jpg".to_string(),
+            "unsafe/plain/https://example.com/image.jpg".to_string(),
+            "sig".to_string(),
+        ];
+
+        let report = verify_signed_paths(&config, &paths);
+        assert_eq!(report.valid, vec![format!("/{}", good)]);
+        assert_eq!(report.invalid, paths[1..].to_vec());
+
+        let mut unsigned_config = config;
+        unsigned_config.allow_unsigned = true;
+        let report = verify_signed_paths(&unsigned_config, &paths);
+        assert_eq!(
+            report.valid,
+            vec![
+                format!("/{}", good),
+                "unsafe/plain/https://example.com/image.jpg".to_string()
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_path_with_resize_and_plain_url() {
         let path = "signature123/resize:fill:300:200/plain/https://example.com/image.jpg";
@@ -233,7 +401,7 @@ mod tests {
 
         assert_eq!(parsed.processing_options.len(), 2);
         assert_eq!(parsed.processing_options[0].name, "resize");
-        assert_eq!(parsed.processing_options[1].name, "format");
+        assert_eq!(parsed.processing_options[1].name, EXTENSION_FORMAT);
         assert_eq!(parsed.processing_options[1].args, vec!["webp"]);
     }
 
@@ -263,10 +431,41 @@ mod tests {
 
         assert_eq!(parsed.processing_options.len(), 2);
         assert_eq!(parsed.processing_options[0].name, "resize");
-        assert_eq!(parsed.processing_options[1].name, "format");
+        assert_eq!(parsed.processing_options[1].name, EXTENSION_FORMAT);
         assert_eq!(parsed.processing_options[1].args, vec!["webp"]);
     }
 
+    #[test]
+    fn test_parse_path_with_base64_url_and_at_extension() {
+        let url = "https://example.com/image.jpg";
+        let encoded = URL_SAFE_NO_PAD.encode(url.as_bytes());
+        let path = format!("sig/resize:fill:300:200/{}@webp", encoded);
+        let parsed = parse_path(&path).unwrap();
+
+        assert_eq!(parsed.processing_options.len(), 2);
+        assert_eq!(parsed.processing_options[1].name, EXTENSION_FORMAT);
+        assert_eq!(parsed.processing_options[1].args, vec!["webp"]);
+        match parsed.source_url {
+            SourceUrlInfo::Base64 { encoded_url } => assert_eq!(encoded_url, encoded),
+            _ => panic!("Expected Base64 source URL"),
+        }
+    }
+
+    #[test]
+    fn test_parse_path_with_base64_url_and_at_extension_split_across_segments() {
+        let encoded = URL_SAFE_NO_PAD.encode(b"https://example.com/a/very/long/path/image.png");
+        let (head, tail) = encoded.split_at(20);
+        let path = format!("sig/{}/{}@avif", head, tail);
+        let parsed = parse_path(&path).unwrap();
+
+        assert_eq!(parsed.processing_options.len(), 1);
+        assert_eq!(parsed.processing_options[0].args, vec!["avif"]);
+        match parsed.source_url {
+            SourceUrlInfo::Base64 { encoded_url } => assert_eq!(encoded_url, format!("{}/{}", head, tail)),
+            _ => panic!("Expected Base64 source URL"),
+        }
+    }
+
     #[test]
     fn test_parse_path_with_multiple_options() {
         let path = "sig/resize:fill:300:200/quality:90/blur:5/plain/https://example.com/image.jpg";
@@ -1,6 +1,22 @@
-use exif::{In, Tag};
+use exif::{Context, In, Tag, Value};
+use image::ImageDecoder;
 use std::io::Cursor;
 
+/// Descriptive metadata embedded in a source image, reported by `/info` on request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMetadata {
+    /// Whether the source embeds an ICC colour profile.
+    pub has_icc_profile: bool,
+    /// EXIF `DateTime`, as written by the camera (`YYYY:MM:DD HH:MM:SS`).
+    pub date_time: Option<String>,
+    /// EXIF camera manufacturer.
+    pub make: Option<String>,
+    /// EXIF camera model.
+    pub model: Option<String>,
+    /// Whether the EXIF data includes GPS coordinates. The coordinates themselves are not reported.
+    pub has_gps: bool,
+}
+
 pub fn format_to_content_type(format: &str) -> &'static str {
     match format {
         "png" | "image/png" => "image/png",
@@ -38,3 +54,39 @@ pub fn read_exif_orientation(image_bytes: &[u8]) -> Option<u32> {
                 .and_then(|field| field.value.get_uint(0))
         })
 }
+
+/// Reads the ICC profile presence and the camera EXIF tags of a source image.
+///
+/// Formats that carry neither, or that the decoders cannot read, yield an empty result.
+pub fn read_source_metadata(image_bytes: &[u8]) -> SourceMetadata {
+    let has_icc_profile = image::ImageReader::new(Cursor::new(image_bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.icc_profile().ok().flatten())
+        .is_some_and(|profile| !profile.is_empty());
+
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(image_bytes)) else {
+        return SourceMetadata {
+            has_icc_profile,
+            ..Default::default()
+        };
+    };
+    let ascii = |tag: Tag| match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Ascii(values)) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim_end_matches(['\0', ' ']).to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    };
+
+    let has_gps = exif.fields().any(|field| field.tag.context() == Context::Gps);
+
+    SourceMetadata {
+        has_icc_profile,
+        date_time: ascii(Tag::DateTime),
+        make: ascii(Tag::Make),
+        model: ascii(Tag::Model),
+        has_gps,
+    }
+}
@@ -0,0 +1,68 @@
+//! Source image builders shared by the integration tests.
+
+use image::ImageEncoder;
+use libvips::{ops, VipsImage};
+
+fn create_solid_rgb_image(color: [u8; 3]) -> VipsImage {
+    let img = image::RgbImage::from_pixel(20, 20, image::Rgb(color));
+    let mut bytes: Vec<u8> = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    VipsImage::new_from_buffer(&bytes, "").unwrap()
+}
+
+/// Two 20x20 pages: red first, blue second.
+pub fn create_two_page_tiff() -> Vec<u8> {
+    let stacked = ops::join(
+        &create_solid_rgb_image([255, 0, 0]),
+        &create_solid_rgb_image([0, 0, 255]),
+        ops::Direction::Vertical,
+    )
+    .unwrap();
+    let opts = ops::TiffsaveBufferOptions {
+        page_height: 20,
+        ..Default::default()
+    };
+    ops::tiffsave_buffer_with_opts(&stacked, &opts).unwrap()
+}
+
+/// 1600x1200 red JPEG with the given EXIF orientation, whose IFD1 carries a 160x120 green JPEG
+/// thumbnail.
+pub fn create_jpeg_with_exif_thumbnail(orientation: u16) -> Vec<u8> {
+    let encode = |width: u32, height: u32, color: [u8; 3], exif: Option<Vec<u8>>| {
+        let img = image::RgbImage::from_pixel(width, height, image::Rgb(color));
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 90);
+        if let Some(exif) = exif {
+            encoder.set_exif_metadata(exif).unwrap();
+        }
+        encoder
+            .write_image(img.as_raw(), width, height, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        bytes
+    };
+    let thumbnail = encode(160, 120, [0, 255, 0], None);
+
+    // Little-endian TIFF header; IFD0 holds the orientation and links to IFD1 at offset 26,
+    // whose two LONG entries point at the thumbnail appended at offset 56.
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&0x0112u16.to_le_bytes());
+    exif.extend_from_slice(&3u16.to_le_bytes());
+    exif.extend_from_slice(&1u32.to_le_bytes());
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0, 0]);
+    exif.extend_from_slice(&26u32.to_le_bytes());
+    exif.extend_from_slice(&2u16.to_le_bytes());
+    for (tag, value) in [(0x0201u16, 56u32), (0x0202, thumbnail.len() as u32)] {
+        exif.extend_from_slice(&tag.to_le_bytes());
+        exif.extend_from_slice(&4u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&value.to_le_bytes());
+    }
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    exif.extend_from_slice(&thumbnail);
+
+    encode(1600, 1200, [255, 0, 0], Some(exif))
+}
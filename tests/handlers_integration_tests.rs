@@ -6,14 +6,15 @@ use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use bytes::Bytes;
 use hmac::{Hmac, Mac};
 use http_body_util::BodyExt;
-use image::{ImageBuffer, Rgba};
-use imgforge::app::AppState;
-use imgforge::caching::cache::ImgforgeCache;
-use imgforge::config::Config;
-use imgforge::handlers::{image_forge_handler, info_handler, status_handler};
+use image::{ImageBuffer, ImageEncoder, Rgba};
+use imgforge::app::{AppState, Imgforge};
+use imgforge::caching::cache::{ImgforgeCache, MetadataCache};
+use imgforge::caching::config::CacheConfig;
+use imgforge::config::{Config, ErrorImageMode};
+use imgforge::handlers::{image_forge_handler, info_handler, srcset_handler, status_handler};
 use imgforge::middleware::request_id_middleware;
-use lazy_static::lazy_static;
-use libvips::VipsApp;
+use imgforge::server::build_router;
+use libvips::VipsImage;
 use serde_json::Value;
 use sha2::Sha256;
 use std::ffi::CString;
@@ -26,12 +27,10 @@ use wiremock::{
     Mock, MockServer, ResponseTemplate,
 };
 
-type HmacSha256 = Hmac<Sha256>;
+mod common;
+use common::{create_jpeg_with_exif_thumbnail, create_two_page_tiff};
 
-lazy_static! {
-    static ref VIPS_APP: Arc<VipsApp> =
-        Arc::new(VipsApp::new("imgforge-test", false).expect("Failed to initialize libvips"));
-}
+type HmacSha256 = Hmac<Sha256>;
 
 fn libvips_supports_format(format: &str) -> bool {
     let lower = format.to_lowercase();
@@ -91,13 +90,19 @@ fn create_test_config(key: Vec<u8>, salt: Vec<u8>, allow_unsigned: bool) -> Conf
     config.workers = 4;
     config.allow_unsigned = allow_unsigned;
     config.allow_security_options = true;
+    // The mock origins listen on loopback
+    config.allow_private_sources = true;
     config
 }
 
 /// Helper function to create test AppState
 async fn create_test_state(config: Config) -> Arc<AppState> {
+    create_test_state_with_metadata_cache(config, MetadataCache::None).await
+}
+
+/// Helper function to create test AppState with a specific metadata cache
+async fn create_test_state_with_metadata_cache(config: Config, metadata_cache: MetadataCache) -> Arc<AppState> {
     let cache = ImgforgeCache::None;
-    let metadata_cache = imgforge::caching::cache::MetadataCache::None;
     let http_client = reqwest::Client::builder()
         .timeout(Duration::from_secs(config.download_timeout))
         .build()
@@ -105,13 +110,22 @@ async fn create_test_state(config: Config) -> Arc<AppState> {
 
     Arc::new(AppState {
         semaphore: Arc::new(Semaphore::new(config.workers)),
+        raw_semaphore: None,
         cache,
         metadata_cache,
+        source_cache: imgforge::caching::cache::SourceCache::new(
+            config.source_cache_capacity,
+            Duration::from_secs(config.source_cache_ttl),
+        ),
         rate_limiter: None,
+        circuit_breaker: None,
+        host_limiter: None,
         config,
-        vips_app: VIPS_APP.clone(),
+        vips_app: Imgforge::init_vips().expect("Failed to initialize libvips"),
         http_client,
         watermark_cache: Mutex::new(None),
+        fallback_image_cache: Mutex::new(None),
+        coalescer: Default::default(),
     })
 }
 
@@ -145,6 +159,27 @@ async fn make_request_bytes(
     (status, body, headers)
 }
 
+async fn make_request_with_accept(app: axum::Router, uri: &str, accept: &str) -> (StatusCode, axum::http::HeaderMap) {
+    let request = Request::builder()
+        .uri(uri)
+        .header("Accept", accept)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    (response.status(), response.headers().clone())
+}
+
+#[test]
+fn test_init_vips_returns_shared_instance() {
+    let handles: Vec<_> = (0..4)
+        .map(|_| std::thread::spawn(|| Imgforge::init_vips().expect("Failed to initialize libvips")))
+        .collect();
+    let apps: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+    let again = Imgforge::init_vips().expect("Failed to initialize libvips twice");
+    assert!(apps.iter().all(|app| Arc::ptr_eq(app, &again)));
+}
+
 #[tokio::test]
 async fn test_status_handler_success() {
     let app = axum::Router::new()
@@ -192,19 +227,21 @@ async fn test_info_handler_with_unsigned_url() {
     let json: Value = serde_json::from_str(&body).unwrap();
     assert_eq!(json["width"], 400);
     assert_eq!(json["height"], 300);
-    assert_eq!(json["format"], "jpeg");
+    // The origin mislabels the PNG fixture; the format comes from the bytes
+    assert_eq!(json["format"], "png");
     assert_eq!(json["content_type"], "image/jpeg");
     assert_eq!(json["size_bytes"], test_image.len());
     assert_eq!(json["channels"], 4);
     assert_eq!(json["has_alpha"], true);
     assert!(json["orientation"].is_null());
+    assert_eq!(json["animated"], false);
     assert!(headers.contains_key("X-Request-ID"));
 }
 
 #[tokio::test]
 async fn test_info_handler_with_signed_url() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(200, 150, [0, 255, 0, 255]);
+    let test_image = create_test_jpeg_image(200, 150, [0, 255, 0]);
 
     Mock::given(method("GET"))
         .and(path("/signed.jpg"))
@@ -241,11 +278,285 @@ async fn test_info_handler_with_signed_url() {
     assert_eq!(json["format"], "jpeg");
     assert_eq!(json["content_type"], "image/jpeg");
     assert_eq!(json["size_bytes"], test_image.len());
-    assert_eq!(json["channels"], 4);
-    assert_eq!(json["has_alpha"], true);
+    assert_eq!(json["channels"], 3);
+    assert_eq!(json["has_alpha"], false);
     assert!(json["orientation"].is_null());
 }
 
+#[tokio::test]
+async fn test_info_handler_reports_animated_gif() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/animated.gif"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_animated_gif(24, 12, &[[255, 0, 0, 255], [0, 0, 255, 255]]))
+                .insert_header("Content-Type", "image/gif"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/info/{*path}", axum::routing::get(info_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/animated.gif", mock_server.uri());
+    let path = format!("/info/unsafe/{}", URL_SAFE_NO_PAD.encode(source_url.as_bytes()));
+    let (status, body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["format"], "gif");
+    assert_eq!(json["animated"], true);
+    assert_eq!((json["width"].as_u64(), json["height"].as_u64()), (Some(24), Some(12)));
+}
+
+/// 64x48 JPEG tagged as a Canon EOS 5D shot rotated 90° (orientation 6), with GPS data and an
+/// embedded ICC profile.
+fn create_jpeg_with_camera_exif() -> Vec<u8> {
+    // IFD0 entries in tag order as (tag, type, count, value); values over four bytes go after the
+    // entries. Type 2 is ASCII, 3 SHORT, 4 LONG and 1 BYTE.
+    let entries: [(u16, u16, Vec<u8>); 5] = [
+        (0x010F, 2, b"Canon\0".to_vec()),
+        (0x0110, 2, b"EOS 5D\0".to_vec()),
+        (0x0112, 3, 6u16.to_le_bytes().to_vec()),
+        (0x0132, 2, b"2024:05:01 12:30:00\0".to_vec()),
+        (0x8825, 4, Vec::new()),
+    ];
+    let data_start = 8 + 2 + entries.len() * 12 + 4;
+    let data_len: usize = entries
+        .iter()
+        .filter(|entry| entry.2.len() > 4)
+        .map(|entry| entry.2.len())
+        .sum();
+    let gps_offset = (data_start + data_len) as u32;
+
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut data = Vec::new();
+    for (tag, kind, value) in &entries {
+        exif.extend_from_slice(&tag.to_le_bytes());
+        exif.extend_from_slice(&kind.to_le_bytes());
+        let count = if *kind == 2 { value.len() as u32 } else { 1 };
+        exif.extend_from_slice(&count.to_le_bytes());
+        if *tag == 0x8825 {
+            exif.extend_from_slice(&gps_offset.to_le_bytes());
+        } else if value.len() > 4 {
+            exif.extend_from_slice(&((data_start + data.len()) as u32).to_le_bytes());
+            data.extend_from_slice(value);
+        } else {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            exif.extend_from_slice(&inline);
+        }
+    }
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    exif.extend_from_slice(&data);
+    // GPS IFD holding only GPSVersionID 2.3.0.0.
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&[0x00, 0x00, 1, 0, 4, 0, 0, 0, 2, 3, 0, 0]);
+    exif.extend_from_slice(&0u32.to_le_bytes());
+
+    let img = image::RgbImage::from_pixel(64, 48, image::Rgb([90, 120, 200]));
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 90);
+    encoder.set_exif_metadata(exif).unwrap();
+    encoder.set_icc_profile(b"test icc profile".to_vec()).unwrap();
+    encoder
+        .write_image(img.as_raw(), 64, 48, image::ExtendedColorType::Rgb8)
+        .unwrap();
+    bytes
+}
+
+#[tokio::test]
+async fn test_info_handler_reports_source_metadata_on_request() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/camera.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_jpeg_with_camera_exif())
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/info/{*path}", axum::routing::get(info_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let encoded_url = URL_SAFE_NO_PAD.encode(format!("{}/camera.jpg", mock_server.uri()).as_bytes());
+
+    let (status, body, _) = make_request(app.clone(), &format!("/info/unsafe/{}", encoded_url), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["orientation"], 6);
+    assert!(json.get("metadata").is_none());
+
+    let path = format!("/info/unsafe/metadata:true/{}", encoded_url);
+    let (status, body, _) = make_request(app, &path, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["orientation"], 6);
+    assert_eq!(json["metadata"]["make"], "Canon");
+    assert_eq!(json["metadata"]["model"], "EOS 5D");
+    assert_eq!(json["metadata"]["date_time"], "2024:05:01 12:30:00");
+    assert_eq!(json["metadata"]["has_gps"], true);
+    assert_eq!(json["metadata"]["has_icc_profile"], true);
+}
+
+#[tokio::test]
+async fn test_info_handler_caches_metadata_by_source_url() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/cached-info.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(30, 20, [0, 255, 0, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // Without a source cache, only the metadata cache can spare the second fetch
+    let mut config = create_test_config(vec![], vec![], true);
+    config.source_cache_capacity = None;
+    let metadata_cache = MetadataCache::new(Some(CacheConfig::Memory { capacity: 1024 * 1024 }))
+        .await
+        .unwrap();
+    let state = create_test_state_with_metadata_cache(config, metadata_cache).await;
+    let app = axum::Router::new()
+        .route("/info/{*path}", axum::routing::get(info_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/cached-info.png", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    // The second path differs but names the same source, so it is served from the same entry
+    for path in [
+        format!("/info/unsafe/{}", encoded_url),
+        format!("/info/unsafe/plain/{}", source_url),
+    ] {
+        let (status, body, _) = make_request(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::OK, "{}", path);
+        let json: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!((json["width"].as_u64(), json["height"].as_u64()), (Some(30), Some(20)));
+    }
+}
+
+#[tokio::test]
+async fn test_srcset_handler_returns_signed_paths_for_each_width() {
+    let key = b"test_key_123";
+    let salt = b"test_salt_456";
+    let config = create_test_config(key.to_vec(), salt.to_vec(), false);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/srcset/{*path}", axum::routing::get(srcset_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let encoded_url = URL_SAFE_NO_PAD.encode(b"https://example.com/photo.jpg");
+    let path_to_sign = format!("/quality:80/widths:320:640:1280/{}", encoded_url);
+    let signature = generate_signature(key, salt, &path_to_sign);
+    let (status, body, _) = make_request(app.clone(), &format!("/srcset/{}{}", signature, path_to_sign), None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let json: Value = serde_json::from_str(&body).unwrap();
+    let images = json["images"].as_array().unwrap();
+    assert_eq!(images.len(), 3);
+    let mut expected_srcset = Vec::new();
+    for (image, width) in images.iter().zip([320, 640, 1280]) {
+        let image_path = format!("/quality:80/width:{}/{}", width, encoded_url);
+        let expected = format!("/{}{}", generate_signature(key, salt, &image_path), image_path);
+        assert_eq!(image["width"], width);
+        assert_eq!(image["path"], expected.as_str());
+        expected_srcset.push(format!("{} {}w", expected, width));
+    }
+    assert_eq!(json["srcset"], expected_srcset.join(", ").as_str());
+
+    // The width list is bounded, required, and replaces any sizing options
+    let too_many = (1..=17)
+        .map(|width| (width * 100).to_string())
+        .collect::<Vec<_>>()
+        .join(":");
+    for options in [
+        format!("widths:{}", too_many),
+        "quality:80".to_string(),
+        "resize:fit:100:100/widths:320".to_string(),
+    ] {
+        let path_to_sign = format!("/{}/{}", options, encoded_url);
+        let signature = generate_signature(key, salt, &path_to_sign);
+        let (status, _, _) = make_request(app.clone(), &format!("/srcset/{}{}", signature, path_to_sign), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", options);
+    }
+
+    // Manifests are authorized like image requests
+    let (status, _, _) = make_request(app, &format!("/srcset/unsafe/widths:320/{}", encoded_url), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_links_signed_breakpoints_for_preloading() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/photo.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(1500, 1000, [40, 80, 120, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let key = b"test_key_123";
+    let salt = b"test_salt_456";
+    let mut config = create_test_config(key.to_vec(), salt.to_vec(), false);
+    config.preload_breakpoints = vec![320, 640, 1280];
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let encoded_url = URL_SAFE_NO_PAD.encode(format!("{}/photo.png", mock_server.uri()));
+    let path_to_sign = format!("/quality:80/resize:fit:640:0/{}", encoded_url);
+    let signature = generate_signature(key, salt, &path_to_sign);
+    let (status, _, headers) = make_request_bytes(app.clone(), &format!("/{}{}", signature, path_to_sign), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The requested width is left out and the size option is replaced by each breakpoint's width
+    let expected_paths: Vec<String> = [320, 1280]
+        .iter()
+        .map(|width| {
+            let image_path = format!("/quality:80/width:{}/{}", width, encoded_url);
+            format!("/{}{}", generate_signature(key, salt, &image_path), image_path)
+        })
+        .collect();
+    let expected_link = expected_paths
+        .iter()
+        .map(|path| format!("<{}>; rel=preload; as=image", path))
+        .collect::<Vec<_>>()
+        .join(", ");
+    assert_eq!(
+        headers.get("link").and_then(|value| value.to_str().ok()),
+        Some(expected_link.as_str())
+    );
+
+    // The linked paths are accepted as they are
+    let (status, body, _) = make_request_bytes(app, &expected_paths[0], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(VipsImage::new_from_buffer(&body, "").unwrap().get_width(), 320);
+}
+
 #[tokio::test]
 async fn test_info_handler_detects_format_without_content_type_header() {
     let mock_server = MockServer::start().await;
@@ -283,6 +594,38 @@ async fn test_info_handler_detects_format_without_content_type_header() {
     assert!(json["orientation"].is_null());
 }
 
+#[tokio::test]
+async fn test_info_handler_reports_source_content_type() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(32, 16, [10, 20, 30, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/declared.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/info/{*path}", axum::routing::get(info_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/declared.png", mock_server.uri());
+    let path = format!("/info/unsafe/{}", URL_SAFE_NO_PAD.encode(source_url.as_bytes()));
+    let (status, body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["content_type"], "image/png");
+    assert_eq!(json["format"], "png");
+    assert_eq!((json["width"].as_u64(), json["height"].as_u64()), (Some(32), Some(16)));
+}
+
 #[tokio::test]
 async fn test_info_handler_invalid_signature() {
     let mock_server = MockServer::start().await;
@@ -440,6 +783,52 @@ async fn test_info_handler_fetch_error() {
     assert!(body.contains("Error fetching image"));
 }
 
+#[tokio::test]
+async fn test_image_forge_handler_rejects_ftp_source_url() {
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+
+    let source_url = "ftp://example.com/test.png";
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path = format!("/unsafe/resize:fit:100:100/{}", encoded_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("Unsupported source URL scheme: ftp"));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_rejects_self_referential_source_url() {
+    let mut config = create_test_config(vec![], vec![], true);
+    config.bind_address = "0.0.0.0:3000".to_string();
+    config.self_hosts = vec!["img.example.com".to_string()];
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    for source_url in [
+        "http://127.0.0.1:3000/unsafe/plain/https://example.com/test.png",
+        "https://img.example.com/unsafe/plain/https://example.com/test.png",
+    ] {
+        let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+        let path = format!("/unsafe/resize:fit:100:100/{}", encoded_url);
+
+        let (status, body, _) = make_request(app.clone(), &path, None).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("Source URL points back at this imgforge instance"));
+    }
+}
+
 #[tokio::test]
 async fn test_image_forge_handler_unsigned_url() {
     let mock_server = MockServer::start().await;
@@ -570,48 +959,68 @@ async fn test_image_forge_handler_with_blur() {
 }
 
 #[tokio::test]
-async fn test_image_forge_handler_raw_option() {
+async fn test_image_forge_handler_clamps_extreme_blur() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(100, 100, [255, 255, 255, 255]);
+    // A hard edge, so blurs of different strength give different pixels
+    let test_image = ImageBuffer::from_fn(200, 200, |x, _| {
+        if x < 100 {
+            Rgba([0u8, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    });
+    let mut bytes = Vec::new();
+    test_image
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
 
     Mock::given(method("GET"))
-        .and(path("/raw.jpg"))
+        .and(path("/huge-blur.png"))
         .respond_with(
             ResponseTemplate::new(200)
-                .set_body_bytes(test_image.clone())
-                .insert_header("Content-Type", "image/jpeg"),
+                .set_body_bytes(bytes)
+                .insert_header("Content-Type", "image/png"),
         )
         .mount(&mock_server)
         .await;
 
-    let config = create_test_config(vec![], vec![], true);
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_blur_sigma = 5.0;
     let state = create_test_state(config).await;
 
-    let source_url = format!("{}/raw.jpg", mock_server.uri());
-    let path = format!("/unsafe/raw:/plain/{}", source_url);
-
+    let source_url = format!("{}/huge-blur.png", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
     let app = axum::Router::new()
         .route("/{*path}", axum::routing::get(image_forge_handler))
         .with_state(state)
         .layer(axum::middleware::from_fn(request_id_middleware));
 
-    let (status, body, headers) = make_request_bytes(app, &path, None).await;
-
+    let (status, clamped, _) = make_request_bytes(
+        app.clone(),
+        &format!("/unsafe/blur:1000/format:png/{}", encoded_url),
+        None,
+    )
+    .await;
     assert_eq!(status, StatusCode::OK);
-    assert_eq!(body.as_ref(), test_image.as_slice());
-    assert_eq!(
-        headers.get("content-type").and_then(|value| value.to_str().ok()),
-        Some("image/jpeg")
-    );
+    let (status, at_max, _) =
+        make_request_bytes(app.clone(), &format!("/unsafe/blur:5/format:png/{}", encoded_url), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, weaker, _) =
+        make_request_bytes(app, &format!("/unsafe/blur:2/format:png/{}", encoded_url), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The extreme sigma renders exactly like the cap, which differs from a weaker blur
+    assert_eq!(clamped, at_max);
+    assert_ne!(clamped, weaker);
 }
 
 #[tokio::test]
-async fn test_image_forge_handler_invalid_processing_option() {
+async fn test_image_forge_handler_with_label() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(100, 100, [0, 0, 0, 255]);
+    let test_image = create_test_image(200, 150, [0, 0, 255, 255]);
 
     Mock::given(method("GET"))
-        .and(path("/invalid.jpg"))
+        .and(path("/label.jpg"))
         .respond_with(
             ResponseTemplate::new(200)
                 .set_body_bytes(test_image)
@@ -623,83 +1032,387 @@ async fn test_image_forge_handler_invalid_processing_option() {
     let config = create_test_config(vec![], vec![], true);
     let state = create_test_state(config).await;
 
-    let source_url = format!("{}/invalid.jpg", mock_server.uri());
+    let source_url = format!("{}/label.jpg", mock_server.uri());
     let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
-    let path = format!("/unsafe/resize:fit:abc:def/{}", encoded_url);
+    let encoded_text = URL_SAFE_NO_PAD.encode("\u{a9} 2025");
+    let path = format!("/unsafe/label:{}:24:ffffff:south_east/{}", encoded_text, encoded_url);
 
     let app = axum::Router::new()
         .route("/{*path}", axum::routing::get(image_forge_handler))
         .with_state(state)
         .layer(axum::middleware::from_fn(request_id_middleware));
 
-    let (status, _body, _) = make_request(app, &path, None).await;
+    let (status, body, _) = make_request_bytes(app, &path, None).await;
 
-    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(status, StatusCode::OK);
+    let output = image::load_from_memory(&body).unwrap();
+    assert_eq!((output.width(), output.height()), (200, 150));
 }
 
 #[tokio::test]
-async fn test_image_forge_handler_max_file_size_exceeded() {
+async fn test_image_forge_handler_encode_datauri_returns_json() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(400, 400, [255, 0, 255, 255]);
+    let test_image = create_test_image(200, 150, [0, 128, 255, 255]);
 
     Mock::given(method("GET"))
-        .and(path("/large.jpg"))
+        .and(path("/inline.png"))
         .respond_with(
             ResponseTemplate::new(200)
-                .set_body_bytes(test_image.clone())
-                .insert_header("Content-Type", "image/jpeg"),
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
         )
         .mount(&mock_server)
         .await;
 
-    let mut config = create_test_config(vec![], vec![], true);
-    config.max_src_file_size = Some(100);
+    let config = create_test_config(vec![], vec![], true);
     let state = create_test_state(config).await;
 
-    let source_url = format!("{}/large.jpg", mock_server.uri());
-    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
-    let path = format!("/unsafe/{}", encoded_url);
+    let source_url = format!("{}/inline.png", mock_server.uri());
+    let path = format!(
+        "/unsafe/resize:fit:40:30/format:png/encode:datauri/plain/{}",
+        source_url
+    );
 
     let app = axum::Router::new()
         .route("/{*path}", axum::routing::get(image_forge_handler))
         .with_state(state)
         .layer(axum::middleware::from_fn(request_id_middleware));
 
-    let (status, body, _) = make_request(app, &path, None).await;
+    let (status, body, headers) = make_request_bytes(app, &path, None).await;
 
-    assert_eq!(status, StatusCode::BAD_REQUEST);
-    assert!(body.contains("Source image exceeds the maximum allowed size of"));
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers.get("content-type").unwrap(), "application/json");
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["width"], 40);
+    assert_eq!(json["height"], 30);
+    let data_uri = json["data_uri"].as_str().unwrap();
+    let encoded = data_uri.strip_prefix("data:image/png;base64,").unwrap();
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+    let output = image::load_from_memory(&decoded).unwrap();
+    assert_eq!((output.width(), output.height()), (40, 30));
 }
 
 #[tokio::test]
-async fn test_image_forge_handler_max_resolution_exceeded() {
+async fn test_image_forge_handler_fill_with_smart_gravity() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(2000, 2000, [100, 100, 100, 255]);
+    let test_image = create_test_image(300, 200, [200, 80, 40, 255]);
 
     Mock::given(method("GET"))
-        .and(path("/highres.jpg"))
+        .and(path("/smart.png"))
         .respond_with(
             ResponseTemplate::new(200)
                 .set_body_bytes(test_image)
-                .insert_header("Content-Type", "image/jpeg"),
+                .insert_header("Content-Type", "image/png"),
         )
         .mount(&mock_server)
         .await;
 
-    let mut config = create_test_config(vec![], vec![], true);
-    config.max_src_resolution = Some(1.0);
+    let config = create_test_config(vec![], vec![], true);
     let state = create_test_state(config).await;
 
-    let source_url = format!("{}/highres.jpg", mock_server.uri());
-    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
-    let path = format!("/unsafe/{}", encoded_url);
+    let source_url = format!("{}/smart.png", mock_server.uri());
+    let path = format!("/unsafe/resize:fill:100:80/gravity:sm/format:png/plain/{}", source_url);
 
     let app = axum::Router::new()
         .route("/{*path}", axum::routing::get(image_forge_handler))
         .with_state(state)
         .layer(axum::middleware::from_fn(request_id_middleware));
 
-    let (status, body, _) = make_request(app, &path, None).await;
+    let (status, body, _) = make_request_bytes(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let output = image::load_from_memory(&body).unwrap();
+    assert_eq!((output.width(), output.height()), (100, 80));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_raw_option() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [255, 255, 255, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/raw.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image.clone())
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+
+    let source_url = format!("{}/raw.jpg", mock_server.uri());
+    let path = format!("/unsafe/raw:/plain/{}", source_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, body, headers) = make_request_bytes(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_ref(), test_image.as_slice());
+    assert_eq!(
+        headers.get("content-type").and_then(|value| value.to_str().ok()),
+        Some("image/jpeg")
+    );
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_raw_limits_enforced_or_bypassed() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [10, 20, 30, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/raw-limits.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image.clone())
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let path = format!("/unsafe/raw:1/plain/{}/raw-limits.png", mock_server.uri());
+    for (enforce_limits, max_src_file_size, allowed_mime_types, expected) in [
+        (true, Some(64), None, StatusCode::BAD_REQUEST),
+        (
+            true,
+            None,
+            Some(vec!["image/jpeg".to_string()]),
+            StatusCode::BAD_REQUEST,
+        ),
+        (false, Some(64), Some(vec!["image/jpeg".to_string()]), StatusCode::OK),
+    ] {
+        let mut config = create_test_config(vec![], vec![], true);
+        config.raw_enforce_limits = enforce_limits;
+        config.max_src_file_size = max_src_file_size;
+        config.allowed_mime_types = allowed_mime_types;
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(create_test_state(config).await)
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        let (status, body, _) = make_request_bytes(app, &path, None).await;
+
+        assert_eq!(status, expected, "raw_enforce_limits={}", enforce_limits);
+        if expected == StatusCode::OK {
+            assert_eq!(body.as_ref(), test_image.as_slice());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_source_format_forces_loader() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [10, 20, 30, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/forced.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    for (source_format, expected) in [("png", StatusCode::OK), ("jpeg", StatusCode::UNPROCESSABLE_ENTITY)] {
+        let config = create_test_config(vec![], vec![], true);
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(create_test_state(config).await)
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        let path = format!(
+            "/unsafe/sf:{}/resize:fit:50:50/format:png/plain/{}/forced.png",
+            source_format,
+            mock_server.uri()
+        );
+        let (status, body, _) = make_request_bytes(app, &path, None).await;
+
+        assert_eq!(status, expected, "source_format={}", source_format);
+        if expected == StatusCode::OK {
+            let decoded = image::load_from_memory(&body).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (50, 50));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_lqip_header() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(400, 300, [0, 128, 255, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/lqip.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+
+    let source_url = format!("{}/lqip.png", mock_server.uri());
+    let path = format!("/unsafe/resize:fit:200:150/lqip:1/plain/{}@jpg", source_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, body, headers) = make_request_bytes(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let full = image::load_from_memory(&body).unwrap();
+    assert_eq!((full.width(), full.height()), (200, 150));
+
+    let lqip = headers
+        .get("X-Imgforge-LQIP")
+        .and_then(|value| value.to_str().ok())
+        .expect("LQIP header present");
+    let encoded = lqip
+        .strip_prefix("data:image/jpeg;base64,")
+        .expect("LQIP is a JPEG data URI");
+    let preview_bytes = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+    let preview = image::load_from_memory(&preview_bytes).unwrap();
+    assert_eq!(preview.width(), 16);
+    assert!(preview_bytes.len() < body.len());
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_invalid_processing_option() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [0, 0, 0, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/invalid.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+
+    let source_url = format!("{}/invalid.jpg", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path = format!("/unsafe/resize:fit:abc:def/{}", encoded_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, _body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_max_file_size_exceeded() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(400, 400, [255, 0, 255, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/large.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image.clone())
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_src_file_size = Some(100);
+    let state = create_test_state(config).await;
+
+    let source_url = format!("{}/large.jpg", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path = format!("/unsafe/{}", encoded_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("Source image exceeds the maximum allowed size of"));
+}
+
+/// Builds a PNG whose header declares `width`x`height` but which carries no pixel data, so only
+/// a header read can succeed on it; any attempt at a full decode fails.
+fn create_header_only_png(width: u32, height: u32) -> Vec<u8> {
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = 0xffff_ffffu32;
+        for byte in bytes {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    let mut ihdr = b"IHDR".to_vec();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // 8-bit RGBA, deflate, adaptive filtering, no interlace.
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend_from_slice(&13u32.to_be_bytes());
+    png.extend_from_slice(&ihdr);
+    png.extend_from_slice(&crc32(&ihdr).to_be_bytes());
+    png
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_max_resolution_exceeded() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(2000, 2000, [100, 100, 100, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/highres.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_src_resolution = Some(1.0);
+    let state = create_test_state(config).await;
+
+    let source_url = format!("{}/highres.jpg", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path = format!("/unsafe/{}", encoded_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, body, _) = make_request(app, &path, None).await;
 
     assert_eq!(status, StatusCode::BAD_REQUEST);
     assert!(body.contains("Source image resolution is too large"));
@@ -871,6 +1584,57 @@ async fn test_image_forge_handler_with_crop() {
     assert_eq!(status, StatusCode::OK);
 }
 
+#[tokio::test]
+async fn test_image_forge_handler_with_region() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/map.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(512, 512, [20, 140, 60, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/map.png", mock_server.uri());
+    let (status, body, _) = make_request_bytes(
+        app.clone(),
+        &format!(
+            "/unsafe/region:256:256:256:256/resize:fit:128:128/format:png/plain/{}",
+            source_url
+        ),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let output = image::load_from_memory(&body).unwrap();
+    assert_eq!((output.width(), output.height()), (128, 128));
+
+    let (status, body, _) = make_request(
+        app,
+        &format!(
+            "/unsafe/region:384:256:256:256/resize:fit:128:128/format:png/plain/{}",
+            source_url
+        ),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(
+        body.contains("region 256x256 at (384, 256) lies outside the 512x512 source"),
+        "{}",
+        body
+    );
+}
+
 #[tokio::test]
 async fn test_image_forge_handler_with_rotation() {
     let mock_server = MockServer::start().await;
@@ -935,6 +1699,40 @@ async fn test_image_forge_handler_with_dpr() {
     assert_eq!(status, StatusCode::OK);
 }
 
+#[tokio::test]
+async fn test_image_forge_handler_css_size_scales_by_dpr() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/css-size.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(400, 300, [40, 160, 90, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+
+    let source_url = format!("{}/css-size.png", mock_server.uri());
+    for (options, expected) in [
+        ("css_size:100:75/dpr:1", (100, 75)),
+        ("css_size:100:75/dpr:2", (200, 150)),
+        ("dpr:2/css_size:100:75", (200, 150)),
+        ("cs:100:75:2", (200, 150)),
+    ] {
+        let path = format!("/unsafe/{}/plain/{}@png", options, source_url);
+        let (status, body, _) = make_request_bytes(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::OK, "{}", options);
+        let img = image::load_from_memory(&body).unwrap();
+        assert_eq!((img.width(), img.height()), expected, "{}", options);
+    }
+}
+
 #[tokio::test]
 async fn test_image_forge_handler_plain_url() {
     let mock_server = MockServer::start().await;
@@ -998,47 +1796,150 @@ async fn test_image_forge_handler_with_extension() {
     if libvips_supports_format("webp") {
         assert_eq!(status, StatusCode::OK);
     } else {
-        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
         assert!(body.contains("not supported"));
     }
 }
 
 #[tokio::test]
-async fn test_image_forge_handler_with_sharpen() {
+async fn test_image_forge_handler_encodes_avif() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(200, 200, [100, 150, 200, 255]);
+    let test_image = create_test_image(64, 48, [100, 150, 200, 255]);
 
     Mock::given(method("GET"))
-        .and(path("/sharpen.jpg"))
+        .and(path("/avif.png"))
         .respond_with(
             ResponseTemplate::new(200)
                 .set_body_bytes(test_image)
-                .insert_header("Content-Type", "image/jpeg"),
+                .insert_header("Content-Type", "image/png"),
         )
         .mount(&mock_server)
         .await;
 
     let config = create_test_config(vec![], vec![], true);
     let state = create_test_state(config).await;
-
-    let source_url = format!("{}/sharpen.jpg", mock_server.uri());
-    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
-    let path = format!("/unsafe/sharpen:0.5/{}", encoded_url);
-
     let app = axum::Router::new()
         .route("/{*path}", axum::routing::get(image_forge_handler))
         .with_state(state)
         .layer(axum::middleware::from_fn(request_id_middleware));
 
-    let (status, _body, _) = make_request(app, &path, None).await;
-
+    let source_url = format!("{}/avif.png", mock_server.uri());
+    let (status, body, headers) = make_request_bytes(
+        app,
+        &format!("/unsafe/format:avif/quality:60/plain/{}", source_url),
+        None,
+    )
+    .await;
+
+    if !libvips_supports_format("avif") {
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(String::from_utf8_lossy(&body).contains("not supported"));
+        return;
+    }
     assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers.get("content-type").and_then(|value| value.to_str().ok()),
+        Some("image/avif")
+    );
+    assert!(!body.is_empty());
+    // ISO-BMFF container: a `ftyp` box whose major brand is `avif`.
+    assert_eq!(&body[4..8], b"ftyp");
+    assert_eq!(&body[8..12], b"avif");
 }
 
 #[tokio::test]
-async fn test_image_forge_handler_with_padding() {
+async fn test_image_forge_handler_negotiates_auto_format_from_accept() {
     let mock_server = MockServer::start().await;
-    let test_image = create_test_image(100, 100, [255, 128, 64, 255]);
+    let test_image = create_test_image(64, 48, [100, 150, 200, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/auto.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let uri = format!("/unsafe/format:auto/plain/{}/auto.png", mock_server.uri());
+    let webp = if libvips_supports_format("webp") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    };
+    let avif = if libvips_supports_format("avif") {
+        "image/avif"
+    } else {
+        webp
+    };
+    // The same path is requested repeatedly against one state, so cached variants must not leak
+    let cases = [
+        ("image/webp,*/*", webp),
+        ("*/*", "image/jpeg"),
+        ("image/avif,image/webp,*/*", avif),
+        ("image/avif;q=0,image/webp", webp),
+        ("image/webp,*/*", webp),
+    ];
+    for (accept, expected) in cases {
+        let (status, headers) = make_request_with_accept(app.clone(), &uri, accept).await;
+        assert_eq!(status, StatusCode::OK, "Accept: {}", accept);
+        assert_eq!(
+            headers.get("content-type").and_then(|value| value.to_str().ok()),
+            Some(expected),
+            "Accept: {}",
+            accept
+        );
+        assert_eq!(
+            headers.get("vary").and_then(|value| value.to_str().ok()),
+            Some("Accept")
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_with_sharpen() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(200, 200, [100, 150, 200, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/sharpen.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+
+    let source_url = format!("{}/sharpen.jpg", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path = format!("/unsafe/sharpen:0.5/{}", encoded_url);
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let (status, _body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_with_padding() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [255, 128, 64, 255]);
 
     Mock::given(method("GET"))
         .and(path("/padding.jpg"))
@@ -1133,3 +2034,1132 @@ async fn test_image_forge_handler_with_background_color_jpeg_source() {
 
     assert_eq!(status, StatusCode::OK);
 }
+
+#[tokio::test]
+async fn test_router_without_metrics_serves_requests_but_not_metrics() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [0, 255, 0, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/nometrics.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.enable_metrics = false;
+    let state = create_test_state(config).await;
+    let app = build_router(state, None);
+
+    let (status, _, _) = make_request(app.clone(), "/metrics", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = make_request(app.clone(), "/status", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let source_url = format!("{}/nometrics.png", mock_server.uri());
+    let image_path = format!("/unsafe/resize:fit:50:50/plain/{}", source_url);
+    let (status, _, headers) = make_request_bytes(app, &image_path, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.contains_key("X-Request-ID"));
+}
+
+#[tokio::test]
+async fn test_oversized_requests_are_rejected_before_handlers() {
+    let mock_server = MockServer::start().await;
+    // Any fetch would mean the request got past the limits
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.enable_metrics = false;
+    config.max_path_length = 256;
+    config.max_request_body_size = 16;
+    let state = create_test_state(config).await;
+    let app = build_router(state, None);
+
+    let long_source = format!("{}/{}.png", mock_server.uri(), "a".repeat(300));
+    let (status, _, headers) = make_request(app.clone(), &format!("/unsafe/plain/{}", long_source), None).await;
+    assert_eq!(status, StatusCode::URI_TOO_LONG);
+    assert!(headers.contains_key("X-Request-ID"));
+
+    let request = Request::builder()
+        .uri(format!("/unsafe/plain/{}/image.png", mock_server.uri()))
+        .header("Content-Length", "64")
+        .body(Body::from(vec![0u8; 64]))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, _, _) = make_request(app, "/status", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_raw_requests_are_limited_independently_of_processing() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(100, 100, [255, 255, 255, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/limited.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+    let raw_semaphore = Arc::new(Semaphore::new(1));
+    let state = Arc::new(AppState {
+        raw_semaphore: Some(raw_semaphore.clone()),
+        ..Arc::try_unwrap(state).ok().expect("state is not shared yet")
+    });
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/limited.png", mock_server.uri());
+    let raw_path = format!("/unsafe/raw:1/plain/{}", source_url);
+    let processed_path = format!("/unsafe/resize:fit:50:50/plain/{}", source_url);
+
+    // Occupy the only raw slot: raw requests queue while processed requests are unaffected.
+    let held = raw_semaphore.clone().acquire_owned().await.unwrap();
+
+    let blocked = tokio::time::timeout(
+        Duration::from_millis(200),
+        make_request_bytes(app.clone(), &raw_path, None),
+    )
+    .await;
+    assert!(blocked.is_err(), "raw request should wait for a raw permit");
+
+    let (status, _, _) = make_request_bytes(app.clone(), &processed_path, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    drop(held);
+    let (status, _, _) = make_request_bytes(app, &raw_path, None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_source_cache_shares_download_across_transforms() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(200, 200, [10, 200, 30, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/shared.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.source_cache_capacity = Some(10 * 1024 * 1024);
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/shared.png", mock_server.uri());
+    for options in ["resize:fit:100:100", "resize:fill:50:80/format:png"] {
+        let path = format!("/unsafe/{}/plain/{}", options, source_url);
+        let (status, _, _) = make_request_bytes(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    mock_server.verify().await;
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_checks_resolution_from_header_before_decoding() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/huge.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_header_only_png(30_000, 30_000))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_src_resolution = Some(1.0);
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/huge.png", mock_server.uri());
+    let path = format!("/unsafe/resize:fit:100:100/plain/{}", source_url);
+
+    // The source has no pixel data, so this error can only come from the header-only check.
+    let started = std::time::Instant::now();
+    let (status, body, _) = make_request(app, &path, None).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("Source image resolution is too large"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_selects_tiff_page() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/pages.tiff"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_two_page_tiff())
+                .insert_header("Content-Type", "image/tiff"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_tiff_pages = 3;
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/pages.tiff", mock_server.uri());
+    let mut centers = Vec::new();
+    for page in [0, 1] {
+        let path = format!("/unsafe/page:{}/format:png/plain/{}", page, source_url);
+        let (status, body, _) = make_request_bytes(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let decoded = image::load_from_memory(&body).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (20, 20));
+        centers.push(decoded.get_pixel(10, 10).0);
+    }
+    assert_eq!(centers, vec![[255, 0, 0, 255], [0, 0, 255, 255]]);
+
+    // Page 2 is past the end of the source; page 5 is past the configured cap.
+    for page in [2, 5] {
+        let path = format!("/unsafe/page:{}/format:png/plain/{}", page, source_url);
+        let (status, _, _) = make_request_bytes(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_uses_embedded_thumbnail_for_small_targets() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/large.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_jpeg_with_exif_thumbnail(1))
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+    // Orientation 6 turns the source upright to 1200x1600, so targets are matched transposed
+    Mock::given(method("GET"))
+        .and(path("/rotated.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_jpeg_with_exif_thumbnail(6))
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    // The thumbnail is green and the full image red, so the pixel colour shows which was used.
+    let cases = [
+        (
+            "large.jpg",
+            "use_embedded_thumbnail:true/resize:fit:100:100",
+            (100, 75),
+            true,
+        ),
+        (
+            "large.jpg",
+            "use_embedded_thumbnail:true/resize:fit:400:400",
+            (400, 300),
+            false,
+        ),
+        ("large.jpg", "resize:fit:100:100", (100, 75), false),
+        (
+            "rotated.jpg",
+            "use_embedded_thumbnail:true/resize:fit:90:140",
+            (90, 120),
+            true,
+        ),
+    ];
+    for (source, options, dimensions, from_thumbnail) in cases {
+        let path = format!("/unsafe/{}/format:png/plain/{}/{}", options, mock_server.uri(), source);
+        let (status, body, _) = make_request_bytes(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::OK, "{}", options);
+
+        let decoded = image::load_from_memory(&body).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), dimensions, "{}", options);
+        let pixel = decoded.get_pixel(dimensions.0 / 2, dimensions.1 / 2).0;
+        assert_eq!(pixel[1] > 200, from_thumbnail, "{}: {:?}", options, pixel);
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_applies_quality_and_strip_on_shrink_on_load_path() {
+    let mock_server = MockServer::start().await;
+
+    // A textured 1600x1200 JPEG with EXIF, large enough to be shrunk by 8 while decoding
+    let img = image::RgbImage::from_fn(1600, 1200, |x, y| {
+        let v = (x.wrapping_mul(31) ^ y.wrapping_mul(17) ^ (x * y)) as u8;
+        image::Rgb([v, v.wrapping_add(85), v.wrapping_add(170)])
+    });
+    let mut exif = b"II*\0".to_vec();
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    let mut source: Vec<u8> = Vec::new();
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut source, 95);
+    encoder.set_exif_metadata(exif).unwrap();
+    encoder
+        .write_image(img.as_raw(), 1600, 1200, image::ExtendedColorType::Rgb8)
+        .unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/textured.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(source)
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/textured.jpg", mock_server.uri());
+    let path = |options: &str| format!("/unsafe/resize:fit:200:150/{}/format:jpg/plain/{}", options, source_url);
+    let has_exif = |bytes: &[u8]| {
+        exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(bytes))
+            .is_ok()
+    };
+
+    let (status, low, _) = make_request_bytes(app.clone(), &path("quality:20"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, high, _) = make_request_bytes(app.clone(), &path("quality:95"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        low.len() < high.len(),
+        "quality:20 gave {} bytes, quality:95 gave {}",
+        low.len(),
+        high.len()
+    );
+    let decoded = image::load_from_memory(&low).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (200, 150));
+    assert!(!has_exif(&high));
+
+    let (status, kept, _) = make_request_bytes(app.clone(), &path("quality:95/strip_metadata:false"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(has_exif(&kept));
+
+    // A 0 dimension of a force resize keeps the source's, so the source is decoded at full size
+    let force_path = format!("/unsafe/resize:force:200:0/format:jpg/plain/{}", source_url);
+    let (status, forced, _) = make_request_bytes(app, &force_path, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let decoded = image::load_from_memory(&forced).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (200, 1200));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_strip_metadata_default_is_overridable() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/camera.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_jpeg_with_camera_exif())
+                .insert_header("Content-Type", "image/jpeg"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let source_url = format!("{}/camera.jpg", mock_server.uri());
+    let has_exif = |bytes: &[u8]| {
+        exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(bytes))
+            .is_ok()
+    };
+
+    for (strip_metadata_default, options, expect_exif) in [
+        (true, "", false),
+        (true, "strip:false/", true),
+        (false, "", true),
+        (false, "strip:true/", false),
+    ] {
+        let mut config = create_test_config(vec![], vec![], true);
+        config.strip_metadata_default = strip_metadata_default;
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(create_test_state(config).await)
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        let path = format!("/unsafe/{}format:jpg/plain/{}", options, source_url);
+        let (status, body, _) = make_request_bytes(app, &path, None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            has_exif(&body),
+            expect_exif,
+            "strip_metadata_default={} options={:?}",
+            strip_metadata_default,
+            options
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_reports_processing_duration_with_debug_headers() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/timed.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(100, 100, [0, 128, 255, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let source_url = format!("{}/timed.png", mock_server.uri());
+    let path = format!("/unsafe/resize:fit:50:50/plain/{}@png", source_url);
+
+    for debug_headers in [true, false] {
+        let mut config = create_test_config(vec![], vec![], true);
+        config.debug_headers = debug_headers;
+        let state = create_test_state(config).await;
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(state)
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        let (status, _, headers) = make_request_bytes(app, &path, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let duration = headers
+            .get("X-Imgforge-Duration-ms")
+            .map(|value| value.to_str().unwrap().parse::<u64>());
+        if debug_headers {
+            assert!(
+                matches!(duration, Some(Ok(_))),
+                "expected numeric duration, got {:?}",
+                duration
+            );
+        } else {
+            assert!(duration.is_none());
+        }
+    }
+}
+
+/// Helper function to create an animated GIF with one solid-colour frame per entry in `colors`.
+fn create_animated_gif(width: u32, height: u32, colors: &[[u8; 4]]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    {
+        let mut encoder = image::codecs::gif::GifEncoder::new(&mut bytes);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite).unwrap();
+        let frames = colors.iter().map(|color| {
+            image::Frame::from_parts(
+                ImageBuffer::from_pixel(width, height, Rgba(*color)),
+                0,
+                0,
+                image::Delay::from_numer_denom_ms(100, 1),
+            )
+        });
+        encoder.encode_frames(frames).unwrap();
+    }
+    bytes
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_converts_animated_gif_to_animated_webp() {
+    if !libvips_supports_format("webp") {
+        return;
+    }
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/animated.gif"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_animated_gif(
+                    40,
+                    40,
+                    &[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]],
+                ))
+                .insert_header("Content-Type", "image/gif"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.gif_to_webp = true;
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/animated.gif", mock_server.uri());
+    let (status, body, headers) =
+        make_request_bytes(app, &format!("/unsafe/resize:fit:20:20/plain/{}", source_url), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers.get("content-type").and_then(|value| value.to_str().ok()),
+        Some("image/webp")
+    );
+
+    let output = VipsImage::new_from_buffer(&body, "n=-1").unwrap();
+    assert_eq!(output.get_n_pages(), 3);
+    assert_eq!(output.get_page_height(), 20);
+    assert_eq!(output.get_width(), 20);
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_keeps_animation_frames_for_explicit_formats() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/frames.gif"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_animated_gif(
+                    40,
+                    30,
+                    &[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]],
+                ))
+                .insert_header("Content-Type", "image/gif"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    // Without GIF-to-WebP conversion, an explicit animated format alone keeps the frames
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let source_url = format!("{}/frames.gif", mock_server.uri());
+    for format in ["gif", "webp"] {
+        if !libvips_supports_format(format) {
+            continue;
+        }
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(state.clone())
+            .layer(axum::middleware::from_fn(request_id_middleware));
+        let (status, body, headers) = make_request_bytes(
+            app,
+            &format!("/unsafe/resize:fit:20:20/format:{}/plain/{}", format, source_url),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "format {}", format);
+        assert_eq!(
+            headers.get("content-type").and_then(|value| value.to_str().ok()),
+            Some(format!("image/{}", format).as_str())
+        );
+
+        let output = VipsImage::new_from_buffer(&body, "n=-1").unwrap();
+        assert_eq!(output.get_n_pages(), 3, "format {}", format);
+        assert_eq!(output.get_page_height(), 15, "format {}", format);
+        assert_eq!(output.get_width(), 20, "format {}", format);
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_counts_every_animation_frame_against_max_resolution() {
+    if !libvips_supports_format("gif") {
+        return;
+    }
+    let mock_server = MockServer::start().await;
+
+    // 30 frames of 100x100 decode to 0.3 megapixels, though each frame is only 0.01
+    let colors: Vec<[u8; 4]> = (0..30)
+        .map(|i| if i % 2 == 0 { [255, 0, 0, 255] } else { [0, 0, 255, 255] })
+        .collect();
+    Mock::given(method("GET"))
+        .and(path("/many.gif"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_animated_gif(100, 100, &colors))
+                .insert_header("Content-Type", "image/gif"),
+        )
+        .mount(&mock_server)
+        .await;
+    let source_url = format!("{}/many.gif", mock_server.uri());
+
+    let request = |max_animation_frames: u32, format: &'static str| {
+        let source_url = source_url.clone();
+        async move {
+            let mut config = create_test_config(vec![], vec![], true);
+            config.max_src_resolution = Some(0.1);
+            config.max_animation_frames = max_animation_frames;
+            let app = axum::Router::new()
+                .route("/{*path}", axum::routing::get(image_forge_handler))
+                .with_state(create_test_state(config).await)
+                .layer(axum::middleware::from_fn(request_id_middleware));
+            make_request_bytes(app, &format!("/unsafe/format:{}/plain/{}", format, source_url), None).await
+        }
+    };
+
+    // Keeping the animation decodes every frame, which exceeds the limit
+    let (status, _, _) = request(256, "gif").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A still output decodes a single frame
+    let (status, _, _) = request(256, "png").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A contact sheet decodes every frame too, whatever `max_animation_frames` allows
+    let (status, _, _) = request(5, "png/contact_sheet:6").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Capping the decoded frames brings the animation back under the limit
+    let (status, body, _) = request(5, "gif").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(VipsImage::new_from_buffer(&body, "n=-1").unwrap().get_n_pages(), 5);
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_rasterizes_dimensionless_svg_at_requested_size() {
+    // Left half red, right half blue, with neither width, height nor viewBox on the root
+    let svg = br##"<svg xmlns="http://www.w3.org/2000/svg"><rect width="5" height="10" fill="#ff0000"/><rect x="5" width="5" height="10" fill="#0000ff"/></svg>"##;
+    if VipsImage::new_from_buffer(svg, "").is_err() {
+        return;
+    }
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/shape.svg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(svg.to_vec())
+                .insert_header("Content-Type", "image/svg+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/shape.svg", mock_server.uri());
+    let (status, body, _) = make_request_bytes(
+        app,
+        &format!("/unsafe/resize:fit:400:400/format:png/plain/{}", source_url),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let output = image::load_from_memory(&body).unwrap().to_rgb8();
+    assert_eq!(output.dimensions(), (400, 400));
+    // Rendered at the target size, the colour edge stays a single pixel wide
+    assert_eq!(output.get_pixel(198, 200).0, [255, 0, 0]);
+    assert_eq!(output.get_pixel(201, 200).0, [0, 0, 255]);
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_caps_dimensionless_svg_raster_at_max_resolution() {
+    let svg = br##"<svg xmlns="http://www.w3.org/2000/svg"><rect width="10" height="10" fill="#ff0000"/></svg>"##;
+    if VipsImage::new_from_buffer(svg, "").is_err() {
+        return;
+    }
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/huge.svg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(svg.to_vec())
+                .insert_header("Content-Type", "image/svg+xml"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_src_resolution = Some(1.0);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/huge.svg", mock_server.uri());
+    let (status, body, _) = make_request_bytes(
+        app,
+        &format!("/unsafe/resize:fit:20000:20000/format:png/plain/{}", source_url),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A 1 MP limit holds the 10x10 drawing to a 1000x1000 raster, which is not enlarged further
+    let output = image::load_from_memory(&body).unwrap();
+    assert_eq!((output.width(), output.height()), (1000, 1000));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_lays_out_contact_sheet() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/sheet.gif"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_animated_gif(
+                    30,
+                    20,
+                    &[
+                        [255, 0, 0, 255],
+                        [0, 255, 0, 255],
+                        [0, 0, 255, 255],
+                        [255, 255, 0, 255],
+                        [0, 255, 255, 255],
+                    ],
+                ))
+                .insert_header("Content-Type", "image/gif"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    // GIF-to-WebP conversion would otherwise keep the animation
+    let mut config = create_test_config(vec![], vec![], true);
+    config.gif_to_webp = true;
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/sheet.gif", mock_server.uri());
+    let (status, body, headers) = make_request_bytes(
+        app,
+        &format!("/unsafe/contact_sheet:2/format:png/plain/{}", source_url),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers.get("content-type").and_then(|value| value.to_str().ok()),
+        Some("image/png")
+    );
+
+    // Five frames in two columns make a 2x3 grid
+    let output = VipsImage::new_from_buffer(&body, "n=-1").unwrap();
+    assert_eq!(output.get_n_pages(), 1);
+    assert_eq!((output.get_width(), output.get_height()), (60, 60));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_maps_processing_errors_to_status_codes() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/garbage.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(b"definitely not an image".to_vec())
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/valid.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(100, 80, [10, 20, 30, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let garbage_url = format!("{}/garbage.png", mock_server.uri());
+    let valid_url = format!("{}/valid.png", mock_server.uri());
+    let cases = [
+        (
+            format!("/unsafe/plain/{}@png", garbage_url),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Error loading image",
+        ),
+        (
+            format!("/unsafe/format:definitely_not_a_format/plain/{}", valid_url),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "not supported",
+        ),
+        (
+            format!("/unsafe/crop:0:0:200:200/plain/{}@png", valid_url),
+            StatusCode::BAD_REQUEST,
+            "Invalid geometry",
+        ),
+    ];
+    for (path, expected_status, expected_message) in cases {
+        let (status, body, _) = make_request(app.clone(), &path, None).await;
+        assert_eq!(status, expected_status, "unexpected status for {}: {}", path, body);
+        assert!(
+            body.contains(expected_message),
+            "unexpected body for {}: {}",
+            path,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_maps_upstream_error_statuses() {
+    let mock_server = MockServer::start().await;
+
+    for (upstream_status, route) in [(404, "/missing.png"), (403, "/forbidden.png"), (500, "/broken.png")] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(upstream_status).set_body_string("<html>error page</html>"))
+            .mount(&mock_server)
+            .await;
+    }
+
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    for (route, expected_status, upstream_status) in [
+        ("/missing.png", StatusCode::NOT_FOUND, "404"),
+        ("/forbidden.png", StatusCode::BAD_GATEWAY, "403"),
+        ("/broken.png", StatusCode::BAD_GATEWAY, "500"),
+    ] {
+        let path = format!("/unsafe/resize:fit:50:50/plain/{}{}", mock_server.uri(), route);
+        let (status, body, _) = make_request(app.clone(), &path, None).await;
+        assert_eq!(status, expected_status, "unexpected status for {}: {}", route, body);
+        assert!(
+            body.contains(&format!("Upstream responded with status {}", upstream_status)),
+            "expected upstream status in body, got {}",
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_retries_transient_upstream_failures() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/flaky.png"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/flaky.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(100, 100, [0, 128, 255, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let path = format!("/unsafe/resize:fit:50:50/plain/{}/flaky.png", mock_server.uri());
+
+    // Without retries the first 503 fails the request
+    let mut config = create_test_config(vec![], vec![], true);
+    config.fetch_retries = 0;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(create_test_state(config).await)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+    let (status, _, _) = make_request_bytes(app, &path, None).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    // One 503 is left, which a single retry gets past
+    let mut config = create_test_config(vec![], vec![], true);
+    config.fetch_retries = 1;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(create_test_state(config).await)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+    let (status, body, _) = make_request_bytes(app, &path, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let decoded = image::load_from_memory(&body).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (50, 50));
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_reports_undecodable_source_urls() {
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let invalid_utf8 = URL_SAFE_NO_PAD.encode([0x68, 0x74, 0xff, 0xfe, 0x70]);
+    for (path, expected) in [
+        (
+            "/unsafe/resize:fit:50:50/not*base64!".to_string(),
+            "invalid base64 source URL",
+        ),
+        (
+            format!("/unsafe/resize:fit:50:50/{}", invalid_utf8),
+            "source URL is not valid UTF-8",
+        ),
+    ] {
+        let (status, body, _) = make_request(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_serves_error_image_on_fetch_failure() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing.png"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&mock_server)
+        .await;
+
+    for (mode, expected_status) in [
+        (ErrorImageMode::KeepStatus, StatusCode::NOT_FOUND),
+        (ErrorImageMode::Ok, StatusCode::OK),
+    ] {
+        let mut config = create_test_config(vec![], vec![], true);
+        config.error_image_mode = Some(mode);
+        let state = create_test_state(config).await;
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(state)
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        let path = format!(
+            "/unsafe/resize:fit:120:80/format:png/plain/{}/missing.png",
+            mock_server.uri()
+        );
+        let (status, body, headers) = make_request_bytes(app, &path, None).await;
+        assert_eq!(status, expected_status);
+        assert_eq!(
+            headers.get("content-type").and_then(|value| value.to_str().ok()),
+            Some("image/png")
+        );
+        let error_image = VipsImage::new_from_buffer(&body, "").unwrap();
+        assert_eq!(error_image.get_width(), 120);
+        assert_eq!(error_image.get_height(), 80);
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_never_validates_error_images() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing.png"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.error_image_mode = Some(ErrorImageMode::Ok);
+    config.upstream_cache_headers = true;
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    // An error image served as 200 must not carry an ETag, or clients would keep it after a fix
+    let path = format!(
+        "/unsafe/resize:fit:120:80/format:png/plain/{}/missing.png",
+        mock_server.uri()
+    );
+    let request = Request::builder()
+        .uri(path.as_str())
+        .header("If-None-Match", "*")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("etag").is_none());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(!body.is_empty());
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_serves_fallback_image_on_source_failure() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing.png"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/corrupt.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(b"definitely not an image".to_vec())
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+    // Valid PNG magic bytes, cut off inside the header
+    let mut truncated = create_test_image(100, 100, [255, 0, 0, 255]);
+    truncated.truncate(20);
+    Mock::given(method("GET"))
+        .and(path("/truncated.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(truncated)
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/fallback.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(400, 400, [0, 255, 0, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        // Loaded once, then reused for every request that needs it
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/oversized.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(vec![0u8; 4096])
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let fallback_dir = tempfile::tempdir().unwrap();
+    let fallback_path = fallback_dir.path().join("fallback.png");
+    std::fs::write(&fallback_path, create_test_image(400, 400, [0, 255, 0, 255])).unwrap();
+
+    // Fetch failures and undecodable sources both get the fallback, from a local path or a URL
+    for fallback_image in [
+        fallback_path.to_string_lossy().into_owned(),
+        format!("{}/fallback.png", mock_server.uri()),
+    ] {
+        let mut config = create_test_config(vec![], vec![], true);
+        config.fallback_image = Some(fallback_image);
+        config.fallback_ttl = 15;
+        config.max_src_file_size = Some(1024);
+        let state = create_test_state(config).await;
+        let app = axum::Router::new()
+            .route("/{*path}", axum::routing::get(image_forge_handler))
+            .with_state(state)
+            .layer(axum::middleware::from_fn(request_id_middleware));
+
+        for source in ["missing.png", "corrupt.png", "truncated.png"] {
+            let path = format!(
+                "/unsafe/resize:fit:120:80/format:png/plain/{}/{}",
+                mock_server.uri(),
+                source
+            );
+            let (status, body, headers) = make_request_bytes(app.clone(), &path, None).await;
+            assert_eq!(status, StatusCode::OK, "source {}", source);
+            assert_eq!(
+                headers.get("cache-control").and_then(|value| value.to_str().ok()),
+                Some("public, max-age=15")
+            );
+            let fallback = VipsImage::new_from_buffer(&body, "").unwrap();
+            assert_eq!(fallback.get_width(), 80);
+            assert_eq!(fallback.get_height(), 80);
+        }
+
+        // Rejections by the server's own limits are reported, not hidden behind the fallback
+        let path = format!(
+            "/unsafe/resize:fit:120:80/format:png/plain/{}/oversized.png",
+            mock_server.uri()
+        );
+        let (status, _, headers) = make_request_bytes(app.clone(), &path, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(headers.get("cache-control").is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_image_forge_handler_coalesces_concurrent_identical_requests() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(120, 80, [0, 128, 255, 255]);
+
+    // The delay keeps the whole burst in flight before the first response can be cached
+    Mock::given(method("GET"))
+        .and(path("/burst.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("Content-Type", "image/png")
+                .set_delay(Duration::from_millis(200)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let state = create_test_state(create_test_config(vec![], vec![], true)).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state.clone())
+        .layer(axum::middleware::from_fn(request_id_middleware));
+
+    let source_url = format!("{}/burst.png", mock_server.uri());
+    let uri = format!("/unsafe/resize:fit:60:40/format:png/plain/{}", source_url);
+    let responses = futures::future::join_all((0..8).map(|_| make_request_bytes(app.clone(), &uri, None))).await;
+
+    for (status, body, _) in &responses {
+        assert_eq!(*status, StatusCode::OK);
+        assert_eq!(body, &responses[0].1);
+    }
+    // Every processing run fetches the source, so one fetch means one run
+    let fetches = mock_server.received_requests().await.unwrap().len();
+    assert_eq!(fetches, 1);
+    assert!(state.coalescer.is_empty());
+}
@@ -1,32 +1,28 @@
 use axum::{
     body::Body,
+    extract::ConnectInfo,
     http::{Request, StatusCode},
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use http_body_util::BodyExt;
 use image::{ImageBuffer, Rgba};
-use imgforge::app::AppState;
+use imgforge::app::{AppState, Imgforge};
 use imgforge::caching::cache::ImgforgeCache;
 use imgforge::caching::config::CacheConfig;
-use imgforge::config::Config;
+use imgforge::config::{Config, S3Config, S3Credentials, SourceHostRule};
 use imgforge::handlers::image_forge_handler;
-use imgforge::middleware::request_id_middleware;
-use lazy_static::lazy_static;
-use libvips::VipsApp;
+use imgforge::middleware::{client_ip_middleware, rate_limit_middleware, request_id_middleware, ClientIp};
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, Semaphore};
 use tower::ServiceExt;
 use wiremock::{
-    matchers::{method, path},
+    matchers::{header, header_regex, method, path},
     Mock, MockServer, ResponseTemplate,
 };
 
-lazy_static! {
-    static ref VIPS_APP: Arc<VipsApp> =
-        Arc::new(VipsApp::new("imgforge-test", false).expect("Failed to initialize libvips"));
-}
-
 /// Helper function to create a test PNG image
 fn create_test_image(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
     let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(width, height);
@@ -45,6 +41,8 @@ fn create_test_config(key: Vec<u8>, salt: Vec<u8>, allow_unsigned: bool) -> Conf
     config.workers = 4;
     config.allow_unsigned = allow_unsigned;
     config.allow_security_options = true;
+    // The mock origins listen on loopback
+    config.allow_private_sources = true;
     config
 }
 
@@ -59,13 +57,22 @@ async fn create_test_state_with_cache(config: Config, cache: ImgforgeCache) -> A
 
     Arc::new(AppState {
         semaphore: Arc::new(Semaphore::new(config.workers)),
+        raw_semaphore: None,
         cache,
         metadata_cache,
+        source_cache: imgforge::caching::cache::SourceCache::new(
+            config.source_cache_capacity,
+            Duration::from_secs(config.source_cache_ttl),
+        ),
         rate_limiter: None,
+        circuit_breaker: None,
+        host_limiter: None,
         config,
-        vips_app: VIPS_APP.clone(),
+        vips_app: Imgforge::init_vips().expect("Failed to initialize libvips"),
         http_client,
         watermark_cache: Mutex::new(None),
+        fallback_image_cache: Mutex::new(None),
+        coalescer: Default::default(),
     })
 }
 
@@ -121,6 +128,428 @@ async fn test_image_caching_with_memory_cache() {
     assert_eq!(status2, StatusCode::OK);
 }
 
+#[tokio::test]
+async fn test_no_op_dpr_shares_the_cache_entry() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/dpr.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(100, 100, [0, 128, 255, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let cache = ImgforgeCache::new(Some(CacheConfig::Memory { capacity: 1024 * 1024 }))
+        .await
+        .unwrap();
+    let state = create_test_state_with_cache(create_test_config(vec![], vec![], true), cache).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+    let cache_status = |uri: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.headers().get("cache-status").cloned()
+        }
+    };
+
+    // `dpr:1` and `dpr:1.0` render the same image as no `dpr`, so all three hit one entry
+    let source_url = format!("{}/dpr.png", mock_server.uri());
+    let uri = |dpr: &str| format!("/unsafe/resize:fit:50:50{}/plain/{}", dpr, source_url);
+    assert!(cache_status(uri("")).await.is_none());
+    assert_eq!(cache_status(uri("/dpr:1")).await.unwrap(), "HIT");
+    assert_eq!(cache_status(uri("/dpr:1.0")).await.unwrap(), "HIT");
+    assert!(cache_status(uri("/dpr:2")).await.is_none());
+}
+
+#[tokio::test]
+async fn test_upstream_cache_headers_round_trip_etag_and_revalidate() {
+    let mock_server = MockServer::start().await;
+
+    // Conditional requests carrying the origin's ETag learn the source is unchanged
+    Mock::given(method("GET"))
+        .and(path("/revalidate.png"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/revalidate.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(100, 100, [10, 20, 30, 255]))
+                .insert_header("Content-Type", "image/png")
+                .insert_header("ETag", "\"v1\"")
+                .insert_header("Cache-Control", "public, max-age=0"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.upstream_cache_headers = true;
+    let cache = ImgforgeCache::new(Some(CacheConfig::Memory { capacity: 1024 * 1024 }))
+        .await
+        .unwrap();
+    let state = create_test_state_with_cache(config, cache).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+
+    let uri = format!("/unsafe/resize:fit:50:50/plain/{}/revalidate.png", mock_server.uri());
+    let send = |if_none_match: Option<&str>| {
+        let mut request = Request::builder().uri(uri.as_str());
+        if let Some(etag) = if_none_match {
+            request = request.header("If-None-Match", etag);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    let first = send(None).await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.headers()["Cache-Control"], "public, max-age=0");
+    let etag = first.headers()["ETag"].to_str().unwrap().to_string();
+    let first_body = first.into_body().collect().await.unwrap().to_bytes();
+
+    // max-age=0 makes the cached image stale, so it is revalidated and served from cache
+    let second = send(None).await.unwrap();
+    assert_eq!(second.status(), StatusCode::OK);
+    assert_eq!(second.headers()["ETag"], etag.as_str());
+    assert_eq!(second.into_body().collect().await.unwrap().to_bytes(), first_body);
+
+    // A client holding the current ETag gets an empty 304
+    let third = send(Some(&etag)).await.unwrap();
+    assert_eq!(third.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(third.headers()["ETag"], etag.as_str());
+    assert!(third.into_body().collect().await.unwrap().to_bytes().is_empty());
+}
+
+#[tokio::test]
+async fn test_stale_cached_image_is_served_while_refreshing() {
+    let mock_server = MockServer::start().await;
+    let source = |color: [u8; 4]| {
+        ResponseTemplate::new(200)
+            .set_body_bytes(create_test_image(40, 40, color))
+            .insert_header("Content-Type", "image/png")
+            .insert_header("Cache-Control", "max-age=0")
+    };
+    Mock::given(method("GET"))
+        .and(path("/swr.png"))
+        .respond_with(source([255, 0, 0, 255]))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+    // Later fetches see an updated, slow origin
+    Mock::given(method("GET"))
+        .and(path("/swr.png"))
+        .respond_with(source([0, 0, 255, 255]).set_delay(Duration::from_secs(2)))
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.upstream_cache_headers = true;
+    config.stale_while_revalidate = 60;
+    let cache = ImgforgeCache::new(Some(CacheConfig::Memory { capacity: 1024 * 1024 }))
+        .await
+        .unwrap();
+    let state = create_test_state_with_cache(config, cache).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+    let uri = format!("/unsafe/format:png/plain/{}/swr.png", mock_server.uri());
+
+    let (status, first_body) = make_request(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // max-age=0 makes the entry stale at once, but it is within the window, so the cached image
+    // is answered without waiting for the slow origin
+    let started = std::time::Instant::now();
+    let (status, stale_body) = make_request(app.clone(), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stale_body, first_body);
+    assert!(
+        started.elapsed() < Duration::from_secs(1),
+        "took {:?}",
+        started.elapsed()
+    );
+
+    // The background refresh replaces the cached image once the origin answers
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let (status, body) = make_request(app.clone(), &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        if body != first_body {
+            let img = image::load_from_memory(&body).unwrap().to_rgba8();
+            assert_eq!(img.get_pixel(20, 20).0, [0, 0, 255, 255]);
+            break;
+        }
+        assert!(std::time::Instant::now() < deadline, "cache was never refreshed");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_local_sources_are_read_from_local_root() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir(root.path().join("images")).unwrap();
+    std::fs::write(
+        root.path().join("images/local.png"),
+        create_test_image(100, 100, [0, 128, 255, 255]),
+    )
+    .unwrap();
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.local_root = Some(root.path().to_string_lossy().into_owned());
+    let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+    let get = |uri: &'static str| {
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+    };
+
+    let response = get("/unsafe/resize:fit:50:50/plain/local:///images/local.png@png")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let img = image::load_from_memory(&body).unwrap();
+    assert_eq!((img.width(), img.height()), (50, 50));
+
+    let response = get("/unsafe/raw:1/plain/file:///images/local.png").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], "image/png");
+
+    let response = get("/unsafe/plain/local:///images/../../outside.png").await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = get("/unsafe/plain/local:///images/missing.png").await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_local_sources_rejected_without_local_root() {
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+
+    let request = Request::builder()
+        .uri("/unsafe/plain/local:///etc/passwd")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_s3_sources_are_fetched_from_configured_endpoint() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/images/photo.png"))
+        .and(header_regex(
+            "authorization",
+            "^AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/",
+        ))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(100, 100, [200, 50, 50, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/images/private.png"))
+        .respond_with(
+            ResponseTemplate::new(403)
+                .set_body_string("<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = create_test_config(vec![], vec![], true);
+    config.fetch_retries = 0;
+    config.s3 = Some(S3Config {
+        endpoint: mock_server.uri(),
+        region: "us-east-1".to_string(),
+        bucket: None,
+        credentials: Some(S3Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        }),
+    });
+    let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+    let get = |uri: &'static str| {
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+    };
+
+    let response = get("/unsafe/resize:fit:50:50/plain/s3://images/photo.png@png")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let img = image::load_from_memory(&body).unwrap();
+    assert_eq!((img.width(), img.height()), (50, 50));
+
+    // Access denied is the object store refusing the request, like any other upstream 4xx
+    let response = get("/unsafe/plain/s3://images/private.png").await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn test_s3_sources_rejected_without_s3_config() {
+    let config = create_test_config(vec![], vec![], true);
+    let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+
+    let request = Request::builder()
+        .uri("/unsafe/plain/s3://images/photo.png")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_private_source_hosts_are_rejected_unless_allowed() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/internal.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(20, 20, [10, 20, 30, 255]))
+                .insert_header("Content-Type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+    let loopback_url = format!("{}/internal.png", mock_server.uri());
+
+    let request = |config: Config, source: &str| {
+        let path = format!("/unsafe/{}", URL_SAFE_NO_PAD.encode(source));
+        async move {
+            let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+            let app = axum::Router::new()
+                .route("/{*path}", axum::routing::get(image_forge_handler))
+                .with_state(state);
+            let response = app
+                .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8_lossy(&body).into_owned())
+        }
+    };
+    let default_config = || {
+        let mut config = create_test_config(vec![], vec![], true);
+        config.allow_private_sources = false;
+        config
+    };
+
+    // Rejected before any request is made, so the metadata address never has to answer
+    for source in [loopback_url.as_str(), "http://169.254.169.254/latest/meta-data/"] {
+        let (status, body) = request(default_config(), source).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", source);
+        assert!(body.contains("resolves to private address"), "{}", body);
+    }
+    assert!(mock_server.received_requests().await.unwrap().is_empty());
+
+    let mut allowed = default_config();
+    allowed.allowed_source_hosts = vec![SourceHostRule::parse("127.0.0.1").unwrap()];
+    let (status, _) = request(allowed.clone(), &loopback_url).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = request(allowed, "http://169.254.169.254/latest/meta-data/").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body.contains("is not allowed"), "{}", body);
+
+    let mut blocked = create_test_config(vec![], vec![], true);
+    blocked.blocked_source_hosts = vec![SourceHostRule::parse("127.0.0.0/8").unwrap()];
+    let (status, body) = request(blocked, &loopback_url).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body.contains("is blocked"), "{}", body);
+
+    // Watermark URLs are checked like sources, even when the source itself needs no fetch
+    let inline_source = format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(create_test_image(20, 20, [10, 20, 30, 255]))
+    );
+    let path = format!(
+        "/unsafe/wmu:{}/{}",
+        URL_SAFE_NO_PAD.encode(&loopback_url),
+        URL_SAFE_NO_PAD.encode(inline_source)
+    );
+    let fetched_before = mock_server.received_requests().await.unwrap().len();
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(create_test_state_with_cache(default_config(), ImgforgeCache::None).await);
+    let response = app
+        .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(mock_server.received_requests().await.unwrap().len(), fetched_before);
+}
+
+#[tokio::test]
+async fn test_data_uri_sources_are_decoded_inline() {
+    let payload = base64::engine::general_purpose::STANDARD.encode(create_test_image(100, 80, [20, 200, 60, 255]));
+    let mut config = create_test_config(vec![], vec![], true);
+    config.max_src_file_size = Some(64 * 1024);
+    let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .with_state(state);
+    let get = |options: &str, source: String| {
+        let uri = format!("/unsafe/{}/{}", options, URL_SAFE_NO_PAD.encode(source));
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+    };
+
+    let response = get(
+        "resize:fit:50:50/format:png",
+        format!("data:image/png;base64,{}", payload),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], "image/png");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let img = image::load_from_memory(&body).unwrap();
+    assert_eq!((img.width(), img.height()), (50, 40));
+
+    let response = get("raw:1", format!("data:image/png,{}", payload)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = get("max_src_file_size:100", format!("data:image/png;base64,{}", payload))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let padded = format!("{}{}", payload, " ".repeat(8192));
+    let response = get("raw:1", format!("data:image/png;base64,{}", padded)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn test_concurrent_image_processing() {
     let mock_server = MockServer::start().await;
@@ -431,3 +860,115 @@ async fn test_pixelate_effect() {
     let (status, _) = make_request(app, &path).await;
     assert_eq!(status, StatusCode::OK);
 }
+
+/// Sends a request from `peer` with the given headers to a route that echoes the resolved
+/// client IP.
+async fn resolve_client_ip_via_router(
+    trusted_proxies: &[&str],
+    peer: &str,
+    headers: &[(&str, &str)],
+) -> Option<String> {
+    let mut config = create_test_config(vec![], vec![], true);
+    config.trusted_proxies = trusted_proxies.iter().map(|ip| ip.parse().unwrap()).collect();
+    let state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+
+    let app = axum::Router::new()
+        .route(
+            "/ip",
+            axum::routing::get(|client_ip: Option<axum::Extension<ClientIp>>| async move {
+                client_ip.map(|ip| ip.0 .0.to_string()).unwrap_or_default()
+            }),
+        )
+        .layer(axum::middleware::from_fn_with_state(state, client_ip_middleware));
+
+    let mut request = Request::builder().uri("/ip");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let mut request = request.body(Body::empty()).unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 40000)));
+
+    let response = app.oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    (!body.is_empty()).then_some(body)
+}
+
+#[tokio::test]
+async fn test_client_ip_uses_peer_without_forwarded_header() {
+    let ip = resolve_client_ip_via_router(&["10.0.0.1"], "10.0.0.1", &[]).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.1"));
+
+    let ip = resolve_client_ip_via_router(&[], "203.0.113.9", &[]).await;
+    assert_eq!(ip.as_deref(), Some("203.0.113.9"));
+}
+
+#[tokio::test]
+async fn test_client_ip_ignores_forwarded_headers_from_untrusted_peer() {
+    let headers = [("X-Forwarded-For", "198.51.100.7"), ("Forwarded", "for=198.51.100.7")];
+    let ip = resolve_client_ip_via_router(&["10.0.0.1"], "203.0.113.9", &headers).await;
+    assert_eq!(ip.as_deref(), Some("203.0.113.9"));
+}
+
+#[tokio::test]
+async fn test_client_ip_reads_x_forwarded_for_from_trusted_proxy() {
+    // The left-most entry is client-supplied; the walk stops at the first untrusted hop.
+    let headers = [("X-Forwarded-For", "192.0.2.1, 198.51.100.7, 10.0.0.2")];
+    let ip = resolve_client_ip_via_router(&["10.0.0.1", "10.0.0.2"], "10.0.0.1", &headers).await;
+    assert_eq!(ip.as_deref(), Some("198.51.100.7"));
+}
+
+#[tokio::test]
+async fn test_client_ip_prefers_forwarded_header_from_trusted_proxy() {
+    let headers = [
+        ("Forwarded", "for=\"[2001:db8::7]:4711\";proto=https"),
+        ("X-Forwarded-For", "198.51.100.7"),
+    ];
+    let ip = resolve_client_ip_via_router(&["10.0.0.1"], "10.0.0.1", &headers).await;
+    assert_eq!(ip.as_deref(), Some("2001:db8::7"));
+
+    let headers = [("Forwarded", "for=unknown")];
+    let ip = resolve_client_ip_via_router(&["10.0.0.1"], "10.0.0.1", &headers).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.1"));
+}
+
+#[tokio::test]
+async fn test_rate_limit_is_kept_per_client_ip() {
+    let mut config = create_test_config(vec![], vec![], true);
+    config.trusted_proxies = vec!["10.0.0.1".parse().unwrap()];
+    let mut state = create_test_state_with_cache(config, ImgforgeCache::None).await;
+    Arc::get_mut(&mut state).unwrap().rate_limiter = Some(governor::RateLimiter::keyed(governor::Quota::per_minute(
+        NonZeroU32::new(1).unwrap(),
+    )));
+
+    let app = axum::Router::new()
+        .route(
+            "/ok",
+            axum::routing::get(|| async { "ok" }).layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit_middleware,
+            )),
+        )
+        .layer(axum::middleware::from_fn_with_state(state, client_ip_middleware));
+    let get = |client: &str| {
+        let mut request = Request::builder()
+            .uri("/ok")
+            .header("X-Forwarded-For", client)
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new("10.0.0.1".parse().unwrap(), 40000)));
+        app.clone().oneshot(request)
+    };
+
+    assert_eq!(get("198.51.100.7").await.unwrap().status(), StatusCode::OK);
+    assert_eq!(
+        get("198.51.100.7").await.unwrap().status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    // Clients behind the same proxy each get their own budget
+    assert_eq!(get("198.51.100.8").await.unwrap().status(), StatusCode::OK);
+}
@@ -6,14 +6,17 @@ use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hmac::{Hmac, Mac};
 use http_body_util::BodyExt;
 use image::{ImageBuffer, Rgba};
-use imgforge::app::AppState;
+use imgforge::app::{AppState, Imgforge};
 use imgforge::caching::cache::ImgforgeCache;
 use imgforge::config::Config;
 use imgforge::handlers::image_forge_handler;
 use imgforge::middleware::request_id_middleware;
-use imgforge::processing::{options::ProcessingOption, presets::parse_options_string};
-use lazy_static::lazy_static;
-use libvips::{VipsApp, VipsImage};
+use imgforge::processing::{
+    options::{parse_all_options, ProcessingOption},
+    presets::{expand_presets, parse_options_string},
+};
+use libvips::VipsImage;
+use metrics::{Counter, CounterFn, Gauge, Histogram, Key, KeyName, Label, Metadata, Recorder, SharedString, Unit};
 use sha2::Sha256;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -27,11 +30,6 @@ use wiremock::{
 
 type HmacSha256 = Hmac<Sha256>;
 
-lazy_static! {
-    static ref VIPS_APP: Arc<VipsApp> =
-        Arc::new(VipsApp::new("imgforge-test", false).expect("Failed to initialize libvips"));
-}
-
 fn create_test_image(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
     let mut img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(width, height);
     for (_x, _y, pixel) in img.enumerate_pixels_mut() {
@@ -62,6 +60,8 @@ fn create_test_config(
     config.workers = 4;
     config.allow_unsigned = allow_unsigned;
     config.allow_security_options = true;
+    // The mock origins listen on loopback
+    config.allow_private_sources = true;
     config.presets = presets;
     config.only_presets = only_presets;
     config
@@ -77,16 +77,75 @@ async fn create_test_state(config: Config) -> Arc<AppState> {
 
     Arc::new(AppState {
         semaphore: Arc::new(Semaphore::new(config.workers)),
+        raw_semaphore: None,
         cache,
         metadata_cache,
+        source_cache: imgforge::caching::cache::SourceCache::new(
+            config.source_cache_capacity,
+            Duration::from_secs(config.source_cache_ttl),
+        ),
         rate_limiter: None,
+        circuit_breaker: None,
+        host_limiter: None,
         config,
-        vips_app: VIPS_APP.clone(),
+        vips_app: Imgforge::init_vips().expect("Failed to initialize libvips"),
         http_client,
         watermark_cache: Mutex::new(None),
+        fallback_image_cache: Mutex::new(None),
+        coalescer: Default::default(),
     })
 }
 
+/// Metrics recorder that totals counter increments by metric name and labels.
+#[derive(Default)]
+struct CounterCapture {
+    counts: Arc<std::sync::Mutex<HashMap<Key, u64>>>,
+}
+
+impl CounterCapture {
+    fn count(&self, name: &'static str, labels: &[(&'static str, &'static str)]) -> u64 {
+        let labels: Vec<Label> = labels.iter().map(|(key, value)| Label::new(*key, *value)).collect();
+        let key = Key::from_parts(name, labels);
+        self.counts.lock().unwrap().get(&key).copied().unwrap_or(0)
+    }
+}
+
+struct CapturedCounter {
+    key: Key,
+    counts: Arc<std::sync::Mutex<HashMap<Key, u64>>>,
+}
+
+impl CounterFn for CapturedCounter {
+    fn increment(&self, value: u64) {
+        *self.counts.lock().unwrap().entry(self.key.clone()).or_default() += value;
+    }
+
+    fn absolute(&self, value: u64) {
+        self.counts.lock().unwrap().insert(self.key.clone(), value);
+    }
+}
+
+impl Recorder for CounterCapture {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::from_arc(Arc::new(CapturedCounter {
+            key: key.clone(),
+            counts: self.counts.clone(),
+        }))
+    }
+
+    fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, _key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::noop()
+    }
+}
+
 async fn make_request(app: axum::Router, uri: &str) -> (StatusCode, Vec<u8>) {
     let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
 
@@ -188,6 +247,100 @@ async fn test_preset_with_default() {
     assert_eq!(img.get_height(), 150);
 }
 
+#[tokio::test]
+async fn test_preset_header_lists_applied_presets() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(400, 300, [0, 255, 0, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/test.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("content-type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut presets = HashMap::new();
+    presets.insert("default".to_string(), parse_options_string("quality:90").unwrap());
+    presets.insert("small".to_string(), parse_options_string("resize:fit:200:200").unwrap());
+
+    let config = create_test_config(vec![], vec![], true, presets, false);
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .layer(axum::middleware::from_fn(request_id_middleware))
+        .with_state(state);
+
+    let source_url = format!("{}/test.png", mock_server.uri());
+    for (options, expected) in [("preset:small", "default, small"), ("blur:1", "default")] {
+        let uri = format!("/unsafe/{}/plain/{}", options, source_url);
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get("x-imgforge-preset")
+                .and_then(|value| value.to_str().ok()),
+            Some(expected)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_requests_counter_labels_auth_and_kind() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/test.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(create_test_image(40, 30, [0, 0, 255, 255]))
+                .insert_header("content-type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let key = b"test_key".to_vec();
+    let salt = b"test_salt".to_vec();
+    let mut presets = HashMap::new();
+    presets.insert("default".to_string(), parse_options_string("quality:90").unwrap());
+    presets.insert("small".to_string(), parse_options_string("resize:fit:20:20").unwrap());
+    let config = create_test_config(key.clone(), salt.clone(), true, presets, false);
+    let state = create_test_state(config).await;
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .layer(axum::middleware::from_fn(request_id_middleware))
+        .with_state(state);
+
+    let source_url = format!("{}/test.png", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let signed_path = format!("/blur:1/{}", encoded_url);
+    let signed_uri = format!("{}{}", generate_signature(&key, &salt, &signed_path), signed_path);
+
+    // The handler runs on this test's thread, so a thread-local recorder sees every increment
+    let capture = CounterCapture::default();
+    let _guard = metrics::set_default_local_recorder(&capture);
+    for uri in [
+        format!("/{}", signed_uri),
+        format!("/unsafe/blur:1/plain/{}", source_url),
+        format!("/unsafe/preset:small/plain/{}", source_url),
+        format!("/unsafe/pr:small/plain/{}", source_url),
+    ] {
+        let (status, _) = make_request(app.clone(), &uri).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+    }
+
+    let requests = |auth, kind| capture.count("imgforge_requests_total", &[("auth", auth), ("kind", kind)]);
+    assert_eq!(requests("signed", "inline"), 1);
+    assert_eq!(requests("unsigned", "inline"), 1);
+    assert_eq!(requests("unsigned", "preset"), 2);
+    assert_eq!(requests("signed", "preset"), 0);
+}
+
 #[tokio::test]
 async fn test_preset_default_only() {
     let mock_server = MockServer::start().await;
@@ -235,6 +388,53 @@ async fn test_preset_default_only() {
     assert_eq!(img.get_height(), 75);
 }
 
+#[tokio::test]
+async fn test_skip_default_bypasses_default_preset() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(400, 300, [0, 0, 255, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/test.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("content-type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let key = b"test_key".to_vec();
+    let salt = b"test_salt".to_vec();
+
+    let mut presets = HashMap::new();
+    presets.insert(
+        "default".to_string(),
+        parse_options_string("resize:fit:100:100").unwrap(),
+    );
+
+    let config = create_test_config(key.clone(), salt.clone(), false, presets, false);
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .layer(axum::middleware::from_fn(request_id_middleware))
+        .with_state(state);
+
+    let source_url = format!("{}/test.png", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path_to_sign = format!("/skip_default/{}", encoded_url);
+    let signature = generate_signature(&key, &salt, &path_to_sign);
+    let uri = format!("/{}{}", signature, path_to_sign);
+
+    let (status, body) = make_request(app, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The default preset's resize is not applied
+    let img = VipsImage::new_from_buffer(&body, "").unwrap();
+    assert_eq!(img.get_width(), 400);
+    assert_eq!(img.get_height(), 300);
+}
+
 #[tokio::test]
 async fn test_preset_unknown_preset_error() {
     let mock_server = MockServer::start().await;
@@ -487,3 +687,70 @@ async fn test_preset_with_url_override() {
     assert_eq!(img.get_width(), 200);
     assert_eq!(img.get_height(), 150);
 }
+
+#[tokio::test]
+async fn test_preset_sets_output_format_and_quality() {
+    let mock_server = MockServer::start().await;
+    let test_image = create_test_image(400, 300, [0, 128, 255, 255]);
+
+    Mock::given(method("GET"))
+        .and(path("/test.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(test_image)
+                .insert_header("content-type", "image/png"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let key = b"test_key".to_vec();
+    let salt = b"test_salt".to_vec();
+
+    let mut presets = HashMap::new();
+    presets.insert(
+        "web".to_string(),
+        parse_options_string("format:webp/quality:70").unwrap(),
+    );
+
+    // Presets supply format and quality; inline options override them wherever they appear.
+    let preset_only = vec![ProcessingOption {
+        name: "preset".to_string(),
+        args: vec!["web".to_string()],
+    }];
+    let parsed = parse_all_options(expand_presets(preset_only, &presets, false).unwrap()).unwrap();
+    assert_eq!(parsed.format.as_deref(), Some("webp"));
+    assert_eq!(parsed.quality, Some(70));
+
+    let inline_first = vec![
+        ProcessingOption {
+            name: "quality".to_string(),
+            args: vec!["50".to_string()],
+        },
+        ProcessingOption {
+            name: "preset".to_string(),
+            args: vec!["web".to_string()],
+        },
+    ];
+    let parsed = parse_all_options(expand_presets(inline_first, &presets, false).unwrap()).unwrap();
+    assert_eq!(parsed.format.as_deref(), Some("webp"));
+    assert_eq!(parsed.quality, Some(50));
+
+    let config = create_test_config(key.clone(), salt.clone(), false, presets, false);
+    let state = create_test_state(config).await;
+
+    let app = axum::Router::new()
+        .route("/{*path}", axum::routing::get(image_forge_handler))
+        .layer(axum::middleware::from_fn(request_id_middleware))
+        .with_state(state);
+
+    let source_url = format!("{}/test.png", mock_server.uri());
+    let encoded_url = URL_SAFE_NO_PAD.encode(source_url.as_bytes());
+    let path_to_sign = format!("/preset:web/{}", encoded_url);
+    let signature = generate_signature(&key, &salt, &path_to_sign);
+    let uri = format!("/{}{}", signature, path_to_sign);
+
+    let (status, body) = make_request(app, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body[0..4], b"RIFF");
+    assert_eq!(&body[8..12], b"WEBP");
+}